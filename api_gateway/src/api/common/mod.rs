@@ -1,6 +1,6 @@
 use std::time::Duration;
 use rocket::tokio::{sync::oneshot, time::sleep};
-use rocket::{tokio, State};
+use rocket::tokio;
 
 use rocket_db_pools::{
     deadpool_redis::redis::AsyncCommands, 
@@ -8,7 +8,6 @@ use rocket_db_pools::{
     Connection
 };
 
-use crate::broker::Rabbit;
 use crate::{Postgres, Redis};
 
 #[get("/healthcheck")]
@@ -34,13 +33,6 @@ pub async fn test_get_site(mut pg: Connection<Postgres>) -> Option<String> {
     row
 }
 
-// test with state
-#[get("/test_state_rabbit")]
-pub async fn test_state_rabbit(rabbit: &State<Rabbit>) -> &'static str {
-    rabbit.publish("hello there".as_bytes()).await;
-    "ok"
-}
-
 #[get("/test_spawn_task")]
 pub async fn get_spawn_task() -> String {
     println!("Creating oneshot");
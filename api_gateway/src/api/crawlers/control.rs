@@ -0,0 +1,98 @@
+#![allow(dead_code,unused)]
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, Deserialize, json::Json};
+use rocket::State;
+use rocket_db_pools::{sqlx, Connection};
+use tonic::Code;
+use uuid::Uuid;
+
+use common::grpc::CrawlerRequest;
+use common::models::{ControlTarget, CrawlerState, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData};
+
+use crate::api::runs::{get_last_run, RunOut};
+use crate::broker::{Broker, ParseraService, SharedBroker};
+use crate::scheduler::SchedulerClient;
+use crate::Postgres;
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ControlOut {
+    pub id: Uuid,
+    pub command: String,
+    pub status: EventCommandStatus,
+}
+
+pub type ControlResponse = Result<status::Custom<Json<ControlOut>>, status::Custom<String>>;
+
+/// Hands a control command over to the scheduler, the result is visible in crawler and run statuses.
+pub async fn send_control(broker: &dyn Broker, command: EventCommand, target: ControlTarget) -> ControlResponse {
+    let id = match target {
+        ControlTarget::Crawler(id) | ControlTarget::Run(id) => id,
+    };
+    let out = ControlOut { id, command: command.to_string(), status: EventCommandStatus::Pending };
+    let event = EventProtocol::new(command, EventProtocolData::Control(target));
+    broker
+        .publish_event(&event, ParseraService::Scheduler)
+        .await
+        .map_err(|err| status::Custom(Status::ServiceUnavailable, format!("cannot reach scheduler: {}", err)))?;
+    Ok(status::Custom(Status::Accepted, Json(out)))
+}
+
+/// Pauses or resumes the crawler through the scheduler gRPC API and answers with the result right away.
+/// The command is sent through the event bus instead when the scheduler cannot be reached.
+async fn control_crawler(scheduler: &SchedulerClient, broker: &dyn Broker, command: EventCommand, crawler_id: Uuid) -> ControlResponse {
+    let mut client = scheduler.clone();
+    let request = CrawlerRequest { crawler_id: crawler_id.to_string() };
+    let response = match command {
+        EventCommand::PauseCrawler(_) => client.pause_crawler(request).await,
+        _ => client.resume_crawler(request).await,
+    };
+    match response {
+        Ok(_) => {
+            let out = ControlOut { id: crawler_id, command: command.to_string(), status: EventCommandStatus::Done };
+            Ok(status::Custom(Status::Ok, Json(out)))
+        },
+        Err(err) if err.code() == Code::NotFound => Err(status::Custom(Status::NotFound, err.message().to_string())),
+        Err(err) if err.code() == Code::InvalidArgument => Err(status::Custom(Status::BadRequest, err.message().to_string())),
+        Err(err) => {
+            tracing::warn!("cannot {} over gRPC, sending it as an event: {}", command, err);
+            send_control(broker, command, ControlTarget::Crawler(crawler_id)).await
+        },
+    }
+}
+
+#[post("/crawler/<crawler_id>/pause")]
+pub async fn pause_crawler(scheduler: &State<SchedulerClient>, broker: &State<SharedBroker>, crawler_id: Uuid) -> ControlResponse {
+    let command = EventCommand::PauseCrawler(EventCommandStatus::Pending);
+    control_crawler(scheduler, broker.inner().as_ref(), command, crawler_id).await
+}
+
+#[post("/crawler/<crawler_id>/resume")]
+pub async fn resume_crawler(scheduler: &State<SchedulerClient>, broker: &State<SharedBroker>, crawler_id: Uuid) -> ControlResponse {
+    let command = EventCommand::ResumeCrawler(EventCommandStatus::Pending);
+    control_crawler(scheduler, broker.inner().as_ref(), command, crawler_id).await
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CrawlerStatusOut {
+    pub crawler_id: Uuid,
+    pub state: CrawlerState,
+    pub last_run: Option<RunOut>,
+}
+
+#[get("/crawler/<crawler_id>/status")]
+pub async fn get_crawler_status(mut pg: Connection<Postgres>, crawler_id: Uuid) -> Json<CrawlerStatusOut> {
+    let state: Option<String> = sqlx::query_scalar("select state from crawler_states where crawler_id = $1::uuid")
+        .bind(crawler_id.to_string())
+        .fetch_optional(&mut **pg)
+        .await
+        .unwrap_or_else(|err| {
+            tracing::error!("cannot get state of crawler {}: {}", crawler_id, err);
+            None
+        });
+    let state = state.and_then(|s| s.parse().ok()).unwrap_or_default();
+    let last_run = get_last_run(&mut pg, crawler_id).await;
+    Json(CrawlerStatusOut { crawler_id, state, last_run })
+}
@@ -1,9 +1,23 @@
 #![allow(dead_code,unused)]
 use std::collections::HashMap;
 
+use rocket::http::Status;
+use rocket::response::status;
 use rocket::serde::{Serialize, Deserialize, json::Json};
+use rocket::State;
+use rocket_db_pools::Connection;
+use tonic::Code;
+use uuid::Uuid;
 
-use common::models::{AddSiteEvent, EventStatus};
+use common::grpc::{CrawlerRequest, RegisterCrawlerRequest};
+use common::models::{
+    AddSiteEvent, Crawler, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, EventStatus, QuotaKind,
+};
+
+use crate::api::quotas::{self, QuotaError, QuotaResponse};
+use crate::broker::{Broker, ParseraService, SharedBroker};
+use crate::scheduler::SchedulerClient;
+use crate::Redis;
 
 // GET
 #[derive(Debug, Deserialize, Serialize)]
@@ -31,52 +45,99 @@ pub async fn get_crawler(payload: Json<GetCrawlerIn>) -> Json<GetCrawlerOut> {
 
 
 // CREATE
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct AddCrawlerIn {
-    pub url: String,
-    pub user_id: usize,
-    pub is_pagination: bool,
-    pub refresh_interval: usize,
-    pub xpaths: HashMap<String, String>,
-}
-
 #[derive(Debug, Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct AddCrawlerOut {
-    pub params: AddCrawlerIn,
-    pub event_id: usize,
-    pub status: EventStatus,
+    pub crawler_id: Uuid,
+    /// First run of the crawler, when it's registered right away.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<Uuid>,
+    /// Event the crawler is sent in, when the scheduler gRPC API cannot be reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<Uuid>,
+    pub status: EventCommandStatus,
 }
 
+/// Takes a crawler from the user's quota and registers it through the scheduler gRPC API.
+/// The crawler is sent through the event bus instead when the scheduler cannot be reached.
+/// The quota is given back when the crawler is refused or cannot be sent at all. It's kept when the outcome
+/// is unknown, e.g. after a timeout, since the scheduler may have registered the crawler.
 #[post("/crawler", format = "json", data = "<payload>")]
-pub async fn add_crawler(payload: Json<AddCrawlerIn>) -> Json<AddCrawlerOut> {
-    todo!("impl")
+pub async fn add_crawler(
+    mut redis: Connection<Redis>,
+    scheduler: &State<SchedulerClient>,
+    broker: &State<SharedBroker>,
+    payload: Json<Crawler>,
+) -> QuotaResponse<status::Custom<Json<AddCrawlerOut>>> {
+    let Json(crawler) = payload;
+    let user_id = crawler.user_id;
+    quotas::consume(&mut redis, user_id, QuotaKind::Crawlers).await?;
+
+    let crawler_id = crawler.id;
+    let request = RegisterCrawlerRequest { crawler: Some(crawler.clone().into()) };
+    match scheduler.inner().clone().register_crawler(request).await {
+        Ok(response) => {
+            let run_id = Uuid::parse_str(&response.into_inner().run_id).ok();
+            let out = AddCrawlerOut { crawler_id, run_id, event_id: None, status: EventCommandStatus::Done };
+            return Ok(status::Custom(Status::Created, Json(out)));
+        },
+        Err(err) if err.code() == Code::InvalidArgument => {
+            quotas::release(&mut redis, user_id, QuotaKind::Crawlers).await;
+            let error = err.message().to_string();
+            return Err(status::Custom(Status::BadRequest, Json(QuotaError { error, quota: None })));
+        },
+        Err(err) if err.code() == Code::Unavailable => {
+            tracing::warn!("cannot register crawler {} over gRPC, sending it as an event: {}", crawler_id, err);
+        },
+        Err(err) => {
+            tracing::error!("registration of crawler {} is unknown: {}", crawler_id, err);
+            let error = format!("crawler {} may be registered, check it before retrying: {}", crawler_id, err.message());
+            return Err(status::Custom(Status::GatewayTimeout, Json(QuotaError { error, quota: None })));
+        },
+    }
+
+    let event = EventProtocol::new(
+        EventCommand::RegisterCrawler(EventCommandStatus::Pending),
+        EventProtocolData::External(crawler),
+    );
+    if let Err(err) = broker.publish_event(&event, ParseraService::Scheduler).await {
+        quotas::release(&mut redis, user_id, QuotaKind::Crawlers).await;
+        let error = format!("cannot reach scheduler: {}", err);
+        return Err(status::Custom(Status::ServiceUnavailable, Json(QuotaError { error, quota: None })));
+    }
+    let out = AddCrawlerOut { crawler_id, run_id: None, event_id: Some(event.id), status: EventCommandStatus::Pending };
+    Ok(status::Custom(Status::Accepted, Json(out)))
 }
 
 
 // DELETE
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct DeleteCrawlerIn {
-    pub url: String,
-    pub user_id: usize,
-    pub is_pagination: bool,
-    pub refresh_interval: usize,
-    pub xpaths: HashMap<String, String>,
-}
-
 #[derive(Debug, Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct DeleteCrawlerOut {
-    pub params: DeleteCrawlerIn,
-    pub event_id: usize,
-    pub status: EventStatus,
+    pub crawler_id: Uuid,
+    pub status: EventCommandStatus,
 }
 
-#[delete("/crawler", format = "json", data = "<payload>")]
-pub async fn delete_crawler(payload: Json<DeleteCrawlerIn>) -> Json<DeleteCrawlerOut> {
-    todo!("impl")
+/// Deletes the crawler through the scheduler gRPC API and gives its crawler back to the owner's quota.
+#[delete("/crawler/<crawler_id>")]
+pub async fn delete_crawler(
+    mut redis: Connection<Redis>,
+    scheduler: &State<SchedulerClient>,
+    crawler_id: Uuid,
+) -> Result<Json<DeleteCrawlerOut>, status::Custom<String>> {
+    let request = CrawlerRequest { crawler_id: crawler_id.to_string() };
+    let deleted = scheduler
+        .inner()
+        .clone()
+        .delete_crawler(request)
+        .await
+        .map_err(|err| match err.code() {
+            Code::NotFound => status::Custom(Status::NotFound, err.message().to_string()),
+            _ => status::Custom(Status::ServiceUnavailable, format!("cannot reach scheduler: {}", err.message())),
+        })?
+        .into_inner();
+    quotas::release(&mut redis, deleted.user_id, QuotaKind::Crawlers).await;
+    Ok(Json(DeleteCrawlerOut { crawler_id, status: EventCommandStatus::Done }))
 }
 
 
@@ -1,5 +1,7 @@
+mod control;
 mod crawler;
 mod site;
 
+pub use control::*;
 pub use crawler::*;
 pub use site::*;
\ No newline at end of file
@@ -1,6 +1,11 @@
 mod crawlers;
 mod users;
 mod common;
+mod quotas;
+mod runs;
+mod scheduler;
+mod suggestions;
+mod webhooks;
 
 use rocket::Route;
 
@@ -11,14 +16,21 @@ use crawlers::{
     update_crawler,
     get_crawlers,
     add_site,
+    pause_crawler,
+    resume_crawler,
+    get_crawler_status,
 };
 use common::{
     get_healthcheck,
     set_bo,
     test_get_site,
-    test_state_rabbit,
     get_spawn_task,
 };
+use quotas::get_quota;
+use runs::{cancel_run, get_run, get_runs};
+use scheduler::{get_jobs, replay_dead_letters};
+use suggestions::{get_suggestion, get_suggestions, request_suggestions};
+use webhooks::{get_deliveries, get_delivery, replay_delivery};
 
 
 pub fn get_routes() -> Vec<Route> {
@@ -27,13 +39,33 @@ pub fn get_routes() -> Vec<Route> {
         set_bo,
         test_get_site,
         get_spawn_task,
-        test_state_rabbit,
-    
+        
         get_crawler,
         add_crawler,
         delete_crawler,
         update_crawler,
         get_crawlers,
-        add_site
+        add_site,
+
+        get_quota,
+
+        get_runs,
+        get_run,
+        cancel_run,
+
+        pause_crawler,
+        resume_crawler,
+        get_crawler_status,
+
+        request_suggestions,
+        get_suggestions,
+        get_suggestion,
+
+        get_jobs,
+        replay_dead_letters,
+
+        get_deliveries,
+        get_delivery,
+        replay_delivery,
     ]
 }
\ No newline at end of file
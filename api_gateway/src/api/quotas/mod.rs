@@ -0,0 +1,111 @@
+use std::fmt::Display;
+
+use chrono::Utc;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, json::Json};
+use rocket_db_pools::{deadpool_redis::redis::AsyncCommands, Connection};
+
+use common::models::{quota_limits_key, quota_plan_key, QuotaExceeded, QuotaKind, QuotaLimits};
+
+use crate::Redis;
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct QuotaError {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaExceeded>,
+}
+
+pub type QuotaResponse<T> = Result<T, status::Custom<Json<QuotaError>>>;
+
+fn exceeded_error(quota: QuotaExceeded) -> status::Custom<Json<QuotaError>> {
+    let error = format!("{} quota exceeded: {}/{}", quota.kind, quota.used, quota.limit);
+    status::Custom(Status::TooManyRequests, Json(QuotaError { error, quota: Some(quota) }))
+}
+
+fn counter_error(err: impl Display) -> status::Custom<Json<QuotaError>> {
+    tracing::error!("cannot update quota counter: {}", err);
+    let error = "cannot check quota, try again later".to_string();
+    status::Custom(Status::InternalServerError, Json(QuotaError { error, quota: None }))
+}
+
+/// Limits of a user: explicit override, then plan limits, then the free plan.
+pub async fn get_limits(redis: &mut Connection<Redis>, user_id: impl Display) -> QuotaLimits {
+    let custom: Option<String> = redis.get(quota_limits_key(&user_id)).await.unwrap_or(None);
+    let plan: Option<String> = redis.get(quota_plan_key(&user_id)).await.unwrap_or(None);
+    QuotaLimits::resolve(custom.as_deref(), plan.as_deref())
+}
+
+pub async fn get_used(redis: &mut Connection<Redis>, user_id: impl Display, kind: QuotaKind) -> u64 {
+    let used: Option<u64> = redis.get(kind.counter_key(user_id, Utc::now())).await.unwrap_or(None);
+    used.unwrap_or(0)
+}
+
+/// Takes one unit of the quota or returns what is exceeded without consuming it.
+pub async fn consume(redis: &mut Connection<Redis>, user_id: impl Display, kind: QuotaKind) -> QuotaResponse<()> {
+    let now = Utc::now();
+    let limit = kind.limit(&get_limits(redis, &user_id).await);
+    let key = kind.counter_key(&user_id, now);
+    let used: u64 = redis.incr(&key, 1).await.map_err(counter_error)?;
+    if used > limit {
+        let _: i64 = redis.decr(&key, 1).await.map_err(counter_error)?;
+        return Err(exceeded_error(QuotaExceeded {
+            user_id: user_id.to_string(),
+            kind,
+            limit,
+            used: used - 1,
+            resets_at: kind.resets_at(now),
+        }));
+    }
+    Ok(())
+}
+
+/// Gives back a unit of the quota, e.g. when the request it was taken for is not accepted.
+pub async fn release(redis: &mut Connection<Redis>, user_id: impl Display, kind: QuotaKind) {
+    let key = kind.counter_key(&user_id, Utc::now());
+    let released: Result<i64, _> = redis.decr(&key, 1).await;
+    match released {
+        Ok(used) if used < 0 => {
+            if let Err(err) = redis.set::<_, _, ()>(&key, 0).await {
+                tracing::error!("cannot reset {} quota counter of user {}: {}", kind, user_id, err);
+            }
+        },
+        Ok(_) => (),
+        Err(err) => tracing::error!("cannot release {} quota of user {}: {}", kind, user_id, err),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct QuotaUsage {
+    pub kind: QuotaKind,
+    pub used: u64,
+    pub limit: u64,
+    pub resets_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GetQuotaOut {
+    pub user_id: String,
+    pub limits: QuotaLimits,
+    pub usage: Vec<QuotaUsage>,
+}
+
+#[get("/quota/<user_id>")]
+pub async fn get_quota(mut redis: Connection<Redis>, user_id: &str) -> Json<GetQuotaOut> {
+    let limits = get_limits(&mut redis, user_id).await;
+    let now = Utc::now();
+    let mut usage = vec![];
+    for kind in [QuotaKind::Crawlers, QuotaKind::PagesPerDay, QuotaKind::PagesPerMonth, QuotaKind::ReparsesPerDay] {
+        usage.push(QuotaUsage {
+            kind,
+            used: get_used(&mut redis, user_id, kind).await,
+            limit: kind.limit(&limits),
+            resets_at: kind.resets_at(now),
+        });
+    }
+    Json(GetQuotaOut { user_id: user_id.to_string(), limits, usage })
+}
@@ -0,0 +1,125 @@
+#![allow(dead_code,unused)]
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, Deserialize, json::Json};
+use rocket::State;
+use rocket_db_pools::{sqlx::{self, FromRow}, Connection};
+use tonic::Code;
+use uuid::Uuid;
+
+use common::grpc::CancelRunRequest;
+use common::models::{ControlTarget, EventCommand, EventCommandStatus, RunStatus};
+
+use crate::api::crawlers::{send_control, ControlOut, ControlResponse};
+use crate::broker::SharedBroker;
+use crate::scheduler::SchedulerClient;
+use crate::Postgres;
+
+const DEFAULT_RUNS_LIMIT: i64 = 20;
+const MAX_RUNS_LIMIT: i64 = 200;
+
+const RUN_COLUMNS: &str = "id::text, crawler_id::text, status, dispatched, scraped, extracted, failed, skipped, bytes, stop_reason, started_at, finished_at";
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct RunOut {
+    pub id: String,
+    pub crawler_id: String,
+    pub status: String,
+    pub dispatched: i64,
+    pub scraped: i64,
+    pub extracted: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub bytes: i64,
+    /// Limit or stop condition which stopped the run early.
+    pub stop_reason: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GetRunsOut {
+    pub crawler_id: String,
+    pub runs: Vec<RunOut>,
+}
+
+/// Run history of a crawler, the latest runs first.
+#[get("/crawler/<crawler_id>/runs?<limit>")]
+pub async fn get_runs(mut pg: Connection<Postgres>, crawler_id: &str, limit: Option<i64>) -> Option<Json<GetRunsOut>> {
+    let limit = limit.unwrap_or(DEFAULT_RUNS_LIMIT).clamp(1, MAX_RUNS_LIMIT);
+    let query = format!(
+        "select {} from crawl_runs where crawler_id = $1::uuid order by started_at desc limit $2",
+        RUN_COLUMNS,
+    );
+    let runs = sqlx::query_as::<_, RunOut>(&query)
+        .bind(crawler_id)
+        .bind(limit)
+        .fetch_all(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get runs of crawler {}: {}", crawler_id, err))
+        .ok()?;
+    Some(Json(GetRunsOut { crawler_id: crawler_id.to_string(), runs }))
+}
+
+#[get("/run/<run_id>")]
+pub async fn get_run(mut pg: Connection<Postgres>, run_id: &str) -> Option<Json<RunOut>> {
+    let query = format!("select {} from crawl_runs where id = $1::uuid", RUN_COLUMNS);
+    sqlx::query_as::<_, RunOut>(&query)
+        .bind(run_id)
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get run {}: {}", run_id, err))
+        .ok()?
+        .map(Json)
+}
+
+pub async fn get_last_run(pg: &mut Connection<Postgres>, crawler_id: Uuid) -> Option<RunOut> {
+    let query = format!(
+        "select {} from crawl_runs where crawler_id = $1::uuid order by started_at desc limit 1",
+        RUN_COLUMNS,
+    );
+    sqlx::query_as::<_, RunOut>(&query)
+        .bind(crawler_id.to_string())
+        .fetch_optional(&mut ***pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get last run of crawler {}: {}", crawler_id, err))
+        .ok()?
+}
+
+/// Cancels a running run. Already queued pages of the run are dropped by services.
+/// The run is cancelled through the scheduler gRPC API, or through the event bus when the scheduler is unavailable.
+#[post("/run/<run_id>/cancel")]
+pub async fn cancel_run(
+    mut pg: Connection<Postgres>,
+    scheduler: &State<SchedulerClient>,
+    broker: &State<SharedBroker>,
+    run_id: Uuid,
+) -> ControlResponse {
+    let status: Option<String> = sqlx::query_scalar("select status from crawl_runs where id = $1::uuid")
+        .bind(run_id.to_string())
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.to_string()))?;
+    let status = status.ok_or_else(|| status::Custom(Status::NotFound, format!("run {} is not found", run_id)))?;
+    if status != RunStatus::Running.to_string() {
+        return Err(status::Custom(Status::Conflict, format!("run {} is already {}", run_id, status)));
+    }
+    let command = EventCommand::CancelRun(EventCommandStatus::Pending);
+    let request = CancelRunRequest { run_id: run_id.to_string() };
+    match scheduler.inner().clone().cancel_run(request).await {
+        Ok(response) if response.get_ref().cancelled => {
+            let out = ControlOut { id: run_id, command: command.to_string(), status: EventCommandStatus::Done };
+            Ok(status::Custom(Status::Ok, Json(out)))
+        },
+        Ok(_) => Err(status::Custom(Status::Conflict, format!("run {} is already finished", run_id))),
+        Err(err) if err.code() == Code::NotFound => Err(status::Custom(Status::NotFound, err.message().to_string())),
+        Err(err) if err.code() == Code::Unavailable => {
+            tracing::warn!("cannot cancel run {} over gRPC, sending it as an event: {}", run_id, err);
+            send_control(broker.inner().as_ref(), command, ControlTarget::Run(run_id)).await
+        },
+        Err(err) => Err(status::Custom(Status::BadGateway, err.message().to_string())),
+    }
+}
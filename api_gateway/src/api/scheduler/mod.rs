@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, json::Json};
+use rocket::State;
+use tonic::Code;
+use uuid::Uuid;
+
+use common::grpc::{self, ListJobsRequest, ReplayDeadLettersRequest};
+use common::models::CrawlerState;
+
+use crate::scheduler::SchedulerClient;
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobOut {
+    pub crawler_id: String,
+    pub user_id: String,
+    pub name: String,
+    pub rule: String,
+    pub state: CrawlerState,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReplayOut {
+    pub service: String,
+    pub replayed: u64,
+}
+
+/// Moves dead letters of a service, e.g. `scraper`, back to its queue.
+#[post("/scheduler/dlq/<service>/replay")]
+pub async fn replay_dead_letters(scheduler: &State<SchedulerClient>, service: &str) -> Result<Json<ReplayOut>, status::Custom<String>> {
+    let request = ReplayDeadLettersRequest { service: service.to_string() };
+    let replayed = scheduler
+        .inner()
+        .clone()
+        .replay_dead_letters(request)
+        .await
+        .map_err(|err| match err.code() {
+            Code::InvalidArgument => status::Custom(Status::NotFound, err.message().to_string()),
+            _ => status::Custom(Status::ServiceUnavailable, format!("cannot reach scheduler: {}", err.message())),
+        })?
+        .into_inner()
+        .replayed;
+    Ok(Json(ReplayOut { service: service.to_string(), replayed }))
+}
+
+/// Cron jobs of registered crawlers as the scheduler sees them right now.
+#[get("/scheduler/jobs?<user_id>")]
+pub async fn get_jobs(scheduler: &State<SchedulerClient>, user_id: Option<Uuid>) -> Result<Json<Vec<JobOut>>, status::Custom<String>> {
+    let request = ListJobsRequest { user_id: user_id.map(|id| id.to_string()).unwrap_or_default() };
+    let jobs = scheduler
+        .inner()
+        .clone()
+        .list_jobs(request)
+        .await
+        .map_err(|err| status::Custom(Status::ServiceUnavailable, format!("cannot reach scheduler: {}", err.message())))?
+        .into_inner()
+        .jobs;
+    let jobs = jobs
+        .into_iter()
+        .map(|job| JobOut {
+            state: match job.state() {
+                grpc::CrawlerState::Paused => CrawlerState::Paused,
+                _ => CrawlerState::Active,
+            },
+            next_run_at: job.next_run_at.and_then(|at| DateTime::from_timestamp(at, 0)),
+            crawler_id: job.crawler_id,
+            user_id: job.user_id,
+            name: job.name,
+            rule: job.rule,
+        })
+        .collect();
+    Ok(Json(jobs))
+}
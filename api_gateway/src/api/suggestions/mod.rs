@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, Deserialize, json::{Json, Value}};
+use rocket::State;
+use rocket_db_pools::{sqlx::{self, types::Json as SqlJson, FromRow}, Connection};
+use uuid::Uuid;
+
+use common::models::{EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, SuggestSelectors};
+
+use crate::broker::{ParseraService, SharedBroker};
+use crate::Postgres;
+
+const DEFAULT_SUGGESTIONS_LIMIT: i64 = 20;
+const MAX_SUGGESTIONS_LIMIT: i64 = 100;
+
+const SUGGESTION_COLUMNS: &str = "id::text, crawler_id::text, status, examples, suggestions, created_at, updated_at";
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SuggestIn {
+    pub html: String,
+    /// Field name to an example value of the field on the page.
+    pub examples: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SuggestOut {
+    pub id: Uuid,
+    pub crawler_id: Uuid,
+    pub status: EventCommandStatus,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct SuggestionOut {
+    pub id: String,
+    pub crawler_id: String,
+    pub status: String,
+    pub examples: SqlJson<Value>,
+    /// Field name to suggested xpaths, the most confident first.
+    pub suggestions: SqlJson<Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GetSuggestionsOut {
+    pub crawler_id: String,
+    pub suggestions: Vec<SuggestionOut>,
+}
+
+/// Asks extractors to suggest xpaths of the fields by their example values on the page.
+/// Suggestions are ready once the request status is Done.
+#[post("/crawler/<crawler_id>/suggestions", data = "<input>")]
+pub async fn request_suggestions(
+    mut pg: Connection<Postgres>,
+    broker: &State<SharedBroker>,
+    crawler_id: Uuid,
+    input: Json<SuggestIn>,
+) -> Result<status::Custom<Json<SuggestOut>>, status::Custom<String>> {
+    let input = input.into_inner();
+    if input.examples.is_empty() {
+        return Err(status::Custom(Status::BadRequest, "no example values to suggest xpaths for".to_string()));
+    }
+    let request = SuggestSelectors {
+        id: Uuid::now_v7(),
+        crawler_id: Some(crawler_id),
+        html: input.html,
+        examples: input.examples,
+        suggestions: HashMap::new(),
+    };
+    // stored before it is sent, so it can be fetched right away
+    sqlx::query("insert into selector_suggestions (id, crawler_id, examples) values ($1::uuid, $2::uuid, $3::json)")
+        .bind(request.id.to_string())
+        .bind(crawler_id.to_string())
+        .bind(SqlJson(&request.examples))
+        .execute(&mut **pg)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.to_string()))?;
+
+    let out = SuggestOut { id: request.id, crawler_id, status: EventCommandStatus::Pending };
+    let event = EventProtocol::new(
+        EventCommand::SuggestSelectors(EventCommandStatus::Pending),
+        EventProtocolData::Suggestions(request),
+    );
+    broker
+        .publish_event(&event, ParseraService::Scheduler)
+        .await
+        .map_err(|err| status::Custom(Status::ServiceUnavailable, format!("cannot reach scheduler: {}", err)))?;
+    Ok(status::Custom(Status::Accepted, Json(out)))
+}
+
+/// Selector suggestion requests of a crawler, the latest first.
+#[get("/crawler/<crawler_id>/suggestions?<limit>")]
+pub async fn get_suggestions(mut pg: Connection<Postgres>, crawler_id: &str, limit: Option<i64>) -> Option<Json<GetSuggestionsOut>> {
+    let limit = limit.unwrap_or(DEFAULT_SUGGESTIONS_LIMIT).clamp(1, MAX_SUGGESTIONS_LIMIT);
+    let query = format!(
+        "select {} from selector_suggestions where crawler_id = $1::uuid order by created_at desc limit $2",
+        SUGGESTION_COLUMNS,
+    );
+    let suggestions = sqlx::query_as::<_, SuggestionOut>(&query)
+        .bind(crawler_id)
+        .bind(limit)
+        .fetch_all(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get suggestions of crawler {}: {}", crawler_id, err))
+        .ok()?;
+    Some(Json(GetSuggestionsOut { crawler_id: crawler_id.to_string(), suggestions }))
+}
+
+#[get("/suggestion/<suggestion_id>")]
+pub async fn get_suggestion(mut pg: Connection<Postgres>, suggestion_id: &str) -> Option<Json<SuggestionOut>> {
+    let query = format!("select {} from selector_suggestions where id = $1::uuid", SUGGESTION_COLUMNS);
+    sqlx::query_as::<_, SuggestionOut>(&query)
+        .bind(suggestion_id)
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get suggestion {}: {}", suggestion_id, err))
+        .ok()?
+        .map(Json)
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{Serialize, json::Json};
+use rocket_db_pools::{sqlx::{self, FromRow}, Connection};
+use uuid::Uuid;
+
+use common::models::WebhookDeliveryStatus;
+
+use crate::Postgres;
+
+const DEFAULT_DELIVERIES_LIMIT: i64 = 50;
+const MAX_DELIVERIES_LIMIT: i64 = 500;
+
+const DELIVERY_COLUMNS: &str = "id::text, crawler_id::text, url, event, status, attempts, response_status, last_error, \
+    next_attempt_at, created_at, delivered_at";
+
+#[derive(Debug, Serialize, FromRow)]
+#[serde(crate = "rocket::serde")]
+pub struct DeliveryOut {
+    pub id: String,
+    pub crawler_id: String,
+    pub url: String,
+    pub event: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GetDeliveriesOut {
+    pub deliveries: Vec<DeliveryOut>,
+}
+
+/// Webhook delivery log, the latest deliveries first.
+#[get("/webhooks/deliveries?<crawler_id>&<status>&<limit>")]
+pub async fn get_deliveries(
+    mut pg: Connection<Postgres>,
+    crawler_id: Option<&str>,
+    status: Option<&str>,
+    limit: Option<i64>,
+) -> Option<Json<GetDeliveriesOut>> {
+    let limit = limit.unwrap_or(DEFAULT_DELIVERIES_LIMIT).clamp(1, MAX_DELIVERIES_LIMIT);
+    let query = format!(
+        "select {} from webhook_deliveries
+         where ($1::uuid is null or crawler_id = $1::uuid) and ($2::text is null or status = $2)
+         order by created_at desc limit $3",
+        DELIVERY_COLUMNS,
+    );
+    let deliveries = sqlx::query_as::<_, DeliveryOut>(&query)
+        .bind(crawler_id)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get webhook deliveries: {}", err))
+        .ok()?;
+    Some(Json(GetDeliveriesOut { deliveries }))
+}
+
+#[get("/webhooks/deliveries/<delivery_id>")]
+pub async fn get_delivery(mut pg: Connection<Postgres>, delivery_id: &str) -> Option<Json<DeliveryOut>> {
+    let query = format!("select {} from webhook_deliveries where id = $1::uuid", DELIVERY_COLUMNS);
+    sqlx::query_as::<_, DeliveryOut>(&query)
+        .bind(delivery_id)
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| tracing::error!("cannot get webhook delivery {}: {}", delivery_id, err))
+        .ok()?
+        .map(Json)
+}
+
+/// Queues a delivery to be sent again by the scheduler's retry job with a fresh attempt budget.
+#[post("/webhooks/deliveries/<delivery_id>/replay")]
+pub async fn replay_delivery(
+    mut pg: Connection<Postgres>,
+    delivery_id: Uuid,
+) -> Result<status::Accepted<Json<DeliveryOut>>, status::Custom<String>> {
+    let query = format!(
+        "update webhook_deliveries set status = $2, attempts = 0, next_attempt_at = now()
+         where id = $1::uuid and status <> $2
+         returning {}",
+        DELIVERY_COLUMNS,
+    );
+    let replayed = sqlx::query_as::<_, DeliveryOut>(&query)
+        .bind(delivery_id.to_string())
+        .bind(WebhookDeliveryStatus::Pending.to_string())
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.to_string()))?;
+    if let Some(delivery) = replayed {
+        return Ok(status::Accepted(Json(delivery)));
+    }
+    let exists: Option<String> = sqlx::query_scalar("select id::text from webhook_deliveries where id = $1::uuid")
+        .bind(delivery_id.to_string())
+        .fetch_optional(&mut **pg)
+        .await
+        .map_err(|err| status::Custom(Status::InternalServerError, err.to_string()))?;
+    match exists {
+        Some(_) => Err(status::Custom(Status::Conflict, format!("delivery {} is already pending", delivery_id))),
+        None => Err(status::Custom(Status::NotFound, format!("delivery {} is not found", delivery_id))),
+    }
+}
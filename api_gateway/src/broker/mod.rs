@@ -1,31 +1,15 @@
-#![allow(dead_code,unused)]
-use std::error::Error;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use rand::{rngs::ThreadRng, seq::SliceRandom};
 use rocket::figment::{Figment, providers::Env};
 use rocket::serde::Deserialize;
-use deadpool_lapin::{
-    Config, 
-    Manager,
-    Object, 
-    Pool,
-    Runtime,
-};
-use deadpool_lapin::lapin::{
-    protocol::channel,
-    types::FieldTable, 
-    Channel,
-    BasicProperties,
-    ExchangeKind,
-};
-use deadpool_lapin::lapin::options::{
-    ExchangeDeclareOptions, 
-    QueueBindOptions, 
-    QueueDeclareOptions,
-    BasicPublishOptions,
-};
 
+pub use common::broker::{
+    Broker, BrokerBackend, BrokerSettings, KafkaSettings, ParseraService, RabbitSettings, Topology,
+};
 
+pub type SharedBroker = Arc<dyn Broker>;
 
 #[derive(Deserialize)]
 #[serde(crate = "rocket::serde")]
@@ -35,17 +19,42 @@ pub struct RabbitConfig {
     pub user: String,
     pub password: String,
     pub vhost: String,
-    pub exchange: String,
-    pub queues: Vec<String>,
+    #[serde(default = "default_prefetch_count")]
+    pub prefetch_count: u16,
+}
+
+fn default_prefetch_count() -> u16 {
+    16
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct KafkaConfig {
+    #[serde(default = "default_kafka_brokers")]
+    pub brokers: String,
+    #[serde(default = "default_kafka_partitions")]
+    pub partitions: i32,
+    #[serde(default = "default_kafka_replication_factor")]
+    pub replication_factor: i32,
+}
+
+fn default_kafka_brokers() -> String {
+    "localhost:9092".into()
+}
+
+fn default_kafka_partitions() -> i32 {
+    12
+}
+
+fn default_kafka_replication_factor() -> i32 {
+    1
 }
 
 impl RabbitConfig {
     pub fn get_url(&self) -> String {
         let vhost = {
             if self.vhost.starts_with("/") {
-                let mut vhost = self.vhost.clone();
-                vhost.replace("/", "%2f");
-                vhost
+                self.vhost.replace("/", "%2f")
             } else {
                 self.vhost.clone()
             }
@@ -59,100 +68,38 @@ impl RabbitConfig {
             vhost,
         )
     }
-}
 
-pub struct Rabbit {
-    pub config: RabbitConfig,
-    pub pool: Pool,
 }
 
-impl Rabbit {
-    pub async fn new() -> Self {
-        let config: RabbitConfig = Figment::new()
-            .merge(Env::prefixed("RABBITMQ_"))
-            .extract()
-            .expect("cannot get rabbit config");
-
-        // TODO: add pool config to my rabbit confiig
-        let mut pool_config: Config = Config::default();
-
-        pool_config.url = Some(config.get_url());
-        let pool = pool_config.create_pool(Some(Runtime::Tokio1)).unwrap();
-
-        let rabbit = Rabbit {
-            pool: pool,
-            config: config,
-        };
-        rabbit.declare_all().await;
-        rabbit
-    }
-
-    pub async fn get_channel(&self) -> Channel {
-        let conn = self.pool.get().await.expect("conn err");
-        let channel = conn.create_channel().await.expect("channel err");
-        channel
-    }
-
-    pub async fn declare_all(&self) {
-        let channel = self.get_channel().await;
-        self.declare_exchange(channel.clone()).await;
-
-        for queue in self.config.queues.clone() {
-            self.declare_queue(channel.clone(), queue.clone()).await;
-            self.bind_queue(channel.clone(), queue).await;
-        }
-    }
-
-    pub async fn declare_exchange(&self, channel: Channel) {
-        channel.exchange_declare(
-            &self.config.exchange, 
-            ExchangeKind::Direct,
-            ExchangeDeclareOptions::default(), 
-            FieldTable::default()
-        ).await
-         .expect("cannot declare exchange");
-    }
-
-    pub async fn declare_queue(&self, channel: Channel, queue: String) {
-        channel.queue_declare(
-            &queue, 
-            QueueDeclareOptions::default(), 
-            FieldTable::default()
-        ).await
-        .expect("cannot declare queue");
-    }
-
-    pub async fn bind_queue(&self, channel: Channel, queue: String) {
-        channel.queue_bind(
-            &queue, 
-            &self.config.exchange, 
-            &queue, 
-            QueueBindOptions::default(), 
-            FieldTable::default()
-        ).await
-         .expect("cannot bind queue");
-    }
-
-    fn choose_queue(&self) -> &String {
-        if self.config.queues.len() > 1 {
-            self.config.queues.choose(&mut rand::thread_rng())
-                .expect("cannot get random queue")
-        } else {
-            &self.config.queues[0]
-        }
-    }
-
-    pub async fn publish(&self, payload: &[u8]) {
-        let channel = self.get_channel().await;
-        let queue = self.choose_queue();
-
-        let confirm = channel.basic_publish(
-            &self.config.exchange, 
-            queue, 
-            BasicPublishOptions::default(), 
-            payload, 
-            BasicProperties::default())
-            .await
-            .expect("cannot send message to the queue");
-    }
+/// Connects to the broker chosen by BROKER_BACKEND and declares the topology shared by all services.
+/// Queue names and retry settings are shared with the scheduler and read from the same variables.
+pub async fn connect() -> SharedBroker {
+    let rabbit: RabbitConfig = Figment::new()
+        .merge(Env::prefixed("RABBITMQ_"))
+        .extract()
+        .expect("cannot get rabbit config");
+    let kafka: KafkaConfig = Figment::new()
+        .merge(Env::prefixed("KAFKA_"))
+        .extract()
+        .expect("cannot get kafka config");
+    let backend = env::var("BROKER_BACKEND")
+        .map(|backend| BrokerBackend::from_str(&backend).expect("unknown broker backend"))
+        .unwrap_or_default();
+
+    let topology = Topology::from_env();
+    let settings = BrokerSettings {
+        backend,
+        rabbit: RabbitSettings {
+            url: rabbit.get_url(),
+            topology: topology.clone(),
+            prefetch_count: rabbit.prefetch_count,
+        },
+        kafka: KafkaSettings {
+            brokers: kafka.brokers,
+            topology,
+            partitions: kafka.partitions,
+            replication_factor: kafka.replication_factor,
+        },
+    };
+    common::broker::connect(settings).await.expect("cannot connect to broker")
 }
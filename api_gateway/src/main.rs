@@ -5,10 +5,9 @@ use std::env;
 
 use rocket_db_pools::{deadpool_redis, sqlx, Database};
 
-use broker::Rabbit;
-
 mod api;
 mod broker;
+mod scheduler;
 
 
 #[derive(Database)]
@@ -26,14 +25,17 @@ async fn main() -> Result<(), rocket::Error> {
         use dotenv::dotenv;
         dotenv().ok();
     }
+    common::models::set_event_producer("api_gateway");
 
-    let rabbit = Rabbit::new().await;
+    let broker = broker::connect().await;
+    let scheduler = scheduler::connect();
     
     let _ = rocket::build()
         .attach(Redis::init())
         .attach(Postgres::init())
         .mount("/", api::get_routes())
-        .manage(rabbit)
+        .manage(broker)
+        .manage(scheduler)
         .launch()
         .await?;
 
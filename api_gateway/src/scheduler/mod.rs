@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use rocket::figment::{Figment, providers::Env};
+use rocket::serde::Deserialize;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::{InterceptedService, Interceptor};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+
+pub use common::grpc::scheduler_client::SchedulerClient as GrpcSchedulerClient;
+
+/// gRPC client of the scheduler. Clones share one connection.
+pub type SchedulerClient = GrpcSchedulerClient<InterceptedService<Channel, BearerToken>>;
+
+/// Adds the token shared with the scheduler to every request.
+#[derive(Clone)]
+pub struct BearerToken(MetadataValue<Ascii>);
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request.metadata_mut().insert("authorization", self.0.clone());
+        Ok(request)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SchedulerConfig {
+    #[serde(default = "default_grpc_url")]
+    pub grpc_url: String,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// The scheduler's GRPC_TOKEN.
+    pub token: String,
+}
+
+fn default_grpc_url() -> String {
+    "http://localhost:50051".into()
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+/// Creates the scheduler client from SCHEDULER_* variables. The connection is established
+/// on the first request, so the gateway starts even if the scheduler is not up yet.
+pub fn connect() -> SchedulerClient {
+    let cfg: SchedulerConfig = Figment::new()
+        .merge(Env::prefixed("SCHEDULER_"))
+        .extract()
+        .expect("cannot get scheduler config");
+    let channel = Endpoint::from_shared(cfg.grpc_url)
+        .expect("invalid scheduler gRPC url")
+        .timeout(Duration::from_millis(cfg.timeout_ms))
+        .connect_lazy();
+    let token = format!("Bearer {}", cfg.token).parse().expect("invalid scheduler token");
+    GrpcSchedulerClient::with_interceptor(channel, BearerToken(token))
+}
@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos().expect("cannot compile protobuf definitions");
+}
+
+/// Generates the scheduler gRPC server and client, protoc is vendored so no system install is needed.
+#[cfg(feature = "grpc")]
+fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    println!("cargo:rerun-if-changed=proto");
+    tonic_build::compile_protos("proto/scheduler.proto")?;
+    Ok(())
+}
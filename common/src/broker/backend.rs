@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use strum_macros::{Display, EnumString};
+
+use crate::broker::{Broker, BrokerResult, KafkaBroker, KafkaSettings, MigratingBroker, RabbitBroker, RabbitSettings};
+
+/// Transport of the event bus, every service of a deployment must use the same one.
+#[derive(Debug, Display, EnumString, Clone, Copy, PartialEq, Eq, Default)]
+#[strum(serialize_all = "snake_case")]
+pub enum BrokerBackend {
+    #[default]
+    Rabbitmq,
+    Kafka,
+    /// Publishes to Kafka and consumes both brokers until RabbitMQ queues are drained.
+    RabbitmqToKafka,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokerSettings {
+    pub backend: BrokerBackend,
+    pub rabbit: RabbitSettings,
+    pub kafka: KafkaSettings,
+}
+
+/// Connects to the configured broker and declares the topology shared by all services.
+pub async fn connect(settings: BrokerSettings) -> BrokerResult<Arc<dyn Broker>> {
+    let broker: Arc<dyn Broker> = match settings.backend {
+        BrokerBackend::Rabbitmq => Arc::new(RabbitBroker::new(settings.rabbit).await?),
+        BrokerBackend::Kafka => Arc::new(KafkaBroker::new(settings.kafka)?),
+        BrokerBackend::RabbitmqToKafka => {
+            let source = Arc::new(RabbitBroker::new(settings.rabbit).await?);
+            let target = Arc::new(KafkaBroker::new(settings.kafka)?);
+            Arc::new(MigratingBroker::new(source, target))
+        },
+    };
+    broker.declare().await?;
+    Ok(broker)
+}
@@ -0,0 +1,603 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{BorrowedMessage, Header, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{Offset, TopicPartitionList};
+
+use crate::broker::{
+    Acker, Broker, BrokerError, BrokerResult, DeadLetter, Delivery, ParseraService, PublishOptions, Subscription,
+    Topology,
+};
+
+/// Number of deliveries of the message so far, kept across retry and dead letter topics.
+const ATTEMPTS_HEADER: &str = "x-attempts";
+/// Unix time in ms before which a delayed message must not be handled.
+const NOT_BEFORE_HEADER: &str = "x-not-before";
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(30);
+const RECEIVE_ERROR_DELAY: Duration = Duration::from_secs(1);
+/// Dead letter topics are read until no message comes within this time, even if their end isn't reached.
+const DEAD_LETTERS_IDLE: Duration = Duration::from_secs(5);
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct KafkaSettings {
+    /// Comma separated bootstrap servers.
+    pub brokers: String,
+    pub topology: Topology,
+    /// Partitions of created topics, bounds the number of consumers of a service working in parallel.
+    pub partitions: i32,
+    pub replication_factor: i32,
+}
+
+impl From<KafkaError> for BrokerError {
+    fn from(err: KafkaError) -> Self {
+        BrokerError::Connection(err.to_string())
+    }
+}
+
+/// What to do with dead letters read from a dead letter topic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeadLetterAction {
+    Inspect,
+    Replay,
+    Purge,
+}
+
+/// Kafka broker. Every service has a topic named after its queue, messages are keyed by crawler id
+/// so events of a crawler keep their order, and every service reads its topic in its own consumer group
+/// so replicas of a service share partitions.
+///
+/// Kafka has no per-message delays, so retried and delayed messages go to `<topic>.retry` and `<topic>.wait.<level>`
+/// topics with a not-before header, and a forwarder of the subscribed service moves them back once they are due.
+/// Priorities are not supported and ignored.
+pub struct KafkaBroker {
+    settings: KafkaSettings,
+    producer: FutureProducer,
+}
+
+impl KafkaBroker {
+    pub fn new(settings: KafkaSettings) -> BrokerResult<Self> {
+        let producer = client_config(&settings.brokers)
+            .set("enable.idempotence", "true")
+            .create()?;
+        Ok(KafkaBroker { settings, producer })
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.settings.topology
+    }
+
+    fn topic(&self, service: ParseraService) -> &str {
+        self.settings.topology.queue(service)
+    }
+
+    /// Topics messages wait in before they return to the service topic.
+    fn delay_topics(&self, topic: &str) -> Vec<String> {
+        let levels = 0..self.settings.topology.delay_levels;
+        let mut topics = vec![Topology::retry_queue(topic)];
+        topics.extend(levels.map(|level| Topology::wait_queue(topic, level)));
+        topics
+    }
+
+    fn consumer(&self, group: &str) -> BrokerResult<StreamConsumer> {
+        Ok(client_config(&self.settings.brokers)
+            .set("group.id", group)
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?)
+    }
+
+    /// Reads the dead letter topic from the offset the last replay or purge stopped at
+    /// up to its end when reading starts, so letters parked again while replaying wait for the next replay.
+    async fn dead_letters(&self, service: ParseraService, limit: usize, action: DeadLetterAction) -> BrokerResult<Vec<DeadLetter>> {
+        let topic = self.topic(service);
+        let dead_letter_topic = Topology::dead_letter_queue(topic);
+        let consumer: Arc<StreamConsumer> = Arc::new(
+            client_config(&self.settings.brokers)
+                .set("group.id", format!("{}.settled", dead_letter_topic))
+                .set("enable.auto.commit", "false")
+                .set("auto.offset.reset", "earliest")
+                .create()?,
+        );
+        let (start, mut ends) = {
+            let consumer = consumer.clone();
+            let dead_letter_topic = dead_letter_topic.clone();
+            tokio::task::spawn_blocking(move || dead_letter_range(&consumer, &dead_letter_topic))
+                .await
+                .map_err(|err| BrokerError::Connection(err.to_string()))??
+        };
+        // assigned rather than subscribed, so reading doesn't wait for a group rebalance
+        consumer.assign(&start)?;
+
+        let mut letters = Vec::new();
+        while letters.len() < limit && !ends.is_empty() {
+            let message = match tokio::time::timeout(DEAD_LETTERS_IDLE, consumer.recv()).await {
+                Ok(message) => message?,
+                Err(_) => break,
+            };
+            let Some(end) = ends.get(&message.partition()).copied() else {
+                continue;
+            };
+            if message.offset() >= end {
+                ends.remove(&message.partition());
+                continue;
+            }
+            if message.offset() + 1 >= end {
+                ends.remove(&message.partition());
+            }
+            let payload = message.payload().unwrap_or_default();
+            if action == DeadLetterAction::Replay {
+                let key = message.key().map(|key| String::from_utf8_lossy(key).into_owned());
+                produce(&self.producer, topic, key.as_deref(), payload, headers(0, None)).await?;
+            }
+            if action != DeadLetterAction::Inspect {
+                consumer.commit_message(&message, CommitMode::Sync)?;
+            }
+            letters.push(DeadLetter {
+                payload: String::from_utf8_lossy(payload).into_owned(),
+                attempts: attempts(&message),
+            });
+        }
+        Ok(letters)
+    }
+}
+
+#[async_trait]
+impl Broker for KafkaBroker {
+    async fn declare(&self) -> BrokerResult<()> {
+        let admin: AdminClient<DefaultClientContext> = client_config(&self.settings.brokers).create()?;
+        let mut names = Vec::new();
+        for service in ParseraService::ALL {
+            let topic = self.topic(service);
+            names.push(topic.to_string());
+            names.push(Topology::dead_letter_queue(topic));
+            names.extend(self.delay_topics(topic));
+        }
+        let topics: Vec<NewTopic> = names
+            .iter()
+            .map(|name| {
+                let replication = TopicReplication::Fixed(self.settings.replication_factor);
+                NewTopic::new(name, self.settings.partitions, replication)
+            })
+            .collect();
+        for result in admin.create_topics(&topics, &AdminOptions::new()).await? {
+            match result {
+                Ok(_) | Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {},
+                Err((name, code)) => {
+                    return Err(BrokerError::Connection(format!("cannot create topic {}: {}", name, code)));
+                },
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(&self, to: ParseraService, payload: &[u8], options: PublishOptions) -> BrokerResult<()> {
+        let topic = self.topic(to);
+        match options.delay_level {
+            Some(level) => {
+                let topology = &self.settings.topology;
+                let wait_topic = Topology::wait_queue(topic, topology.delay_level(level));
+                let not_before = now_ms() + u64::from(topology.wait_delay_ms(level));
+                produce(&self.producer, &wait_topic, options.key.as_deref(), payload, headers(0, Some(not_before))).await
+            },
+            None => produce(&self.producer, topic, options.key.as_deref(), payload, headers(0, None)).await,
+        }
+    }
+
+    async fn subscribe(&self, service: ParseraService) -> BrokerResult<Box<dyn Subscription>> {
+        let topic = self.topic(service).to_string();
+        for delay_topic in self.delay_topics(&topic) {
+            let consumer = self.consumer(&format!("{}.forwarder", delay_topic))?;
+            consumer.subscribe(&[&delay_topic])?;
+            tokio::spawn(forward_due(consumer, self.producer.clone(), delay_topic, topic.clone()));
+        }
+
+        let consumer = self.consumer(&service.to_string())?;
+        consumer.subscribe(&[&topic])?;
+        Ok(Box::new(KafkaSubscription {
+            consumer: Arc::new(consumer),
+            in_flight: InFlight::default(),
+            producer: self.producer.clone(),
+            retry_topic: Topology::retry_queue(&topic),
+            dead_letter_topic: Topology::dead_letter_queue(&topic),
+            retry_delay_ms: self.settings.topology.retry_delay_ms,
+            max_attempts: self.settings.topology.max_attempts,
+        }))
+    }
+
+    async fn close(&self) -> BrokerResult<()> {
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.flush(PUBLISH_TIMEOUT))
+            .await
+            .map_err(|err| BrokerError::Connection(err.to_string()))??;
+        Ok(())
+    }
+
+    async fn inspect_dead_letters(&self, service: ParseraService, limit: usize) -> BrokerResult<Vec<DeadLetter>> {
+        self.dead_letters(service, limit, DeadLetterAction::Inspect).await
+    }
+
+    async fn replay_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        Ok(self.dead_letters(service, usize::MAX, DeadLetterAction::Replay).await?.len())
+    }
+
+    async fn purge_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        Ok(self.dead_letters(service, usize::MAX, DeadLetterAction::Purge).await?.len())
+    }
+}
+
+/// Offsets to read the dead letter topic from, starting at what the group committed,
+/// and the end offsets of partitions with letters to read.
+fn dead_letter_range(consumer: &StreamConsumer, topic: &str) -> BrokerResult<(TopicPartitionList, HashMap<i32, i64>)> {
+    let metadata = consumer.fetch_metadata(Some(topic), METADATA_TIMEOUT)?;
+    let mut partitions = TopicPartitionList::new();
+    for partition in metadata.topics().iter().filter(|meta| meta.name() == topic).flat_map(|meta| meta.partitions()) {
+        partitions.add_partition(topic, partition.id());
+    }
+    let committed = consumer.committed_offsets(partitions, METADATA_TIMEOUT)?;
+
+    let mut start = TopicPartitionList::new();
+    let mut ends = HashMap::new();
+    for partition in committed.elements() {
+        let (low, high) = consumer.fetch_watermarks(topic, partition.partition(), METADATA_TIMEOUT)?;
+        let from = match partition.offset() {
+            Offset::Offset(offset) => offset.max(low),
+            _ => low,
+        };
+        start.add_partition_offset(topic, partition.partition(), Offset::Offset(from))?;
+        if from < high {
+            ends.insert(partition.partition(), high);
+        }
+    }
+    Ok((start, ends))
+}
+
+/// Moves messages of a delay topic back to the service topic once they are due.
+/// Messages of a delay topic share the delay, so waiting for the head of a partition never holds back due ones.
+async fn forward_due(consumer: StreamConsumer, producer: FutureProducer, from: String, to: String) {
+    tracing::info!("forwarding due messages from {} to {}", from, to);
+    loop {
+        let message = match consumer.recv().await {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!("cannot receive from {}: {}", from, err);
+                tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+                continue;
+            },
+        };
+        if let Some(not_before) = header_u64(&message, NOT_BEFORE_HEADER) {
+            let wait = not_before.saturating_sub(now_ms());
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+        }
+        let key = message.key().map(|key| String::from_utf8_lossy(key).into_owned());
+        let payload = message.payload().unwrap_or_default();
+        while let Err(err) = produce(&producer, &to, key.as_deref(), payload, headers(attempts(&message), None)).await {
+            tracing::error!("cannot forward a due message to {}: {}", to, err);
+            tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+        }
+        if let Err(err) = consumer.store_offset_from_message(&message) {
+            tracing::error!("cannot store offset of {}: {}", from, err);
+        }
+    }
+}
+
+/// Offsets of a partition handed out to handlers and not settled yet, and settled ones above them.
+/// Handlers settle messages out of order, while Kafka only keeps one committed offset per partition.
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    settled: BTreeSet<i64>,
+}
+
+impl PartitionOffsets {
+    fn deliver(&mut self, offset: i64) {
+        self.in_flight.insert(offset);
+    }
+
+    /// Settles the offset and returns the last offset every message up to is settled, if it moved.
+    fn settle(&mut self, offset: i64) -> Option<i64> {
+        self.in_flight.remove(&offset);
+        self.settled.insert(offset);
+        let committable = match self.in_flight.first() {
+            Some(oldest) => self.settled.range(..*oldest).next_back().copied(),
+            None => self.settled.last().copied(),
+        }?;
+        self.settled = self.settled.split_off(&(committable + 1));
+        Some(committable)
+    }
+}
+
+/// In-flight offsets of every partition a subscription reads.
+type InFlight = Arc<Mutex<HashMap<(String, i32), PartitionOffsets>>>;
+
+fn partition_offsets<T>(in_flight: &InFlight, topic: &str, partition: i32, update: impl FnOnce(&mut PartitionOffsets) -> T) -> T {
+    let mut partitions = in_flight.lock().expect("in-flight offsets lock is poisoned");
+    update(partitions.entry((topic.to_string(), partition)).or_default())
+}
+
+struct KafkaSubscription {
+    consumer: Arc<StreamConsumer>,
+    in_flight: InFlight,
+    producer: FutureProducer,
+    retry_topic: String,
+    dead_letter_topic: String,
+    retry_delay_ms: u32,
+    max_attempts: u32,
+}
+
+#[async_trait]
+impl Subscription for KafkaSubscription {
+    /// librdkafka reconnects by itself, receive errors are only logged.
+    async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            match self.consumer.recv().await {
+                Ok(message) => {
+                    let attempts = attempts(&message);
+                    partition_offsets(&self.in_flight, message.topic(), message.partition(), |offsets| {
+                        offsets.deliver(message.offset())
+                    });
+                    let acker = KafkaAcker {
+                        consumer: self.consumer.clone(),
+                        in_flight: self.in_flight.clone(),
+                        producer: self.producer.clone(),
+                        topic: message.topic().to_string(),
+                        partition: message.partition(),
+                        offset: message.offset(),
+                        key: message.key().map(|key| String::from_utf8_lossy(key).into_owned()),
+                        payload: message.payload().unwrap_or_default().to_vec(),
+                        attempts,
+                        retry_topic: self.retry_topic.clone(),
+                        dead_letter_topic: self.dead_letter_topic.clone(),
+                        retry_delay_ms: self.retry_delay_ms,
+                    };
+                    let payload = acker.payload.clone();
+                    return Some(Delivery::new(payload, attempts, self.max_attempts, Box::new(acker)));
+                },
+                Err(err) => {
+                    tracing::warn!("cannot receive a message: {}", err);
+                    tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+                },
+            }
+        }
+    }
+}
+
+/// Settles a message by storing the offset of its partition below the oldest unsettled message,
+/// which is committed in the background. A crash while concurrent handlers are busy redelivers
+/// messages settled above it, but never skips unsettled ones.
+struct KafkaAcker {
+    consumer: Arc<StreamConsumer>,
+    in_flight: InFlight,
+    producer: FutureProducer,
+    topic: String,
+    partition: i32,
+    offset: i64,
+    key: Option<String>,
+    payload: Vec<u8>,
+    attempts: u32,
+    retry_topic: String,
+    dead_letter_topic: String,
+    retry_delay_ms: u32,
+}
+
+impl KafkaAcker {
+    fn store(&self) -> BrokerResult<()> {
+        let committable = partition_offsets(&self.in_flight, &self.topic, self.partition, |offsets| {
+            offsets.settle(self.offset)
+        });
+        match committable {
+            Some(offset) => Ok(self.consumer.store_offset(&self.topic, self.partition, offset)?),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Acker for KafkaAcker {
+    async fn ack(&self) -> BrokerResult<()> {
+        self.store()
+    }
+
+    async fn retry(&self) -> BrokerResult<()> {
+        let not_before = now_ms() + u64::from(self.retry_delay_ms);
+        let headers = headers(self.attempts + 1, Some(not_before));
+        produce(&self.producer, &self.retry_topic, self.key.as_deref(), &self.payload, headers).await?;
+        self.store()
+    }
+
+    async fn requeue(&self) -> BrokerResult<()> {
+        let not_before = now_ms() + u64::from(self.retry_delay_ms);
+        let headers = headers(self.attempts, Some(not_before));
+        produce(&self.producer, &self.retry_topic, self.key.as_deref(), &self.payload, headers).await?;
+        self.store()
+    }
+
+    async fn park(&self) -> BrokerResult<()> {
+        let headers = headers(self.attempts + 1, None);
+        produce(&self.producer, &self.dead_letter_topic, self.key.as_deref(), &self.payload, headers).await?;
+        self.store()
+    }
+}
+
+fn client_config(brokers: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", brokers);
+    config
+}
+
+/// Produces a message and waits until the broker acknowledges it.
+async fn produce(producer: &FutureProducer, topic: &str, key: Option<&str>, payload: &[u8], headers: OwnedHeaders) -> BrokerResult<()> {
+    let mut record: FutureRecord<str, [u8]> = FutureRecord::to(topic).payload(payload).headers(headers);
+    if let Some(key) = key {
+        record = record.key(key);
+    }
+    producer
+        .send(record, PUBLISH_TIMEOUT)
+        .await
+        .map_err(|(err, _)| BrokerError::NotConfirmed(err.to_string()))?;
+    Ok(())
+}
+
+fn headers(attempts: u32, not_before: Option<u64>) -> OwnedHeaders {
+    let attempts = attempts.to_string();
+    let headers = OwnedHeaders::new().insert(Header { key: ATTEMPTS_HEADER, value: Some(attempts.as_str()) });
+    match not_before {
+        Some(not_before) => {
+            let not_before = not_before.to_string();
+            headers.insert(Header { key: NOT_BEFORE_HEADER, value: Some(not_before.as_str()) })
+        },
+        None => headers,
+    }
+}
+
+fn header_u64(message: &BorrowedMessage<'_>, key: &str) -> Option<u64> {
+    let value = message.headers()?.iter().find(|header| header.key == key)?.value?;
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+fn attempts(message: &BorrowedMessage<'_>) -> u32 {
+    header_u64(message, ATTEMPTS_HEADER)
+        .and_then(|attempts| u32::try_from(attempts).ok())
+        .unwrap_or_default()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn commits_below_the_oldest_unsettled_offset() {
+        let mut offsets = PartitionOffsets::default();
+        for offset in [3, 4, 5, 7] {
+            offsets.deliver(offset);
+        }
+
+        assert_eq!(offsets.settle(4), None);
+        assert_eq!(offsets.settle(7), None);
+        assert_eq!(offsets.settle(3), Some(4));
+        assert_eq!(offsets.settle(5), Some(7));
+        assert!(offsets.in_flight.is_empty());
+        assert!(offsets.settled.is_empty());
+    }
+
+    #[test]
+    fn commits_settled_offsets_in_order() {
+        let mut offsets = PartitionOffsets::default();
+        offsets.deliver(0);
+        assert_eq!(offsets.settle(0), Some(0));
+        offsets.deliver(1);
+        offsets.deliver(2);
+        assert_eq!(offsets.settle(1), Some(1));
+        assert_eq!(offsets.settle(2), Some(2));
+    }
+
+    /// Topics of a fresh topology, so tests don't see each other's messages.
+    fn settings() -> KafkaSettings {
+        let prefix = format!("test.{}", Uuid::now_v7().simple());
+        let queue = |name: &str| format!("{}.{}", prefix, name);
+        KafkaSettings {
+            brokers: std::env::var("KAFKA_BROKERS").unwrap_or("localhost:9092".into()),
+            topology: Topology {
+                scheduler_queue: queue("to_scheduler"),
+                scraper_queue: queue("scraper"),
+                heavy_artillery_queue: queue("heavy_artillery"),
+                extractor_queue: queue("extractor"),
+                notification_queue: queue("notification"),
+                db_manager_queue: queue("db_manager"),
+                status_manager_queue: queue("status_manager"),
+                retry_delay_ms: 100,
+                delay_levels: 1,
+                ..Topology::default()
+            },
+            partitions: 1,
+            replication_factor: 1,
+        }
+    }
+
+    async fn broker() -> KafkaBroker {
+        let broker = KafkaBroker::new(settings()).unwrap();
+        broker.declare().await.unwrap();
+        broker
+    }
+
+    async fn next(subscription: &mut Box<dyn Subscription>) -> Delivery {
+        tokio::time::timeout(Duration::from_secs(30), subscription.next())
+            .await
+            .expect("no message within 30s")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Kafka broker, start one with docker compose up redpanda"]
+    async fn redelivers_messages_unsettled_below_settled_ones() {
+        let broker = broker().await;
+        for payload in [b"1", b"2", b"3"] {
+            broker.publish(payload, ParseraService::Scraper).await.unwrap();
+        }
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        let first = next(&mut subscription).await;
+        next(&mut subscription).await.ack().await.unwrap();
+        next(&mut subscription).await.ack().await.unwrap();
+        // closing the consumer commits the stored offsets
+        drop(first);
+        drop(subscription);
+
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+
+        assert_eq!(next(&mut subscription).await.payload, b"1");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Kafka broker, start one with docker compose up redpanda"]
+    async fn reads_dead_letters_right_after_parking() {
+        let broker = broker().await;
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        for payload in [b"a", b"b"] {
+            broker.publish(payload, ParseraService::Scraper).await.unwrap();
+            next(&mut subscription).await.park().await.unwrap();
+        }
+
+        let letters = broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap();
+        assert_eq!(letters.iter().map(|letter| letter.payload.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(letters.iter().all(|letter| letter.attempts == 1));
+
+        assert_eq!(broker.replay_dead_letters(ParseraService::Scraper).await.unwrap(), 2);
+        assert_eq!(next(&mut subscription).await.payload, b"a");
+        assert_eq!(broker.replay_dead_letters(ParseraService::Scraper).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Kafka broker, start one with docker compose up redpanda"]
+    async fn replays_letters_parked_before_the_replay_only() {
+        let broker = broker().await;
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        broker.publish(b"poison", ParseraService::Scraper).await.unwrap();
+        next(&mut subscription).await.park().await.unwrap();
+
+        assert_eq!(broker.replay_dead_letters(ParseraService::Scraper).await.unwrap(), 1);
+        // parked again after the replay started, left for the next one
+        next(&mut subscription).await.park().await.unwrap();
+
+        assert_eq!(broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap().len(), 1);
+        assert_eq!(broker.purge_dead_letters(ParseraService::Scraper).await.unwrap(), 1);
+        assert!(broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::broker::{
+    message_priority, Acker, Broker, BrokerResult, DeadLetter, Delivery, ParseraService, PublishOptions, Subscription,
+    Topology,
+};
+use crate::models::EventProtocol;
+
+#[derive(Debug, Clone)]
+struct MemoryMessage {
+    payload: Vec<u8>,
+    priority: u8,
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct Queues {
+    ready: HashMap<ParseraService, VecDeque<MemoryMessage>>,
+    dead: HashMap<ParseraService, Vec<MemoryMessage>>,
+}
+
+struct MemoryInner {
+    topology: Topology,
+    queues: Mutex<Queues>,
+    published: Notify,
+}
+
+impl MemoryInner {
+    fn lock(&self) -> MutexGuard<'_, Queues> {
+        self.queues.lock().expect("memory broker lock is poisoned")
+    }
+
+    /// Queues the message behind messages of the same or higher priority.
+    fn push(&self, service: ParseraService, message: MemoryMessage) {
+        let mut queues = self.lock();
+        let queue = queues.ready.entry(service).or_default();
+        let position = queue.iter().position(|queued| queued.priority < message.priority).unwrap_or(queue.len());
+        queue.insert(position, message);
+        drop(queues);
+        self.published.notify_waiters();
+    }
+
+    fn pop(&self, service: ParseraService) -> Option<MemoryMessage> {
+        self.lock().ready.get_mut(&service)?.pop_front()
+    }
+}
+
+/// In-process broker for tests and local runs. Delayed messages are delivered right away
+/// and retried messages go back to the end of their queue.
+#[derive(Clone)]
+pub struct MemoryBroker {
+    inner: Arc<MemoryInner>,
+}
+
+impl Default for MemoryBroker {
+    fn default() -> Self {
+        MemoryBroker::new(Topology::default())
+    }
+}
+
+impl MemoryBroker {
+    pub fn new(topology: Topology) -> Self {
+        MemoryBroker {
+            inner: Arc::new(MemoryInner {
+                topology,
+                queues: Mutex::new(Queues::default()),
+                published: Notify::new(),
+            }),
+        }
+    }
+
+    /// Payloads waiting in the service queue, in delivery order.
+    pub fn queued(&self, service: ParseraService) -> Vec<Vec<u8>> {
+        self.inner
+            .lock()
+            .ready
+            .get(&service)
+            .map(|queue| queue.iter().map(|message| message.payload.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Events waiting in the service queue, payloads which aren't events are skipped.
+    pub fn queued_events(&self, service: ParseraService) -> Vec<EventProtocol> {
+        self.queued(service)
+            .iter()
+            .filter_map(|payload| EventProtocol::decode(payload).ok())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Broker for MemoryBroker {
+    async fn declare(&self) -> BrokerResult<()> {
+        Ok(())
+    }
+
+    async fn send(&self, to: ParseraService, payload: &[u8], options: PublishOptions) -> BrokerResult<()> {
+        let message = MemoryMessage {
+            payload: payload.to_vec(),
+            priority: options.priority.as_ref().map(message_priority).unwrap_or_default(),
+            attempts: 0,
+        };
+        self.inner.push(to, message);
+        Ok(())
+    }
+
+    async fn subscribe(&self, service: ParseraService) -> BrokerResult<Box<dyn Subscription>> {
+        Ok(Box::new(MemorySubscription { inner: self.inner.clone(), service }))
+    }
+
+    async fn inspect_dead_letters(&self, service: ParseraService, limit: usize) -> BrokerResult<Vec<DeadLetter>> {
+        let queues = self.inner.lock();
+        let letters = queues.dead.get(&service).map(Vec::as_slice).unwrap_or_default();
+        Ok(letters
+            .iter()
+            .take(limit)
+            .map(|message| DeadLetter {
+                payload: String::from_utf8_lossy(&message.payload).into_owned(),
+                attempts: message.attempts,
+            })
+            .collect())
+    }
+
+    async fn replay_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        let letters = self.inner.lock().dead.remove(&service).unwrap_or_default();
+        let replayed = letters.len();
+        for message in letters {
+            self.inner.push(service, MemoryMessage { attempts: 0, ..message });
+        }
+        Ok(replayed)
+    }
+
+    async fn purge_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        Ok(self.inner.lock().dead.remove(&service).map(|letters| letters.len()).unwrap_or_default())
+    }
+}
+
+struct MemorySubscription {
+    inner: Arc<MemoryInner>,
+    service: ParseraService,
+}
+
+#[async_trait]
+impl Subscription for MemorySubscription {
+    async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            // registered before checking the queue so a publish in between isn't missed
+            let published = self.inner.published.notified();
+            if let Some(message) = self.inner.pop(self.service) {
+                let acker = MemoryAcker {
+                    inner: self.inner.clone(),
+                    service: self.service,
+                    message: message.clone(),
+                };
+                let max_attempts = self.inner.topology.max_attempts;
+                return Some(Delivery::new(message.payload, message.attempts, max_attempts, Box::new(acker)));
+            }
+            published.await;
+        }
+    }
+}
+
+struct MemoryAcker {
+    inner: Arc<MemoryInner>,
+    service: ParseraService,
+    message: MemoryMessage,
+}
+
+#[async_trait]
+impl Acker for MemoryAcker {
+    async fn ack(&self) -> BrokerResult<()> {
+        Ok(())
+    }
+
+    async fn retry(&self) -> BrokerResult<()> {
+        let message = MemoryMessage { attempts: self.message.attempts + 1, ..self.message.clone() };
+        self.inner.push(self.service, message);
+        Ok(())
+    }
+
+    async fn requeue(&self) -> BrokerResult<()> {
+        self.inner.push(self.service, self.message.clone());
+        Ok(())
+    }
+
+    async fn park(&self) -> BrokerResult<()> {
+        let message = MemoryMessage { attempts: self.message.attempts + 1, ..self.message.clone() };
+        self.inner.lock().dead.entry(self.service).or_default().push(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Priority;
+
+    async fn next(subscription: &mut Box<dyn Subscription>) -> Delivery {
+        subscription.next().await.expect("memory subscriptions don't close")
+    }
+
+    #[tokio::test]
+    async fn delivers_higher_priorities_first_in_publish_order() {
+        let broker = MemoryBroker::default();
+        broker.publish_with_priority(b"low", ParseraService::Scraper, Some(&Priority::Low)).await.unwrap();
+        broker.publish_with_priority(b"top", ParseraService::Scraper, Some(&Priority::Top)).await.unwrap();
+        broker.publish_with_priority(b"high 1", ParseraService::Scraper, Some(&Priority::High)).await.unwrap();
+        broker.publish_with_priority(b"high 2", ParseraService::Scraper, Some(&Priority::High)).await.unwrap();
+        broker.publish(b"other", ParseraService::Extractor).await.unwrap();
+
+        let queued = broker.queued(ParseraService::Scraper);
+
+        let expected: Vec<&[u8]> = vec![b"top", b"high 1", b"high 2", b"low"];
+        assert_eq!(queued, expected);
+        assert_eq!(broker.queued(ParseraService::Extractor), vec![b"other".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn subscription_waits_for_messages() {
+        let broker = MemoryBroker::default();
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+
+        let (delivery, published) = tokio::join!(next(&mut subscription), async {
+            tokio::task::yield_now().await;
+            broker.publish(b"late", ParseraService::Scraper).await
+        });
+
+        published.unwrap();
+        assert_eq!(delivery.payload, b"late");
+        assert_eq!(delivery.attempts, 0);
+        delivery.ack().await.unwrap();
+        assert!(broker.queued(ParseraService::Scraper).is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_until_attempts_run_out_then_parks() {
+        let broker = MemoryBroker::new(Topology { max_attempts: 2, ..Topology::default() });
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        broker.publish(b"flaky", ParseraService::Scraper).await.unwrap();
+
+        next(&mut subscription).await.retry().await.unwrap();
+        let delivery = next(&mut subscription).await;
+        assert_eq!(delivery.attempts, 1);
+        delivery.retry().await.unwrap();
+
+        assert!(broker.queued(ParseraService::Scraper).is_empty());
+        let letters = broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].payload, "flaky");
+        assert_eq!(letters[0].attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn requeued_messages_keep_their_attempts() {
+        let broker = MemoryBroker::new(Topology { max_attempts: 2, ..Topology::default() });
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        broker.publish(b"busy", ParseraService::Scraper).await.unwrap();
+        next(&mut subscription).await.retry().await.unwrap();
+
+        for _ in 0..3 {
+            let delivery = next(&mut subscription).await;
+            assert_eq!(delivery.attempts, 1);
+            delivery.requeue().await.unwrap();
+        }
+
+        assert!(broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap().is_empty());
+        assert_eq!(broker.queued(ParseraService::Scraper), vec![b"busy".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn replays_dead_letters_with_fresh_attempts() {
+        let broker = MemoryBroker::default();
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        broker.publish(b"broken", ParseraService::Scraper).await.unwrap();
+        next(&mut subscription).await.park().await.unwrap();
+
+        assert_eq!(broker.replay_dead_letters(ParseraService::Scraper).await.unwrap(), 1);
+
+        let delivery = next(&mut subscription).await;
+        assert_eq!(delivery.payload, b"broken");
+        assert_eq!(delivery.attempts, 0);
+        assert!(broker.inspect_dead_letters(ParseraService::Scraper, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn purges_dead_letters() {
+        let broker = MemoryBroker::default();
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        for payload in [b"a", b"b"] {
+            broker.publish(payload, ParseraService::Scraper).await.unwrap();
+            next(&mut subscription).await.park().await.unwrap();
+        }
+
+        assert_eq!(broker.inspect_dead_letters(ParseraService::Scraper, 1).await.unwrap().len(), 1);
+        assert_eq!(broker.purge_dead_letters(ParseraService::Scraper).await.unwrap(), 2);
+        assert_eq!(broker.replay_dead_letters(ParseraService::Scraper).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::broker::{Broker, BrokerResult, DeadLetter, Delivery, ParseraService, PublishOptions, Subscription};
+
+/// Moves the event bus from one broker to another without stopping services.
+/// Messages are published to the target broker only, while subscriptions read both brokers
+/// until the source one is drained. Dead letters of both brokers are managed together.
+pub struct MigratingBroker {
+    source: Arc<dyn Broker>,
+    target: Arc<dyn Broker>,
+}
+
+impl MigratingBroker {
+    pub fn new(source: Arc<dyn Broker>, target: Arc<dyn Broker>) -> Self {
+        MigratingBroker { source, target }
+    }
+}
+
+#[async_trait]
+impl Broker for MigratingBroker {
+    async fn declare(&self) -> BrokerResult<()> {
+        self.source.declare().await?;
+        self.target.declare().await
+    }
+
+    async fn send(&self, to: ParseraService, payload: &[u8], options: PublishOptions) -> BrokerResult<()> {
+        self.target.send(to, payload, options).await
+    }
+
+    async fn subscribe(&self, service: ParseraService) -> BrokerResult<Box<dyn Subscription>> {
+        let (sender, receiver) = mpsc::channel(1);
+        for subscription in [self.source.subscribe(service).await?, self.target.subscribe(service).await?] {
+            tokio::spawn(pipe(subscription, sender.clone()));
+        }
+        Ok(Box::new(MergedSubscription { receiver }))
+    }
+
+    async fn close(&self) -> BrokerResult<()> {
+        self.source.close().await?;
+        self.target.close().await
+    }
+
+    async fn inspect_dead_letters(&self, service: ParseraService, limit: usize) -> BrokerResult<Vec<DeadLetter>> {
+        let mut letters = self.source.inspect_dead_letters(service, limit).await?;
+        let left = limit.saturating_sub(letters.len());
+        letters.extend(self.target.inspect_dead_letters(service, left).await?);
+        Ok(letters)
+    }
+
+    async fn replay_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        Ok(self.source.replay_dead_letters(service).await? + self.target.replay_dead_letters(service).await?)
+    }
+
+    async fn purge_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        Ok(self.source.purge_dead_letters(service).await? + self.target.purge_dead_letters(service).await?)
+    }
+}
+
+/// Forwards deliveries into the merged subscription. A delivery is only taken from a broker
+/// once the previous one is handed over, so unsettled deliveries don't pile up.
+async fn pipe(mut subscription: Box<dyn Subscription>, sender: mpsc::Sender<Delivery>) {
+    while let Some(delivery) = subscription.next().await {
+        if sender.send(delivery).await.is_err() {
+            return;
+        }
+    }
+}
+
+struct MergedSubscription {
+    receiver: mpsc::Receiver<Delivery>,
+}
+
+#[async_trait]
+impl Subscription for MergedSubscription {
+    async fn next(&mut self) -> Option<Delivery> {
+        self.receiver.recv().await
+    }
+}
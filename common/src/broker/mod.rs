@@ -0,0 +1,208 @@
+//! Event bus shared by every service. The `Broker` trait hides the transport,
+//! `RabbitBroker` talks to RabbitMQ, `KafkaBroker` to Kafka and `MemoryBroker` keeps messages in memory for tests.
+use std::fmt;
+
+use async_trait::async_trait;
+
+use crate::models::{EventDecodeError, EventProtocol, Priority};
+
+#[cfg(all(feature = "rabbitmq", feature = "kafka"))]
+mod backend;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod memory;
+mod migration;
+#[cfg(feature = "rabbitmq")]
+mod rabbit;
+mod topology;
+
+#[cfg(all(feature = "rabbitmq", feature = "kafka"))]
+pub use backend::*;
+#[cfg(feature = "kafka")]
+pub use kafka::*;
+pub use memory::*;
+pub use migration::*;
+#[cfg(feature = "rabbitmq")]
+pub use rabbit::*;
+pub use topology::*;
+
+pub type BrokerResult<T> = Result<T, BrokerError>;
+
+#[derive(Debug)]
+pub enum BrokerError {
+    /// Connection or channel to the broker is lost or cannot be established.
+    Connection(String),
+    /// The broker refused or didn't confirm a published message.
+    NotConfirmed(String),
+    Decode(EventDecodeError),
+    Encode(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerError::Connection(err) => write!(f, "broker connection error: {}", err),
+            BrokerError::NotConfirmed(err) => write!(f, "message is not confirmed: {}", err),
+            BrokerError::Decode(err) => write!(f, "{}", err),
+            BrokerError::Encode(err) => write!(f, "cannot encode message: {}", err),
+            BrokerError::Unsupported(what) => write!(f, "{} is not supported by the broker", what),
+        }
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+impl From<EventDecodeError> for BrokerError {
+    fn from(err: EventDecodeError) -> Self {
+        BrokerError::Decode(err)
+    }
+}
+
+/// Delivery settings of a published message.
+#[derive(Debug, Clone, Default)]
+pub struct PublishOptions {
+    pub priority: Option<Priority>,
+    /// Backoff level of a delayed delivery, the message waits `retry base delay * 2^level`.
+    pub delay_level: Option<u32>,
+    /// Messages with the same key keep their order on partitioned transports.
+    pub key: Option<String>,
+}
+
+/// A message parked after it couldn't be handled.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub payload: String,
+    pub attempts: u32,
+}
+
+/// Settles a delivery on the transport it came from.
+#[async_trait]
+pub trait Acker: Send + Sync {
+    async fn ack(&self) -> BrokerResult<()>;
+    /// Sends the message back to be delivered again after the retry delay.
+    async fn retry(&self) -> BrokerResult<()>;
+    /// Sends the message back like retry, without using up an attempt.
+    async fn requeue(&self) -> BrokerResult<()>;
+    /// Moves the message to the dead letter queue.
+    async fn park(&self) -> BrokerResult<()>;
+}
+
+/// A received message. It must be settled with exactly one of ack, retry or park.
+pub struct Delivery {
+    pub payload: Vec<u8>,
+    /// Number of times the message was already delivered and retried.
+    pub attempts: u32,
+    max_attempts: u32,
+    acker: Box<dyn Acker>,
+}
+
+impl Delivery {
+    pub fn new(payload: Vec<u8>, attempts: u32, max_attempts: u32, acker: Box<dyn Acker>) -> Self {
+        Delivery { payload, attempts, max_attempts, acker }
+    }
+
+    pub fn event(&self) -> BrokerResult<EventProtocol> {
+        Ok(EventProtocol::decode(&self.payload)?)
+    }
+
+    pub async fn ack(self) -> BrokerResult<()> {
+        self.acker.ack().await
+    }
+
+    /// Retries the message later or parks it once it ran out of attempts.
+    pub async fn retry(self) -> BrokerResult<()> {
+        let attempt = self.attempts + 1;
+        if attempt >= self.max_attempts {
+            tracing::error!("parking message after {} attempts", attempt);
+            return self.acker.park().await;
+        }
+        tracing::warn!("{}/{} attempt failed, retrying later", attempt, self.max_attempts);
+        self.acker.retry().await
+    }
+
+    /// Delivers the message again later as is, for messages which cannot be handled yet
+    /// rather than failed, e.g. while another delivery of them is being handled.
+    pub async fn requeue(self) -> BrokerResult<()> {
+        self.acker.requeue().await
+    }
+
+    pub async fn park(self) -> BrokerResult<()> {
+        self.acker.park().await
+    }
+}
+
+/// Stream of deliveries of a service queue. Lost connections are restored transparently.
+#[async_trait]
+pub trait Subscription: Send {
+    /// Waits for the next delivery, None once the subscription is closed for good.
+    async fn next(&mut self) -> Option<Delivery>;
+}
+
+#[async_trait]
+pub trait Broker: Send + Sync {
+    /// Declares exchanges, queues and their retry and dead letter queues. Safe to call by every service.
+    async fn declare(&self) -> BrokerResult<()>;
+
+    /// Publishes a message to the service queue and waits for the broker to confirm it.
+    async fn send(&self, to: ParseraService, payload: &[u8], options: PublishOptions) -> BrokerResult<()>;
+
+    async fn subscribe(&self, service: ParseraService) -> BrokerResult<Box<dyn Subscription>>;
+
+    /// Peeks at up to limit parked messages of the service without removing them.
+    async fn inspect_dead_letters(&self, _service: ParseraService, _limit: usize) -> BrokerResult<Vec<DeadLetter>> {
+        Err(BrokerError::Unsupported("inspecting dead letters"))
+    }
+
+    /// Moves parked messages of the service back to its queue with a fresh set of attempts.
+    async fn replay_dead_letters(&self, _service: ParseraService) -> BrokerResult<usize> {
+        Err(BrokerError::Unsupported("replaying dead letters"))
+    }
+
+    async fn purge_dead_letters(&self, _service: ParseraService) -> BrokerResult<usize> {
+        Err(BrokerError::Unsupported("purging dead letters"))
+    }
+
+    /// Waits for outstanding publisher confirms and closes connections to the broker.
+    /// Nothing can be published after that.
+    async fn close(&self) -> BrokerResult<()> {
+        Ok(())
+    }
+
+    async fn publish(&self, payload: &[u8], to: ParseraService) -> BrokerResult<()> {
+        self.send(to, payload, PublishOptions::default()).await
+    }
+
+    async fn publish_with_priority(&self, payload: &[u8], to: ParseraService, priority: Option<&Priority>) -> BrokerResult<()> {
+        let options = PublishOptions { priority: priority.cloned(), ..Default::default() };
+        self.send(to, payload, options).await
+    }
+
+    /// Publishes to the service queue after the delay of the backoff level.
+    async fn publish_delayed(&self, payload: &[u8], to: ParseraService, level: u32, priority: Option<&Priority>) -> BrokerResult<()> {
+        let options = PublishOptions { priority: priority.cloned(), delay_level: Some(level), ..Default::default() };
+        self.send(to, payload, options).await
+    }
+
+    /// Publishes an event keyed by its crawler with the priority of its page.
+    async fn publish_event(&self, event: &EventProtocol, to: ParseraService) -> BrokerResult<()> {
+        let payload = event.encode().map_err(|err| BrokerError::Encode(err.to_string()))?;
+        self.send(to, &payload, event_options(event)).await
+    }
+
+    /// Publishes an event to the service queue after the delay of the backoff level.
+    async fn publish_event_delayed(&self, event: &EventProtocol, to: ParseraService, level: u32) -> BrokerResult<()> {
+        let payload = event.encode().map_err(|err| BrokerError::Encode(err.to_string()))?;
+        let options = PublishOptions { delay_level: Some(level), ..event_options(event) };
+        self.send(to, &payload, options).await
+    }
+}
+
+/// Priority and key an event is published with.
+pub fn event_options(event: &EventProtocol) -> PublishOptions {
+    PublishOptions {
+        priority: event.data.priority().cloned(),
+        delay_level: None,
+        key: event.data.crawler_id().map(|id| id.to_string()),
+    }
+}
@@ -0,0 +1,471 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_lite::stream::StreamExt;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicGetOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+    BasicRejectOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+    QueuePurgeOptions,
+};
+use lapin::publisher_confirm::Confirmation;
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, Consumer, ExchangeKind};
+use tokio::sync::Mutex;
+
+use crate::broker::{
+    Acker, Broker, BrokerError, BrokerResult, DeadLetter, Delivery, ParseraService, PublishOptions, Subscription,
+    Topology, MAX_PRIORITY, message_priority,
+};
+
+/// Publish attempts before an error is returned, the channel is reopened between them.
+const PUBLISH_ATTEMPTS: u32 = 8;
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(200);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone)]
+pub struct RabbitSettings {
+    pub url: String,
+    pub topology: Topology,
+    /// Unacked deliveries per consumer, keeps priorities effective on the broker side.
+    pub prefetch_count: u16,
+}
+
+impl From<lapin::Error> for BrokerError {
+    fn from(err: lapin::Error) -> Self {
+        BrokerError::Connection(err.to_string())
+    }
+}
+
+/// RabbitMQ broker. Publishes go through one confirm channel which is reopened,
+/// with its connection, when it's lost. Every subscription has its own connection.
+pub struct RabbitBroker {
+    settings: RabbitSettings,
+    connection: Mutex<Option<Connection>>,
+    publisher: Mutex<Option<Channel>>,
+}
+
+impl RabbitBroker {
+    pub async fn new(settings: RabbitSettings) -> BrokerResult<Self> {
+        let broker = RabbitBroker {
+            settings,
+            connection: Mutex::new(None),
+            publisher: Mutex::new(None),
+        };
+        broker.channel().await?;
+        Ok(broker)
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.settings.topology
+    }
+
+    async fn channel(&self) -> BrokerResult<Channel> {
+        let mut connection = self.connection.lock().await;
+        let connected = connection.as_ref().is_some_and(|conn| conn.status().connected());
+        if !connected {
+            *connection = Some(connect(&self.settings.url).await?);
+        }
+        let conn = connection.as_ref().expect("connection is established above");
+        Ok(conn.create_channel().await?)
+    }
+
+    async fn publisher(&self) -> BrokerResult<Channel> {
+        let mut publisher = self.publisher.lock().await;
+        if let Some(channel) = publisher.as_ref().filter(|channel| channel.status().connected()) {
+            return Ok(channel.clone());
+        }
+        let channel = self.channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        *publisher = Some(channel.clone());
+        Ok(channel)
+    }
+
+    async fn publish_confirmed(&self, exchange: &str, routing_key: &str, payload: &[u8], properties: BasicProperties) -> BrokerResult<()> {
+        let channel = self.publisher().await?;
+        let confirm = channel
+            .basic_publish(exchange, routing_key, BasicPublishOptions::default(), payload, properties)
+            .await?
+            .await?;
+        match confirm {
+            Confirmation::Ack(_) | Confirmation::NotRequested => Ok(()),
+            Confirmation::Nack(_) => Err(BrokerError::NotConfirmed(format!("{} nacked the message", routing_key))),
+        }
+    }
+
+    /// Declares the queue with its retry wait queue and dead letter queue.
+    /// Rejected messages wait in the retry queue and come back through the default exchange.
+    async fn declare_queue(&self, channel: &Channel, queue: &str) -> BrokerResult<()> {
+        let topology = self.topology();
+        let retry_queue = Topology::retry_queue(queue);
+        declare_queue_with_args(channel, &Topology::dead_letter_queue(queue), FieldTable::default()).await?;
+
+        let mut retry_args = FieldTable::default();
+        retry_args.insert("x-message-ttl".into(), AMQPValue::LongUInt(topology.retry_delay_ms));
+        retry_args.insert("x-dead-letter-exchange".into(), AMQPValue::LongString("".into()));
+        retry_args.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue.into()));
+        declare_queue_with_args(channel, &retry_queue, retry_args).await?;
+
+        let mut args = FieldTable::default();
+        args.insert("x-dead-letter-exchange".into(), AMQPValue::LongString("".into()));
+        args.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(retry_queue.as_str().into()));
+        args.insert("x-max-priority".into(), AMQPValue::ShortShortUInt(MAX_PRIORITY));
+        declare_queue_with_args(channel, queue, args).await
+    }
+
+    /// Declares TTL'd wait queues of every backoff level which dead-letter back to the service queue.
+    async fn declare_wait_queues(&self, channel: &Channel, service: ParseraService) -> BrokerResult<()> {
+        let topology = self.topology();
+        let queue = topology.queue(service);
+        for level in 0..topology.delay_levels {
+            let mut args = FieldTable::default();
+            args.insert("x-message-ttl".into(), AMQPValue::LongUInt(topology.wait_delay_ms(level)));
+            args.insert("x-dead-letter-exchange".into(), AMQPValue::LongString(topology.exchange(service).into()));
+            args.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue.into()));
+            declare_queue_with_args(channel, &Topology::wait_queue(queue, level), args).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Broker for RabbitBroker {
+    async fn declare(&self) -> BrokerResult<()> {
+        let topology = self.topology();
+        let channel = self.channel().await?;
+        declare_exchange(&channel, &topology.scheduler_exchange, ExchangeKind::Fanout).await?;
+        declare_exchange(&channel, &topology.services_exchange, ExchangeKind::Topic).await?;
+        for service in ParseraService::ALL {
+            let queue = topology.queue(service);
+            tracing::debug!("declaring queue {}", queue);
+            self.declare_queue(&channel, queue).await?;
+            self.declare_wait_queues(&channel, service).await?;
+            channel
+                .queue_bind(queue, topology.exchange(service), queue, QueueBindOptions::default(), FieldTable::default())
+                .await?;
+        }
+        channel.close(200, "OK").await?;
+        Ok(())
+    }
+
+    async fn send(&self, to: ParseraService, payload: &[u8], options: PublishOptions) -> BrokerResult<()> {
+        let topology = self.topology();
+        let queue = topology.queue(to);
+        let (exchange, routing_key) = match options.delay_level {
+            Some(level) => ("", Topology::wait_queue(queue, topology.delay_level(level))),
+            None => (topology.exchange(to), queue.to_string()),
+        };
+        let properties = match &options.priority {
+            Some(priority) => BasicProperties::default().with_priority(message_priority(priority)),
+            None => BasicProperties::default(),
+        };
+        let mut delay = RECONNECT_MIN_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.publish_confirmed(exchange, &routing_key, payload, properties.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(err @ BrokerError::NotConfirmed(_)) => return Err(err),
+                Err(err) if attempt >= PUBLISH_ATTEMPTS => return Err(err),
+                Err(err) => {
+                    tracing::warn!("{}/{} cannot publish to {}: {}", attempt, PUBLISH_ATTEMPTS, routing_key, err);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                    attempt += 1;
+                },
+            }
+        }
+    }
+
+    async fn subscribe(&self, service: ParseraService) -> BrokerResult<Box<dyn Subscription>> {
+        let mut subscription = RabbitSubscription {
+            url: self.settings.url.clone(),
+            queue: self.topology().queue(service).to_string(),
+            prefetch_count: self.settings.prefetch_count,
+            max_attempts: self.topology().max_attempts,
+            consumer: None,
+        };
+        subscription.consumer = Some(subscription.consume().await?);
+        tracing::info!("consuming from queue {}", subscription.queue);
+        Ok(Box::new(subscription))
+    }
+
+    async fn close(&self) -> BrokerResult<()> {
+        if let Some(publisher) = self.publisher.lock().await.take() {
+            if publisher.status().connected() {
+                publisher.wait_for_confirms().await?;
+                publisher.close(200, "OK").await?;
+            }
+        }
+        if let Some(connection) = self.connection.lock().await.take() {
+            if connection.status().connected() {
+                connection.close(200, "OK").await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn inspect_dead_letters(&self, service: ParseraService, limit: usize) -> BrokerResult<Vec<DeadLetter>> {
+        let queue = self.topology().queue(service);
+        let dlq = Topology::dead_letter_queue(queue);
+        let channel = self.channel().await?;
+        let mut letters = vec![];
+        let mut tags = vec![];
+        while letters.len() < limit {
+            let message = match channel.basic_get(&dlq, BasicGetOptions::default()).await? {
+                Some(message) => message,
+                None => break,
+            };
+            letters.push(DeadLetter {
+                payload: String::from_utf8_lossy(&message.delivery.data).into_owned(),
+                attempts: death_count(&message.delivery.properties, queue),
+            });
+            tags.push(message.delivery.delivery_tag);
+        }
+        for tag in tags {
+            channel
+                .basic_nack(tag, BasicNackOptions { multiple: false, requeue: true })
+                .await?;
+        }
+        channel.close(200, "OK").await?;
+        Ok(letters)
+    }
+
+    async fn replay_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        let queue = self.topology().queue(service);
+        let dlq = Topology::dead_letter_queue(queue);
+        let channel = self.channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        // only messages parked before the replay started, those failing again wait for the next one
+        let parked = channel
+            .queue_declare(&dlq, QueueDeclareOptions { passive: true, ..Default::default() }, FieldTable::default())
+            .await?
+            .message_count() as usize;
+        let mut replayed = 0;
+        while replayed < parked {
+            let message = match channel.basic_get(&dlq, BasicGetOptions::default()).await? {
+                Some(message) => message,
+                None => break,
+            };
+            // x-death headers are dropped so the message gets a fresh set of attempts
+            let properties = message.delivery.properties.clone().with_headers(Default::default());
+            channel
+                .basic_publish("", queue, BasicPublishOptions::default(), &message.delivery.data, properties)
+                .await?
+                .await?;
+            channel
+                .basic_ack(message.delivery.delivery_tag, BasicAckOptions::default())
+                .await?;
+            replayed += 1;
+        }
+        channel.close(200, "OK").await?;
+        tracing::info!("replayed {} messages from {}", replayed, dlq);
+        Ok(replayed)
+    }
+
+    async fn purge_dead_letters(&self, service: ParseraService) -> BrokerResult<usize> {
+        let dlq = Topology::dead_letter_queue(self.topology().queue(service));
+        let channel = self.channel().await?;
+        let purged = channel.queue_purge(&dlq, QueuePurgeOptions::default()).await?;
+        channel.close(200, "OK").await?;
+        tracing::info!("purged {} messages from {}", purged, dlq);
+        Ok(purged as usize)
+    }
+}
+
+struct RabbitConsumer {
+    // the connection is kept open while its channel consumes
+    _connection: Connection,
+    channel: Channel,
+    /// Confirm channel deliveries are moved to the retry or dead letter queue through.
+    publisher: Channel,
+    consumer: Consumer,
+}
+
+/// Consumer of a service queue with its own connection, restored when the connection is lost.
+/// Deliveries unacked on a lost channel are redelivered by RabbitMQ.
+struct RabbitSubscription {
+    url: String,
+    queue: String,
+    prefetch_count: u16,
+    max_attempts: u32,
+    consumer: Option<RabbitConsumer>,
+}
+
+impl RabbitSubscription {
+    async fn consume(&self) -> BrokerResult<RabbitConsumer> {
+        let connection = connect(&self.url).await?;
+        let channel = connection.create_channel().await?;
+        channel.basic_qos(self.prefetch_count, BasicQosOptions::default()).await?;
+        let consumer = channel
+            .basic_consume(&self.queue, "", BasicConsumeOptions::default(), FieldTable::default())
+            .await?;
+        let publisher = connection.create_channel().await?;
+        publisher.confirm_select(ConfirmSelectOptions::default()).await?;
+        Ok(RabbitConsumer { _connection: connection, channel, publisher, consumer })
+    }
+
+    async fn reconnect(&mut self) -> RabbitConsumer {
+        let mut delay = RECONNECT_MIN_DELAY;
+        loop {
+            match self.consume().await {
+                Ok(consumer) => {
+                    tracing::info!("resumed consuming from queue {}", self.queue);
+                    return consumer;
+                },
+                Err(err) => {
+                    tracing::error!("cannot resume consuming from {}, retrying in {:?}: {}", self.queue, delay, err);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                },
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Subscription for RabbitSubscription {
+    async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            let consumer = match self.consumer.take() {
+                Some(consumer) => consumer,
+                None => self.reconnect().await,
+            };
+            let mut consumer = consumer;
+            match consumer.consumer.next().await {
+                Some(Ok(delivery)) => {
+                    let attempts = death_count(&delivery.properties, &self.queue);
+                    let acker = RabbitAcker {
+                        channel: consumer.channel.clone(),
+                        publisher: consumer.publisher.clone(),
+                        retry_queue: Topology::retry_queue(&self.queue),
+                        dead_letter_queue: Topology::dead_letter_queue(&self.queue),
+                        delivery_tag: delivery.delivery_tag,
+                        payload: delivery.data.clone(),
+                        properties: delivery.properties.clone(),
+                    };
+                    self.consumer = Some(consumer);
+                    return Some(Delivery::new(delivery.data, attempts, self.max_attempts, Box::new(acker)));
+                },
+                Some(Err(err)) => tracing::error!("consumer of {} failed: {}", self.queue, err),
+                None => tracing::warn!("consumer of {} is cancelled", self.queue),
+            }
+        }
+    }
+}
+
+struct RabbitAcker {
+    channel: Channel,
+    publisher: Channel,
+    retry_queue: String,
+    dead_letter_queue: String,
+    delivery_tag: u64,
+    payload: Vec<u8>,
+    properties: BasicProperties,
+}
+
+#[async_trait]
+impl Acker for RabbitAcker {
+    async fn ack(&self) -> BrokerResult<()> {
+        Ok(self.channel.basic_ack(self.delivery_tag, BasicAckOptions::default()).await?)
+    }
+
+    async fn retry(&self) -> BrokerResult<()> {
+        // rejected messages are dead-lettered to the retry queue
+        Ok(self
+            .channel
+            .basic_reject(self.delivery_tag, BasicRejectOptions { requeue: false })
+            .await?)
+    }
+
+    async fn requeue(&self) -> BrokerResult<()> {
+        // published to the retry queue as is, x-death of the service queue only counts rejects
+        self.move_to(&self.retry_queue).await
+    }
+
+    async fn park(&self) -> BrokerResult<()> {
+        tracing::error!("parking message in {}", self.dead_letter_queue);
+        self.move_to(&self.dead_letter_queue).await
+    }
+}
+
+impl RabbitAcker {
+    /// Acks the delivery once its copy is confirmed in the queue, a failed publish leaves it unacked for redelivery.
+    async fn move_to(&self, queue: &str) -> BrokerResult<()> {
+        let confirm = self
+            .publisher
+            .basic_publish("", queue, BasicPublishOptions::default(), &self.payload, self.properties.clone())
+            .await?
+            .await?;
+        if let Confirmation::Nack(_) = confirm {
+            return Err(BrokerError::NotConfirmed(format!("{} nacked the message", queue)));
+        }
+        self.ack().await
+    }
+}
+
+async fn connect(url: &str) -> BrokerResult<Connection> {
+    Ok(Connection::connect(url, ConnectionProperties::default()).await?)
+}
+
+async fn declare_exchange(channel: &Channel, exchange: &str, kind: ExchangeKind) -> BrokerResult<()> {
+    Ok(channel
+        .exchange_declare(exchange, kind, ExchangeDeclareOptions::default(), FieldTable::default())
+        .await?)
+}
+
+async fn declare_queue_with_args(channel: &Channel, queue: &str, args: FieldTable) -> BrokerResult<()> {
+    channel.queue_declare(queue, QueueDeclareOptions::default(), args).await?;
+    Ok(())
+}
+
+/// Number of times a message was dead-lettered from the queue according to x-death header.
+pub fn death_count(properties: &BasicProperties, queue: &str) -> u32 {
+    let deaths = match properties.headers().as_ref().and_then(|h| h.inner().get("x-death")) {
+        Some(AMQPValue::FieldArray(deaths)) => deaths,
+        _ => return 0,
+    };
+    deaths
+        .as_slice()
+        .iter()
+        .filter_map(|death| match death {
+            AMQPValue::FieldTable(death) => Some(death.inner()),
+            _ => None,
+        })
+        .filter(|death| matches!(death.get("queue"), Some(AMQPValue::LongString(q)) if q.as_bytes() == queue.as_bytes()))
+        .filter_map(|death| match death.get("count") {
+            Some(AMQPValue::LongLongInt(count)) => Some(*count as u32),
+            _ => None,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use lapin::types::{FieldArray, LongString};
+
+    use super::*;
+
+    fn death(queue: &str, count: i64) -> AMQPValue {
+        let mut death = FieldTable::default();
+        death.insert("queue".into(), AMQPValue::LongString(LongString::from(queue)));
+        death.insert("count".into(), AMQPValue::LongLongInt(count));
+        AMQPValue::FieldTable(death)
+    }
+
+    fn properties(deaths: Vec<AMQPValue>) -> BasicProperties {
+        let mut headers = FieldTable::default();
+        headers.insert("x-death".into(), AMQPValue::FieldArray(FieldArray::from(deaths)));
+        BasicProperties::default().with_headers(headers)
+    }
+
+    #[test]
+    fn counts_deaths_of_the_queue_only() {
+        let properties = properties(vec![death("scraper", 3), death("scraper.retry", 3), death("other", 7)]);
+        assert_eq!(death_count(&properties, "scraper"), 3);
+        assert_eq!(death_count(&properties, "missing"), 0);
+    }
+
+    #[test]
+    fn messages_without_deaths_have_no_attempts() {
+        assert_eq!(death_count(&BasicProperties::default(), "scraper"), 0);
+    }
+}
@@ -0,0 +1,187 @@
+use strum_macros::{Display, EnumString};
+
+use crate::models::Priority;
+
+/// Services connected to the event bus, each consumes its own queue.
+#[derive(Debug, Display, EnumString, Clone, Copy, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum ParseraService {
+    Scheduler,
+    Scraper,
+    HeavyArtillery,
+    Extractor,
+    Notification,
+    #[strum(to_string = "db_manager", serialize = "database_manager")]
+    DatabaseManager,
+    StatusManager,
+}
+
+impl ParseraService {
+    pub const ALL: [ParseraService; 7] = [
+        ParseraService::Scheduler,
+        ParseraService::Scraper,
+        ParseraService::HeavyArtillery,
+        ParseraService::Extractor,
+        ParseraService::Notification,
+        ParseraService::DatabaseManager,
+        ParseraService::StatusManager,
+    ];
+}
+
+/// Value of x-max-priority for service queues.
+pub const MAX_PRIORITY: u8 = 10;
+
+/// Maps a crawler priority to a message priority, up to `MAX_PRIORITY`.
+pub fn message_priority(priority: &Priority) -> u8 {
+    match priority {
+        Priority::Top => 9,
+        Priority::High => 6,
+        Priority::Common => 3,
+        Priority::Low => 0,
+    }
+}
+
+/// Names and retry settings of the queues every service declares.
+/// Services must agree on them, RabbitMQ refuses to redeclare a queue with other arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topology {
+    /// Every service sends events for the scheduler to this exchange.
+    pub scheduler_exchange: String,
+    /// The scheduler dispatches work to service queues through this exchange.
+    pub services_exchange: String,
+    pub scheduler_queue: String,
+    pub scraper_queue: String,
+    pub heavy_artillery_queue: String,
+    pub extractor_queue: String,
+    pub notification_queue: String,
+    pub db_manager_queue: String,
+    pub status_manager_queue: String,
+    /// Deliveries of a message before it's parked in the dead letter queue.
+    pub max_attempts: u32,
+    /// Time a rejected message waits in the retry queue.
+    pub retry_delay_ms: u32,
+    /// Delay of the first backoff level of delayed deliveries, doubled on every next level.
+    pub delay_base_ms: u32,
+    pub delay_levels: u32,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology {
+            scheduler_exchange: "to_scheduler".into(),
+            services_exchange: "from_scheduler".into(),
+            scheduler_queue: "to_scheduler".into(),
+            scraper_queue: "scraper".into(),
+            heavy_artillery_queue: "heavy_artillery".into(),
+            extractor_queue: "extractor".into(),
+            notification_queue: "notification".into(),
+            db_manager_queue: "db_manager".into(),
+            status_manager_queue: "status_manager".into(),
+            max_attempts: 5,
+            retry_delay_ms: 5000,
+            delay_base_ms: 10000,
+            delay_levels: 5,
+        }
+    }
+}
+
+impl Topology {
+    /// Reads the topology from the RABBITMQ_* variables the scheduler is configured with,
+    /// so services that don't own the topology declare it the same way.
+    pub fn from_env() -> Self {
+        let default = Topology::default();
+        let var = |name: &str, default: String| std::env::var(name).unwrap_or(default);
+        let num = |name: &str, default: u32| {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        };
+        Topology {
+            scheduler_exchange: var("RABBITMQ_CONSUME_EXCHANGE", default.scheduler_exchange),
+            services_exchange: var("RABBITMQ_PRODUCE_EXCHANGE", default.services_exchange),
+            scheduler_queue: var("RABBITMQ_CONSUME_QUEUE", default.scheduler_queue),
+            scraper_queue: var("RABBITMQ_SCRAPER_QUEUE", default.scraper_queue),
+            heavy_artillery_queue: var("RABBITMQ_HEAVY_ARTILLERY_QUEUE", default.heavy_artillery_queue),
+            extractor_queue: var("RABBITMQ_EXTRACTOR_QUEUE", default.extractor_queue),
+            notification_queue: var("RABBITMQ_NOTIFICATION_QUEUE", default.notification_queue),
+            db_manager_queue: var("RABBITMQ_DB_MANAGER_QUEUE", default.db_manager_queue),
+            status_manager_queue: var("RABBITMQ_STATUS_MANAGER_QUEUE", default.status_manager_queue),
+            max_attempts: num("RABBITMQ_MAX_ATTEMPTS", default.max_attempts),
+            retry_delay_ms: num("RABBITMQ_RETRY_DELAY_MS", default.retry_delay_ms),
+            delay_base_ms: num("SCRAPE_RETRY_BASE_DELAY_MS", default.delay_base_ms),
+            delay_levels: num("SCRAPE_RETRY_LEVELS", default.delay_levels),
+        }
+    }
+
+    pub fn queue(&self, service: ParseraService) -> &str {
+        match service {
+            ParseraService::Scheduler => &self.scheduler_queue,
+            ParseraService::Scraper => &self.scraper_queue,
+            ParseraService::HeavyArtillery => &self.heavy_artillery_queue,
+            ParseraService::Extractor => &self.extractor_queue,
+            ParseraService::Notification => &self.notification_queue,
+            ParseraService::DatabaseManager => &self.db_manager_queue,
+            ParseraService::StatusManager => &self.status_manager_queue,
+        }
+    }
+
+    /// Exchange messages for the service are published to.
+    pub fn exchange(&self, service: ParseraService) -> &str {
+        match service {
+            ParseraService::Scheduler => &self.scheduler_exchange,
+            _ => &self.services_exchange,
+        }
+    }
+
+    /// Parking queue for messages that exceeded max attempts or cannot be handled.
+    pub fn dead_letter_queue(queue: &str) -> String {
+        format!("{}.dlq", queue)
+    }
+
+    /// Wait queue that returns rejected messages back to the origin queue after a delay.
+    pub fn retry_queue(queue: &str) -> String {
+        format!("{}.retry", queue)
+    }
+
+    /// Delayed delivery queue of the level, messages wait base * 2^level ms there.
+    pub fn wait_queue(queue: &str, level: u32) -> String {
+        format!("{}.wait.{}", queue, level)
+    }
+
+    /// Clamps the level to the deepest declared one.
+    pub fn delay_level(&self, level: u32) -> u32 {
+        level.min(self.delay_levels.saturating_sub(1))
+    }
+
+    pub fn wait_delay_ms(&self, level: u32) -> u32 {
+        self.delay_base_ms.saturating_mul(2u32.saturating_pow(self.delay_level(level)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_priorities_in_order() {
+        let priorities = [Priority::Low, Priority::Common, Priority::High, Priority::Top];
+        let mapped: Vec<u8> = priorities.iter().map(message_priority).collect();
+        assert!(mapped.windows(2).all(|w| w[0] < w[1]));
+        assert!(mapped.iter().all(|p| *p <= MAX_PRIORITY));
+    }
+
+    #[test]
+    fn clamps_delay_levels() {
+        let topology = Topology { delay_base_ms: 1000, delay_levels: 3, ..Topology::default() };
+        assert_eq!(topology.wait_delay_ms(0), 1000);
+        assert_eq!(topology.wait_delay_ms(2), 4000);
+        assert_eq!(topology.wait_delay_ms(10), 4000);
+        assert_eq!(Topology::wait_queue("scraper", topology.delay_level(7)), "scraper.wait.2");
+    }
+
+    #[test]
+    fn parses_service_names() {
+        assert_eq!("db_manager".parse::<ParseraService>().unwrap(), ParseraService::DatabaseManager);
+        assert_eq!("database_manager".parse::<ParseraService>().unwrap(), ParseraService::DatabaseManager);
+        assert_eq!(ParseraService::HeavyArtillery.to_string(), "heavy_artillery");
+        assert!("nope".parse::<ParseraService>().is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::grpc;
+use crate::models::{
+    CrawlBudget, Crawler, NotificationLevel, NotificationOptions, NotifyEvery, NotifyVia, Priority, Site, SitemapSeed,
+    StartPages, StopCondition, UrlPatterns,
+};
+
+/// A message which doesn't make a valid model, e.g. with a malformed id or a missing required field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError(pub String);
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid message: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+fn parse_id(id: &str, what: &str) -> Result<Uuid, ConversionError> {
+    Uuid::parse_str(id).map_err(|err| ConversionError(format!("{} {}: {}", what, id, err)))
+}
+
+fn parse_time(secs: i64, what: &str) -> Result<DateTime<Utc>, ConversionError> {
+    DateTime::from_timestamp(secs, 0).ok_or_else(|| ConversionError(format!("{} {} is out of range", what, secs)))
+}
+
+impl From<Crawler> for grpc::Crawler {
+    fn from(crawler: Crawler) -> Self {
+        grpc::Crawler {
+            id: crawler.id.to_string(),
+            name: crawler.name,
+            user_id: crawler.user_id.to_string(),
+            timer_rule: crawler.timer_rule,
+            priority: grpc::Priority::from(crawler.priority).into(),
+            notification: Some(crawler.notification.into()),
+            created_at: crawler.created_at.timestamp(),
+            updated_at: crawler.updated_at.timestamp(),
+            site: Some(crawler.site.into()),
+            budget: Some(crawler.budget.into()),
+            stop_conditions: crawler.stop_conditions.into_iter().map(Into::into).collect(),
+            url_patterns: Some(grpc::UrlPatterns {
+                include: crawler.url_patterns.include,
+                exclude: crawler.url_patterns.exclude,
+            }),
+            meta: crawler.meta,
+        }
+    }
+}
+
+impl TryFrom<grpc::Crawler> for Crawler {
+    type Error = ConversionError;
+
+    fn try_from(crawler: grpc::Crawler) -> Result<Self, Self::Error> {
+        let priority = crawler.priority().try_into()?;
+        let notification = crawler
+            .notification
+            .ok_or_else(|| ConversionError("crawler without notification options".into()))?;
+        let site = crawler.site.ok_or_else(|| ConversionError("crawler without a site".into()))?;
+        let url_patterns = crawler.url_patterns.unwrap_or_default();
+        Ok(Crawler {
+            id: parse_id(&crawler.id, "crawler id")?,
+            name: crawler.name,
+            user_id: parse_id(&crawler.user_id, "user id")?,
+            timer_rule: crawler.timer_rule,
+            priority,
+            notification: notification.try_into()?,
+            created_at: parse_time(crawler.created_at, "created_at")?,
+            updated_at: parse_time(crawler.updated_at, "updated_at")?,
+            site: site.try_into()?,
+            budget: crawler.budget.map(Into::into).unwrap_or_default(),
+            stop_conditions: crawler
+                .stop_conditions
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            url_patterns: UrlPatterns {
+                include: url_patterns.include,
+                exclude: url_patterns.exclude,
+            },
+            meta: crawler.meta,
+        })
+    }
+}
+
+impl From<Priority> for grpc::Priority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Top => grpc::Priority::Top,
+            Priority::High => grpc::Priority::High,
+            Priority::Common => grpc::Priority::Common,
+            Priority::Low => grpc::Priority::Low,
+        }
+    }
+}
+
+impl TryFrom<grpc::Priority> for Priority {
+    type Error = ConversionError;
+
+    fn try_from(priority: grpc::Priority) -> Result<Self, Self::Error> {
+        match priority {
+            grpc::Priority::Top => Ok(Priority::Top),
+            grpc::Priority::High => Ok(Priority::High),
+            grpc::Priority::Common => Ok(Priority::Common),
+            grpc::Priority::Low => Ok(Priority::Low),
+            grpc::Priority::Unspecified => Err(ConversionError("priority is unspecified".into())),
+        }
+    }
+}
+
+impl From<NotificationOptions> for grpc::NotificationOptions {
+    fn from(options: NotificationOptions) -> Self {
+        let level = match options.level {
+            NotificationLevel::JobsDone => grpc::NotificationLevel::JobsDone,
+            NotificationLevel::JobsFailed => grpc::NotificationLevel::JobsFailed,
+            NotificationLevel::Statistics => grpc::NotificationLevel::Statistics,
+            NotificationLevel::DoNotDisturb => grpc::NotificationLevel::DoNotDisturb,
+        };
+        let every = match options.every {
+            Some(NotifyEvery::Day) => grpc::NotifyEvery::Day,
+            Some(NotifyEvery::Week) => grpc::NotifyEvery::Week,
+            Some(NotifyEvery::Month) => grpc::NotifyEvery::Month,
+            None => grpc::NotifyEvery::Unspecified,
+        };
+        let via = options
+            .via
+            .into_iter()
+            .map(|via| grpc::NotifyVia {
+                via: Some(match via {
+                    NotifyVia::Email(email) => grpc::notify_via::Via::Email(email),
+                    NotifyVia::Telegram(chat) => grpc::notify_via::Via::Telegram(chat),
+                    NotifyVia::Webhook { url, secret } => grpc::notify_via::Via::Webhook(grpc::Webhook { url, secret }),
+                }),
+            })
+            .collect();
+        grpc::NotificationOptions {
+            level: level.into(),
+            via,
+            every: every.into(),
+            send_at: options.send_at,
+            timezone: options.timezone,
+        }
+    }
+}
+
+impl TryFrom<grpc::NotificationOptions> for NotificationOptions {
+    type Error = ConversionError;
+
+    fn try_from(options: grpc::NotificationOptions) -> Result<Self, Self::Error> {
+        let level = match options.level() {
+            grpc::NotificationLevel::JobsDone => NotificationLevel::JobsDone,
+            grpc::NotificationLevel::JobsFailed => NotificationLevel::JobsFailed,
+            grpc::NotificationLevel::Statistics => NotificationLevel::Statistics,
+            grpc::NotificationLevel::DoNotDisturb => NotificationLevel::DoNotDisturb,
+            grpc::NotificationLevel::Unspecified => {
+                return Err(ConversionError("notification level is unspecified".into()))
+            },
+        };
+        let every = match options.every() {
+            grpc::NotifyEvery::Day => Some(NotifyEvery::Day),
+            grpc::NotifyEvery::Week => Some(NotifyEvery::Week),
+            grpc::NotifyEvery::Month => Some(NotifyEvery::Month),
+            grpc::NotifyEvery::Unspecified => None,
+        };
+        let via = options
+            .via
+            .into_iter()
+            .map(|via| match via.via {
+                Some(grpc::notify_via::Via::Email(email)) => Ok(NotifyVia::Email(email)),
+                Some(grpc::notify_via::Via::Telegram(chat)) => Ok(NotifyVia::Telegram(chat)),
+                Some(grpc::notify_via::Via::Webhook(webhook)) => Ok(NotifyVia::Webhook {
+                    url: webhook.url,
+                    secret: webhook.secret,
+                }),
+                None => Err(ConversionError("notification channel is unspecified".into())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(NotificationOptions {
+            level,
+            via,
+            every,
+            send_at: options.send_at,
+            timezone: options.timezone,
+        })
+    }
+}
+
+impl From<Site> for grpc::Site {
+    fn from(site: Site) -> Self {
+        let start_pages = site.start_pages;
+        grpc::Site {
+            id: site.id.to_string(),
+            domain: site.domain,
+            start_page: site.start_page,
+            page_xpaths: site.page_xpaths,
+            pagination_xpaths: site.pagination_xpaths,
+            start_pages: Some(grpc::StartPages {
+                urls: start_pages.urls,
+                csv: start_pages.csv,
+                template: start_pages.template,
+                variables: start_pages
+                    .variables
+                    .into_iter()
+                    .map(|(name, values)| (name, grpc::StringList { values }))
+                    .collect(),
+            }),
+            sitemap: site.sitemap.map(|sitemap| grpc::SitemapSeed {
+                url: sitemap.url,
+                changed_only: sitemap.changed_only,
+            }),
+            meta: site.meta,
+        }
+    }
+}
+
+impl TryFrom<grpc::Site> for Site {
+    type Error = ConversionError;
+
+    fn try_from(site: grpc::Site) -> Result<Self, Self::Error> {
+        let start_pages = site.start_pages.unwrap_or_default();
+        Ok(Site {
+            id: parse_id(&site.id, "site id")?,
+            domain: site.domain,
+            start_page: site.start_page,
+            page_xpaths: site.page_xpaths,
+            pagination_xpaths: site.pagination_xpaths,
+            start_pages: StartPages {
+                urls: start_pages.urls,
+                csv: start_pages.csv,
+                template: start_pages.template,
+                variables: start_pages
+                    .variables
+                    .into_iter()
+                    .map(|(name, list)| (name, list.values))
+                    .collect(),
+            },
+            sitemap: site.sitemap.map(|sitemap| SitemapSeed {
+                url: sitemap.url,
+                changed_only: sitemap.changed_only,
+            }),
+            meta: site.meta,
+        })
+    }
+}
+
+impl From<CrawlBudget> for grpc::CrawlBudget {
+    fn from(budget: CrawlBudget) -> Self {
+        grpc::CrawlBudget {
+            max_pages: budget.max_pages,
+            max_depth: budget.max_depth,
+            max_duration_secs: budget.max_duration_secs,
+            max_bytes: budget.max_bytes,
+        }
+    }
+}
+
+impl From<grpc::CrawlBudget> for CrawlBudget {
+    fn from(budget: grpc::CrawlBudget) -> Self {
+        CrawlBudget {
+            max_pages: budget.max_pages,
+            max_depth: budget.max_depth,
+            max_duration_secs: budget.max_duration_secs,
+            max_bytes: budget.max_bytes,
+        }
+    }
+}
+
+impl From<StopCondition> for grpc::StopCondition {
+    fn from(condition: StopCondition) -> Self {
+        let condition = match condition {
+            StopCondition::FieldEquals { field, value } => {
+                grpc::stop_condition::Condition::FieldEquals(grpc::FieldEquals { field, value })
+            },
+            StopCondition::SeenLastRun { field } => grpc::stop_condition::Condition::SeenLastRun(grpc::SeenLastRun { field }),
+        };
+        grpc::StopCondition { condition: Some(condition) }
+    }
+}
+
+impl TryFrom<grpc::StopCondition> for StopCondition {
+    type Error = ConversionError;
+
+    fn try_from(condition: grpc::StopCondition) -> Result<Self, Self::Error> {
+        match condition.condition {
+            Some(grpc::stop_condition::Condition::FieldEquals(condition)) => Ok(StopCondition::FieldEquals {
+                field: condition.field,
+                value: condition.value,
+            }),
+            Some(grpc::stop_condition::Condition::SeenLastRun(condition)) => {
+                Ok(StopCondition::SeenLastRun { field: condition.field })
+            },
+            None => Err(ConversionError("stop condition is unspecified".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn crawler() -> Crawler {
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        Crawler {
+            id: Uuid::now_v7(),
+            name: "shop".into(),
+            user_id: Uuid::now_v7(),
+            timer_rule: "every 1h".into(),
+            priority: Priority::High,
+            notification: NotificationOptions {
+                level: NotificationLevel::JobsFailed,
+                via: vec![
+                    NotifyVia::Email("user@parsera.site".into()),
+                    NotifyVia::Webhook { url: "https://hooks.site/parsera".into(), secret: "secret".into() },
+                ],
+                every: Some(NotifyEvery::Week),
+                send_at: Some("08:30".into()),
+                timezone: None,
+            },
+            created_at: now,
+            updated_at: now,
+            site: Site {
+                id: Uuid::now_v7(),
+                domain: "shop.site".into(),
+                start_page: "https://shop.site/".into(),
+                page_xpaths: HashMap::from([("title".into(), "//h1".into())]),
+                pagination_xpaths: HashMap::new(),
+                start_pages: StartPages {
+                    urls: vec!["https://shop.site/sale".into()],
+                    csv: None,
+                    template: Some("https://shop.site/search?q={q}".into()),
+                    variables: HashMap::from([("q".into(), vec!["tea".into(), "coffee".into()])]),
+                },
+                sitemap: Some(SitemapSeed { url: None, changed_only: true }),
+                meta: None,
+            },
+            budget: CrawlBudget { max_pages: Some(100), max_depth: Some(2), ..CrawlBudget::default() },
+            stop_conditions: vec![
+                StopCondition::FieldEquals { field: "title".into(), value: "Sold out".into() },
+                StopCondition::SeenLastRun { field: "title".into() },
+            ],
+            url_patterns: UrlPatterns { include: vec![], exclude: vec!["/cart".into()] },
+            meta: Some("{}".into()),
+        }
+    }
+
+    #[test]
+    fn crawlers_round_trip() {
+        let crawler = crawler();
+        let converted = Crawler::try_from(grpc::Crawler::from(crawler.clone())).unwrap();
+        assert_eq!(serde_json::to_value(&converted).unwrap(), serde_json::to_value(&crawler).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_ids() {
+        let mut message = grpc::Crawler::from(crawler());
+        message.user_id = "user".into();
+        assert!(Crawler::try_from(message).unwrap_err().0.starts_with("user id user"));
+    }
+
+    #[test]
+    fn rejects_missing_required_fields() {
+        let mut message = grpc::Crawler::from(crawler());
+        message.site = None;
+        assert!(Crawler::try_from(message).is_err());
+
+        let mut message = grpc::Crawler::from(crawler());
+        message.priority = grpc::Priority::Unspecified.into();
+        assert!(Crawler::try_from(message).is_err());
+
+        let mut message = grpc::Crawler::from(crawler());
+        message.stop_conditions.push(grpc::StopCondition { condition: None });
+        assert!(Crawler::try_from(message).is_err());
+    }
+}
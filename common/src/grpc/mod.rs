@@ -0,0 +1,6 @@
+//! Protobuf contracts of the scheduler gRPC API with the generated server and client.
+tonic::include_proto!("parsera.scheduler");
+
+mod convert;
+
+pub use convert::*;
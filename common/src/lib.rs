@@ -1,3 +1,7 @@
 
+#[cfg(feature = "broker")]
+pub mod broker;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod models;
 pub mod tools;
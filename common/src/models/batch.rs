@@ -0,0 +1,46 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::models::{EventCause, EventCommandStatus, Page, Priority};
+
+/// Pages sent to a scraper in one message. Pages of a batch have the same priority and
+/// come from different domains, so a scraper never hits one site with a whole batch at once.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrapeBatch {
+    pub id: Uuid,
+    pub items: Vec<ScrapeBatchItem>,
+    /// Times the scheduler sent back items it failed to handle.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A page of a batch with the result of scraping it, Pending until the page is scraped.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrapeBatchItem {
+    /// Id of the scrape event of the page, the item is reported back as a follow-up of it.
+    pub event_id: Uuid,
+    pub correlation_id: Uuid,
+    pub status: EventCommandStatus,
+    pub page: Page,
+}
+
+impl ScrapeBatch {
+    pub fn new(items: Vec<ScrapeBatchItem>) -> Self {
+        ScrapeBatch { id: Uuid::now_v7(), items, attempts: 0 }
+    }
+
+    pub fn priority(&self) -> Option<&Priority> {
+        self.items.first().map(|item| &item.page.priority)
+    }
+}
+
+impl ScrapeBatchItem {
+    /// What events about the page produced from the item need to know.
+    pub fn cause(&self) -> EventCause {
+        EventCause {
+            id: self.event_id,
+            correlation_id: self.correlation_id,
+            run_id: self.page.run_id,
+        }
+    }
+}
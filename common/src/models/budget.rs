@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::models::run::StopReason;
+
+/// Limits of every run of a crawler, unset limits are not checked.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CrawlBudget {
+    /// Pages let through to scrapers, sitemaps and pages turned away by the frontier are not counted.
+    pub max_pages: Option<u64>,
+    /// Start pages are at depth 0, every followed link goes one level deeper.
+    /// Deeper pages are skipped, the run goes on with the rest.
+    pub max_depth: Option<u32>,
+    pub max_duration_secs: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl CrawlBudget {
+    pub fn is_unlimited(&self) -> bool {
+        *self == CrawlBudget::default()
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration_secs.map(Duration::from_secs)
+    }
+
+    pub fn allows_depth(&self, depth: u32) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// The limit the run went over, the page being admitted included.
+    pub fn exceeded(&self, usage: &RunUsage, now: DateTime<Utc>) -> Option<StopReason> {
+        if self.max_pages.is_some_and(|max_pages| usage.pages > max_pages) {
+            return Some(StopReason::MaxPages);
+        }
+        if self.max_bytes.is_some_and(|max_bytes| usage.bytes >= max_bytes) {
+            return Some(StopReason::MaxBytes);
+        }
+        let elapsed = (now - usage.started_at).to_std().unwrap_or_default();
+        if self.max_duration().is_some_and(|max_duration| elapsed >= max_duration) {
+            return Some(StopReason::MaxDuration);
+        }
+        None
+    }
+}
+
+/// What a run used up so far, checked against the budget of its crawler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunUsage {
+    /// Pages admitted to the run.
+    pub pages: u64,
+    pub bytes: u64,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Ends a run once an extracted page matches it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum StopCondition {
+    /// The field is extracted with the value.
+    #[serde(alias = "field_equals")]
+    FieldEquals { field: String, value: String },
+    /// The field is extracted with a value the previous run extracted too,
+    /// e.g. the newest article of the last run is reached.
+    #[serde(alias = "seen_last_run")]
+    SeenLastRun { field: String },
+}
+
+impl StopCondition {
+    pub fn field(&self) -> &str {
+        match self {
+            StopCondition::FieldEquals { field, .. } | StopCondition::SeenLastRun { field } => field,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn usage(pages: u64, bytes: u64) -> RunUsage {
+        RunUsage { pages, bytes, started_at: Utc::now() }
+    }
+
+    #[test]
+    fn unlimited_budgets_are_never_exceeded() {
+        let budget = CrawlBudget::default();
+        assert!(budget.is_unlimited());
+        assert!(budget.allows_depth(u32::MAX));
+        assert_eq!(budget.exceeded(&usage(u64::MAX, u64::MAX), Utc::now()), None);
+    }
+
+    #[test]
+    fn max_pages_counts_the_page_being_admitted() {
+        let budget = CrawlBudget { max_pages: Some(2), ..CrawlBudget::default() };
+        assert_eq!(budget.exceeded(&usage(2, 0), Utc::now()), None);
+        assert_eq!(budget.exceeded(&usage(3, 0), Utc::now()), Some(StopReason::MaxPages));
+    }
+
+    #[test]
+    fn bytes_and_duration_stop_runs() {
+        let budget = CrawlBudget { max_bytes: Some(100), max_duration_secs: Some(60), ..CrawlBudget::default() };
+        assert_eq!(budget.exceeded(&usage(1, 100), Utc::now()), Some(StopReason::MaxBytes));
+        let started = RunUsage { started_at: Utc::now() - TimeDelta::seconds(61), ..usage(1, 0) };
+        assert_eq!(budget.exceeded(&started, Utc::now()), Some(StopReason::MaxDuration));
+    }
+
+    #[test]
+    fn deeper_pages_are_not_allowed() {
+        let budget = CrawlBudget { max_depth: Some(1), ..CrawlBudget::default() };
+        assert!(budget.allows_depth(1));
+        assert!(!budget.allows_depth(2));
+    }
+}
@@ -7,9 +7,12 @@ use serde::{Serialize, Deserialize};
 use strum_macros::Display;
 use uuid::Uuid;
 
+use crate::models::budget::{CrawlBudget, StopCondition};
 use crate::models::notification::NotificationOptions;
+use crate::models::failure::ScrapeFailure;
+use crate::models::sitemap::SitemapSeed;
 
-#[derive(Debug, Display, Serialize, Deserialize, Clone)]
+#[derive(Debug, Display, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub enum Priority {
     Top,
     High,
@@ -29,9 +32,25 @@ pub struct Crawler {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub site: Site,
+    #[serde(default)]
+    pub budget: CrawlBudget,
+    #[serde(default)]
+    pub stop_conditions: Vec<StopCondition>,
+    #[serde(default)]
+    pub url_patterns: UrlPatterns,
     pub meta: Option<String>,
 }
 
+/// Regex patterns matched against canonical urls before pages are dispatched.
+/// A url is crawled when it matches no exclude pattern and, if there are include patterns, any of them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UrlPatterns {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
 // TODO: refactor
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Site {
@@ -40,25 +59,81 @@ pub struct Site {
     pub start_page: String,
     pub page_xpaths: HashMap<String, String>,
     pub pagination_xpaths: HashMap<String, String>,
+    #[serde(default)]
+    pub start_pages: StartPages,
+    #[serde(default)]
+    pub sitemap: Option<SitemapSeed>,
     pub meta: Option<String>,
 }
 
+/// First pages of a run besides `start_page`, expanded when the run starts.
+/// An empty `start_page` leaves the run to these and the sitemap.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct StartPages {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// Uploaded CSV, urls are taken from its `url` column or the first one.
+    #[serde(default)]
+    pub csv: Option<String>,
+    /// Url with `{1..50}` ranges, `{a,b}` lists and `{name}` variables,
+    /// e.g. `https://shop.site/search?page={1..50}&q={keywords}`. Every combination is crawled.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Values of variables used in the template.
+    #[serde(default)]
+    pub variables: HashMap<String, Vec<String>>,
+}
+
 // TODO: refactor
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Page {
     pub id: Uuid,
     pub crawler_id: Uuid,
+    #[serde(default)]
+    pub user_id: Uuid,
+    #[serde(default)]
+    pub run_id: Option<Uuid>,
     pub site_id: Uuid,
     pub url: String,
     pub domain: String,
     pub is_pagination: bool,
+    /// Links followed from a start page to get here.
+    #[serde(default)]
+    pub depth: u32,
     pub times_reparsed: u32,
+    /// Failed fetches of the page so far, delayed retries and escalation are decided by it.
+    #[serde(default)]
+    pub fetch_attempts: u32,
     pub priority: Priority,
+    /// Fair share slot of the user the page holds while it's being scraped.
+    #[serde(default)]
+    pub slot: Option<Uuid>,
     pub notification: NotificationOptions,
     pub xpaths: HashMap<String, String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub html: Option<String>,
     pub data: Option<HashMap<String, String>>,
+    /// Xpaths of the page the extractor couldn't parse, with the parse errors.
+    #[serde(default)]
+    pub invalid_xpaths: HashMap<String, String>,
+    /// Xpaths of links to follow, e.g. to the next page of a listing.
+    #[serde(default)]
+    pub pagination_xpaths: HashMap<String, String>,
+    /// Links the extractor found by the pagination xpaths, followed one level deeper.
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub fingerprint: Option<ContentFingerprint>,
+    #[serde(default)]
+    pub failure: Option<ScrapeFailure>,
     pub meta: Option<String>,
 }
+
+/// Normalized content hash and HTTP cache validators of the last fetched page body.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ContentFingerprint {
+    pub hash: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum FieldChange {
+    #[serde(alias = "added")]
+    Added { field: String, value: String },
+    #[serde(alias = "removed")]
+    Removed { field: String, value: String },
+    #[serde(alias = "changed")]
+    Changed { field: String, old: String, new: String },
+}
+
+impl FieldChange {
+    pub fn field(&self) -> &str {
+        match self {
+            FieldChange::Added { field, .. } => field,
+            FieldChange::Removed { field, .. } => field,
+            FieldChange::Changed { field, .. } => field,
+        }
+    }
+}
+
+/// Field-level difference between two snapshots of the same page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PageDiff {
+    pub id: Uuid,
+    pub page_id: Uuid,
+    pub crawler_id: Uuid,
+    #[serde(default)]
+    pub run_id: Option<Uuid>,
+    pub url: String,
+    pub previous_snapshot_id: Option<Uuid>,
+    pub snapshot_id: Uuid,
+    pub changes: Vec<FieldChange>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
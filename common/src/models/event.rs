@@ -1,8 +1,12 @@
 #![allow(unused)]
+use std::fmt;
 use std::string::ToString;
+use std::sync::OnceLock;
 
+use chrono::{DateTime, Utc};
 use strum_macros::Display;
 use serde::{Serialize, Deserialize};
+use uuid::Uuid;
 
 use super::*;
 
@@ -13,12 +17,26 @@ pub enum EventCommand {
     RegisterCrawler(EventCommandStatus),
     #[serde(alias = "scrape_page")]
     ScrapePage(EventCommandStatus),
+    #[serde(alias = "scrape_batch")]
+    ScrapeBatch(EventCommandStatus),
+    #[serde(alias = "fetch_sitemap")]
+    FetchSitemap(EventCommandStatus),
     #[serde(alias = "extract_page")]
     ExtractPage(EventCommandStatus),
     #[serde(alias = "store_page")]
     StorePage(EventCommandStatus),
+    #[serde(alias = "page_changed")]
+    PageChanged(EventCommandStatus),
     #[serde(alias = "notify_user")]
     NotifyUser(EventCommandStatus),
+    #[serde(alias = "pause_crawler")]
+    PauseCrawler(EventCommandStatus),
+    #[serde(alias = "resume_crawler")]
+    ResumeCrawler(EventCommandStatus),
+    #[serde(alias = "cancel_run")]
+    CancelRun(EventCommandStatus),
+    #[serde(alias = "suggest_selectors")]
+    SuggestSelectors(EventCommandStatus),
     #[serde(alias = "sleep")]
     Sleep(EventCommandStatus),   // TODO: think about it
 }
@@ -31,6 +49,11 @@ pub enum EventCommandStatus {
     Done,
     #[serde(alias = "failed")]
     Failed,
+    #[serde(alias = "unchanged")]
+    Unchanged,
+    /// Dropped without handling, e.g. the page's run was cancelled while it was queued.
+    #[serde(alias = "skipped")]
+    Skipped,
 }
 
 #[derive(Debug, Display, Serialize, Deserialize)]
@@ -39,10 +62,327 @@ pub enum EventProtocolData {
     External(Crawler),
     #[serde(alias = "internal")]
     Internal(Page),
+    #[serde(alias = "changes")]
+    Changes(PageDiff),
+    #[serde(alias = "quota")]
+    Quota(QuotaExceeded),
+    #[serde(alias = "control")]
+    Control(ControlTarget),
+    #[serde(alias = "batch")]
+    Batch(ScrapeBatch),
+    #[serde(alias = "sitemap")]
+    Sitemap(SitemapFetch),
+    #[serde(alias = "suggestions")]
+    Suggestions(SuggestSelectors),
 }
 
+/// Schema version of events produced by this build.
+pub const EVENT_SCHEMA_VERSION: u16 = 2;
+/// Oldest schema version consumers still accept. Version 1 events are a bare `{ command, data }`.
+pub const MIN_EVENT_SCHEMA_VERSION: u16 = 1;
+
+const UNKNOWN_PRODUCER: &str = "unknown";
+
+/// Namespace of ids derived from the payload of events which were sent without one.
+const LEGACY_EVENT_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_2a4e_93d7_4b8a_b5e2_0d3f_7c91_a846);
+
+static EVENT_PRODUCER: OnceLock<String> = OnceLock::new();
+
+/// Sets the service name stamped on every event this process creates. Called once on startup.
+pub fn set_event_producer(name: &str) {
+    if EVENT_PRODUCER.set(name.to_string()).is_err() {
+        tracing::warn!("event producer is already set to {}, keeping it instead of {}", event_producer(), name);
+    }
+}
+
+pub fn event_producer() -> &'static str {
+    EVENT_PRODUCER.get().map(String::as_str).unwrap_or(UNKNOWN_PRODUCER)
+}
+
+fn legacy_version() -> u16 {
+    MIN_EVENT_SCHEMA_VERSION
+}
+
+fn unknown_producer() -> String {
+    UNKNOWN_PRODUCER.to_string()
+}
+
+/// Versioned envelope of every message services exchange.
+/// Envelope fields missing in older versions are filled in on decoding.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EventProtocol {
+    #[serde(default = "Uuid::now_v7")]
+    pub id: Uuid,
+    #[serde(default = "legacy_version")]
+    pub version: u16,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "unknown_producer")]
+    pub producer: String,
+    /// Shared by every event of one page journey, so it can be traced across services.
+    #[serde(default)]
+    pub correlation_id: Option<Uuid>,
+    /// Id of the event this one was produced in response to.
+    #[serde(default)]
+    pub causation_id: Option<Uuid>,
     pub command: EventCommand,
     pub data: EventProtocolData,
+    #[serde(default)]
+    pub run_id: Option<Uuid>,
+}
+
+/// Ids an event passes on to the events produced in response to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventCause {
+    pub id: Uuid,
+    pub correlation_id: Uuid,
+    pub run_id: Option<Uuid>,
+}
+
+/// Only the version and id of an event, read before the rest so newer events are rejected with a clear error
+/// and events sent without an id are told apart from ones with it.
+#[derive(Deserialize)]
+struct EventVersion {
+    #[serde(default = "legacy_version")]
+    version: u16,
+    #[serde(default)]
+    id: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub enum EventDecodeError {
+    Malformed(serde_json::Error),
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for EventDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventDecodeError::Malformed(err) => write!(f, "malformed event: {}", err),
+            EventDecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "event schema version {} is not supported, expected {}..={}",
+                version, MIN_EVENT_SCHEMA_VERSION, EVENT_SCHEMA_VERSION,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EventDecodeError {}
+
+impl From<serde_json::Error> for EventDecodeError {
+    fn from(err: serde_json::Error) -> Self {
+        EventDecodeError::Malformed(err)
+    }
+}
+
+impl EventProtocolData {
+    /// Crawl run the data belongs to.
+    pub fn run_id(&self) -> Option<Uuid> {
+        match self {
+            EventProtocolData::Internal(page) => page.run_id,
+            EventProtocolData::Changes(diff) => diff.run_id,
+            EventProtocolData::Control(ControlTarget::Run(run_id)) => Some(*run_id),
+            EventProtocolData::Sitemap(sitemap) => sitemap.run_id,
+            EventProtocolData::External(_)
+            | EventProtocolData::Quota(_)
+            | EventProtocolData::Control(_)
+            | EventProtocolData::Batch(_)
+            | EventProtocolData::Suggestions(_) => None,
+        }
+    }
+
+    pub fn crawler_id(&self) -> Option<Uuid> {
+        match self {
+            EventProtocolData::External(crawler) => Some(crawler.id),
+            EventProtocolData::Internal(page) => Some(page.crawler_id),
+            EventProtocolData::Changes(diff) => Some(diff.crawler_id),
+            EventProtocolData::Control(ControlTarget::Crawler(crawler_id)) => Some(*crawler_id),
+            EventProtocolData::Sitemap(sitemap) => Some(sitemap.crawler_id),
+            EventProtocolData::Suggestions(request) => request.crawler_id,
+            EventProtocolData::Quota(_) | EventProtocolData::Control(_) | EventProtocolData::Batch(_) => None,
+        }
+    }
+
+    pub fn priority(&self) -> Option<&Priority> {
+        match self {
+            EventProtocolData::External(crawler) => Some(&crawler.priority),
+            EventProtocolData::Internal(page) => Some(&page.priority),
+            EventProtocolData::Batch(batch) => batch.priority(),
+            _ => None,
+        }
+    }
+
+    /// Id the events about the data are correlated by when there is no parent event.
+    pub fn correlation_id(&self) -> Option<Uuid> {
+        match self {
+            EventProtocolData::Internal(page) => Some(page.id),
+            EventProtocolData::Changes(diff) => Some(diff.page_id),
+            EventProtocolData::External(crawler) => Some(crawler.id),
+            EventProtocolData::Batch(batch) => Some(batch.id),
+            EventProtocolData::Sitemap(sitemap) => Some(sitemap.id),
+            EventProtocolData::Suggestions(request) => Some(request.id),
+            EventProtocolData::Control(_) | EventProtocolData::Quota(_) => None,
+        }
+    }
+}
+
+impl EventProtocol {
+    /// Creates an event tagged with the run id of its data.
+    pub fn new(command: EventCommand, data: EventProtocolData) -> Self {
+        let id = Uuid::now_v7();
+        let run_id = data.run_id();
+        let correlation_id = data.correlation_id().or(Some(id));
+        EventProtocol {
+            id,
+            version: EVENT_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            producer: event_producer().to_string(),
+            correlation_id,
+            causation_id: None,
+            command,
+            data,
+            run_id,
+        }
+    }
+
+    /// Creates an event in response to this one, keeping its correlation and run.
+    pub fn follow(&self, command: EventCommand, data: EventProtocolData) -> Self {
+        EventProtocol::caused_by(self.cause(), command, data)
+    }
+
+    /// Creates an event in response to the one the cause was taken from.
+    pub fn caused_by(cause: EventCause, command: EventCommand, data: EventProtocolData) -> Self {
+        let run_id = data.run_id().or(cause.run_id);
+        EventProtocol {
+            correlation_id: Some(cause.correlation_id),
+            causation_id: Some(cause.id),
+            run_id,
+            ..EventProtocol::new(command, data)
+        }
+    }
+
+    /// What a response to the event needs to know, kept when the event data is consumed.
+    pub fn cause(&self) -> EventCause {
+        EventCause {
+            id: self.id,
+            correlation_id: self.correlation_id.unwrap_or(self.id),
+            run_id: self.run_id,
+        }
+    }
+
+    /// Passes the event on to another service as a new hop of the same journey.
+    pub fn forward(self) -> Self {
+        let parent = self.id;
+        EventProtocol {
+            id: Uuid::now_v7(),
+            version: EVENT_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            producer: event_producer().to_string(),
+            correlation_id: self.correlation_id.or(Some(parent)),
+            causation_id: Some(parent),
+            ..self
+        }
+    }
+
+    /// Decodes an event of any supported version, filling in envelope fields older versions lack.
+    pub fn decode(msg: &[u8]) -> Result<Self, EventDecodeError> {
+        let EventVersion { version, id } = serde_json::from_slice(msg)?;
+        if !(MIN_EVENT_SCHEMA_VERSION..=EVENT_SCHEMA_VERSION).contains(&version) {
+            return Err(EventDecodeError::UnsupportedVersion(version));
+        }
+        let mut event: EventProtocol = serde_json::from_slice(msg)?;
+        if id.is_none() {
+            // the same for every delivery of the event, so redeliveries are still recognized by id
+            event.id = Uuid::new_v5(&LEGACY_EVENT_NAMESPACE, msg);
+        }
+        if event.run_id.is_none() {
+            event.run_id = event.data.run_id();
+        }
+        if event.correlation_id.is_none() {
+            event.correlation_id = event.data.correlation_id().or(Some(event.id));
+        }
+        Ok(event)
+    }
+
+    pub fn encode(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+
+    /// Short description of the event for logs.
+    pub fn trace(&self) -> String {
+        format!(
+            "{} event {} v{} from {} (correlation {})",
+            self.command,
+            self.id,
+            self.version,
+            self.producer,
+            self.correlation_id.map(|id| id.to_string()).unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUN_ID: &str = "0190a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b";
+
+    #[test]
+    fn decodes_v1_events_without_envelope() {
+        let msg = format!(r#"{{"command":{{"cancel_run":"pending"}},"data":{{"control":{{"run":"{}"}}}}}}"#, RUN_ID);
+
+        let event = EventProtocol::decode(msg.as_bytes()).unwrap();
+
+        let run_id = Uuid::parse_str(RUN_ID).unwrap();
+        assert_eq!(event.version, 1);
+        assert_eq!(event.producer, UNKNOWN_PRODUCER);
+        assert_eq!(event.run_id, Some(run_id));
+        assert_eq!(event.correlation_id, Some(event.id));
+        assert!(event.causation_id.is_none());
+        assert!(matches!(event.command, EventCommand::CancelRun(EventCommandStatus::Pending)));
+    }
+
+    #[test]
+    fn derives_the_same_id_for_every_delivery_of_events_without_one() {
+        let msg = format!(r#"{{"command":{{"cancel_run":"pending"}},"data":{{"control":{{"run":"{}"}}}}}}"#, RUN_ID);
+        let other = format!(r#"{{"command":{{"cancel_run":"done"}},"data":{{"control":{{"run":"{}"}}}}}}"#, RUN_ID);
+
+        let first = EventProtocol::decode(msg.as_bytes()).unwrap();
+        let redelivered = EventProtocol::decode(msg.as_bytes()).unwrap();
+
+        assert_eq!(first.id, redelivered.id);
+        assert_ne!(first.id, EventProtocol::decode(other.as_bytes()).unwrap().id);
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let msg = format!(r#"{{"version":99,"command":{{"CancelRun":"Pending"}},"data":{{"Control":{{"Run":"{}"}}}}}}"#, RUN_ID);
+
+        let err = EventProtocol::decode(msg.as_bytes()).unwrap_err();
+
+        assert!(matches!(err, EventDecodeError::UnsupportedVersion(99)));
+        assert!(matches!(EventProtocol::decode(br#"{"version":0}"#), Err(EventDecodeError::UnsupportedVersion(0))));
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        assert!(matches!(EventProtocol::decode(b"not json"), Err(EventDecodeError::Malformed(_))));
+        assert!(matches!(EventProtocol::decode(br#"{"version":2}"#), Err(EventDecodeError::Malformed(_))));
+    }
+
+    #[test]
+    fn round_trips_current_events() {
+        let run_id = Uuid::parse_str(RUN_ID).unwrap();
+        let event = EventProtocol::new(EventCommand::CancelRun(EventCommandStatus::Pending), EventProtocolData::Control(ControlTarget::Run(run_id)));
+        let reply = event.follow(EventCommand::CancelRun(EventCommandStatus::Done), EventProtocolData::Control(ControlTarget::Run(run_id)));
+
+        let decoded = EventProtocol::decode(&reply.encode().unwrap()).unwrap();
+
+        assert_eq!(decoded.id, reply.id);
+        assert_eq!(decoded.version, EVENT_SCHEMA_VERSION);
+        assert_eq!(decoded.causation_id, Some(event.id));
+        assert_eq!(decoded.correlation_id, event.correlation_id);
+        assert_eq!(decoded.run_id, Some(run_id));
+    }
 }
@@ -0,0 +1,29 @@
+#![allow(unused)]
+use std::string::ToString;
+
+use serde::{Serialize, Deserialize};
+use strum_macros::Display;
+
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureKind {
+    #[serde(alias = "network")]
+    Network,        // dns, connection refused/reset
+    #[serde(alias = "timeout")]
+    Timeout,
+    #[serde(alias = "client_error")]
+    ClientError,    // 4xx except blocking ones
+    #[serde(alias = "server_error")]
+    ServerError,    // 5xx
+    #[serde(alias = "blocked")]
+    Blocked,        // 403, 429, captcha pages
+    #[serde(alias = "unknown")]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrapeFailure {
+    pub kind: FailureKind,
+    pub status: Option<u16>,
+    pub message: String,
+}
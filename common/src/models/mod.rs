@@ -3,12 +3,28 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 mod notification;
+mod batch;
+mod budget;
 mod crawler;
 mod event;
+mod diff;
+mod failure;
+mod quota;
+mod run;
+mod sitemap;
+mod suggestion;
 
 pub use notification::*;
+pub use batch::*;
+pub use budget::*;
 pub use crawler::*;
 pub use event::*;
+pub use diff::*;
+pub use failure::*;
+pub use quota::*;
+pub use run::*;
+pub use sitemap::*;
+pub use suggestion::*;
 
 // TODO: remove this
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -2,7 +2,7 @@
 use std::string::ToString;
 
 use serde::{Serialize, Deserialize};
-use strum_macros::Display;
+use strum_macros::{Display, EnumString};
 
 
 #[derive(Debug, Display, Serialize, Deserialize, Clone)]
@@ -13,7 +13,7 @@ pub enum NotificationLevel {
     DoNotDisturb,
 }
 
-#[derive(Debug, Display, Serialize, Deserialize, Clone)]
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum NotifyEvery {
     Day,
     Week,
@@ -24,6 +24,8 @@ pub enum NotifyEvery {
 pub enum NotifyVia {
     Email(String),  // email
     Telegram(String),   //telegram user id ?
+    /// Extracted pages and run summaries are POSTed to the url, signed with HMAC-SHA256 of the secret.
+    Webhook { url: String, secret: String },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,4 +33,33 @@ pub struct NotificationOptions {
     pub level: NotificationLevel,
     pub via: Vec<NotifyVia>,
     pub every: Option<NotifyEvery>,
+    /// Local "HH:MM" time digests are sent at, 09:00 by default.
+    #[serde(default)]
+    pub send_at: Option<String>,
+    /// IANA timezone of send_at, UTC by default.
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+impl NotificationOptions {
+    pub const DEFAULT_SEND_AT: &'static str = "09:00";
+    pub const DEFAULT_TIMEZONE: &'static str = "UTC";
+
+    pub fn send_at(&self) -> &str {
+        self.send_at.as_deref().unwrap_or(Self::DEFAULT_SEND_AT)
+    }
+
+    pub fn timezone(&self) -> &str {
+        self.timezone.as_deref().unwrap_or(Self::DEFAULT_TIMEZONE)
+    }
+}
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookDeliveryStatus {
+    #[serde(alias = "pending")]
+    Pending,
+    #[serde(alias = "delivered")]
+    Delivered,
+    #[serde(alias = "failed")]
+    Failed,
 }
@@ -0,0 +1,158 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+use strum_macros::Display;
+
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Plan {
+    #[default]
+    #[serde(alias = "free")]
+    Free,
+    #[serde(alias = "pro")]
+    Pro,
+    #[serde(alias = "business")]
+    Business,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuotaLimits {
+    pub crawlers: u64,
+    pub pages_per_day: u64,
+    pub pages_per_month: u64,
+    pub reparses_per_day: u64,
+}
+
+impl QuotaLimits {
+    /// Limits of a user from the values stored at quota_limits_key and quota_plan_key:
+    /// the explicit override, then the plan limits, then the free plan.
+    pub fn resolve(custom: Option<&str>, plan: Option<&str>) -> QuotaLimits {
+        if let Some(limits) = custom.and_then(|l| serde_json::from_str(l).ok()) {
+            return limits;
+        }
+        plan.and_then(|p| serde_json::from_value::<Plan>(serde_json::Value::String(p.to_string())).ok())
+            .unwrap_or_default()
+            .limits()
+    }
+}
+
+impl Plan {
+    pub fn limits(&self) -> QuotaLimits {
+        match self {
+            Plan::Free => QuotaLimits {
+                crawlers: 3,
+                pages_per_day: 1_000,
+                pages_per_month: 10_000,
+                reparses_per_day: 100,
+            },
+            Plan::Pro => QuotaLimits {
+                crawlers: 50,
+                pages_per_day: 50_000,
+                pages_per_month: 1_000_000,
+                reparses_per_day: 5_000,
+            },
+            Plan::Business => QuotaLimits {
+                crawlers: 500,
+                pages_per_day: 500_000,
+                pages_per_month: 10_000_000,
+                reparses_per_day: 50_000,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaKind {
+    #[serde(alias = "crawlers")]
+    Crawlers,
+    #[serde(alias = "pages_per_day")]
+    PagesPerDay,
+    #[serde(alias = "pages_per_month")]
+    PagesPerMonth,
+    #[serde(alias = "reparses_per_day")]
+    ReparsesPerDay,
+}
+
+impl QuotaKind {
+    pub fn limit(&self, limits: &QuotaLimits) -> u64 {
+        match self {
+            QuotaKind::Crawlers => limits.crawlers,
+            QuotaKind::PagesPerDay => limits.pages_per_day,
+            QuotaKind::PagesPerMonth => limits.pages_per_month,
+            QuotaKind::ReparsesPerDay => limits.reparses_per_day,
+        }
+    }
+
+    /// Window suffix of a counter key, crawlers are counted for all time.
+    pub fn window(&self, now: DateTime<Utc>) -> String {
+        match self {
+            QuotaKind::Crawlers => "total".into(),
+            QuotaKind::PagesPerDay | QuotaKind::ReparsesPerDay => now.format("%Y-%m-%d").to_string(),
+            QuotaKind::PagesPerMonth => now.format("%Y-%m").to_string(),
+        }
+    }
+
+    /// When the current window is over and the counter starts from zero.
+    pub fn resets_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            QuotaKind::Crawlers => None,
+            QuotaKind::PagesPerDay | QuotaKind::ReparsesPerDay => {
+                let today = now.date_naive().and_hms_opt(0, 0, 0)?;
+                Some(Utc.from_utc_datetime(&today) + Duration::days(1))
+            },
+            QuotaKind::PagesPerMonth => {
+                let (year, month) = if now.month() == 12 { (now.year() + 1, 1) } else { (now.year(), now.month() + 1) };
+                Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()
+            },
+        }
+    }
+
+    /// Redis key of the counter shared by the gateway and the scheduler.
+    pub fn counter_key(&self, user_id: impl Display, now: DateTime<Utc>) -> String {
+        format!("quota:{}:{}:{}", user_id, self, self.window(now))
+    }
+}
+
+/// Redis key with a json QuotaLimits overriding the plan limits of a user.
+pub fn quota_limits_key(user_id: impl Display) -> String {
+    format!("quota:{}:limits", user_id)
+}
+
+/// Redis key with the plan name of a user.
+pub fn quota_plan_key(user_id: impl Display) -> String {
+    format!("quota:{}:plan", user_id)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuotaExceeded {
+    pub user_id: String,
+    pub kind: QuotaKind,
+    pub limit: u64,
+    pub used: u64,
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_override_then_plan_then_free() {
+        let custom = r#"{"crawlers":1,"pages_per_day":2,"pages_per_month":3,"reparses_per_day":4}"#;
+        assert_eq!(QuotaLimits::resolve(Some(custom), Some("pro")).crawlers, 1);
+        assert_eq!(QuotaLimits::resolve(None, Some("pro")), Plan::Pro.limits());
+        assert_eq!(QuotaLimits::resolve(Some("broken"), Some("Business")), Plan::Business.limits());
+        assert_eq!(QuotaLimits::resolve(None, Some("unknown")), Plan::Free.limits());
+        assert_eq!(QuotaLimits::resolve(None, None), Plan::Free.limits());
+    }
+
+    #[test]
+    fn windows_reset_at_the_next_period() {
+        let now = Utc.with_ymd_and_hms(2024, 12, 31, 15, 0, 0).unwrap();
+        assert_eq!(QuotaKind::PagesPerDay.resets_at(now), Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).single());
+        assert_eq!(QuotaKind::PagesPerMonth.resets_at(now), Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).single());
+        assert_eq!(QuotaKind::Crawlers.resets_at(now), None);
+        assert_eq!(QuotaKind::PagesPerMonth.counter_key("u", now), "quota:u:PagesPerMonth:2024-12");
+    }
+}
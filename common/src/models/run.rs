@@ -0,0 +1,213 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use strum_macros::{Display, EnumString};
+use uuid::Uuid;
+
+use crate::models::crawler::Crawler;
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    #[serde(alias = "running")]
+    Running,
+    #[serde(alias = "completed")]
+    Completed,
+    #[serde(alias = "partially_failed")]
+    PartiallyFailed,
+    #[serde(alias = "cancelled")]
+    Cancelled,
+    /// Ended early by the crawler's budget or a stop condition.
+    #[serde(alias = "stopped")]
+    Stopped,
+}
+
+/// Why a run was stopped before all its pages were crawled.
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    #[serde(alias = "max_pages")]
+    MaxPages,
+    #[serde(alias = "max_duration")]
+    MaxDuration,
+    #[serde(alias = "max_bytes")]
+    MaxBytes,
+    #[serde(alias = "stop_condition")]
+    StopCondition,
+}
+
+impl RunStatus {
+    pub const ALL: [RunStatus; 5] = [
+        RunStatus::Running,
+        RunStatus::Completed,
+        RunStatus::PartiallyFailed,
+        RunStatus::Cancelled,
+        RunStatus::Stopped,
+    ];
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, RunStatus::Running)
+    }
+
+    /// Only running runs can move on, finished runs are final.
+    pub fn can_transition_to(&self, next: RunStatus) -> bool {
+        matches!(self, RunStatus::Running) && next != RunStatus::Running
+    }
+
+    /// Statuses a run can move to `next` from, the ones a run update has to match.
+    pub fn preceding(next: RunStatus) -> Vec<RunStatus> {
+        RunStatus::ALL.into_iter().filter(|status| status.can_transition_to(next)).collect()
+    }
+}
+
+/// Page outcomes counted per run.
+#[derive(Debug, Display, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RunCounter {
+    Dispatched,
+    Scraped,
+    Extracted,
+    Failed,
+    Skipped,
+}
+
+impl RunCounter {
+    /// Column of the counter in crawl_runs table.
+    pub fn column(&self) -> &'static str {
+        match self {
+            RunCounter::Dispatched => "dispatched",
+            RunCounter::Scraped => "scraped",
+            RunCounter::Extracted => "extracted",
+            RunCounter::Failed => "failed",
+            RunCounter::Skipped => "skipped",
+        }
+    }
+
+    /// Whether the page is done within the run after this outcome.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, RunCounter::Extracted | RunCounter::Failed | RunCounter::Skipped)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RunStats {
+    pub dispatched: u64,
+    pub scraped: u64,
+    pub extracted: u64,
+    pub failed: u64,
+    pub skipped: u64,
+    /// Bytes of page bodies downloaded.
+    #[serde(default)]
+    pub bytes: u64,
+}
+
+impl RunStats {
+    pub fn record(&mut self, counter: RunCounter) {
+        match counter {
+            RunCounter::Dispatched => self.dispatched += 1,
+            RunCounter::Scraped => self.scraped += 1,
+            RunCounter::Extracted => self.extracted += 1,
+            RunCounter::Failed => self.failed += 1,
+            RunCounter::Skipped => self.skipped += 1,
+        }
+    }
+
+    /// Pages which reached a final outcome.
+    pub fn settled(&self) -> u64 {
+        self.extracted + self.failed + self.skipped
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.dispatched > 0 && self.settled() >= self.dispatched
+    }
+}
+
+/// A single firing of a crawler with all pages it produced.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrawlRun {
+    pub id: Uuid,
+    pub crawler_id: Uuid,
+    pub user_id: Uuid,
+    pub status: RunStatus,
+    pub stats: RunStats,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub stop_reason: Option<StopReason>,
+}
+
+impl CrawlRun {
+    pub fn new(crawler: &Crawler) -> Self {
+        CrawlRun {
+            id: Uuid::now_v7(),
+            crawler_id: crawler.id,
+            user_id: crawler.user_id,
+            status: RunStatus::Running,
+            stats: RunStats::default(),
+            started_at: Utc::now(),
+            finished_at: None,
+            stop_reason: None,
+        }
+    }
+
+    /// Status of a run with settled pages: any failed page makes it partially failed.
+    pub fn final_status(stats: &RunStats) -> RunStatus {
+        if stats.failed > 0 {
+            RunStatus::PartiallyFailed
+        } else {
+            RunStatus::Completed
+        }
+    }
+}
+
+/// Cancelled run ids are kept in redis for a week, long enough for queued messages to drain.
+pub const CANCELLED_RUN_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Redis key marking a cancelled run, services drop queued messages of such runs.
+pub fn cancelled_run_key(run_id: impl Display) -> String {
+    format!("run:{}:cancelled", run_id)
+}
+
+#[derive(Debug, Display, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrawlerState {
+    #[default]
+    #[serde(alias = "active")]
+    Active,
+    #[serde(alias = "paused")]
+    Paused,
+}
+
+/// What a control command from the gateway is applied to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ControlTarget {
+    #[serde(alias = "crawler")]
+    Crawler(Uuid),
+    #[serde(alias = "run")]
+    Run(Uuid),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_settle_when_every_dispatched_page_has_an_outcome() {
+        let mut stats = RunStats::default();
+        assert!(!stats.is_settled());
+        stats.record(RunCounter::Dispatched);
+        stats.record(RunCounter::Dispatched);
+        stats.record(RunCounter::Scraped);
+        stats.record(RunCounter::Extracted);
+        assert!(!stats.is_settled());
+        stats.record(RunCounter::Failed);
+        assert!(stats.is_settled());
+        assert_eq!(CrawlRun::final_status(&stats), RunStatus::PartiallyFailed);
+    }
+
+    #[test]
+    fn finished_runs_are_final() {
+        assert!(RunStatus::Running.can_transition_to(RunStatus::Cancelled));
+        assert!(!RunStatus::Running.can_transition_to(RunStatus::Running));
+        assert!(!RunStatus::Completed.can_transition_to(RunStatus::Cancelled));
+        assert_eq!(RunStatus::preceding(RunStatus::Stopped), [RunStatus::Running]);
+        assert!(RunStatus::preceding(RunStatus::Running).is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::models::failure::ScrapeFailure;
+
+/// Sitemap the runs of a crawler are seeded from, besides the start page.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SitemapSeed {
+    /// Sitemap or sitemap index url. When empty, sitemaps are looked up in robots.txt of the start page's host.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Seed only pages whose lastmod is after the start of the previous run.
+    #[serde(default)]
+    pub changed_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SitemapKind {
+    /// robots.txt listing sitemaps in `Sitemap:` lines.
+    #[serde(alias = "robots")]
+    Robots,
+    /// Sitemap or sitemap index, gzip compressed or not.
+    #[serde(alias = "sitemap")]
+    Sitemap,
+}
+
+/// A page listed in a sitemap.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub url: String,
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+/// A sitemap of a run to fetch. Scrapers fetch it like any page and send it back
+/// with the pages and nested sitemaps it lists.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SitemapFetch {
+    pub id: Uuid,
+    pub crawler_id: Uuid,
+    pub user_id: Uuid,
+    pub run_id: Option<Uuid>,
+    pub url: String,
+    pub kind: SitemapKind,
+    /// Sitemap indexes followed to get here.
+    pub depth: u32,
+    /// Pages with an older lastmod are not seeded.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sitemaps: Vec<String>,
+    #[serde(default)]
+    pub pages: Vec<SitemapEntry>,
+    #[serde(default)]
+    pub failure: Option<ScrapeFailure>,
+}
+
+impl SitemapFetch {
+    /// A fetch of a sitemap listed in this one.
+    pub fn nested(&self, url: String) -> Self {
+        SitemapFetch {
+            id: Uuid::now_v7(),
+            url,
+            kind: SitemapKind::Sitemap,
+            depth: self.depth + 1,
+            sitemaps: Vec::new(),
+            pages: Vec::new(),
+            failure: None,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+/// How a suggested xpath locates a node, from the most to the least robust one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum SelectorStrategy {
+    Id,
+    DataAttribute,
+    StableAttribute,
+    SemanticClass,
+    AnchoredPath,
+    Positional,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SelectorSuggestion {
+    pub xpath: String,
+    pub strategy: SelectorStrategy,
+    pub confidence: f32,
+}
+
+/// A request to suggest xpaths of fields by example values found on a page.
+/// Extractors send it back with the suggestions filled in.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SuggestSelectors {
+    pub id: Uuid,
+    #[serde(default)]
+    pub crawler_id: Option<Uuid>,
+    pub html: String,
+    /// Field name to an example value of the field on the page.
+    pub examples: HashMap<String, String>,
+    /// Field name to suggested xpaths, the most confident first.
+    #[serde(default)]
+    pub suggestions: HashMap<String, Vec<SelectorSuggestion>>,
+}
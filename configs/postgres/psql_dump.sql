@@ -37,12 +37,111 @@ create table if not exists page_events (
     id uuid primary key,
     page_id uuid not null references pages(id),
     pagination_event_id uuid references pagination_events(id),
+    -- id of the ExtractPage event the snapshot was stored from, a redelivered event is not stored twice
+    source_event_id uuid unique,
     created_at timestamp not null default now(),
     status text not null default 'pending',
     html text,
     data json not null default '{}'::json
 );
 
+create table if not exists page_diffs (
+    id uuid primary key,
+    page_id uuid not null references pages(id),
+    page_event_id uuid not null references page_events(id),
+    previous_page_event_id uuid references page_events(id),
+    created_at timestamp not null default now(),
+    changes json not null default '[]'::json
+);
+
+create table if not exists page_fingerprints (
+    url text primary key,
+    hash text not null,
+    etag text,
+    last_modified text,
+    updated_at timestamp not null default now()
+);
+
+create table if not exists crawl_runs (
+    id uuid primary key,
+    crawler_id uuid not null,
+    user_id uuid not null,
+    status text not null default 'Running',
+    dispatched bigint not null default 0,
+    scraped bigint not null default 0,
+    extracted bigint not null default 0,
+    failed bigint not null default 0,
+    skipped bigint not null default 0,
+    bytes bigint not null default 0,
+    stop_reason text,
+    started_at timestamptz not null default now(),
+    finished_at timestamptz
+);
+
+-- values of fields with a SeenLastRun stop condition, kept for the latest two runs of a crawler
+create table if not exists run_seen_values (
+    run_id uuid not null,
+    crawler_id uuid not null,
+    field text not null,
+    value text not null,
+    primary key (run_id, field, value)
+);
+
+create table if not exists scheduled_crawlers (
+    crawler_id uuid primary key,
+    crawler json not null,
+    updated_at timestamptz not null default now()
+);
+
+create table if not exists crawler_start_urls (
+    crawler_id uuid primary key,
+    urls text[] not null,
+    updated_at timestamptz not null default now()
+);
+
+-- xpaths suggested by extractors from example field values, requested by users per crawler
+create table if not exists selector_suggestions (
+    id uuid primary key,
+    crawler_id uuid not null,
+    status text not null default 'Pending',
+    examples json not null default '{}'::json,
+    suggestions json not null default '{}'::json,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now()
+);
+
+create table if not exists crawler_states (
+    crawler_id uuid primary key,
+    state text not null default 'Active',
+    updated_at timestamptz not null default now()
+);
+
+create table if not exists routines (
+    id uuid primary key,
+    name text not null,
+    rule text not null,
+    task text not null,
+    last_run_at timestamptz,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now()
+);
+
+create table if not exists webhook_deliveries (
+    id uuid primary key,
+    crawler_id uuid not null,
+    user_id uuid not null,
+    url text not null,
+    event text not null,
+    payload json not null,
+    status text not null default 'Pending',
+    attempts int not null default 0,
+    response_status int,
+    last_error text,
+    next_attempt_at timestamptz,
+    created_at timestamptz not null default now(),
+    delivered_at timestamptz
+);
+
 -- create table if not exists page_event_errors (
 --     id uuid primary key,
 --     page_event_id uuid not null references page_events(id),
@@ -62,4 +161,10 @@ create table if not exists utils.users (
 
 create index if not exists pages_site_id_idx on pages(site_id);
 create index if not exists pagination_events_page_id_idx on pagination_events(page_id);
+create index if not exists selector_suggestions_crawler_id_idx on selector_suggestions(crawler_id, created_at desc);
 create index if not exists page_events_page_id_idx on page_events(page_id);
+create index if not exists page_diffs_page_id_idx on page_diffs(page_id);
+create index if not exists crawl_runs_crawler_id_idx on crawl_runs(crawler_id, started_at desc);
+create index if not exists run_seen_values_crawler_id_idx on run_seen_values(crawler_id);
+create index if not exists webhook_deliveries_crawler_id_idx on webhook_deliveries(crawler_id, created_at desc);
+create index if not exists webhook_deliveries_due_idx on webhook_deliveries(next_attempt_at) where status = 'Pending';
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+pub use common::broker::{Broker, ParseraService};
+
+use crate::config::BrokerConfig;
+use crate::handlers;
+use crate::repo::Postgres;
+
+pub type SharedBroker = Arc<dyn Broker>;
+
+/// Connects to the configured broker and declares the topology shared by all services.
+pub async fn connect(cfg: &BrokerConfig) -> Result<SharedBroker> {
+    Ok(common::broker::connect(cfg.settings()).await?)
+}
+
+/// Stores extracted pages. Messages which aren't events are parked, failed ones are retried.
+pub async fn consume(broker: &dyn Broker, db: &Postgres) -> Result<()> {
+    tracing::info!("consuming events for {}", ParseraService::DatabaseManager);
+    let mut subscription = broker.subscribe(ParseraService::DatabaseManager).await?;
+    while let Some(delivery) = subscription.next().await {
+        let event = match delivery.event() {
+            Ok(event) => event,
+            Err(err) => {
+                tracing::error!("parking malformed message: {}", err);
+                if let Err(err) = delivery.park().await {
+                    tracing::error!("cannot park a delivery: {}", err);
+                }
+                continue;
+            }
+        };
+        let settled = match handlers::handle_event(broker, db, event).await {
+            Ok(()) => delivery.ack().await,
+            Err(err) => {
+                tracing::error!("cannot store page: {}", err);
+                delivery.retry().await
+            }
+        };
+        if let Err(err) = settled {
+            tracing::error!("cannot settle a delivery: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use common::models::{FieldChange, Page, PageDiff};
+
+use crate::repo::Snapshot;
+
+/// Compares two extracted data maps. Changes are sorted by field name.
+pub fn diff_data(old: &HashMap<String, String>, new: &HashMap<String, String>) -> Vec<FieldChange> {
+    let mut changes: Vec<FieldChange> = new
+        .iter()
+        .filter_map(|(field, value)| match old.get(field) {
+            None => Some(FieldChange::Added {
+                field: field.clone(),
+                value: value.clone(),
+            }),
+            Some(old) if old != value => Some(FieldChange::Changed {
+                field: field.clone(),
+                old: old.clone(),
+                new: value.clone(),
+            }),
+            Some(_) => None,
+        })
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|(field, _)| !new.contains_key(*field))
+            .map(|(field, value)| FieldChange::Removed {
+                field: field.clone(),
+                value: value.clone(),
+            }),
+    );
+    changes.sort_by(|a, b| a.field().cmp(b.field()));
+    changes
+}
+
+/// Builds a diff between the previous snapshot and the freshly stored one.
+/// There is nothing to compare for the first snapshot of a page or a page without extracted data.
+pub fn detect_changes(page: &Page, previous: Option<&Snapshot>, current: &Snapshot) -> Option<PageDiff> {
+    let previous = previous?;
+    page.data.as_ref()?;
+    Some(PageDiff {
+        id: Uuid::now_v7(),
+        page_id: current.page_id,
+        crawler_id: page.crawler_id,
+        run_id: page.run_id,
+        url: page.url.clone(),
+        previous_snapshot_id: Some(previous.id),
+        snapshot_id: current.id,
+        changes: diff_data(&previous.data, &current.data),
+        created_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn data(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn page(data: Option<HashMap<String, String>>) -> Page {
+        serde_json::from_value(json!({
+            "id": Uuid::now_v7(),
+            "crawler_id": Uuid::now_v7(),
+            "site_id": Uuid::now_v7(),
+            "url": "https://shop.site/item",
+            "domain": "shop.site",
+            "is_pagination": false,
+            "times_reparsed": 0,
+            "priority": "Common",
+            "notification": { "level": "JobsDone", "via": [], "every": null },
+            "xpaths": {},
+            "created_at": Utc::now(),
+            "updated_at": Utc::now(),
+            "html": null,
+            "data": data,
+            "meta": null,
+        }))
+        .unwrap()
+    }
+
+    fn snapshot(data: HashMap<String, String>) -> Snapshot {
+        Snapshot {
+            id: Uuid::now_v7(),
+            page_id: Uuid::now_v7(),
+            data,
+        }
+    }
+
+    #[test]
+    fn diffs_fields_sorted_by_name() {
+        let old = data(&[("price", "10"), ("title", "Widget"), ("color", "blue")]);
+        let new = data(&[("price", "12"), ("title", "Widget"), ("stock", "3")]);
+        assert_eq!(
+            diff_data(&old, &new),
+            vec![
+                FieldChange::Removed { field: "color".into(), value: "blue".into() },
+                FieldChange::Changed { field: "price".into(), old: "10".into(), new: "12".into() },
+                FieldChange::Added { field: "stock".into(), value: "3".into() },
+            ]
+        );
+        assert!(diff_data(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn first_snapshot_has_no_diff() {
+        let fields = data(&[("title", "Widget")]);
+        let page = page(Some(fields.clone()));
+        assert!(detect_changes(&page, None, &snapshot(fields)).is_none());
+    }
+
+    #[test]
+    fn page_without_data_has_no_diff() {
+        let previous = snapshot(data(&[("title", "Widget")]));
+        let page = page(None);
+        assert!(detect_changes(&page, Some(&previous), &snapshot(HashMap::new())).is_none());
+    }
+
+    #[test]
+    fn diffs_against_previous_snapshot() {
+        let previous = snapshot(data(&[("title", "Widget")]));
+        let current = snapshot(data(&[("title", "Gadget")]));
+        let page = page(Some(current.data.clone()));
+        let diff = detect_changes(&page, Some(&previous), &current).unwrap();
+        assert_eq!(diff.previous_snapshot_id, Some(previous.id));
+        assert_eq!(diff.snapshot_id, current.id);
+        assert_eq!(diff.changes.len(), 1);
+    }
+}
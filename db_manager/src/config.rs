@@ -0,0 +1,142 @@
+use std::env;
+
+use common::broker::{BrokerBackend, BrokerSettings, KafkaSettings, RabbitSettings, Topology};
+use envconfig::Envconfig;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+pub trait DbAddr {
+    fn get_addr(&self) -> String;
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct DatabaseConfig {
+    #[envconfig(from = "POSTGRES_HOST", default = "localhost")]
+    pub host: String,
+    #[envconfig(from = "POSTGRES_PORT", default = "5432")]
+    pub port: u16,
+    #[envconfig(from = "POSTGRES_PASSWORD", default = "")]
+    pub password: String,
+    #[envconfig(from = "POSTGRES_DB", default = "postgres")]
+    pub db: String,
+    #[envconfig(from = "POSTGRES_USER", default = "postgres")]
+    pub user: String,
+    #[envconfig(from = "POSTGRES_POOL_MAX_SIZE", default = "10")]
+    pub pool_max_size: u32,
+}
+
+impl DbAddr for DatabaseConfig {
+    fn get_addr(&self) -> String {
+        format!(
+            "postgres://{}:{}@{}:{}/{}",
+            self.user, self.password, self.host, self.port, self.db
+        )
+    }
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct BrokerConfig {
+    #[envconfig(from = "RABBITMQ_HOST")]
+    pub host: String,
+    #[envconfig(from = "RABBITMQ_PORT")]
+    pub port: u16,
+    #[envconfig(from = "RABBITMQ_USER")]
+    pub user: String,
+    #[envconfig(from = "RABBITMQ_PASSWORD")]
+    pub password: String,
+    #[envconfig(from = "RABBITMQ_VHOST")]
+    pub vhost: String,
+    #[envconfig(from = "RABBITMQ_PREFETCH_COUNT", default = "16")]
+    pub prefetch_count: u16,
+    #[envconfig(from = "BROKER_BACKEND", default = "rabbitmq")]
+    pub backend: BrokerBackend,
+    #[envconfig(from = "KAFKA_BROKERS", default = "localhost:9092")]
+    pub kafka_brokers: String,
+    #[envconfig(from = "KAFKA_PARTITIONS", default = "12")]
+    pub kafka_partitions: i32,
+    #[envconfig(from = "KAFKA_REPLICATION_FACTOR", default = "1")]
+    pub kafka_replication_factor: i32,
+}
+
+impl BrokerConfig {
+    /// Queue names and retry settings are shared with the scheduler and read from the same variables.
+    pub fn settings(&self) -> BrokerSettings {
+        let topology = Topology::from_env();
+        BrokerSettings {
+            backend: self.backend,
+            rabbit: RabbitSettings {
+                url: self.get_addr(),
+                topology: topology.clone(),
+                prefetch_count: self.prefetch_count,
+            },
+            kafka: KafkaSettings {
+                brokers: self.kafka_brokers.clone(),
+                topology,
+                partitions: self.kafka_partitions,
+                replication_factor: self.kafka_replication_factor,
+            },
+        }
+    }
+}
+
+impl DbAddr for BrokerConfig {
+    fn get_addr(&self) -> String {
+        let vhost = if self.vhost.starts_with('/') {
+            self.vhost.replace('/', "%2f")
+        } else {
+            self.vhost.clone()
+        };
+        format!(
+            "amqp://{}:{}@{}:{}/{}",
+            self.user, self.password, self.host, self.port, vhost
+        )
+    }
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct Config {
+    #[envconfig(nested = true)]
+    pub database: DatabaseConfig,
+    #[envconfig(nested = true)]
+    pub broker: BrokerConfig,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        if env::var("RUST_LOG").is_err() {
+            env::set_var("RUST_LOG", "debug")
+        }
+        let log_format = env::var("LOG_FORMAT").unwrap_or_else(|_| "text".into());
+        Self::init_tracing(log_format.as_str());
+
+        if env::var("LOCAL_RUN").is_ok_and(|local| local == "true") {
+            use dotenv::dotenv;
+
+            dotenv().ok();
+            tracing::info!("Local Run mode enabled")
+        }
+
+        match Config::init_from_env() {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!("Cannot load config: {}", err);
+                panic!("cannot load config");
+            }
+        }
+    }
+
+    pub fn init_tracing(log_format: &str) {
+        let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "db_manager=debug".into());
+        if log_format == "json" {
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(tracing_subscriber::fmt::layer().json())
+                .init();
+        } else {
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(tracing_subscriber::fmt::layer())
+                .init();
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+
+use common::models::{
+    EventCause, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, Page,
+};
+
+use crate::broker::{Broker, ParseraService};
+use crate::repo::{Database, Postgres};
+
+pub async fn handle_event(broker: &dyn Broker, db: &Postgres, event: EventProtocol) -> Result<()> {
+    tracing::debug!("handling {}", event.trace());
+    let cause = event.cause();
+    let page = match (event.command, event.data) {
+        (EventCommand::ExtractPage(EventCommandStatus::Done), EventProtocolData::Internal(page)) => page,
+        (command, _) => {
+            tracing::warn!("skipping unsupported event {}", command);
+            return Ok(());
+        }
+    };
+    handle_store(broker, db, cause, page).await
+}
+
+/// Stores a new snapshot of the page and emits PageChanged if extracted data differs from the last one.
+/// PageChanged of a redelivered event is published again under the same id, so consumers drop the duplicate.
+pub async fn handle_store(broker: &dyn Broker, db: &Postgres, cause: EventCause, page: Page) -> Result<()> {
+    let diff = match db.add_snapshot(cause.id, &page).await? {
+        Some(diff) => diff,
+        None => {
+            tracing::debug!("page {} has not changed", page.url);
+            return Ok(());
+        }
+    };
+    tracing::info!("page {} has {} changed fields", page.url, diff.changes.len());
+
+    let event = EventProtocol {
+        id: diff.id,
+        ..EventProtocol::caused_by(
+            cause,
+            EventCommand::PageChanged(EventCommandStatus::Done),
+            EventProtocolData::Changes(diff),
+        )
+    };
+    broker.publish_event(&event, ParseraService::Scheduler).await?;
+    Ok(())
+}
@@ -1,33 +1,23 @@
 use anyhow::Result;
-use uuid::Uuid;
-use scylla::{Session, SessionBuilder};
-use scylla::FromRow;
-use scylla::ValueList;
 
-mod duration;
-mod repo;
-// use chrono::Duration;
-
-#[derive(Debug, FromRow, ValueList)]
-pub struct TemperatureMeasurement {
-    pub device: Uuid,
-    // pub time: duration::Duration,
-    pub temperature: i16,
-}
-
-async fn create_session(url: &str) -> Result<Session> {
-    SessionBuilder::new()
-        .known_node(url)
-        .build()
-        .await
-        .map_err(From::from)
+use common::infinite_retry;
 
-}
+mod broker;
+mod changes;
+mod config;
+mod handlers;
+mod repo;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    
-    let session = create_session("127.0.0.1:7199").await?;
+    let cfg = config::Config::new();
+    common::models::set_event_producer("db_manager");
+
+    tracing::info!("Connecting to postgres");
+    let db = repo::Postgres::new(cfg.database.clone()).await?;
 
+    tracing::info!("Connecting to rabbitmq");
+    let broker = broker::connect(&cfg.broker).await?;
+    infinite_retry!("broker consumer", broker::consume(broker.as_ref(), &db).await, 1.0)?;
     Ok(())
 }
@@ -1,10 +1,25 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
 use uuid::Uuid;
 
+use common::models::{Page, PageDiff};
+
+mod postgres;
 
-mod scylla;
+pub use postgres::*;
 
+/// Stored extraction result of a page.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub id: Uuid,
+    pub page_id: Uuid,
+    pub data: HashMap<String, String>,
+}
 
 pub trait Database {
-    async fn add();
-    async fn get(crawler_id: Uuid);
-}
\ No newline at end of file
+    /// Stores a snapshot of the page from event `event_id` and its diff against the previous snapshot in one transaction.
+    /// Returns the diff when the data has changed. A redelivered event stores nothing and returns the diff stored the first time.
+    /// Snapshots of pages without extracted data are never previous ones.
+    async fn add_snapshot(&self, event_id: Uuid, page: &Page) -> Result<Option<PageDiff>>;
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use common::models::{FieldChange, Page, PageDiff};
+
+use crate::changes;
+use crate::config::{DatabaseConfig, DbAddr};
+use crate::repo::{Database, Snapshot};
+
+pub struct Postgres {
+    pool: PgPool,
+}
+
+impl Postgres {
+    pub async fn new(cfg: DatabaseConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(cfg.pool_max_size)
+            .connect(&cfg.get_addr())
+            .await?;
+        Ok(Postgres { pool })
+    }
+}
+
+impl Database for Postgres {
+    async fn add_snapshot(&self, event_id: Uuid, page: &Page) -> Result<Option<PageDiff>> {
+        let mut tx = self.pool.begin().await?;
+
+        let stored: Option<Uuid> = sqlx::query_scalar("select id from page_events where source_event_id = $1")
+            .bind(event_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(snapshot_id) = stored {
+            tracing::debug!("event {} is stored already as snapshot {}", event_id, snapshot_id);
+            let diff = sqlx::query_as::<_, (Uuid, Uuid, Option<Uuid>, Json<Vec<FieldChange>>, NaiveDateTime)>(
+                "select id, page_id, previous_page_event_id, changes, created_at from page_diffs where page_event_id = $1",
+            )
+            .bind(snapshot_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(|(id, page_id, previous_snapshot_id, changes, created_at)| PageDiff {
+                id,
+                page_id,
+                crawler_id: page.crawler_id,
+                run_id: page.run_id,
+                url: page.url.clone(),
+                previous_snapshot_id,
+                snapshot_id,
+                changes: changes.0,
+                created_at: created_at.and_utc(),
+            });
+            return Ok(diff);
+        }
+
+        let site_id: Uuid = sqlx::query_scalar(
+            "insert into sites (id, domain) values ($1, $2)
+             on conflict (domain) do update set domain = excluded.domain
+             returning id",
+        )
+        .bind(page.site_id)
+        .bind(&page.domain)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let page_id: Uuid = sqlx::query_scalar(
+            "insert into pages (id, site_id, url, is_pagination, xpaths) values ($1, $2, $3, $4, $5)
+             on conflict (url) do update set updated_at = now(), xpaths = excluded.xpaths
+             returning id",
+        )
+        .bind(page.id)
+        .bind(site_id)
+        .bind(&page.url)
+        .bind(page.is_pagination)
+        .bind(Json(&page.xpaths))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let previous = sqlx::query_as::<_, (Uuid, Json<HashMap<String, String>>)>(
+            "select id, data from page_events where page_id = $1 and status = 'done'
+             order by created_at desc limit 1",
+        )
+        .bind(page_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|(id, data)| Snapshot { id, page_id, data: data.0 });
+
+        let current = Snapshot {
+            id: Uuid::now_v7(),
+            page_id,
+            data: page.data.clone().unwrap_or_default(),
+        };
+        let status = match page.data {
+            Some(_) => "done",
+            None => "empty",
+        };
+        sqlx::query(
+            "insert into page_events (id, page_id, source_event_id, status, html, data) values ($1, $2, $3, $4, $5, $6)",
+        )
+            .bind(current.id)
+            .bind(page_id)
+            .bind(event_id)
+            .bind(status)
+            .bind(&page.html)
+            .bind(Json(&current.data))
+            .execute(&mut *tx)
+            .await?;
+
+        // saved with the snapshot, so a page that failed to store is not skipped as unchanged next time
+        if let Some(fingerprint) = page.fingerprint.as_ref().filter(|f| f.hash.is_some()) {
+            sqlx::query(
+                "insert into page_fingerprints (url, hash, etag, last_modified) values ($1, $2, $3, $4)
+                 on conflict (url) do update
+                 set hash = excluded.hash, etag = excluded.etag, last_modified = excluded.last_modified, updated_at = now()",
+            )
+            .bind(&page.url)
+            .bind(&fingerprint.hash)
+            .bind(&fingerprint.etag)
+            .bind(&fingerprint.last_modified)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // stored with the snapshot, so a retried delivery cannot diff against its own snapshot and lose the change
+        let diff = changes::detect_changes(page, previous.as_ref(), &current).filter(|diff| !diff.is_empty());
+        if let Some(diff) = &diff {
+            sqlx::query(
+                "insert into page_diffs (id, page_id, page_event_id, previous_page_event_id, changes)
+                 values ($1, $2, $3, $4, $5)",
+            )
+            .bind(diff.id)
+            .bind(diff.page_id)
+            .bind(diff.snapshot_id)
+            .bind(diff.previous_snapshot_id)
+            .bind(Json(&diff.changes))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(diff)
+    }
+}
@@ -1,19 +1,23 @@
 use crate::{
-    broker::Broker, config::Config, database::Database, extractor::XpathExtractor, models,
+    broker::{self, Delivery, ParseraService, SharedBroker},
+    cancellation::CancelledRuns,
+    config::Config,
+    database::Database,
+    extractor::XpathExtractor,
+    suggest::SelectorSuggester,
 };
 
 use std::sync::Arc;
 
-use futures_lite::stream::StreamExt;
-use lapin::message::Delivery;
-use log;
-use serde::de;
-
+use common::models::{
+    EventCause, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, Page, SuggestSelectors,
+};
 
 pub struct App {
+    #[allow(dead_code)]
     pub database: Database,
-    pub broker: Broker,
-    pub config: Config,
+    pub broker: SharedBroker,
+    pub cancelled: CancelledRuns,
 }
 
 impl App {
@@ -21,68 +25,152 @@ impl App {
         let database = Database::new(config.postgres.clone())
             .await
             .expect("database error");
-        let broker = Broker::new(config.rabbit.clone())
+        let cancelled = CancelledRuns::new(&config.redis.get_url())
+            .await
+            .expect("redis error");
+        log::info!("Connecting to RabbitMQ and declaring queues");
+        let broker = broker::connect(&config.rabbit)
             .await
             .expect("broker error");
 
         let app = App {
             database,
             broker,
-            config,
+            cancelled,
         };
         Arc::new(app)
     }
 
     pub async fn run(self: Arc<Self>) {
-        log::info!("Declaring and binding queues");
-        self.broker.declare_all().await.expect("declare error");
         log::info!("Starting consuming");
         self.start_consuming().await;
     }
 
     async fn start_consuming(self: Arc<Self>) {
-        let mut consumer = self.broker.consumer().await;
+        let mut subscription = self
+            .broker
+            .subscribe(ParseraService::Extractor)
+            .await
+            .expect("subscribe error");
 
         log::info!(" [*] Waiting for messages. To exit press CTRL+C");
-        while let Some(delivery) = consumer.next().await {
+        while let Some(delivery) = subscription.next().await {
             let app = Arc::clone(&self);
-            match delivery {
-                Ok(delivery) => {
-                    // app.handle_message(delivery).await;
-                    let msg = std::str::from_utf8(&delivery.data).expect("cannot");
-                    log::info!("DELIVERY! {}", msg);
-                }
-                Err(e) => {
-                    log::error!("Error receiving message: {:?}", e);
-                }
-            }
+            app.handle_message(delivery).await;
         }
     }
 
+    /// Routes an event to extraction or selector suggestion, other events are parked.
     async fn handle_message(self: Arc<Self>, delivery: Delivery) {
-        let msg_in = serde_json::from_slice::<models::MessageIn>(delivery.data.as_slice())
-            .expect("deserialize error in handle_message");
-        self.broker
-            .ack(delivery.delivery_tag)
-            .await
-            .expect("ack error");
-        log::info!("Received message: {}", msg_in);
-    
+        let event = match delivery.event() {
+            Ok(event) => event,
+            Err(e) => {
+                log::error!("deserialize error in handle_message: {}", e);
+                settle(delivery.park().await);
+                return;
+            }
+        };
+        log::info!("Received {}", event.trace());
+        let cause = event.cause();
+        match (event.command, event.data) {
+            (EventCommand::ScrapePage(EventCommandStatus::Done), EventProtocolData::Internal(page)) => {
+                self.handle_page(delivery, cause, page).await
+            }
+            (EventCommand::SuggestSelectors(EventCommandStatus::Pending), EventProtocolData::Suggestions(request)) => {
+                self.handle_suggestion(delivery, cause, request).await
+            }
+            (command, _) => {
+                log::error!("cannot handle {} event", command);
+                settle(delivery.park().await);
+            }
+        }
+    }
+
+    /// Extracts xpaths of a scraped page and sends the page with its data back to the scheduler.
+    /// Links found by pagination xpaths go with it for the scheduler to follow.
+    async fn handle_page(self: Arc<Self>, delivery: Delivery, cause: EventCause, mut page: Page) {
+        let html = match page.html.clone() {
+            Some(html) => html,
+            None => {
+                log::error!("page {} has no html to extract data from", page.url);
+                settle(delivery.park().await);
+                return;
+            }
+        };
+        if let Some(run_id) = page.run_id {
+            if self.cancelled.contains(&run_id.to_string()).await {
+                log::info!("Dropping message of cancelled run {}", run_id);
+                settle(delivery.ack().await);
+                return;
+            }
+        }
+
         log::info!("Spawning task to parse and store");
         tokio::spawn(async move {
-            let extractor = XpathExtractor::new(msg_in.html.clone(), msg_in.xpaths);
-            let res = extractor.extract().await;
-            let msg_out = models::MessageOut {
-                html: msg_in.html,
-                values: res,
-                invalid_xpaths: extractor.invalid_exprs,
+            let extractor = XpathExtractor::new(html.clone(), page.xpaths.clone());
+            let values = extractor.extract().await;
+            let mut invalid_xpaths = extractor.invalid_exprs;
+            if !page.pagination_xpaths.is_empty() {
+                let links = XpathExtractor::new(html, page.pagination_xpaths.clone());
+                page.links = links.extract().await.into_values().filter(|link| !link.trim().is_empty()).collect();
+                invalid_xpaths.extend(links.invalid_exprs);
+            }
+            if !invalid_xpaths.is_empty() {
+                log::warn!("page {} has invalid xpaths: {:?}", page.url, invalid_xpaths);
+            }
+            page.data = Some(values);
+            page.invalid_xpaths = invalid_xpaths;
+            let event = EventProtocol::caused_by(
+                cause,
+                EventCommand::ExtractPage(EventCommandStatus::Done),
+                EventProtocolData::Internal(page),
+            );
+            log::info!("Sending {}", event.trace());
+            let settled = match self.broker.publish_event(&event, ParseraService::Scheduler).await {
+                Ok(()) => delivery.ack().await,
+                Err(e) => {
+                    log::error!("send error: {}", e);
+                    delivery.retry().await
+                }
+            };
+            settle(settled);
+        });
+    }
+
+    /// Suggests xpaths for the example values of the request and sends it back to the scheduler.
+    async fn handle_suggestion(self: Arc<Self>, delivery: Delivery, cause: EventCause, mut request: SuggestSelectors) {
+        log::info!("Received suggestion request {} for fields {:?}", request.id, request.examples.keys());
+        tokio::spawn(async move {
+            let status = match SelectorSuggester::new(request.html.clone()) {
+                Ok(suggester) => {
+                    request.suggestions = suggester.suggest(&request.examples);
+                    EventCommandStatus::Done
+                }
+                Err(e) => {
+                    log::error!("cannot parse html of suggestion request {}: {}", request.id, e);
+                    EventCommandStatus::Failed
+                }
+            };
+            let event = EventProtocol::caused_by(
+                cause,
+                EventCommand::SuggestSelectors(status),
+                EventProtocolData::Suggestions(request),
+            );
+            log::info!("Sending {}", event.trace());
+            let settled = match self.broker.publish_event(&event, ParseraService::Scheduler).await {
+                Ok(()) => delivery.ack().await,
+                Err(e) => {
+                    log::error!("send error: {}", e);
+                    delivery.retry().await
+                }
             };
-            log::info!("Sending message: {}", msg_out);
-            let out = serde_json::to_vec(&msg_out).expect("serialize error in handle_message");
-            self.broker
-                .publish(out.as_slice())
-                .await
-                .expect("send error");
+            settle(settled);
         });
     }
 }
+
+fn settle(settled: broker::BrokerResult<()>) {
+    if let Err(e) = settled {
+        log::error!("cannot settle message: {}", e);
+    }
+}
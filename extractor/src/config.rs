@@ -1,7 +1,7 @@
 extern crate dotenv;
 
+use common::broker::{BrokerBackend, BrokerSettings, KafkaSettings, RabbitSettings, Topology};
 use envconfig::Envconfig;
-use log;
 use std::env;
 
 #[derive(Debug, Envconfig, Clone)]
@@ -16,15 +16,48 @@ pub struct ConfigRabbitMQ {
     pub vhost: String,
     #[envconfig(from = "RABBITMQ_PORT")]
     pub port: u16,
+    #[envconfig(from = "RABBITMQ_PREFETCH_COUNT", default = "16")]
+    pub prefetch_count: u16,
+    #[envconfig(from = "BROKER_BACKEND", default = "rabbitmq")]
+    pub backend: BrokerBackend,
+    #[envconfig(from = "KAFKA_BROKERS", default = "localhost:9092")]
+    pub kafka_brokers: String,
+    #[envconfig(from = "KAFKA_PARTITIONS", default = "12")]
+    pub kafka_partitions: i32,
+    #[envconfig(from = "KAFKA_REPLICATION_FACTOR", default = "1")]
+    pub kafka_replication_factor: i32,
 }
 
 impl ConfigRabbitMQ {
     pub fn get_url(&self) -> String {
         format!(
-            "amqp://{}:{}@{}:{}",
-            self.user, self.password, self.host, self.port
+            "amqp://{}:{}@{}:{}/{}",
+            self.user,
+            self.password,
+            self.host,
+            self.port,
+            self.vhost.replace('/', "%2f")
         )
     }
+
+    /// Queue names and retry settings are shared with the scheduler and read from the same variables.
+    pub fn settings(&self) -> BrokerSettings {
+        let topology = Topology::from_env();
+        BrokerSettings {
+            backend: self.backend,
+            rabbit: RabbitSettings {
+                url: self.get_url(),
+                topology: topology.clone(),
+                prefetch_count: self.prefetch_count,
+            },
+            kafka: KafkaSettings {
+                brokers: self.kafka_brokers.clone(),
+                topology,
+                partitions: self.kafka_partitions,
+                replication_factor: self.kafka_replication_factor,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Envconfig, Clone)]
@@ -50,19 +83,35 @@ impl ConfigPostgres {
     }
 }
 
+#[derive(Debug, Envconfig, Clone)]
+pub struct ConfigRedis {
+    #[envconfig(from = "REDIS_HOST")]
+    pub host: String,
+    #[envconfig(from = "REDIS_PORT", default = "6379")]
+    pub port: u16,
+    #[envconfig(from = "REDIS_PASSWORD")]
+    pub password: String,
+}
+
+impl ConfigRedis {
+    pub fn get_url(&self) -> String {
+        format!("redis://default:{}@{}:{}/", self.password, self.host, self.port)
+    }
+}
+
 #[derive(Debug, Envconfig, Clone)]
 pub struct Config {
     #[envconfig(nested = true)]
     pub rabbit: ConfigRabbitMQ,
     #[envconfig(nested = true)]
     pub postgres: ConfigPostgres,
-    #[envconfig(from = "LOG_LEVEL")]
-    pub log_level: String,
+    #[envconfig(nested = true)]
+    pub redis: ConfigRedis,
 }
 
 pub fn get() -> Config {
     // If LOCAL_RUN is set, load .env file
-    if !env::var("LOCAL_RUN").is_err() {
+    if env::var("LOCAL_RUN").is_ok() {
         use dotenv::dotenv;
 
         dotenv().ok();
@@ -1,6 +1,5 @@
 use std::{collections::HashMap, sync::Arc};
 
-use log;
 use skyscraper::{html, xpath};
 use tokio::sync::mpsc;
 
@@ -35,6 +34,10 @@ impl XpathExtractor {
 
     pub async fn extract(&self) -> HashMap<String, String> {
         let mut values = HashMap::new();
+        // a channel can't be made without capacity
+        if self.exprs.is_empty() {
+            return values;
+        }
         let doc = Arc::new(self.doc.clone());
 
         let (tx, mut rx) = mpsc::channel(self.exprs.len());
@@ -2,14 +2,13 @@ extern crate log;
 
 use std::error::Error;
 
-use tokio;
-
 mod app;
 mod config;
 mod broker;
+mod cancellation;
 mod database;
 mod extractor;
-mod models;
+mod suggest;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
 async fn main() -> Result<(), Box<dyn Error>> {
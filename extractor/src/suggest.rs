@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use common::models::{SelectorStrategy, SelectorSuggestion};
+use skyscraper::{
+    html::{self, DocumentNode, HtmlNode},
+    xpath,
+};
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Attributes that are usually stable between page renders.
+const STABLE_ATTRIBUTES: [&str; 4] = ["itemprop", "name", "role", "aria-label"];
+
+fn base_confidence(strategy: SelectorStrategy) -> f32 {
+    match strategy {
+        SelectorStrategy::Id => 0.95,
+        SelectorStrategy::DataAttribute => 0.9,
+        SelectorStrategy::StableAttribute => 0.85,
+        SelectorStrategy::SemanticClass => 0.75,
+        SelectorStrategy::AnchoredPath => 0.6,
+        SelectorStrategy::Positional => 0.3,
+    }
+}
+
+/// Proposes xpaths for fields by locating nodes that contain example values.
+pub struct SelectorSuggester {
+    pub doc: html::HtmlDocument,
+}
+
+impl SelectorSuggester {
+    pub fn new(doc: String) -> Result<Self, String> {
+        let doc = html::parse(&doc).map_err(|e| e.to_string())?;
+        Ok(SelectorSuggester { doc })
+    }
+
+    /// Returns ranked suggestions for every field of examples (field name -> example value).
+    pub fn suggest(&self, examples: &HashMap<String, String>) -> HashMap<String, Vec<SelectorSuggestion>> {
+        examples
+            .iter()
+            .map(|(field, example)| (field.to_string(), self.suggest_one(example)))
+            .collect()
+    }
+
+    pub fn suggest_one(&self, example: &str) -> Vec<SelectorSuggestion> {
+        let example = normalize(example);
+        if example.is_empty() {
+            return vec![];
+        }
+
+        let mut suggestions: Vec<SelectorSuggestion> = vec![];
+        for (node, exact) in self.find_nodes(&example) {
+            for (xpath, strategy) in self.candidates(&node) {
+                if suggestions.iter().any(|s| s.xpath == xpath) {
+                    continue;
+                }
+                let mut confidence = match self.validate(&xpath, &example) {
+                    Some(confidence) => confidence * base_confidence(strategy),
+                    None => continue,
+                };
+                if !exact {
+                    confidence *= 0.8;
+                }
+                suggestions.push(SelectorSuggestion {
+                    xpath,
+                    strategy,
+                    confidence,
+                });
+            }
+        }
+        suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    /// Collects every tag whose text contains the example, ancestors included, exact matches first.
+    fn find_nodes(&self, example: &str) -> Vec<(DocumentNode, bool)> {
+        let mut found = vec![];
+        let mut stack = vec![self.doc.root_node];
+        while let Some(node) = stack.pop() {
+            stack.extend(node.children(&self.doc));
+            if self.tag_name(&node).is_none() {
+                continue;
+            }
+            let text = node.get_text(&self.doc).map(|t| normalize(&t)).unwrap_or_default();
+            if text.contains(example) {
+                found.push((node, text == example));
+            }
+        }
+        found.sort_by_key(|(_, exact)| !exact);
+        found
+    }
+
+    /// Builds xpath candidates for a node, from the most to the least robust one.
+    fn candidates(&self, node: &DocumentNode) -> Vec<(String, SelectorStrategy)> {
+        let mut candidates = vec![];
+        let tag = match self.tag_name(node) {
+            Some(tag) => tag,
+            None => return candidates,
+        };
+        let attributes = self.attributes(node);
+
+        if let Some(id) = attributes.get("id").filter(|id| is_stable_token(id)) {
+            candidates.push((format!("//{}[@id='{}']", tag, id), SelectorStrategy::Id));
+        }
+        let mut data_attributes: Vec<_> = attributes
+            .iter()
+            .filter(|(name, value)| name.starts_with("data-") && is_stable_token(value))
+            .collect();
+        data_attributes.sort();
+        for (name, value) in data_attributes {
+            candidates.push((
+                format!("//{}[@{}='{}']", tag, name, value),
+                SelectorStrategy::DataAttribute,
+            ));
+        }
+        for name in STABLE_ATTRIBUTES {
+            if let Some(value) = attributes.get(name).filter(|v| is_stable_token(v)) {
+                candidates.push((
+                    format!("//{}[@{}='{}']", tag, name, value),
+                    SelectorStrategy::StableAttribute,
+                ));
+            }
+        }
+        if let Some(class) = attributes.get("class") {
+            if !class.is_empty() && class.split_whitespace().all(is_stable_token) {
+                candidates.push((
+                    format!("//{}[@class='{}']", tag, class),
+                    SelectorStrategy::SemanticClass,
+                ));
+            }
+        }
+        if let Some(anchored) = self.anchored_path(node) {
+            candidates.push((anchored, SelectorStrategy::AnchoredPath));
+        }
+        candidates.push((self.positional_path(node), SelectorStrategy::Positional));
+        candidates
+    }
+
+    /// Path relative to the closest ancestor with a stable id.
+    fn anchored_path(&self, node: &DocumentNode) -> Option<String> {
+        let mut steps = vec![self.step(node)];
+        let mut current = node.parent(&self.doc);
+        while let Some(ancestor) = current {
+            let tag = self.tag_name(&ancestor)?;
+            if let Some(id) = self.attributes(&ancestor).get("id").filter(|id| is_stable_token(id)) {
+                steps.reverse();
+                return Some(format!("//{}[@id='{}']/{}", tag, id, steps.join("/")));
+            }
+            steps.push(self.step(&ancestor));
+            current = ancestor.parent(&self.doc);
+        }
+        None
+    }
+
+    fn positional_path(&self, node: &DocumentNode) -> String {
+        let mut steps = vec![];
+        let mut current = Some(*node);
+        while let Some(n) = current {
+            if self.tag_name(&n).is_some() {
+                steps.push(self.step(&n));
+            }
+            current = n.parent(&self.doc);
+        }
+        steps.reverse();
+        format!("/{}", steps.join("/"))
+    }
+
+    /// Single path step with a 1-based index among siblings of the same tag.
+    fn step(&self, node: &DocumentNode) -> String {
+        let tag = self.tag_name(node).unwrap_or_default();
+        let parent = match node.parent(&self.doc) {
+            Some(parent) => parent,
+            None => return tag,
+        };
+        let position = parent
+            .children(&self.doc)
+            .filter(|sibling| self.tag_name(sibling).as_deref() == Some(tag.as_str()))
+            .position(|sibling| sibling == *node)
+            .unwrap_or(0);
+        format!("{}[{}]", tag, position + 1)
+    }
+
+    /// Applies xpath to the document and scores it by how precisely it hits the example.
+    fn validate(&self, expr: &str, example: &str) -> Option<f32> {
+        let parsed = match xpath::parse(expr) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::debug!("skipping unsupported xpath {}: {}", expr, e);
+                return None;
+            }
+        };
+        let results = parsed.apply(&self.doc).ok()?;
+        let first = results.first()?;
+        let text = normalize(&first.get_text(&self.doc)?);
+        if !text.contains(example) {
+            return None;
+        }
+        Some(1.0 / results.len() as f32)
+    }
+
+    fn tag_name(&self, node: &DocumentNode) -> Option<String> {
+        match self.doc.get_html_node(node)? {
+            HtmlNode::Tag(tag) => Some(tag.name.clone()),
+            HtmlNode::Text(_) => None,
+        }
+    }
+
+    fn attributes(&self, node: &DocumentNode) -> HashMap<String, String> {
+        match self.doc.get_html_node(node) {
+            Some(HtmlNode::Tag(tag)) => tag.attributes.clone(),
+            _ => HashMap::new(),
+        }
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Heuristic to skip generated tokens like "css-1x9f3a" or "ember1234".
+fn is_stable_token(token: &str) -> bool {
+    let digits = token.chars().filter(|c| c.is_ascii_digit()).count();
+    !token.is_empty() && !token.contains('\'') && token.len() <= 40 && digits * 3 < token.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <div id="product">
+            <h1 itemprop="name">Blue  Widget</h1>
+            <span class="price">19.99 USD</span>
+            <ul><li>first</li><li>second</li></ul>
+        </div>
+    </body></html>"#;
+
+    fn suggester() -> SelectorSuggester {
+        SelectorSuggester::new(PAGE.to_string()).unwrap()
+    }
+
+    #[test]
+    fn prefers_stable_attributes() {
+        let suggestions = suggester().suggest_one("Blue Widget");
+        let best = suggestions.first().unwrap();
+        assert_eq!(best.xpath, "//h1[@itemprop='name']");
+        assert_eq!(best.strategy, SelectorStrategy::StableAttribute);
+        assert!(suggestions.windows(2).all(|w| w[0].confidence >= w[1].confidence));
+    }
+
+    #[test]
+    fn anchors_paths_to_stable_ids() {
+        let suggestions = suggester().suggest_one("second");
+        assert!(suggestions
+            .iter()
+            .any(|s| s.strategy == SelectorStrategy::AnchoredPath && s.xpath.starts_with("//div[@id='product']/")));
+    }
+
+    #[test]
+    fn partial_matches_are_less_confident() {
+        let suggester = suggester();
+        let confidence = |example: &str| {
+            suggester
+                .suggest_one(example)
+                .into_iter()
+                .find(|s| s.xpath == "//span[@class='price']")
+                .map(|s| s.confidence)
+                .unwrap()
+        };
+        assert!(confidence("19.99") < confidence("19.99 USD"));
+    }
+
+    #[test]
+    fn missing_and_empty_examples_have_no_suggestions() {
+        let suggester = suggester();
+        assert!(suggester.suggest_one("not on the page").is_empty());
+        assert!(suggester.suggest_one("   ").is_empty());
+    }
+
+    #[test]
+    fn suggests_every_field() {
+        let examples = HashMap::from([
+            ("title".to_string(), "Blue Widget".to_string()),
+            ("price".to_string(), "19.99 USD".to_string()),
+        ]);
+        let suggestions = suggester().suggest(&examples);
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions.values().all(|s| !s.is_empty() && s.len() <= MAX_SUGGESTIONS));
+    }
+
+    #[test]
+    fn generated_tokens_are_not_stable() {
+        assert!(is_stable_token("product-title"));
+        assert!(!is_stable_token("ember1234"));
+        assert!(!is_stable_token(""));
+        assert!(!is_stable_token("it's"));
+        assert_eq!(normalize("  Blue \n Widget "), "Blue Widget");
+    }
+}
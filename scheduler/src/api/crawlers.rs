@@ -0,0 +1,114 @@
+use actix_web::{delete, get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use common::models::Crawler;
+
+use crate::frontier::{csv_urls, StartUrls};
+use crate::orchestrator::SharedOrchestrator;
+
+/// Uploaded CSVs may be larger than the default payload limit.
+const MAX_CSV_BYTES: usize = 16 * 1024 * 1024;
+const DEFAULT_PREVIEW: usize = 20;
+
+#[derive(Debug, Deserialize)]
+struct PreviewIn {
+    limit: Option<usize>,
+}
+
+fn crawler_or_404(orch: &SharedOrchestrator, crawler_id: Uuid) -> Result<Crawler, HttpResponse> {
+    orch.crawlers
+        .get(crawler_id)
+        .ok_or_else(|| HttpResponse::NotFound().json(json!({"error": format!("crawler {} is not registered", crawler_id)})))
+}
+
+fn internal_error(err: anyhow::Error) -> HttpResponse {
+    tracing::error!("cannot save start pages: {}", err);
+    HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+}
+
+/// Replaces the CSV of start urls with the request body. Its urls are stored apart from the crawler,
+/// every instance takes them when the next run starts.
+async fn upload_start_pages_csv(crawler_id: web::Path<Uuid>, csv: String, orch: web::Data<SharedOrchestrator>) -> HttpResponse {
+    let crawler = match crawler_or_404(&orch, crawler_id.into_inner()) {
+        Ok(crawler) => crawler,
+        Err(resp) => return resp,
+    };
+    let uploaded = csv_urls(&csv);
+    if uploaded.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "the CSV has no urls"}));
+    }
+    let urls = match StartUrls::new(&crawler.site, uploaded.clone()) {
+        Ok(urls) => urls.len(),
+        Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    };
+    match orch.db.set_start_urls(crawler.id, &uploaded).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"start_pages": urls})),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[delete("/crawler/{crawler_id}/start_pages/csv")]
+async fn delete_start_pages_csv(crawler_id: web::Path<Uuid>, orch: web::Data<SharedOrchestrator>) -> HttpResponse {
+    let mut crawler = match crawler_or_404(&orch, crawler_id.into_inner()) {
+        Ok(crawler) => crawler,
+        Err(resp) => return resp,
+    };
+    let urls = match StartUrls::new(&crawler.site, Vec::new()) {
+        Ok(urls) => urls.len(),
+        Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    };
+    if let Err(err) = orch.db.delete_start_urls(crawler.id).await {
+        return internal_error(err);
+    }
+    // crawlers stored before CSVs were kept apart hold theirs
+    if crawler.site.start_pages.csv.take().is_some() {
+        let saved = match orch.db.add_crawler(&crawler).await {
+            Ok(()) => orch.crawlers.update(crawler),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = saved {
+            return internal_error(err);
+        }
+    }
+    HttpResponse::Ok().json(json!({"start_pages": urls}))
+}
+
+/// Start pages the next run expands to, to check lists and templates before it starts.
+/// Only the previewed urls are built.
+#[get("/crawler/{crawler_id}/start_pages")]
+async fn preview_start_pages(
+    crawler_id: web::Path<Uuid>,
+    query: web::Query<PreviewIn>,
+    orch: web::Data<SharedOrchestrator>,
+) -> HttpResponse {
+    let crawler = match crawler_or_404(&orch, crawler_id.into_inner()) {
+        Ok(crawler) => crawler,
+        Err(resp) => return resp,
+    };
+    let uploaded = match orch.db.load_start_urls(crawler.id).await {
+        Ok(uploaded) => uploaded,
+        Err(err) => {
+            tracing::error!("cannot load start pages of crawler {}: {}", crawler.id, err);
+            return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
+        },
+    };
+    match StartUrls::new(&crawler.site, uploaded) {
+        Ok(urls) => HttpResponse::Ok().json(json!({
+            "total": urls.len(),
+            "urls": urls.iter().take(query.limit.unwrap_or(DEFAULT_PREVIEW)).collect::<Vec<_>>(),
+        })),
+        Err(err) => HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/crawler/{crawler_id}/start_pages/csv")
+            .app_data(web::PayloadConfig::new(MAX_CSV_BYTES))
+            .route(web::put().to(upload_start_pages_csv)),
+    )
+    .service(delete_start_pages_csv)
+    .service(preview_start_pages);
+}
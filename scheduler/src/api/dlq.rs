@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::grpc::tokens_match;
+use crate::broker::{ParseraService, SharedBroker};
+
+const DEFAULT_INSPECT_LIMIT: usize = 20;
+
+#[derive(Debug, Deserialize)]
+struct InspectQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeadLetterOut {
+    payload: String,
+    attempts: u32,
+}
+
+/// Bearer token the dead letter endpoints require, the one gRPC clients present as well.
+pub struct DlqToken(pub String);
+
+fn authorize(req: &HttpRequest, token: &DlqToken) -> Result<(), HttpResponse> {
+    let presented = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if tokens_match(presented, &token.0) => Ok(()),
+        _ => Err(HttpResponse::Unauthorized().json(json!({"error": "invalid or missing bearer token"}))),
+    }
+}
+
+fn service_or_404(service: &str) -> Result<ParseraService, HttpResponse> {
+    ParseraService::from_str(service).map_err(|_| {
+        HttpResponse::NotFound().json(json!({"error": format!("unknown service {}", service)}))
+    })
+}
+
+#[get("/dlq/{service}")]
+async fn inspect_dlq(
+    req: HttpRequest,
+    service: web::Path<String>,
+    query: web::Query<InspectQuery>,
+    broker: web::Data<SharedBroker>,
+    token: web::Data<DlqToken>,
+) -> HttpResponse {
+    if let Err(resp) = authorize(&req, &token) {
+        return resp;
+    }
+    let service = match service_or_404(&service) {
+        Ok(service) => service,
+        Err(resp) => return resp,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_INSPECT_LIMIT);
+    match broker.inspect_dead_letters(service, limit).await {
+        Ok(letters) => {
+            let letters: Vec<DeadLetterOut> = letters
+                .into_iter()
+                .map(|letter| DeadLetterOut { payload: letter.payload, attempts: letter.attempts })
+                .collect();
+            HttpResponse::Ok().json(letters)
+        },
+        Err(err) => {
+            tracing::error!("cannot inspect dead letters of {}: {}", service, err);
+            HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+#[post("/dlq/{service}/replay")]
+async fn replay_dlq(
+    req: HttpRequest,
+    service: web::Path<String>,
+    broker: web::Data<SharedBroker>,
+    token: web::Data<DlqToken>,
+) -> HttpResponse {
+    if let Err(resp) = authorize(&req, &token) {
+        return resp;
+    }
+    let service = match service_or_404(&service) {
+        Ok(service) => service,
+        Err(resp) => return resp,
+    };
+    match broker.replay_dead_letters(service).await {
+        Ok(replayed) => HttpResponse::Ok().json(json!({"replayed": replayed})),
+        Err(err) => {
+            tracing::error!("cannot replay dead letters of {}: {}", service, err);
+            HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+#[delete("/dlq/{service}")]
+async fn purge_dlq(
+    req: HttpRequest,
+    service: web::Path<String>,
+    broker: web::Data<SharedBroker>,
+    token: web::Data<DlqToken>,
+) -> HttpResponse {
+    if let Err(resp) = authorize(&req, &token) {
+        return resp;
+    }
+    let service = match service_or_404(&service) {
+        Ok(service) => service,
+        Err(resp) => return resp,
+    };
+    match broker.purge_dead_letters(service).await {
+        Ok(purged) => HttpResponse::Ok().json(json!({"purged": purged})),
+        Err(err) => {
+            tracing::error!("cannot purge dead letters of {}: {}", service, err);
+            HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+/// Dead letter endpoints, every one of them requires the bearer token.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(inspect_dlq).service(replay_dlq).service(purge_dlq);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn lets_through_requests_with_the_token() {
+        let token = DlqToken("secret".to_string());
+        let req = TestRequest::default().insert_header((AUTHORIZATION, "Bearer secret")).to_http_request();
+        assert!(authorize(&req, &token).is_ok());
+    }
+
+    #[test]
+    fn refuses_requests_without_the_token() {
+        let token = DlqToken("secret".to_string());
+        let missing = TestRequest::default().to_http_request();
+        let wrong = TestRequest::default().insert_header((AUTHORIZATION, "Bearer other")).to_http_request();
+        for req in [missing, wrong] {
+            assert_eq!(authorize(&req, &token).unwrap_err().status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+}
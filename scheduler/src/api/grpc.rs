@@ -0,0 +1,291 @@
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use anyhow::Result;
+use tokio::sync::watch;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use common::grpc::scheduler_server::{Scheduler, SchedulerServer};
+use common::grpc::{
+    CancelRunRequest, CancelRunResponse, CrawlerRequest, CrawlerStateResponse, DeleteCrawlerResponse, GetRunRequest, Job, ListJobsRequest,
+    ListJobsResponse, RegisterCrawlerRequest, RegisterCrawlerResponse, ReplayDeadLettersRequest,
+    ReplayDeadLettersResponse, Run,
+};
+use common::models::{Crawler, CrawlerState};
+
+use crate::broker::{Broker, ParseraService, SharedBroker};
+use crate::orchestrator::{self, SharedOrchestrator};
+use crate::schedule::ScheduleError;
+
+/// gRPC API of the scheduler, the api_gateway uses it where it needs an answer right away.
+pub struct SchedulerService {
+    orch: SharedOrchestrator,
+    broker: SharedBroker,
+}
+
+impl SchedulerService {
+    pub fn new(orch: SharedOrchestrator, broker: SharedBroker) -> Self {
+        SchedulerService { orch, broker }
+    }
+}
+
+fn parse_id(id: &str, what: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(id).map_err(|err| Status::invalid_argument(format!("invalid {} {}: {}", what, id, err)))
+}
+
+fn internal(err: anyhow::Error) -> Status {
+    tracing::error!("gRPC request failed: {}", err);
+    Status::internal(err.to_string())
+}
+
+fn grpc_state(state: CrawlerState) -> common::grpc::CrawlerState {
+    match state {
+        CrawlerState::Active => common::grpc::CrawlerState::Active,
+        CrawlerState::Paused => common::grpc::CrawlerState::Paused,
+    }
+}
+
+impl SchedulerService {
+    /// Crawlers are looked up in the database, they may be registered through another instance.
+    async fn registered(&self, crawler_id: &str) -> Result<Uuid, Status> {
+        let crawler_id = parse_id(crawler_id, "crawler id")?;
+        match self.orch.db.load_crawler(crawler_id).await.map_err(internal)? {
+            Some(_) => Ok(crawler_id),
+            None => Err(Status::not_found(format!("crawler {} is not registered", crawler_id))),
+        }
+    }
+}
+
+/// Moves dead letters of the named service back to its queue.
+async fn replay_dead_letters(broker: &dyn Broker, service: &str) -> Result<ReplayDeadLettersResponse, Status> {
+    let service = ParseraService::from_str(service)
+        .map_err(|_| Status::invalid_argument(format!("unknown service {}", service)))?;
+    let replayed = broker.replay_dead_letters(service).await.map_err(|err| internal(err.into()))?;
+    tracing::info!("replayed {} dead letters of {}", replayed, service);
+    Ok(ReplayDeadLettersResponse { replayed: replayed as u64 })
+}
+
+fn crawler_state(crawler_id: Uuid, state: CrawlerState) -> CrawlerStateResponse {
+    CrawlerStateResponse {
+        crawler_id: crawler_id.to_string(),
+        state: grpc_state(state).into(),
+    }
+}
+
+#[tonic::async_trait]
+impl Scheduler for SchedulerService {
+    async fn register_crawler(&self, request: Request<RegisterCrawlerRequest>) -> Result<Response<RegisterCrawlerResponse>, Status> {
+        let crawler = request
+            .into_inner()
+            .crawler
+            .ok_or_else(|| Status::invalid_argument("crawler is missing"))?;
+        let crawler = Crawler::try_from(crawler).map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let crawler_id = crawler.id;
+        let run_id = orchestrator::register_crawler(self.broker.as_ref(), &self.orch, crawler, Uuid::now_v7())
+            .await
+            .map_err(|err| match err.downcast_ref::<ScheduleError>() {
+                Some(err) => Status::invalid_argument(err.to_string()),
+                None => internal(err),
+            })?;
+        Ok(Response::new(RegisterCrawlerResponse {
+            crawler_id: crawler_id.to_string(),
+            run_id: run_id.to_string(),
+        }))
+    }
+
+    async fn list_jobs(&self, request: Request<ListJobsRequest>) -> Result<Response<ListJobsResponse>, Status> {
+        let user_id = match request.into_inner().user_id.as_str() {
+            "" => None,
+            user_id => Some(parse_id(user_id, "user id")?),
+        };
+        let mut jobs = Vec::new();
+        for (crawler, job_id) in self.orch.crawlers.list() {
+            if user_id.is_some_and(|user_id| user_id != crawler.user_id) {
+                continue;
+            }
+            let next_run_at = match job_id {
+                Some(job_id) => self
+                    .orch
+                    .sched
+                    .lock()
+                    .await
+                    .next_tick_for_job(job_id)
+                    .await
+                    .map_err(|err| internal(err.into()))?
+                    .map(|at| at.timestamp()),
+                None => None,
+            };
+            let state = match job_id {
+                Some(_) => CrawlerState::Active,
+                None => CrawlerState::Paused,
+            };
+            jobs.push(Job {
+                crawler_id: crawler.id.to_string(),
+                user_id: crawler.user_id.to_string(),
+                name: crawler.name,
+                rule: crawler.timer_rule,
+                state: grpc_state(state).into(),
+                next_run_at,
+            });
+        }
+        Ok(Response::new(ListJobsResponse { jobs }))
+    }
+
+    async fn pause_crawler(&self, request: Request<CrawlerRequest>) -> Result<Response<CrawlerStateResponse>, Status> {
+        let crawler_id = self.registered(&request.into_inner().crawler_id).await?;
+        orchestrator::pause_crawler(&self.orch, crawler_id).await.map_err(internal)?;
+        Ok(Response::new(crawler_state(crawler_id, CrawlerState::Paused)))
+    }
+
+    async fn resume_crawler(&self, request: Request<CrawlerRequest>) -> Result<Response<CrawlerStateResponse>, Status> {
+        let crawler_id = self.registered(&request.into_inner().crawler_id).await?;
+        orchestrator::resume_crawler(&self.orch, crawler_id).await.map_err(internal)?;
+        Ok(Response::new(crawler_state(crawler_id, CrawlerState::Active)))
+    }
+
+    async fn delete_crawler(&self, request: Request<CrawlerRequest>) -> Result<Response<DeleteCrawlerResponse>, Status> {
+        let crawler_id = parse_id(&request.into_inner().crawler_id, "crawler id")?;
+        let crawler = orchestrator::delete_crawler(&self.orch, crawler_id)
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found(format!("crawler {} is not registered", crawler_id)))?;
+        Ok(Response::new(DeleteCrawlerResponse {
+            crawler_id: crawler_id.to_string(),
+            user_id: crawler.user_id.to_string(),
+        }))
+    }
+
+    async fn get_run(&self, request: Request<GetRunRequest>) -> Result<Response<Run>, Status> {
+        let run_id = parse_id(&request.into_inner().run_id, "run id")?;
+        let run = self
+            .orch
+            .db
+            .get_run(run_id)
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found(format!("run {} is not found", run_id)))?;
+        Ok(Response::new(Run {
+            id: run.id.to_string(),
+            crawler_id: run.crawler_id.to_string(),
+            status: run.status.to_string(),
+            dispatched: run.stats.dispatched,
+            scraped: run.stats.scraped,
+            extracted: run.stats.extracted,
+            failed: run.stats.failed,
+            skipped: run.stats.skipped,
+            started_at: run.started_at.timestamp(),
+            finished_at: run.finished_at.map(|at| at.timestamp()),
+            bytes: run.stats.bytes,
+            stop_reason: run.stop_reason.map(|reason| reason.to_string()),
+        }))
+    }
+
+    async fn cancel_run(&self, request: Request<CancelRunRequest>) -> Result<Response<CancelRunResponse>, Status> {
+        let run_id = parse_id(&request.into_inner().run_id, "run id")?;
+        if self.orch.db.get_run(run_id).await.map_err(internal)?.is_none() {
+            return Err(Status::not_found(format!("run {} is not found", run_id)));
+        }
+        let cancelled = orchestrator::cancel_run(&self.orch, run_id).await.map_err(internal)?;
+        Ok(Response::new(CancelRunResponse { run_id: run_id.to_string(), cancelled }))
+    }
+
+    async fn replay_dead_letters(
+        &self,
+        request: Request<ReplayDeadLettersRequest>,
+    ) -> Result<Response<ReplayDeadLettersResponse>, Status> {
+        let replayed = replay_dead_letters(self.broker.as_ref(), &request.into_inner().service).await?;
+        Ok(Response::new(replayed))
+    }
+}
+
+/// Lets through requests with the `authorization: Bearer <token>` metadata entry.
+fn check_token(token: &str, request: Request<()>) -> Result<Request<()>, Status> {
+    let presented = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if tokens_match(presented, token) => Ok(request),
+        _ => Err(Status::unauthenticated("invalid or missing bearer token")),
+    }
+}
+
+/// Compares in time independent of where the tokens differ.
+pub(crate) fn tokens_match(presented: &str, token: &str) -> bool {
+    presented.len() == token.len()
+        && presented.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Serves the gRPC API until shutdown is signalled, requests in flight are finished first.
+/// Requests without the token are refused.
+pub async fn serve_grpc(
+    addr: SocketAddr,
+    token: String,
+    orch: SharedOrchestrator,
+    broker: SharedBroker,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    tracing::info!("Starting gRPC server on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(SchedulerServer::with_interceptor(
+            SchedulerService::new(orch, broker),
+            move |request| check_token(&token, request),
+        ))
+        .serve_with_shutdown(addr, async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::broker::MemoryBroker;
+    use tonic::Code;
+
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request.metadata_mut().insert("authorization", authorization.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn lets_through_requests_with_the_token() {
+        assert!(check_token("secret", request(Some("Bearer secret"))).is_ok());
+    }
+
+    #[test]
+    fn refuses_requests_without_the_token() {
+        for authorization in [None, Some("Bearer other"), Some("Bearer secre"), Some("secret"), Some("Bearer ")] {
+            let err = check_token("secret", request(authorization)).unwrap_err();
+            assert_eq!(err.code(), Code::Unauthenticated, "{:?}", authorization);
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_dead_letters_of_the_service() {
+        let broker = MemoryBroker::default();
+        let mut subscription = broker.subscribe(ParseraService::Scraper).await.unwrap();
+        broker.publish(b"failed", ParseraService::Scraper).await.unwrap();
+        subscription.next().await.unwrap().park().await.unwrap();
+
+        let replayed = replay_dead_letters(&broker, "scraper").await.unwrap();
+        assert_eq!(replayed.replayed, 1);
+        assert_eq!(broker.queued(ParseraService::Scraper), vec![b"failed".to_vec()]);
+        assert_eq!(replay_dead_letters(&broker, "scraper").await.unwrap().replayed, 0);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_replay_unknown_services() {
+        let err = replay_dead_letters(&MemoryBroker::default(), "nobody").await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+}
@@ -1,4 +1,10 @@
 
-mod api;
+mod server;
+mod crawlers;
+mod dlq;
+mod grpc;
+mod routines;
+mod schedule;
 
-pub use api::*;
\ No newline at end of file
+pub use server::*;
+pub use grpc::*;
\ No newline at end of file
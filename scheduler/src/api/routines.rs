@@ -0,0 +1,77 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use tracing_actix_web::RequestId;
+use uuid::Uuid;
+
+use crate::jobs::{RoutineError, RoutineSpec, SharedRoutines};
+
+#[derive(Debug, Deserialize)]
+struct RuleIn {
+    rule: String,
+}
+
+fn error_response(err: RoutineError) -> HttpResponse {
+    let body = json!({"error": err.to_string()});
+    match err {
+        RoutineError::InvalidRule(_) => HttpResponse::BadRequest().json(body),
+        RoutineError::NotFound(_) => HttpResponse::NotFound().json(body),
+        RoutineError::Scheduler(_) => {
+            tracing::error!("routine request failed: {}", err);
+            HttpResponse::InternalServerError().json(body)
+        },
+    }
+}
+
+#[post("/routine")]
+async fn add_routine(routine: web::Json<RoutineSpec>, routines: web::Data<SharedRoutines>, req_id: RequestId) -> HttpResponse {
+    tracing::debug!("Adding a new routine for {}: {:?}", req_id, routine);
+    match routines.add(routine.into_inner()).await {
+        Ok(routine) => HttpResponse::Created().json(routine),
+        Err(err) => error_response(err),
+    }
+}
+
+#[get("/routines")]
+async fn get_routines(routines: web::Data<SharedRoutines>) -> HttpResponse {
+    match routines.list().await {
+        Ok(routines) => HttpResponse::Ok().json(routines),
+        Err(err) => error_response(err),
+    }
+}
+
+#[get("/routine/{routine_id}")]
+async fn get_routine(routine_id: web::Path<Uuid>, routines: web::Data<SharedRoutines>) -> HttpResponse {
+    match routines.get(routine_id.into_inner()).await {
+        Ok(routine) => HttpResponse::Ok().json(routine),
+        Err(err) => error_response(err),
+    }
+}
+
+#[put("/routine/{routine_id}")]
+async fn update_routine(
+    routine_id: web::Path<Uuid>,
+    rule: web::Json<RuleIn>,
+    routines: web::Data<SharedRoutines>,
+) -> HttpResponse {
+    match routines.update_rule(routine_id.into_inner(), rule.into_inner().rule).await {
+        Ok(routine) => HttpResponse::Ok().json(routine),
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/routine/{routine_id}")]
+async fn delete_routine(routine_id: web::Path<Uuid>, routines: web::Data<SharedRoutines>) -> HttpResponse {
+    match routines.remove(routine_id.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => error_response(err),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(add_routine)
+        .service(get_routines)
+        .service(get_routine)
+        .service(update_routine)
+        .service(delete_routine);
+}
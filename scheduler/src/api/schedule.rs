@@ -0,0 +1,41 @@
+use actix_web::{get, web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::schedule::Schedule;
+
+const MAX_PREVIEW: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct PreviewIn {
+    rule: String,
+    count: Option<usize>,
+    /// Crawler or routine id, picks the jitter offset. Without it fires are shown at the start of the window.
+    key: Option<Uuid>,
+}
+
+/// Normalized rule and the next fire times, to check a rule before a crawler or a routine gets it.
+#[get("/schedule/preview")]
+async fn preview_schedule(query: web::Query<PreviewIn>) -> HttpResponse {
+    let schedule: Schedule = match query.rule.parse() {
+        Ok(schedule) => schedule,
+        Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    };
+    let count = query.count.unwrap_or(5).min(MAX_PREVIEW);
+    let key = query.key.unwrap_or(Uuid::nil());
+    let next_runs = match schedule.next_runs(key, Utc::now(), count) {
+        Ok(next_runs) => next_runs,
+        Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    };
+    HttpResponse::Ok().json(json!({
+        "rule": schedule.to_string(),
+        "jitter_offset_ms": schedule.offset(key).as_millis() as u64,
+        "next_runs": next_runs,
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(preview_schedule);
+}
@@ -0,0 +1,40 @@
+// #![allow(dead_code,unused)]
+use actix_web::dev::Server;
+use actix_web::{get, web, App, HttpServer};
+use anyhow::Result;
+use tracing_actix_web::TracingLogger;
+
+use crate::api::{crawlers, dlq, routines, schedule};
+use crate::broker::SharedBroker;
+use crate::config::Config;
+use crate::jobs::SharedRoutines;
+use crate::orchestrator::SharedOrchestrator;
+
+#[get("/healthcheck")]
+async fn get_healthcheck() -> &'static str {
+    tracing::debug!("got healthcheck");
+    "ok"
+}
+
+/// Binds the web server. Signals are left to the caller, which stops the server on shutdown.
+pub fn server(cfg: &Config, routines: SharedRoutines, broker: SharedBroker, orch: SharedOrchestrator) -> Result<Server> {
+    tracing::info!("Starting web server on {}", cfg.get_socket_addr());
+    let token = cfg.grpc_token.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .app_data(web::Data::new(routines.clone()))
+            .app_data(web::Data::new(broker.clone()))
+            .app_data(web::Data::new(orch.clone()))
+            .app_data(web::Data::new(dlq::DlqToken(token.clone())))
+            .configure(crawlers::configure)
+            .configure(dlq::configure)
+            .configure(routines::configure)
+            .configure(schedule::configure)
+            .service(get_healthcheck)
+    })
+    .disable_signals()
+    .bind(cfg.get_socket_addr())?
+    .run();
+    Ok(server)
+}
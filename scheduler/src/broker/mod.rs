@@ -1,14 +1,53 @@
+use std::sync::Arc;
+
 use anyhow::Result;
+use tokio::sync::watch;
 
-pub use broker::*;
+pub use common::broker::{Broker, ParseraService};
 
-use crate::utils::ParseraService;
+use crate::config::BrokerConfig;
+use crate::orchestrator::{self, HandleError, SharedOrchestrator};
 
-mod broker;
+pub type SharedBroker = Arc<dyn Broker>;
 
+/// Connects to the configured broker and declares the topology shared by all services.
+pub async fn connect(cfg: &BrokerConfig) -> Result<SharedBroker> {
+    Ok(common::broker::connect(cfg.settings()).await?)
+}
 
-// TODO: finish this
-pub trait Broker {
-    async fn publish(payload: &[u8], to: ParseraService) -> Result<()>;
-    async fn consume() -> Result<()>;
-}
\ No newline at end of file
+/// Handles events sent to the scheduler. Failed events are retried through the retry queue,
+/// malformed ones and those out of attempts are parked in the dead letter queue.
+/// Events claimed by another delivery wait in the retry queue without using up attempts.
+/// Stops taking new events once shutdown is signalled, the event in hand is handled and settled first.
+pub async fn consume(broker: &dyn Broker, orch: SharedOrchestrator, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+    let mut subscription = broker.subscribe(ParseraService::Scheduler).await?;
+    loop {
+        let delivery = tokio::select! {
+            biased;
+            _ = shutdown.wait_for(|stop| *stop) => break,
+            delivery = subscription.next() => match delivery {
+                Some(delivery) => delivery,
+                None => break,
+            },
+        };
+        let settled = match orchestrator::handle_event(broker, &orch, &delivery.payload).await {
+            Ok(()) => delivery.ack().await,
+            Err(HandleError::Malformed(err)) => {
+                tracing::error!("parking malformed message: {}", err);
+                delivery.park().await
+            },
+            Err(HandleError::Failed(err)) => {
+                tracing::warn!("cannot handle event: {}", err);
+                delivery.retry().await
+            },
+            Err(HandleError::Busy(err)) => {
+                tracing::info!("requeueing event: {}", err);
+                delivery.requeue().await
+            },
+        };
+        if let Err(err) = settled {
+            tracing::error!("cannot settle a delivery: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::Script;
+
+use crate::config::ClusterConfig;
+
+const LEADER_KEY: &str = "scheduler:leader";
+
+/// Extends the lease only if it is still held by this instance.
+const RENEW_SCRIPT: &str = r#"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('pexpire', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('del', KEYS[1])
+end
+return 0
+"#;
+
+/// Leader election through a lease in redis. Every scheduler instance consumes events,
+/// only the leader fires cron jobs. A leader that stops renewing loses the lease once it
+/// expires and another instance takes over.
+pub struct Leadership {
+    conn: ConnectionManager,
+    instance_id: String,
+    lease_ms: u64,
+    leader: AtomicBool,
+}
+
+impl Leadership {
+    pub fn new(conn: ConnectionManager, cluster: &ClusterConfig) -> Self {
+        Leadership {
+            conn,
+            instance_id: cluster.instance_id(),
+            lease_ms: cluster.leader_lease_ms,
+            leader: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    /// Renews the lease held by this instance or tries to take a free one.
+    /// Returns whether this instance is the leader until the next campaign.
+    pub async fn campaign(&self) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        let leader = match self.is_leader() {
+            true => {
+                let renewed: i64 = Script::new(RENEW_SCRIPT)
+                    .key(LEADER_KEY)
+                    .arg(&self.instance_id)
+                    .arg(self.lease_ms)
+                    .invoke_async(&mut conn)
+                    .await?;
+                renewed == 1
+            }
+            false => {
+                let taken: Option<String> = redis::cmd("SET")
+                    .arg(LEADER_KEY)
+                    .arg(&self.instance_id)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(self.lease_ms)
+                    .query_async(&mut conn)
+                    .await?;
+                taken.is_some()
+            }
+        };
+        self.set_leader(leader);
+        Ok(leader)
+    }
+
+    /// Gives up the lease, so another instance doesn't wait for it to expire.
+    pub async fn resign(&self) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: i64 = Script::new(RELEASE_SCRIPT)
+            .key(LEADER_KEY)
+            .arg(&self.instance_id)
+            .invoke_async(&mut conn)
+            .await?;
+        self.set_leader(false);
+        Ok(())
+    }
+
+    /// Steps down without redis, used when the lease cannot be renewed in time.
+    pub fn step_down(&self) {
+        self.set_leader(false);
+    }
+
+    fn set_leader(&self, leader: bool) {
+        if self.leader.swap(leader, Ordering::SeqCst) != leader {
+            match leader {
+                true => tracing::info!("instance {} is the leader now", self.instance_id),
+                false => tracing::warn!("instance {} is not the leader anymore", self.instance_id),
+            }
+        }
+    }
+}
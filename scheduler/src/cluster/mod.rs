@@ -0,0 +1,5 @@
+mod lease;
+mod processed;
+
+pub use lease::*;
+pub use processed::*;
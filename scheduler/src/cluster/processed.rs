@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use uuid::Uuid;
+
+use crate::config::ClusterConfig;
+
+const DONE: &str = "done";
+const IN_PROGRESS: &str = "in_progress";
+/// Renewals of a claim within its expiry, so one failed renewal doesn't lose it.
+const RENEWALS_PER_CLAIM: u64 = 3;
+
+/// Extends the claim if it's still the one taken by this handler.
+/// KEYS: event. ARGV: claim, claim expiry in ms.
+const RENEW_SCRIPT: &str = r#"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('pexpire', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+fn processed_key(event_id: Uuid) -> String {
+    format!("scheduler:event:{}", event_id)
+}
+
+/// Result of claiming an event before handling it.
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    /// Nobody handled the event yet, it's ours now. Holds the claim to keep it with.
+    New(String),
+    /// Another delivery of the event is being handled right now.
+    InProgress,
+    /// The event is handled already, the delivery is a duplicate.
+    Done,
+}
+
+/// Ids of events handled by any scheduler instance. Brokers deliver at least once and a failover
+/// redelivers unacked events, so every event is claimed by its id before it's handled.
+/// A claim of an instance that died mid-handling expires, and the event can be handled again.
+/// Claims of events handled for long are renewed while their handler runs.
+pub struct ProcessedEvents {
+    conn: ConnectionManager,
+    claim_ms: u64,
+    done_secs: u64,
+}
+
+impl ProcessedEvents {
+    pub fn new(conn: ConnectionManager, cluster: &ClusterConfig) -> Self {
+        ProcessedEvents {
+            conn,
+            claim_ms: cluster.event_claim_ms,
+            done_secs: cluster.processed_event_ttl_secs,
+        }
+    }
+
+    pub async fn claim(&self, event_id: Uuid) -> Result<Claim> {
+        let mut conn = self.conn.clone();
+        let key = processed_key(event_id);
+        let claim = format!("{}:{}", IN_PROGRESS, Uuid::now_v7());
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&claim)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.claim_ms)
+            .query_async(&mut conn)
+            .await?;
+        if claimed.is_some() {
+            return Ok(Claim::New(claim));
+        }
+        let state: Option<String> = conn.get(&key).await?;
+        Ok(match state.as_deref() {
+            Some(DONE) => Claim::Done,
+            Some(_) => Claim::InProgress,
+            // the claim expired in between, the next delivery takes it
+            None => Claim::InProgress,
+        })
+    }
+
+    /// Renews the claim until it's taken over, e.g. after redis lost it. Never returns while the claim is held,
+    /// so it's raced against the handler of the event.
+    pub async fn keep(&self, event_id: Uuid, claim: &str) {
+        let every = Duration::from_millis((self.claim_ms / RENEWALS_PER_CLAIM).max(1));
+        let key = processed_key(event_id);
+        loop {
+            tokio::time::sleep(every).await;
+            let mut conn = self.conn.clone();
+            let renewed: Result<i64, _> = Script::new(RENEW_SCRIPT)
+                .key(&key)
+                .arg(claim)
+                .arg(self.claim_ms)
+                .invoke_async(&mut conn)
+                .await;
+            match renewed {
+                Ok(1) => (),
+                Ok(_) => return,
+                Err(err) => tracing::warn!("cannot renew the claim of event {}: {}", event_id, err),
+            }
+        }
+    }
+
+    pub async fn done(&self, event_id: Uuid) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.set_ex(processed_key(event_id), DONE, self.done_secs).await?;
+        Ok(())
+    }
+
+    /// Drops the claim of a failed event so its retry is handled.
+    pub async fn release(&self, event_id: Uuid) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.del(processed_key(event_id)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use envconfig::Envconfig;
+
+    use super::*;
+    use crate::testing;
+
+    async fn processed(claim_ms: u64) -> ProcessedEvents {
+        let mut cluster = ClusterConfig::init_from_env().unwrap();
+        cluster.event_claim_ms = claim_ms;
+        ProcessedEvents::new(testing::redis().await, &cluster)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn kept_claims_outlast_their_expiry() {
+        let processed = processed(300).await;
+        let event_id = Uuid::now_v7();
+        let Claim::New(claim) = processed.claim(event_id).await.unwrap() else {
+            panic!("a new event is claimed already");
+        };
+
+        let kept = tokio::time::timeout(Duration::from_millis(900), processed.keep(event_id, &claim)).await;
+
+        assert!(kept.is_err(), "the claim is lost while it's kept");
+        assert_eq!(processed.claim(event_id).await.unwrap(), Claim::InProgress);
+        processed.done(event_id).await.unwrap();
+        assert_eq!(processed.claim(event_id).await.unwrap(), Claim::Done);
+    }
+}
@@ -5,10 +5,9 @@ use std::{
 };
 
 use envconfig::Envconfig;
-use tracing;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::utils::ParseraService;
+use common::broker::{BrokerBackend, BrokerSettings, KafkaSettings, RabbitSettings, Topology};
 
 pub trait DbAddr {
     fn get_addr(&self) -> String;
@@ -26,6 +25,8 @@ pub struct DatabaseConfig {
     pub db: String,
     #[envconfig(from = "POSTGRES_USER", default = "postgres")]
     pub user: String,
+    #[envconfig(from = "POSTGRES_POOL_MAX_SIZE", default = "10")]
+    pub pool_max_size: u32,
 }
 
 impl DbAddr for DatabaseConfig {
@@ -37,6 +38,101 @@ impl DbAddr for DatabaseConfig {
     }
 }
 
+#[derive(Envconfig, Clone, Debug)]
+pub struct RedisConfig {
+    #[envconfig(from = "REDIS_HOST", default = "localhost")]
+    pub host: String,
+    #[envconfig(from = "REDIS_PORT", default = "6379")]
+    pub port: u16,
+    #[envconfig(from = "REDIS_PASSWORD", default = "")]
+    pub password: String,
+}
+
+impl DbAddr for RedisConfig {
+    fn get_addr(&self) -> String {
+        format!("redis://default:{}@{}:{}/", self.password, self.host, self.port)
+    }
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct ClusterConfig {
+    /// Name of the instance in the leader lease, a random one when empty.
+    #[envconfig(from = "INSTANCE_ID", default = "")]
+    pub instance_id: String,
+    /// The leader renews its lease three times per lease period.
+    #[envconfig(from = "LEADER_LEASE_MS", default = "15000")]
+    pub leader_lease_ms: u64,
+    /// How long an event being handled is claimed by an instance. Other deliveries of the event
+    /// are requeued meanwhile, so it may outlast the retries of a failing event.
+    /// The claim is renewed three times per period while the event is handled.
+    #[envconfig(from = "EVENT_CLAIM_MS", default = "60000")]
+    pub event_claim_ms: u64,
+    /// How long ids of handled events are kept to drop duplicates.
+    #[envconfig(from = "PROCESSED_EVENT_TTL_SECS", default = "86400")]
+    pub processed_event_ttl_secs: u64,
+}
+
+impl ClusterConfig {
+    pub fn instance_id(&self) -> String {
+        match self.instance_id.is_empty() {
+            true => uuid::Uuid::now_v7().to_string(),
+            false => self.instance_id.clone(),
+        }
+    }
+
+    pub fn renew_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.leader_lease_ms / 3)
+    }
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct FrontierConfig {
+    /// Query params dropped from urls, a trailing `*` matches any suffix.
+    #[envconfig(from = "FRONTIER_TRACKING_PARAMS", default = "utm_*,gclid,fbclid,msclkid,yclid,mc_cid,mc_eid,_ga,_hsenc,_hsmi")]
+    pub tracking_params: String,
+    /// How long urls seen by a run are kept after the last one is added.
+    #[envconfig(from = "FRONTIER_SEEN_TTL_SECS", default = "604800")]
+    pub seen_ttl_secs: u64,
+}
+
+#[derive(Envconfig, Clone, Debug)]
+pub struct NotificationConfig {
+    /// "scheduler" sends notifications from here, "service" leaves them to the notification service queue.
+    #[envconfig(from = "NOTIFICATION_SENDER", default = "scheduler")]
+    pub sender: String,
+    #[envconfig(from = "SMTP_HOST", default = "localhost")]
+    pub smtp_host: String,
+    #[envconfig(from = "SMTP_PORT", default = "1025")]
+    pub smtp_port: u16,
+    #[envconfig(from = "SMTP_USER", default = "")]
+    pub smtp_user: String,
+    #[envconfig(from = "SMTP_PASSWORD", default = "")]
+    pub smtp_password: String,
+    #[envconfig(from = "SMTP_TLS", default = "false")]
+    pub smtp_tls: bool,
+    #[envconfig(from = "SMTP_FROM", default = "Parsera <noreply@parsera.site>")]
+    pub smtp_from: String,
+    #[envconfig(from = "TELEGRAM_API_URL", default = "https://api.telegram.org")]
+    pub telegram_api_url: String,
+    #[envconfig(from = "TELEGRAM_BOT_TOKEN", default = "")]
+    pub telegram_bot_token: String,
+    #[envconfig(from = "WEBHOOK_TIMEOUT_MS", default = "10000")]
+    pub webhook_timeout_ms: u64,
+    #[envconfig(from = "WEBHOOK_MAX_ATTEMPTS", default = "8")]
+    pub webhook_max_attempts: i32,
+    #[envconfig(from = "WEBHOOK_RETRY_BASE_DELAY_MS", default = "30000")]
+    pub webhook_retry_base_delay_ms: i64,
+    /// Lets webhooks reach loopback and private networks, for local development only.
+    #[envconfig(from = "WEBHOOK_ALLOW_PRIVATE_TARGETS", default = "false")]
+    pub webhook_allow_private_targets: bool,
+}
+
+impl NotificationConfig {
+    pub fn is_sent_by_scheduler(&self) -> bool {
+        self.sender == "scheduler"
+    }
+}
+
 #[derive(Envconfig, Clone, Debug)]
 pub struct BrokerConfig {
     #[envconfig(from = "RABBITMQ_HOST")]
@@ -49,10 +145,8 @@ pub struct BrokerConfig {
     pub password: String,
     #[envconfig(from = "RABBITMQ_VHOST")]
     pub vhost: String,
-    #[envconfig(from = "RABBITMQ_POOL_MAX_SIZE", default = "16")]
-    pub pool_max_size: u16,
-    #[envconfig(from = "RABBITMQ_POOL_TIMEOUT", default = "2")]
-    pub pool_timeout: u16,
+    #[envconfig(from = "RABBITMQ_PREFETCH_COUNT", default = "16")]
+    pub prefetch_count: u16,
     #[envconfig(from = "RABBITMQ_CONSUME_EXCHANGE", default = "to_scheduler")]
     pub consume_exchange: String,
     #[envconfig(from = "RABBITMQ_PRODUCE_EXCHANGE", default = "from_scheduler")]
@@ -71,24 +165,55 @@ pub struct BrokerConfig {
     pub db_manager_queue: String,
     #[envconfig(from = "RABBITMQ_STATUS_MANAGER_QUEUE")]
     pub status_manager_queue: String,
+    #[envconfig(from = "RABBITMQ_MAX_ATTEMPTS", default = "5")]
+    pub max_attempts: u32,
+    #[envconfig(from = "RABBITMQ_RETRY_DELAY_MS", default = "5000")]
+    pub retry_delay_ms: u32,
+    #[envconfig(from = "SCRAPE_RETRY_BASE_DELAY_MS", default = "10000")]
+    pub scrape_retry_base_delay_ms: u32,
+    #[envconfig(from = "SCRAPE_RETRY_LEVELS", default = "5")]
+    pub scrape_retry_levels: u32,
+    #[envconfig(from = "BROKER_BACKEND", default = "rabbitmq")]
+    pub backend: BrokerBackend,
+    #[envconfig(from = "KAFKA_BROKERS", default = "localhost:9092")]
+    pub kafka_brokers: String,
+    #[envconfig(from = "KAFKA_PARTITIONS", default = "12")]
+    pub kafka_partitions: i32,
+    #[envconfig(from = "KAFKA_REPLICATION_FACTOR", default = "1")]
+    pub kafka_replication_factor: i32,
 }
 
 impl BrokerConfig {
-    // TODO: remove this mock
-    pub fn queues_to_produce(&self) -> Vec<&str> {
-        // self.produce_queues.split(',').map(|q| q.to_string()).collect()
-        vec![
-            &self.scraper_queue,
-            &self.heavy_artillery_queue,
-            &self.extractor_queue,
-            &self.notification_queue,
-            &self.db_manager_queue,
-            &self.status_manager_queue,
-        ]
-    }
-
-    pub fn queue_to_consume(&self) -> String {
-        self.consume_queue.clone()
+    pub fn settings(&self) -> BrokerSettings {
+        let topology = Topology {
+            scheduler_exchange: self.consume_exchange.clone(),
+            services_exchange: self.produce_exchange.clone(),
+            scheduler_queue: self.consume_queue.clone(),
+            scraper_queue: self.scraper_queue.clone(),
+            heavy_artillery_queue: self.heavy_artillery_queue.clone(),
+            extractor_queue: self.extractor_queue.clone(),
+            notification_queue: self.notification_queue.clone(),
+            db_manager_queue: self.db_manager_queue.clone(),
+            status_manager_queue: self.status_manager_queue.clone(),
+            max_attempts: self.max_attempts,
+            retry_delay_ms: self.retry_delay_ms,
+            delay_base_ms: self.scrape_retry_base_delay_ms,
+            delay_levels: self.scrape_retry_levels,
+        };
+        BrokerSettings {
+            backend: self.backend,
+            rabbit: RabbitSettings {
+                url: self.get_addr(),
+                topology: topology.clone(),
+                prefetch_count: self.prefetch_count,
+            },
+            kafka: KafkaSettings {
+                brokers: self.kafka_brokers.clone(),
+                topology,
+                partitions: self.kafka_partitions,
+                replication_factor: self.kafka_replication_factor,
+            },
+        }
     }
 }
 
@@ -96,9 +221,7 @@ impl DbAddr for BrokerConfig {
     fn get_addr(&self) -> String {
         let vhost = {
             if self.vhost.starts_with("/") {
-                let mut vhost = self.vhost.clone();
-                vhost.replace("/", "%2f");
-                vhost
+                self.vhost.replace("/", "%2f")
             } else {
                 self.vhost.clone()
             }
@@ -116,12 +239,43 @@ pub struct Config {
     pub database: DatabaseConfig,
     #[envconfig(nested = true)]
     pub broker: BrokerConfig,
-    #[envconfig(from = "LOG_FORMAT", default = "text")]
-    pub log_format: String,
+    #[envconfig(nested = true)]
+    pub redis: RedisConfig,
+    #[envconfig(nested = true)]
+    pub notification: NotificationConfig,
+    #[envconfig(nested = true)]
+    pub cluster: ClusterConfig,
+    #[envconfig(nested = true)]
+    pub frontier: FrontierConfig,
     #[envconfig(from = "HOST", default = "localhost")]
     pub host: String,
     #[envconfig(from = "PORT", default = "8080")]
     pub port: u16,
+    #[envconfig(from = "GRPC_PORT", default = "50051")]
+    pub grpc_port: u16,
+    /// Bearer token gRPC clients have to present, shared with the api_gateway.
+    #[envconfig(from = "GRPC_TOKEN")]
+    pub grpc_token: String,
+    #[envconfig(from = "FAIR_SHARE_MAX_IN_FLIGHT", default = "200")]
+    pub fair_share_max_in_flight: usize,
+    /// Slots of pages whose outcome never comes back are freed after this long.
+    #[envconfig(from = "FAIR_SHARE_SLOT_TTL_MS", default = "1800000")]
+    pub fair_share_slot_ttl_ms: u64,
+    /// How long the event in hand may take to be handled on shutdown.
+    #[envconfig(from = "SHUTDOWN_DRAIN_TIMEOUT_MS", default = "30000")]
+    pub shutdown_drain_timeout_ms: u64,
+    /// Pages sent to scrapers in one message, 1 turns batching off.
+    #[envconfig(from = "SCRAPE_BATCH_SIZE", default = "1")]
+    pub scrape_batch_size: usize,
+    /// Partial batches are sent after waiting this long for more pages.
+    #[envconfig(from = "SCRAPE_BATCH_LINGER_MS", default = "1000")]
+    pub scrape_batch_linger_ms: u64,
+    #[envconfig(from = "QUOTA_RESUME_RULE", default = "0 */10 * * * *")]
+    pub quota_resume_rule: String,
+    #[envconfig(from = "DIGEST_CHECK_RULE", default = "0 */5 * * * *")]
+    pub digest_check_rule: String,
+    #[envconfig(from = "WEBHOOK_RETRY_RULE", default = "*/30 * * * * *")]
+    pub webhook_retry_rule: String,
 }
 
 impl Config {
@@ -172,6 +326,11 @@ impl Config {
         }
     }
 
+    /// The gRPC server listens on the same host as the web server.
+    pub fn get_grpc_socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.get_socket_addr().ip(), self.grpc_port)
+    }
+
     pub fn get_socket_addr(&self) -> SocketAddr {
         let default_socket_addr = SocketAddr::from(([127, 0, 0, 1], self.port));
         if self.host == "localhost" {
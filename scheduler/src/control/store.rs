@@ -0,0 +1,28 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use common::models::{cancelled_run_key, CANCELLED_RUN_TTL_SECS};
+
+/// Cancelled run ids shared with scrapers, so they can drop already queued messages of a run.
+pub struct Cancellations {
+    conn: ConnectionManager,
+}
+
+impl Cancellations {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Cancellations { conn }
+    }
+
+    pub async fn cancel(&self, run_id: Uuid) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.set_ex(cancelled_run_key(run_id), 1, CANCELLED_RUN_TTL_SECS).await?;
+        Ok(())
+    }
+
+    pub async fn is_cancelled(&self, run_id: Uuid) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.exists(cancelled_run_key(run_id)).await?)
+    }
+}
@@ -1,27 +1,493 @@
-use std::{collections::HashMap, hash::Hash};
+use std::collections::HashMap;
 
 use anyhow::{Ok, Result};
 
 use chrono::{DateTime, Utc};
-use common::models::{Crawler, NotificationLevel, NotificationOptions, NotifyEvery, NotifyVia, Priority, Site};
+use common::models::{ContentFingerprint, Crawler, CrawlerState, CrawlRun, EventCommandStatus, SuggestSelectors, RunCounter, RunStats, RunStatus, NotificationLevel, NotificationOptions, NotifyVia, Priority, Site, StopReason, WebhookDeliveryStatus};
+use sqlx::postgres::{PgPool, PgPoolOptions};
 use uuid::Uuid;
 
-use crate::config::DatabaseConfig;
+use crate::config::{DatabaseConfig, DbAddr};
+use crate::jobs::RoutineSpec;
+use crate::notification::{WebhookAttempt, WebhookDelivery};
+
+/// Stored statuses a run can move to `next` from, see `RunStatus::can_transition_to`.
+fn preceding_statuses(next: RunStatus) -> Vec<String> {
+    RunStatus::preceding(next).iter().map(ToString::to_string).collect()
+}
+
+/// Counters and status of a run after an update.
+pub struct RunProgress {
+    pub crawler_id: Uuid,
+    pub status: RunStatus,
+    pub stats: RunStats,
+}
 
 pub struct Postgres {
-    cfg: DatabaseConfig,
+    pool: PgPool,
 }
 
 impl Postgres {
-    pub fn new(cfg: DatabaseConfig) -> Self {
-        Postgres {cfg}
+    pub fn new(cfg: DatabaseConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(cfg.pool_max_size)
+            .connect_lazy(&cfg.get_addr())?;
+        Ok(Postgres { pool })
+    }
+
+    pub async fn get_fingerprint(&self, url: &str) -> Result<Option<ContentFingerprint>> {
+        let fingerprint = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+            "select hash, etag, last_modified from page_fingerprints where url = $1",
+        )
+        .bind(url)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|(hash, etag, last_modified)| ContentFingerprint {
+            hash: Some(hash),
+            etag,
+            last_modified,
+        });
+        Ok(fingerprint)
+    }
+
+    /// Returns false when the run is added already, e.g. by a failed delivery of the same event.
+    pub async fn add_run(&self, run: &CrawlRun) -> Result<bool> {
+        let result = sqlx::query(
+            "insert into crawl_runs (id, crawler_id, user_id, status, started_at) values ($1, $2, $3, $4, $5)
+             on conflict (id) do nothing",
+        )
+        .bind(run.id)
+        .bind(run.crawler_id)
+        .bind(run.user_id)
+        .bind(run.status.to_string())
+        .bind(run.started_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_run(&self, run_id: Uuid) -> Result<Option<CrawlRun>> {
+        let row = sqlx::query_as::<_, (Uuid, Uuid, Uuid, String, i64, i64, i64, i64, i64, i64, DateTime<Utc>, Option<DateTime<Utc>>, Option<String>)>(
+            "select id, crawler_id, user_id, status, dispatched, scraped, extracted, failed, skipped, bytes, started_at, finished_at, stop_reason
+             from crawl_runs where id = $1",
+        )
+        .bind(run_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(id, crawler_id, user_id, status, dispatched, scraped, extracted, failed, skipped, bytes, started_at, finished_at, stop_reason)| {
+            CrawlRun {
+                id,
+                crawler_id,
+                user_id,
+                status: status.parse().unwrap_or(RunStatus::Running),
+                stats: RunStats {
+                    dispatched: dispatched as u64,
+                    scraped: scraped as u64,
+                    extracted: extracted as u64,
+                    failed: failed as u64,
+                    skipped: skipped as u64,
+                    bytes: bytes as u64,
+                },
+                started_at,
+                finished_at,
+                stop_reason: stop_reason.and_then(|reason| reason.parse().ok()),
+            }
+        }))
+    }
+
+    /// Increments a run counter and returns the run status with updated stats.
+    pub async fn record_run(&self, run_id: Uuid, counter: RunCounter) -> Result<Option<RunProgress>> {
+        let query = format!(
+            "update crawl_runs set {col} = {col} + 1 where id = $1
+             returning crawler_id, status, dispatched, scraped, extracted, failed, skipped, bytes",
+            col = counter.column(),
+        );
+        let row = sqlx::query_as::<_, (Uuid, String, i64, i64, i64, i64, i64, i64)>(&query)
+            .bind(run_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(crawler_id, status, dispatched, scraped, extracted, failed, skipped, bytes)| {
+            let stats = RunStats {
+                dispatched: dispatched as u64,
+                scraped: scraped as u64,
+                extracted: extracted as u64,
+                failed: failed as u64,
+                skipped: skipped as u64,
+                bytes: bytes as u64,
+            };
+            RunProgress {
+                crawler_id,
+                status: status.parse().unwrap_or(RunStatus::Running),
+                stats,
+            }
+        }))
+    }
+
+    /// Moves a running run to a final status. Returns false if the run cannot move to it, e.g. it's finished already.
+    pub async fn finish_run(&self, run_id: Uuid, status: RunStatus) -> Result<bool> {
+        let updated = sqlx::query(
+            "update crawl_runs set status = $2, finished_at = now() where id = $1 and status = any($3)",
+        )
+        .bind(run_id)
+        .bind(status.to_string())
+        .bind(preceding_statuses(status))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(updated > 0)
+    }
+
+    /// Start of the crawler's run before `run_id`.
+    pub async fn previous_run_started_at(&self, crawler_id: Uuid, run_id: Uuid) -> Result<Option<DateTime<Utc>>> {
+        let started_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            "select started_at from crawl_runs where crawler_id = $1 and started_at < (
+                select started_at from crawl_runs where id = $2
+            ) order by started_at desc limit 1",
+        )
+        .bind(crawler_id)
+        .bind(run_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(started_at)
+    }
+
+    pub async fn add_run_bytes(&self, run_id: Uuid, bytes: u64) -> Result<()> {
+        sqlx::query("update crawl_runs set bytes = bytes + $2 where id = $1")
+            .bind(run_id)
+            .bind(bytes as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Moves a running run to Stopped with the reason. Returns false if the run cannot be stopped, e.g. it's finished already.
+    pub async fn stop_run(&self, run_id: Uuid, reason: StopReason) -> Result<bool> {
+        let updated = sqlx::query(
+            "update crawl_runs set status = $2, stop_reason = $3, finished_at = now() where id = $1 and status = any($4)",
+        )
+        .bind(run_id)
+        .bind(RunStatus::Stopped.to_string())
+        .bind(reason.to_string())
+        .bind(preceding_statuses(RunStatus::Stopped))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(updated > 0)
+    }
+
+    pub async fn add_seen_value(&self, run_id: Uuid, crawler_id: Uuid, field: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            "insert into run_seen_values (run_id, crawler_id, field, value) values ($1, $2, $3, $4)
+             on conflict do nothing",
+        )
+        .bind(run_id)
+        .bind(crawler_id)
+        .bind(field)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Whether the run before `run_id` of the crawler extracted the value of the field.
+    pub async fn seen_last_run(&self, crawler_id: Uuid, run_id: Uuid, field: &str, value: &str) -> Result<bool> {
+        let seen = sqlx::query_scalar::<_, bool>(
+            "select exists(
+                select 1 from run_seen_values
+                where run_id = (
+                    select id from crawl_runs where crawler_id = $1 and started_at < (
+                        select started_at from crawl_runs where id = $2
+                    ) order by started_at desc limit 1
+                ) and field = $3 and value = $4
+            )",
+        )
+        .bind(crawler_id)
+        .bind(run_id)
+        .bind(field)
+        .bind(value)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(seen)
+    }
+
+    /// Drops seen values of the crawler's runs older than the one before `run_id`.
+    pub async fn prune_seen_values(&self, crawler_id: Uuid, run_id: Uuid) -> Result<()> {
+        sqlx::query(
+            "delete from run_seen_values where crawler_id = $1 and run_id not in (
+                select id from crawl_runs where crawler_id = $1 and started_at <= (
+                    select started_at from crawl_runs where id = $2
+                ) order by started_at desc limit 2
+            )",
+        )
+        .bind(crawler_id)
+        .bind(run_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn add_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            "insert into webhook_deliveries (id, crawler_id, user_id, url, event, payload, status, next_attempt_at)
+             values ($1, $2, $3, $4, $5, $6::json, $7, now())",
+        )
+        .bind(delivery.id)
+        .bind(delivery.crawler_id)
+        .bind(delivery.user_id)
+        .bind(&delivery.url)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(WebhookDeliveryStatus::Pending.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Leases pending deliveries whose retry time has come, so concurrent retries don't send them twice.
+    pub async fn lease_webhook_deliveries(&self, limit: i64, lease_secs: i64) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, (Uuid, Uuid, Uuid, String, String, String, i32)>(
+            "update webhook_deliveries set next_attempt_at = now() + make_interval(secs => $3)
+             where id in (
+                 select id from webhook_deliveries
+                 where status = $1 and next_attempt_at <= now()
+                 order by next_attempt_at
+                 limit $2
+                 for update skip locked
+             )
+             returning id, crawler_id, user_id, url, event, payload::text, attempts",
+        )
+        .bind(WebhookDeliveryStatus::Pending.to_string())
+        .bind(limit)
+        .bind(lease_secs as f64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, crawler_id, user_id, url, event, payload, attempts)| WebhookDelivery {
+                id, crawler_id, user_id, url, event, payload, attempts,
+            })
+            .collect())
+    }
+
+    pub async fn record_webhook_attempt(&self, delivery_id: Uuid, attempt: &WebhookAttempt) -> Result<()> {
+        sqlx::query(
+            "update webhook_deliveries set
+                status = $2,
+                attempts = attempts + 1,
+                response_status = $3,
+                last_error = $4,
+                next_attempt_at = $5,
+                delivered_at = case when $2 = $6 then now() else delivered_at end
+             where id = $1",
+        )
+        .bind(delivery_id)
+        .bind(attempt.status.to_string())
+        .bind(attempt.response_status.map(i32::from))
+        .bind(&attempt.error)
+        .bind(attempt.next_attempt_at)
+        .bind(WebhookDeliveryStatus::Delivered.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
-    pub fn add_crawler(&self, crawler: Crawler) -> Result<()> {
-        tracing::info!("Save crawler to database");
+    pub async fn set_crawler_state(&self, crawler_id: Uuid, state: CrawlerState) -> Result<()> {
+        sqlx::query(
+            "insert into crawler_states (crawler_id, state) values ($1, $2)
+             on conflict (crawler_id) do update set state = excluded.state, updated_at = now()",
+        )
+        .bind(crawler_id)
+        .bind(state.to_string())
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
+    /// Stores a registered crawler, so every scheduler instance can schedule its cron job.
+    pub async fn add_crawler(&self, crawler: &Crawler) -> Result<()> {
+        sqlx::query(
+            "insert into scheduled_crawlers (crawler_id, crawler) values ($1, $2::json)
+             on conflict (crawler_id) do update set crawler = excluded.crawler, updated_at = now()",
+        )
+        .bind(crawler.id)
+        .bind(serde_json::to_string(crawler)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Registered crawlers with their states, crawlers without a state are active.
+    pub async fn load_crawlers(&self) -> Result<Vec<(Crawler, CrawlerState)>> {
+        let rows = sqlx::query_as::<_, (String, Option<String>)>(
+            "select c.crawler::text, s.state from scheduled_crawlers c
+             left join crawler_states s on s.crawler_id = c.crawler_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut crawlers = Vec::with_capacity(rows.len());
+        for (crawler, state) in rows {
+            let crawler: Crawler = serde_json::from_str(&crawler)?;
+            let state = state.and_then(|state| state.parse().ok()).unwrap_or_default();
+            crawlers.push((crawler, state));
+        }
+        Ok(crawlers)
+    }
+
+    /// A registered crawler with its state, None if it's not registered.
+    pub async fn load_crawler(&self, crawler_id: Uuid) -> Result<Option<(Crawler, CrawlerState)>> {
+        let row = sqlx::query_as::<_, (String, Option<String>)>(
+            "select c.crawler::text, s.state from scheduled_crawlers c
+             left join crawler_states s on s.crawler_id = c.crawler_id
+             where c.crawler_id = $1",
+        )
+        .bind(crawler_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((crawler, state)) = row else {
+            return Ok(None);
+        };
+        let state = state.and_then(|state| state.parse().ok()).unwrap_or_default();
+        Ok(Some((serde_json::from_str(&crawler)?, state)))
+    }
+
+    /// Deletes the crawler with its state and start urls, returns false if it's not registered.
+    /// Other scheduler instances drop its cron job on their next sync.
+    pub async fn delete_crawler(&self, crawler_id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        for table in ["crawler_states", "crawler_start_urls"] {
+            sqlx::query(&format!("delete from {} where crawler_id = $1", table))
+                .bind(crawler_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let result = sqlx::query("delete from scheduled_crawlers where crawler_id = $1")
+            .bind(crawler_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replaces the start urls uploaded for the crawler. They are kept apart from the crawler,
+    /// so crawlers held by every instance don't carry them.
+    pub async fn set_start_urls(&self, crawler_id: Uuid, urls: &[String]) -> Result<()> {
+        sqlx::query(
+            "insert into crawler_start_urls (crawler_id, urls) values ($1, $2)
+             on conflict (crawler_id) do update set urls = excluded.urls, updated_at = now()",
+        )
+        .bind(crawler_id)
+        .bind(urls)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Start urls uploaded for the crawler, none when nothing is uploaded.
+    pub async fn load_start_urls(&self, crawler_id: Uuid) -> Result<Vec<String>> {
+        let urls = sqlx::query_scalar::<_, Vec<String>>("select urls from crawler_start_urls where crawler_id = $1")
+            .bind(crawler_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(urls.unwrap_or_default())
+    }
+
+    pub async fn delete_start_urls(&self, crawler_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("delete from crawler_start_urls where crawler_id = $1")
+            .bind(crawler_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores a selector suggestion request of a crawler, a stored request is kept as it is.
+    pub async fn add_suggestion_request(&self, crawler_id: Uuid, request: &SuggestSelectors) -> Result<()> {
+        sqlx::query(
+            "insert into selector_suggestions (id, crawler_id, examples) values ($1, $2, $3::json)
+             on conflict (id) do nothing",
+        )
+        .bind(request.id)
+        .bind(crawler_id)
+        .bind(serde_json::to_string(&request.examples)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores the outcome of a selector suggestion request, returns false if the request is unknown.
+    pub async fn set_suggestions(&self, request: &SuggestSelectors, status: EventCommandStatus) -> Result<bool> {
+        let result = sqlx::query(
+            "update selector_suggestions set status = $2, suggestions = $3::json, updated_at = now() where id = $1",
+        )
+        .bind(request.id)
+        .bind(status.to_string())
+        .bind(serde_json::to_string(&request.suggestions)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn add_routine(&self, routine_id: Uuid, spec: &RoutineSpec) -> Result<()> {
+        sqlx::query("insert into routines (id, name, rule, task) values ($1, $2, $3, $4)")
+            .bind(routine_id)
+            .bind(&spec.name)
+            .bind(&spec.rule)
+            .bind(&spec.task)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Routines in the order they were added, with their last runs.
+    pub async fn load_routines(&self) -> Result<Vec<(Uuid, RoutineSpec, Option<DateTime<Utc>>)>> {
+        let rows = sqlx::query_as::<_, (Uuid, String, String, String, Option<DateTime<Utc>>)>(
+            "select id, name, rule, task, last_run_at from routines order by id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, name, rule, task, last_run_at)| (id, RoutineSpec { name, rule, task }, last_run_at))
+            .collect())
+    }
+
+    pub async fn load_routine(&self, routine_id: Uuid) -> Result<Option<(RoutineSpec, Option<DateTime<Utc>>)>> {
+        let row = sqlx::query_as::<_, (String, String, String, Option<DateTime<Utc>>)>(
+            "select name, rule, task, last_run_at from routines where id = $1",
+        )
+        .bind(routine_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(name, rule, task, last_run_at)| (RoutineSpec { name, rule, task }, last_run_at)))
+    }
+
+    /// False if the routine is not found.
+    pub async fn set_routine_rule(&self, routine_id: Uuid, rule: &str) -> Result<bool> {
+        let result = sqlx::query("update routines set rule = $2, updated_at = now() where id = $1")
+            .bind(routine_id)
+            .bind(rule)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_routine_run(&self, routine_id: Uuid, at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("update routines set last_run_at = $2 where id = $1")
+            .bind(routine_id)
+            .bind(at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// False if the routine is not found.
+    pub async fn delete_routine(&self, routine_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("delete from routines where id = $1")
+            .bind(routine_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     pub fn get_crawler(&self, crawler_id: Uuid) -> Result<Crawler> {
         let crawler = Crawler {
             id: crawler_id,
@@ -33,6 +499,8 @@ impl Postgres {
                 level: NotificationLevel::JobsDone,
                 via: vec![NotifyVia::Email("abobus@gmail.com".into())],
                 every: None,
+                send_at: None,
+                timezone: None,
             },
             site: Site{
                 id: Uuid::now_v7(),
@@ -44,8 +512,13 @@ impl Postgres {
                 pagination_xpaths: HashMap::from([
                     ("next_page".into(), "//a/@href".into())
                 ]),
+                start_pages: Default::default(),
+                sitemap: None,
                 meta: None,
             },
+            budget: Default::default(),
+            stop_conditions: Vec::new(),
+            url_patterns: Default::default(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
             meta: None,
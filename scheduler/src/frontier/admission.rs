@@ -0,0 +1,62 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use uuid::Uuid;
+
+use common::models::UrlPatterns;
+
+use crate::config::FrontierConfig;
+use crate::frontier::{Canonicalizer, CrawlerPatterns, SeenUrls};
+
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    /// The page is crawled at its canonical url.
+    New(String),
+    /// The url cannot be parsed or isn't an http one.
+    Invalid(String),
+    /// The url doesn't pass the crawler's include and exclude patterns.
+    Excluded(String),
+    /// The run has crawled the url already.
+    Seen(String),
+}
+
+/// Decides which pages of a run are crawled: canonicalizes their urls, applies url patterns
+/// of the crawler and drops urls the run has seen.
+pub struct Frontier {
+    canonicalizer: Canonicalizer,
+    patterns: CrawlerPatterns,
+    seen: SeenUrls,
+}
+
+impl Frontier {
+    pub fn new(conn: ConnectionManager, frontier: &FrontierConfig) -> Self {
+        Frontier {
+            canonicalizer: Canonicalizer::new(&frontier.tracking_params),
+            patterns: CrawlerPatterns::default(),
+            seen: SeenUrls::new(conn, frontier),
+        }
+    }
+
+    pub fn canonicalizer(&self) -> &Canonicalizer {
+        &self.canonicalizer
+    }
+
+    /// Pages outside of runs are only canonicalized and filtered.
+    pub async fn admit(&self, crawler_id: Uuid, patterns: &UrlPatterns, run_id: Option<Uuid>, url: &str) -> Result<Admission> {
+        let url = match self.canonicalizer.canonicalize(url) {
+            Ok(url) => url,
+            Err(err) => return Ok(Admission::Invalid(format!("{}: {}", url, err))),
+        };
+        if !self.patterns.allows(crawler_id, patterns, &url)? {
+            return Ok(Admission::Excluded(url));
+        }
+        match run_id {
+            Some(run_id) if !self.seen.insert(run_id, &url).await? => Ok(Admission::Seen(url)),
+            _ => Ok(Admission::New(url)),
+        }
+    }
+
+    /// Forgets a url admitted to the run which could not be dispatched, so a retry admits it again.
+    pub async fn forget(&self, run_id: Uuid, url: &str) -> Result<()> {
+        self.seen.remove(run_id, url).await
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{anyhow, Result};
+use url::{form_urlencoded, Url};
+
+/// Brings trivially different variants of a url to one form: lowercase scheme and host,
+/// no default port, no fragment, query params sorted with tracking ones removed.
+/// Params are kept as they are encoded, so the canonical url points to the same page.
+pub struct Canonicalizer {
+    /// Lowercase param names, a trailing `*` matches any suffix.
+    tracking_params: Vec<String>,
+}
+
+impl Canonicalizer {
+    pub fn new(tracking_params: &str) -> Self {
+        let tracking_params = tracking_params
+            .split(',')
+            .map(|param| param.trim().to_lowercase())
+            .filter(|param| !param.is_empty())
+            .collect();
+        Canonicalizer { tracking_params }
+    }
+
+    pub fn canonicalize(&self, raw: &str) -> Result<String> {
+        let mut url = Url::parse(raw.trim())?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("{} is not an http url", raw));
+        }
+        if url.host_str().is_none() {
+            return Err(anyhow!("{} has no host", raw));
+        }
+        url.set_fragment(None);
+        let query = url.query().unwrap_or_default().to_string();
+        let mut params: Vec<&str> = query
+            .split('&')
+            .filter(|param| match form_urlencoded::parse(param.as_bytes()).next() {
+                Some((name, _)) => !name.is_empty() && !self.is_tracking(&name),
+                None => false,
+            })
+            .collect();
+        params.sort();
+        match params.is_empty() {
+            true => url.set_query(None),
+            false => url.set_query(Some(&params.join("&"))),
+        }
+        Ok(url.into())
+    }
+
+    /// Resolves a link found on the page at `base` and canonicalizes it.
+    pub fn join(&self, base: &str, link: &str) -> Result<String> {
+        self.canonicalize(Url::parse(base)?.join(link.trim())?.as_str())
+    }
+
+    fn is_tracking(&self, param: &str) -> bool {
+        let param = param.to_lowercase();
+        self.tracking_params.iter().any(|tracking| match tracking.strip_suffix('*') {
+            Some(prefix) => param.starts_with(prefix),
+            None => param == *tracking,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonicalizer() -> Canonicalizer {
+        Canonicalizer::new("utm_*, fbclid")
+    }
+
+    #[test]
+    fn brings_variants_to_one_form() {
+        let canonical = canonicalizer();
+        assert_eq!(canonical.canonicalize(" HTTPS://Shop.Site:443/Items?b=2&a=1#top ").unwrap(), "https://shop.site/Items?a=1&b=2");
+        assert_eq!(canonical.canonicalize("http://shop.site:80").unwrap(), "http://shop.site/");
+        assert_eq!(canonical.canonicalize("http://shop.site:8080/").unwrap(), "http://shop.site:8080/");
+    }
+
+    #[test]
+    fn removes_tracking_and_empty_params() {
+        let canonical = canonicalizer();
+        assert_eq!(canonical.canonicalize("https://shop.site/?utm_source=x&UTM_medium=y&fbclid=z&=1&id=7").unwrap(), "https://shop.site/?id=7");
+        assert_eq!(canonical.canonicalize("https://shop.site/?utm_source=x").unwrap(), "https://shop.site/");
+        // only a trailing star matches a prefix
+        assert_eq!(canonical.canonicalize("https://shop.site/?fbclid_x=1").unwrap(), "https://shop.site/?fbclid_x=1");
+    }
+
+    #[test]
+    fn keeps_params_as_they_are_encoded() {
+        let canonical = canonicalizer();
+        for url in [
+            "https://shop.site/search?q=a+b",
+            "https://shop.site/search?q=a%20b",
+            "https://shop.site/search?q=%2B1&r=%26",
+            "https://shop.site/search?q=caf%C3%A9",
+            "https://shop.site/search?flag",
+        ] {
+            assert_eq!(canonical.canonicalize(url).unwrap(), url);
+        }
+    }
+
+    #[test]
+    fn refuses_non_http_urls() {
+        let canonical = canonicalizer();
+        assert!(canonical.canonicalize("ftp://shop.site/file").is_err());
+        assert!(canonical.canonicalize("mailto:shop@shop.site").is_err());
+        assert!(canonical.canonicalize("/relative").is_err());
+    }
+
+    #[test]
+    fn joins_links_to_their_page() {
+        let canonical = canonicalizer();
+        assert_eq!(canonical.join("https://shop.site/list/1", " 2?utm_source=x ").unwrap(), "https://shop.site/list/2");
+        assert_eq!(canonical.join("https://shop.site/list?page=1", "?page=2").unwrap(), "https://shop.site/list?page=2");
+        assert_eq!(canonical.join("https://shop.site/list", "//cdn.site/a").unwrap(), "https://cdn.site/a");
+        assert!(canonical.join("https://shop.site/list", "javascript:void(0)").is_err());
+    }
+}
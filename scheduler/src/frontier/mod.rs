@@ -0,0 +1,11 @@
+mod admission;
+mod canonical;
+mod patterns;
+mod start_pages;
+mod store;
+
+pub use admission::*;
+pub use canonical::*;
+pub use patterns::*;
+pub use start_pages::*;
+pub use store::*;
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use regex::RegexSet;
+use uuid::Uuid;
+
+use common::models::UrlPatterns;
+
+struct Compiled {
+    source: UrlPatterns,
+    include: RegexSet,
+    exclude: RegexSet,
+}
+
+impl Compiled {
+    fn new(source: &UrlPatterns) -> Result<Self> {
+        Ok(Compiled {
+            source: source.clone(),
+            include: RegexSet::new(&source.include)?,
+            exclude: RegexSet::new(&source.exclude)?,
+        })
+    }
+
+    fn allows(&self, url: &str) -> bool {
+        !self.exclude.is_match(url) && (self.include.is_empty() || self.include.is_match(url))
+    }
+}
+
+/// Compiled url patterns of crawlers, recompiled when a crawler's patterns change.
+#[derive(Default)]
+pub struct CrawlerPatterns {
+    compiled: Mutex<HashMap<Uuid, Compiled>>,
+}
+
+impl CrawlerPatterns {
+    /// Fails on an invalid regex, so crawlers with broken patterns are not registered.
+    pub fn validate(patterns: &UrlPatterns) -> Result<()> {
+        Compiled::new(patterns).map(|_| ())
+    }
+
+    pub fn allows(&self, crawler_id: Uuid, patterns: &UrlPatterns, url: &str) -> Result<bool> {
+        if *patterns == UrlPatterns::default() {
+            return Ok(true);
+        }
+        let mut compiled = self.compiled.lock().expect("url patterns lock is poisoned");
+        match compiled.get(&crawler_id) {
+            Some(known) if known.source == *patterns => return Ok(known.allows(url)),
+            _ => {},
+        }
+        let fresh = Compiled::new(patterns)?;
+        let allows = fresh.allows(url);
+        compiled.insert(crawler_id, fresh);
+        Ok(allows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(include: &[&str], exclude: &[&str]) -> UrlPatterns {
+        UrlPatterns {
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn no_patterns_allow_everything() {
+        let compiled = CrawlerPatterns::default();
+        assert!(compiled.allows(Uuid::now_v7(), &UrlPatterns::default(), "https://shop.site/any").unwrap());
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let compiled = CrawlerPatterns::default();
+        let patterns = patterns(&["/items/"], &[r"\?sort="]);
+        let crawler_id = Uuid::now_v7();
+        assert!(compiled.allows(crawler_id, &patterns, "https://shop.site/items/1").unwrap());
+        assert!(!compiled.allows(crawler_id, &patterns, "https://shop.site/items/1?sort=price").unwrap());
+        assert!(!compiled.allows(crawler_id, &patterns, "https://shop.site/about").unwrap());
+    }
+
+    #[test]
+    fn recompiles_changed_patterns() {
+        let compiled = CrawlerPatterns::default();
+        let crawler_id = Uuid::now_v7();
+        assert!(!compiled.allows(crawler_id, &patterns(&[], &["about"]), "https://shop.site/about").unwrap());
+        assert!(compiled.allows(crawler_id, &patterns(&[], &["contact"]), "https://shop.site/about").unwrap());
+    }
+
+    #[test]
+    fn refuses_invalid_regexes() {
+        assert!(CrawlerPatterns::validate(&patterns(&["items("], &[])).is_err());
+        assert!(CrawlerPatterns::validate(&patterns(&["items"], &["[a-"])).is_err());
+        assert!(CrawlerPatterns::validate(&patterns(&["items"], &["about"])).is_ok());
+    }
+}
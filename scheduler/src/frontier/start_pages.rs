@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use url::form_urlencoded;
+
+use common::models::Site;
+
+/// Start pages a single run may expand to.
+pub const MAX_START_PAGES: usize = 100_000;
+
+/// Urls a run of the site starts from: the start page, listed urls, urls of the uploaded CSV and
+/// every combination of the template. Combinations are built as they are taken, so a preview
+/// or a resumed run doesn't build the urls it skips.
+pub struct StartUrls {
+    listed: Vec<String>,
+    template: Vec<Vec<String>>,
+    combinations: usize,
+}
+
+impl StartUrls {
+    /// Fails on a broken template or when there are too many.
+    pub fn new(site: &Site, uploaded: Vec<String>) -> Result<Self> {
+        let pages = &site.start_pages;
+        let mut listed: Vec<String> = Some(site.start_page.trim().to_string())
+            .into_iter()
+            .chain(pages.urls.iter().map(|url| url.trim().to_string()))
+            .chain(uploaded)
+            .filter(|url| !url.is_empty())
+            .collect();
+        // crawlers stored before CSVs were kept apart may still hold theirs
+        if let Some(csv) = &pages.csv {
+            listed.extend(csv_urls(csv));
+        }
+        if listed.len() > MAX_START_PAGES {
+            return Err(anyhow!("{} start pages, at most {} are allowed", listed.len(), MAX_START_PAGES));
+        }
+        let (template, combinations) = match &pages.template {
+            Some(template) => template_parts(template, &pages.variables, MAX_START_PAGES - listed.len())?,
+            None => (Vec::new(), 0),
+        };
+        // robots.txt listing the sitemaps is looked up at the host of the first start url
+        if listed.is_empty() && combinations == 0 && site.sitemap.as_ref().is_none_or(|sitemap| sitemap.url.is_none()) {
+            return Err(anyhow!("site {} has neither start pages nor a sitemap url", site.domain));
+        }
+        Ok(StartUrls { listed, template, combinations })
+    }
+
+    pub fn len(&self) -> usize {
+        self.listed.len() + self.combinations
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        self.iter_from(0)
+    }
+
+    /// Urls after the first `start` ones.
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = String> + '_ {
+        (start..self.len()).map(|index| match self.listed.get(index) {
+            Some(url) => url.clone(),
+            None => combination(&self.template, index - self.listed.len()),
+        })
+    }
+}
+
+/// Urls of the `url` column, or of the first column when the header has none or there is no header.
+pub fn csv_urls(csv: &str) -> Vec<String> {
+    let mut rows = csv.lines().map(csv_fields).filter(|row| row.iter().any(|field| !field.is_empty())).peekable();
+    let column = match rows.peek() {
+        Some(header) if !header.iter().any(|field| field.contains("://")) => {
+            let column = header.iter().position(|field| field.eq_ignore_ascii_case("url")).unwrap_or(0);
+            rows.next();
+            column
+        },
+        _ => 0,
+    };
+    rows.filter_map(|mut row| (column < row.len()).then(|| row.swap_remove(column)))
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+/// Splits a CSV line by commas, double quoted fields may hold commas and `""` quotes.
+fn csv_fields(line: &str) -> Vec<String> {
+    let (mut fields, mut field, mut quoted) = (Vec::new(), String::new(), false);
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Literal parts and placeholder values of the template with the number of their combinations,
+/// every combination is a url. Fails when there are more than `limit` combinations.
+fn template_parts(template: &str, variables: &HashMap<String, Vec<String>>, limit: usize) -> Result<(Vec<Vec<String>>, usize)> {
+    let mut parts: Vec<Vec<String>> = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|close| open + close)
+            .ok_or_else(|| anyhow!("unclosed {{ in template {}", template))?;
+        parts.push(vec![rest[..open].to_string()]);
+        parts.push(placeholder_values(&rest[open + 1..close], variables, limit)?);
+        rest = &rest[close + 1..];
+    }
+    parts.push(vec![rest.to_string()]);
+
+    let combinations = parts.iter().try_fold(1usize, |total, values| total.checked_mul(values.len()));
+    match combinations {
+        Some(combinations) if combinations <= limit => Ok((parts, combinations)),
+        _ => Err(anyhow!("template {} expands to more than {} urls", template, limit)),
+    }
+}
+
+/// The combination at `index`, the last placeholder changes fastest.
+fn combination(parts: &[Vec<String>], mut index: usize) -> String {
+    let mut values = Vec::with_capacity(parts.len());
+    for part in parts.iter().rev() {
+        values.push(part[index % part.len()].as_str());
+        index /= part.len();
+    }
+    values.into_iter().rev().collect()
+}
+
+/// Values of `{1..50}`, `{01..50}` keeping the zero padding, `{a,b}` and `{name}`.
+/// Listed values are url encoded, they usually go to query params.
+/// A range is refused before it's built when it has more than `limit` values.
+fn placeholder_values(placeholder: &str, variables: &HashMap<String, Vec<String>>, limit: usize) -> Result<Vec<String>> {
+    let placeholder = placeholder.trim();
+    if let Some((from, to)) = placeholder.split_once("..") {
+        let (start, end): (u64, u64) = match (from.trim().parse(), to.trim().parse()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return Err(anyhow!("bad range {{{}}}, expected e.g. {{1..50}}", placeholder)),
+        };
+        if start.abs_diff(end) >= limit as u64 {
+            return Err(anyhow!("range {{{}}} has more than {} values", placeholder, limit));
+        }
+        let width = if from.trim().starts_with('0') { from.trim().len() } else { 0 };
+        let range: Box<dyn Iterator<Item = u64>> = match start <= end {
+            true => Box::new(start..=end),
+            false => Box::new((end..=start).rev()),
+        };
+        return Ok(range.map(|n| format!("{:0width$}", n, width = width)).collect());
+    }
+    let values = match placeholder.contains(',') {
+        true => placeholder.split(',').map(|value| value.trim().to_string()).collect(),
+        false => variables
+            .get(placeholder)
+            .cloned()
+            .ok_or_else(|| anyhow!("template variable {} has no values", placeholder))?,
+    };
+    Ok(values.iter().map(|value| form_urlencoded::byte_serialize(value.as_bytes()).collect()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn expand_template(template: &str, variables: &HashMap<String, Vec<String>>, limit: usize) -> Result<Vec<String>> {
+        let (parts, combinations) = template_parts(template, variables, limit)?;
+        Ok((0..combinations).map(|index| combination(&parts, index)).collect())
+    }
+
+    #[test]
+    fn splits_csv_fields() {
+        assert_eq!(csv_fields(" a , b,,c "), vec!["a", "b", "", "c"]);
+        assert_eq!(csv_fields(r#""a, b","say ""hi""",c"#), vec!["a, b", r#"say "hi""#, "c"]);
+        assert_eq!(csv_fields(""), vec![""]);
+    }
+
+    #[test]
+    fn takes_urls_of_the_url_column() {
+        let csv = "name,URL\nfirst,https://shop.site/1\n\n,\nno url\nsecond,https://shop.site/2\n";
+        assert_eq!(csv_urls(csv), vec!["https://shop.site/1", "https://shop.site/2"]);
+    }
+
+    #[test]
+    fn takes_the_first_column_without_a_url_header() {
+        assert_eq!(csv_urls("link,name\nhttps://shop.site/1,a\n"), vec!["https://shop.site/1"]);
+        assert_eq!(csv_urls("https://shop.site/1,a\nhttps://shop.site/2\n"), vec!["https://shop.site/1", "https://shop.site/2"]);
+        assert!(csv_urls("url\n").is_empty());
+    }
+
+    #[test]
+    fn expands_every_combination() {
+        let variables = HashMap::from([("q".to_string(), vec!["red shoes".to_string(), "hat".to_string()])]);
+        assert_eq!(
+            expand_template("https://shop.site/s?q={q}&page={1..2}", &variables, 10).unwrap(),
+            vec![
+                "https://shop.site/s?q=red+shoes&page=1",
+                "https://shop.site/s?q=red+shoes&page=2",
+                "https://shop.site/s?q=hat&page=1",
+                "https://shop.site/s?q=hat&page=2",
+            ]
+        );
+        assert_eq!(expand_template("https://shop.site/{08..10}/{b, a}", &variables, 10).unwrap(), vec![
+            "https://shop.site/08/b",
+            "https://shop.site/08/a",
+            "https://shop.site/09/b",
+            "https://shop.site/09/a",
+            "https://shop.site/10/b",
+            "https://shop.site/10/a",
+        ]);
+        assert_eq!(expand_template("https://shop.site/{3..1}", &variables, 10).unwrap(), vec![
+            "https://shop.site/3",
+            "https://shop.site/2",
+            "https://shop.site/1",
+        ]);
+    }
+
+    #[test]
+    fn refuses_broken_templates() {
+        let variables = HashMap::new();
+        assert!(expand_template("https://shop.site/{1..2", &variables, 10).is_err());
+        assert!(expand_template("https://shop.site/{a..b}", &variables, 10).is_err());
+        assert!(expand_template("https://shop.site/{missing}", &variables, 10).is_err());
+        assert!(expand_template("https://shop.site/{1..5}/{1..3}", &variables, 10).is_err());
+    }
+
+    #[test]
+    fn refuses_huge_ranges_before_building_them() {
+        assert!(expand_template("https://shop.site/{0..99999999999}", &HashMap::new(), MAX_START_PAGES).is_err());
+        assert!(expand_template("https://shop.site/{18446744073709551615..0}", &HashMap::new(), MAX_START_PAGES).is_err());
+        assert_eq!(expand_template("https://shop.site/{1..10}", &HashMap::new(), 10).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn lists_start_urls_in_order() {
+        let mut site = testing::crawler().site;
+        site.start_pages.urls = vec![" https://shop.site/a ".to_string(), "".to_string()];
+        site.start_pages.template = Some("https://shop.site/p/{1..3}".to_string());
+        let urls = StartUrls::new(&site, vec!["https://shop.site/csv".to_string()]).unwrap();
+        assert_eq!(urls.len(), 6);
+        assert_eq!(urls.iter().collect::<Vec<_>>(), vec![
+            "https://shop.site/",
+            "https://shop.site/a",
+            "https://shop.site/csv",
+            "https://shop.site/p/1",
+            "https://shop.site/p/2",
+            "https://shop.site/p/3",
+        ]);
+        assert_eq!(urls.iter_from(4).collect::<Vec<_>>(), vec!["https://shop.site/p/2", "https://shop.site/p/3"]);
+        assert_eq!(urls.iter_from(6).count(), 0);
+    }
+
+    #[test]
+    fn limits_start_urls() {
+        let mut site = testing::crawler().site;
+        site.start_pages.template = Some(format!("https://shop.site/p/{{1..{}}}", MAX_START_PAGES));
+        assert!(StartUrls::new(&site, vec![]).is_err());
+        site.start_page = String::new();
+        assert_eq!(StartUrls::new(&site, vec![]).unwrap().len(), MAX_START_PAGES);
+
+        site.start_pages.template = None;
+        assert!(StartUrls::new(&site, vec![]).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use crate::config::FrontierConfig;
+
+fn seen_urls_key(run_id: Uuid) -> String {
+    format!("run:{}:frontier", run_id)
+}
+
+/// Canonical urls dispatched within a run, kept in a redis set per run so every scheduler instance
+/// sees the same ones. Sets expire a while after the last url is added.
+pub struct SeenUrls {
+    conn: ConnectionManager,
+    ttl_secs: i64,
+}
+
+impl SeenUrls {
+    pub fn new(conn: ConnectionManager, frontier: &FrontierConfig) -> Self {
+        SeenUrls { conn, ttl_secs: frontier.seen_ttl_secs as i64 }
+    }
+
+    /// Adds the url to the run's set. Returns false if the run has seen it already.
+    pub async fn insert(&self, run_id: Uuid, url: &str) -> Result<bool> {
+        let key = seen_urls_key(run_id);
+        let mut conn = self.conn.clone();
+        let (added,): (i64,) = redis::pipe()
+            .atomic()
+            .sadd(&key, url)
+            .expire(&key, self.ttl_secs).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(added == 1)
+    }
+
+    /// Takes the url out of the run's set, so it's admitted again.
+    pub async fn remove(&self, run_id: Uuid, url: &str) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.srem(seen_urls_key(run_id), url).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use envconfig::Envconfig;
+
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn forgotten_urls_are_admitted_again() {
+        let seen = SeenUrls::new(testing::redis().await, &FrontierConfig::init_from_env().unwrap());
+        let run_id = Uuid::now_v7();
+        assert!(seen.insert(run_id, "https://shop.site/").await.unwrap());
+        assert!(!seen.insert(run_id, "https://shop.site/").await.unwrap());
+        seen.remove(run_id, "https://shop.site/").await.unwrap();
+        assert!(seen.insert(run_id, "https://shop.site/").await.unwrap());
+    }
+}
@@ -0,0 +1,9 @@
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::jobs::register_leader_job;
+use crate::orchestrator::{self, SharedOrchestrator};
+
+pub async fn register_digest_jobs(rule: &str, orch: SharedOrchestrator) -> Result<Uuid> {
+    register_leader_job(orch, rule, "digest", |orch| async move { orchestrator::send_digests(&orch).await }).await
+}
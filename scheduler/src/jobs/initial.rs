@@ -1,20 +1,22 @@
 use anyhow::Result;
-use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+use tokio_cron_scheduler::{Job, JobSchedulerError};
+use uuid::Uuid;
 
 use crate::SharedSheduler;
 
+pub const INITIAL_JOB_RULE: &str = "1/10 * * * * *";
+
 fn initial_job() -> Result<Job, JobSchedulerError> {
-    Job::new("1/10 * * * * *", |_uuid, _lock| {
+    Job::new(INITIAL_JOB_RULE, |_uuid, _lock| {
         tracing::warn!("I am initial job!");
     })
 }
 
-pub async fn register_initial_jobs(mut sched: &mut SharedSheduler) -> Result<()> {
+pub async fn register_initial_jobs(sched: &mut SharedSheduler) -> Result<Uuid> {
     tracing::info!("Registering initial jobs for scheduler");
-    sched
+    Ok(sched
         .lock()
         .await
         .add(initial_job().expect("cannot register initial job"))
-        .await?;
-    Ok(())
+        .await?)
 }
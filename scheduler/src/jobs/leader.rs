@@ -0,0 +1,33 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio_cron_scheduler::Job;
+use uuid::Uuid;
+
+use crate::orchestrator::SharedOrchestrator;
+
+/// Registers a cron job which every instance schedules but only the leader runs.
+/// A failed run is logged with the name of the job and retried on the next firing.
+pub async fn register_leader_job<F, Fut>(orch: SharedOrchestrator, rule: &str, name: &'static str, run: F) -> Result<Uuid>
+where
+    F: Fn(SharedOrchestrator) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    tracing::info!("Registering {} jobs for scheduler", name);
+    let job_orch = orch.clone();
+    let run = Arc::new(run);
+    let job = Job::new_async(rule, move |_uuid, _lock| {
+        let orch = job_orch.clone();
+        let run = run.clone();
+        Box::pin(async move {
+            if !orch.leader.is_leader() {
+                return;
+            }
+            if let Err(err) = run(orch).await {
+                tracing::error!("{} job failed: {}", name, err);
+            }
+        })
+    })?;
+    Ok(orch.sched.lock().await.add(job).await?)
+}
@@ -1,4 +1,14 @@
 
+mod digest;
 mod initial;
+mod leader;
+mod quota;
+mod routines;
+mod webhook;
 
+pub use digest::*;
 pub use initial::*;
+pub use leader::*;
+pub use quota::*;
+pub use routines::*;
+pub use webhook::*;
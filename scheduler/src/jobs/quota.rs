@@ -0,0 +1,37 @@
+use anyhow::Result;
+use uuid::Uuid;
+
+use common::models::QuotaKind;
+
+use crate::broker::{Broker, SharedBroker};
+use crate::jobs::register_leader_job;
+use crate::orchestrator::{self, SharedOrchestrator};
+
+/// Max pages of a user resumed per job run.
+const RESUME_BATCH: u64 = 500;
+
+/// Dispatches paused pages of users whose quota windows were reset.
+async fn resume_paused(broker: &dyn Broker, orch: &SharedOrchestrator) -> Result<()> {
+    for user_id in orch.quotas.paused_users().await? {
+        let day = orch.quotas.remaining(user_id, QuotaKind::PagesPerDay).await?;
+        let month = orch.quotas.remaining(user_id, QuotaKind::PagesPerMonth).await?;
+        let available = day.min(month).min(RESUME_BATCH);
+        if available == 0 {
+            continue;
+        }
+        let pages = orch.quotas.take_paused(user_id, available as usize).await?;
+        tracing::info!("resuming {} paused pages of user {}", pages.len(), user_id);
+        for page in pages {
+            orchestrator::dispatch_scrape(broker, orch, page).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn register_quota_jobs(rule: &str, broker: SharedBroker, orch: SharedOrchestrator) -> Result<Uuid> {
+    register_leader_job(orch, rule, "quota", move |orch| {
+        let broker = broker.clone();
+        async move { resume_paused(broker.as_ref(), &orch).await }
+    })
+    .await
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_cron_scheduler::{Job, JobSchedulerError};
+use uuid::Uuid;
+
+use crate::orchestrator::SharedOrchestrator;
+use crate::schedule::Schedule;
+
+/// What a routine is and when it fires.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RoutineSpec {
+    pub name: String,
+    pub rule: String,
+    pub task: String,
+}
+
+/// Where a job listed with the routines comes from.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutineKind {
+    /// Added over the API, can be changed and removed.
+    Routine,
+    /// Cron job of a registered crawler, its id is the crawler's.
+    Crawler,
+    /// Registered by the scheduler itself on start.
+    System,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoutineInfo {
+    pub routine_id: Uuid,
+    pub kind: RoutineKind,
+    #[serde(flatten)]
+    pub spec: RoutineSpec,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum RoutineError {
+    InvalidRule(String),
+    NotFound(Uuid),
+    Scheduler(anyhow::Error),
+}
+
+impl fmt::Display for RoutineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutineError::InvalidRule(err) => write!(f, "invalid rule: {}", err),
+            RoutineError::NotFound(routine_id) => write!(f, "routine {} is not found", routine_id),
+            RoutineError::Scheduler(err) => write!(f, "scheduler error: {}", err),
+        }
+    }
+}
+
+impl From<JobSchedulerError> for RoutineError {
+    fn from(err: JobSchedulerError) -> Self {
+        RoutineError::Scheduler(err.into())
+    }
+}
+
+impl From<anyhow::Error> for RoutineError {
+    fn from(err: anyhow::Error) -> Self {
+        RoutineError::Scheduler(err)
+    }
+}
+
+struct Routine {
+    spec: RoutineSpec,
+    job_id: Uuid,
+}
+
+struct SystemJob {
+    spec: RoutineSpec,
+    job_id: Uuid,
+}
+
+/// Changes bringing local routine jobs in line with the stored routines.
+#[derive(Debug, PartialEq)]
+struct SyncPlan {
+    /// Routines deleted through any instance.
+    remove: Vec<Uuid>,
+    /// Routines added or with a rule changed through any instance.
+    schedule: Vec<(Uuid, RoutineSpec)>,
+}
+
+fn plan_sync(local: &HashMap<Uuid, RoutineSpec>, stored: Vec<(Uuid, RoutineSpec)>) -> SyncPlan {
+    let mut remove: Vec<Uuid> = local
+        .keys()
+        .filter(|routine_id| !stored.iter().any(|(stored_id, _)| stored_id == *routine_id))
+        .copied()
+        .collect();
+    remove.sort();
+    let schedule = stored
+        .into_iter()
+        .filter(|(routine_id, spec)| local.get(routine_id) != Some(spec))
+        .collect();
+    SyncPlan { remove, schedule }
+}
+
+pub type SharedRoutines = Arc<Routines>;
+
+/// Routines added over the API live in postgres, so they survive restarts and are the same on every instance.
+/// Every instance keeps their jobs in sync like crawler jobs, and only the leader runs them.
+/// The job scheduler doesn't list its jobs, so routines are tracked here by an id that stays the same
+/// when the rule changes and the job behind it is replaced.
+pub struct Routines {
+    orch: SharedOrchestrator,
+    routines: Mutex<HashMap<Uuid, Routine>>,
+    system: Mutex<Vec<SystemJob>>,
+}
+
+impl Routines {
+    pub fn new(orch: SharedOrchestrator) -> SharedRoutines {
+        Arc::new(Routines {
+            orch,
+            routines: Mutex::new(HashMap::new()),
+            system: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Lists a job the scheduler registered for itself with the routines.
+    pub fn add_system(&self, spec: RoutineSpec, job_id: Uuid) {
+        self.system.lock().expect("system jobs lock is poisoned").push(SystemJob { spec, job_id });
+    }
+
+    pub async fn add(self: &Arc<Self>, spec: RoutineSpec) -> Result<RoutineInfo, RoutineError> {
+        let routine_id = Uuid::now_v7();
+        let job = self.job(routine_id, &spec)?;
+        self.orch.db.add_routine(routine_id, &spec).await?;
+        self.replace_job(routine_id, spec, job).await?;
+        self.get(routine_id).await
+    }
+
+    pub async fn get(&self, routine_id: Uuid) -> Result<RoutineInfo, RoutineError> {
+        let (spec, last_run_at) = self
+            .orch
+            .db
+            .load_routine(routine_id)
+            .await?
+            .ok_or(RoutineError::NotFound(routine_id))?;
+        let job_id = self.lock().get(&routine_id).map(|routine| routine.job_id);
+        let next_run_at = self.next_run_at(routine_id, &spec.rule, job_id).await?;
+        Ok(RoutineInfo { routine_id, kind: RoutineKind::Routine, spec, next_run_at, last_run_at })
+    }
+
+    /// Routines in the order they were added, then jobs of crawlers and the scheduler's own jobs.
+    pub async fn list(&self) -> Result<Vec<RoutineInfo>, RoutineError> {
+        let mut routines = Vec::new();
+        for (routine_id, spec, last_run_at) in self.orch.db.load_routines().await? {
+            let job_id = self.lock().get(&routine_id).map(|routine| routine.job_id);
+            let next_run_at = self.next_run_at(routine_id, &spec.rule, job_id).await?;
+            routines.push(RoutineInfo { routine_id, kind: RoutineKind::Routine, spec, next_run_at, last_run_at });
+        }
+
+        let mut crawlers = self.orch.crawlers.list();
+        crawlers.sort_by_key(|(crawler, _)| crawler.id);
+        for (crawler, job_id) in crawlers {
+            let next_run_at = match job_id {
+                Some(job_id) => self.next_run_at(crawler.id, &crawler.timer_rule, Some(job_id)).await?,
+                // paused
+                None => None,
+            };
+            let spec = RoutineSpec {
+                name: crawler.name,
+                rule: crawler.timer_rule,
+                task: "start a crawl run".into(),
+            };
+            routines.push(RoutineInfo { routine_id: crawler.id, kind: RoutineKind::Crawler, spec, next_run_at, last_run_at: None });
+        }
+
+        let system: Vec<(RoutineSpec, Uuid)> = self
+            .system
+            .lock()
+            .expect("system jobs lock is poisoned")
+            .iter()
+            .map(|job| (job.spec.clone(), job.job_id))
+            .collect();
+        for (spec, job_id) in system {
+            let next_run_at = self.orch.sched.lock().await.next_tick_for_job(job_id).await?;
+            routines.push(RoutineInfo { routine_id: job_id, kind: RoutineKind::System, spec, next_run_at, last_run_at: None });
+        }
+        Ok(routines)
+    }
+
+    /// Replaces the job of the routine with one firing on the new rule.
+    pub async fn update_rule(self: &Arc<Self>, routine_id: Uuid, rule: String) -> Result<RoutineInfo, RoutineError> {
+        let (spec, _) = self
+            .orch
+            .db
+            .load_routine(routine_id)
+            .await?
+            .ok_or(RoutineError::NotFound(routine_id))?;
+        let spec = RoutineSpec { rule, ..spec };
+        let job = self.job(routine_id, &spec)?;
+        if !self.orch.db.set_routine_rule(routine_id, &spec.rule).await? {
+            return Err(RoutineError::NotFound(routine_id));
+        }
+        self.replace_job(routine_id, spec, job).await?;
+        self.get(routine_id).await
+    }
+
+    pub async fn remove(&self, routine_id: Uuid) -> Result<(), RoutineError> {
+        if !self.orch.db.delete_routine(routine_id).await? {
+            return Err(RoutineError::NotFound(routine_id));
+        }
+        self.remove_job(routine_id).await?;
+        tracing::info!("routine {} is removed", routine_id);
+        Ok(())
+    }
+
+    /// Brings routine jobs in line with routines added, changed and removed through any scheduler instance.
+    /// A routine whose job cannot be added, e.g. a one-shot in the past, doesn't hold back the others.
+    pub async fn sync(self: &Arc<Self>) -> Result<(), RoutineError> {
+        let stored = self.orch.db.load_routines().await?;
+        let local: HashMap<Uuid, RoutineSpec> = self
+            .lock()
+            .iter()
+            .map(|(routine_id, routine)| (*routine_id, routine.spec.clone()))
+            .collect();
+        let plan = plan_sync(&local, stored.into_iter().map(|(routine_id, spec, _)| (routine_id, spec)).collect());
+        for routine_id in plan.remove {
+            if let Err(err) = self.remove_job(routine_id).await {
+                tracing::warn!("cannot remove the job of deleted routine {}: {}", routine_id, err);
+            }
+        }
+        for (routine_id, spec) in plan.schedule {
+            let replaced = match self.job(routine_id, &spec) {
+                Ok(job) => self.replace_job(routine_id, spec, job).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = replaced {
+                tracing::warn!("cannot sync the job of routine {}: {}", routine_id, err);
+            }
+        }
+        Ok(())
+    }
+
+    async fn replace_job(&self, routine_id: Uuid, spec: RoutineSpec, job: Job) -> Result<(), RoutineError> {
+        let sched = self.orch.sched.lock().await;
+        let job_id = sched.add(job).await?;
+        let previous = self.lock().insert(routine_id, Routine { spec, job_id });
+        if let Some(previous) = previous {
+            sched.remove(&previous.job_id).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_job(&self, routine_id: Uuid) -> Result<(), RoutineError> {
+        let removed = self.lock().remove(&routine_id);
+        if let Some(routine) = removed {
+            self.orch.sched.lock().await.remove(&routine.job_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Next fire time of the local job, or as the rule has it when this instance has no job yet.
+    async fn next_run_at(&self, key: Uuid, rule: &str, job_id: Option<Uuid>) -> Result<Option<DateTime<Utc>>, RoutineError> {
+        let Ok(schedule) = rule.parse::<Schedule>() else {
+            return Ok(None);
+        };
+        let next_run_at = match job_id {
+            Some(job_id) => self
+                .orch
+                .sched
+                .lock()
+                .await
+                .next_tick_for_job(job_id)
+                .await?
+                .map(|at| at + schedule.offset(key)),
+            None => schedule.next_runs(key, Utc::now(), 1).ok().and_then(|runs| runs.into_iter().next()),
+        };
+        Ok(next_run_at)
+    }
+
+    /// The job fires on every instance, only the leader runs the routine.
+    fn job(self: &Arc<Self>, routine_id: Uuid, spec: &RoutineSpec) -> Result<Job, RoutineError> {
+        let routines = Arc::downgrade(self);
+        let (name, task) = (spec.name.clone(), spec.task.clone());
+        let schedule: Schedule = spec.rule.parse().map_err(|err| RoutineError::InvalidRule(format!("{}", err)))?;
+        schedule
+            .job(routine_id, move || {
+                let Some(routines) = routines.upgrade() else {
+                    return;
+                };
+                if !routines.orch.leader.is_leader() {
+                    return;
+                }
+                tracing::warn!("{}: {}", name, task);
+                tokio::spawn(async move {
+                    if let Err(err) = routines.orch.db.set_routine_run(routine_id, Utc::now()).await {
+                        tracing::error!("cannot record the run of routine {}: {}", routine_id, err);
+                    }
+                });
+            })
+            .map_err(|err| RoutineError::InvalidRule(err.to_string()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Routine>> {
+        self.routines.lock().expect("routines lock is poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(rule: &str) -> RoutineSpec {
+        RoutineSpec { name: "cleanup".into(), rule: rule.into(), task: "drop old pages".into() }
+    }
+
+    #[test]
+    fn sync_schedules_new_and_changed_routines() {
+        let (kept, changed, added) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+        let local = HashMap::from([(kept, spec("every 1h")), (changed, spec("every 1h"))]);
+        let stored = vec![(kept, spec("every 1h")), (changed, spec("daily at 03:00")), (added, spec("every 5m"))];
+
+        let plan = plan_sync(&local, stored);
+
+        assert!(plan.remove.is_empty());
+        assert_eq!(plan.schedule, vec![(changed, spec("daily at 03:00")), (added, spec("every 5m"))]);
+    }
+
+    #[test]
+    fn sync_removes_deleted_routines() {
+        let (kept, deleted) = (Uuid::now_v7(), Uuid::now_v7());
+        let local = HashMap::from([(kept, spec("every 1h")), (deleted, spec("every 1h"))]);
+
+        let plan = plan_sync(&local, vec![(kept, spec("every 1h"))]);
+
+        assert_eq!(plan, SyncPlan { remove: vec![deleted], schedule: Vec::new() });
+    }
+
+    #[test]
+    fn listed_jobs_tell_their_kind() {
+        let info = RoutineInfo {
+            routine_id: Uuid::nil(),
+            kind: RoutineKind::Crawler,
+            spec: spec("every 1h"),
+            next_run_at: None,
+            last_run_at: None,
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["kind"], "crawler");
+        assert_eq!(json["name"], "cleanup");
+    }
+}
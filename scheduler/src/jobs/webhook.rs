@@ -0,0 +1,9 @@
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::jobs::register_leader_job;
+use crate::orchestrator::{self, SharedOrchestrator};
+
+pub async fn register_webhook_jobs(rule: &str, orch: SharedOrchestrator) -> Result<Uuid> {
+    register_leader_job(orch, rule, "webhook retry", |orch| async move { orchestrator::retry_webhooks(&orch).await }).await
+}
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use common::models::{NotificationOptions, NotifyEvery, RunStats, RunStatus};
+
+use crate::notification::NotificationKind;
+
+const DIGEST_BUCKETS_KEY: &str = "digest:buckets";
+
+/// A single event collected for a digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DigestEntry {
+    Changed { crawler_id: Uuid, url: String, fields: Vec<String> },
+    Failed { crawler_id: Uuid, url: String, reason: String },
+    Run { crawler_name: String, run_id: Uuid, status: RunStatus, stats: RunStats },
+}
+
+impl DigestEntry {
+    pub fn kind(&self) -> NotificationKind {
+        match self {
+            DigestEntry::Changed { .. } => NotificationKind::JobDone,
+            DigestEntry::Failed { .. } => NotificationKind::JobFailed,
+            DigestEntry::Run { .. } => NotificationKind::Stats,
+        }
+    }
+}
+
+/// Digest of a user for a period, sent to the latest notification options of the bucket.
+pub struct DueDigest {
+    pub bucket: String,
+    pub options: NotificationOptions,
+}
+
+/// Entries of a digest about to be sent.
+pub struct PendingDigest {
+    pub entries: Vec<DigestEntry>,
+    /// Entries read from the bucket, including ones which cannot be deserialized.
+    taken: usize,
+}
+
+/// Per-user digest buckets in redis, one per NotifyEvery period.
+pub struct Digests {
+    conn: ConnectionManager,
+}
+
+impl Digests {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Digests { conn }
+    }
+
+    /// Collects the entry if the user's level allows it. Options without a period are ignored.
+    pub async fn add(&self, user_id: Uuid, options: &NotificationOptions, entry: DigestEntry) -> Result<()> {
+        let every = match options.every {
+            Some(every) => every,
+            None => return Ok(()),
+        };
+        if !entry.kind().is_allowed(&options.level) {
+            return Ok(());
+        }
+        let bucket = format!("{}:{}", user_id, every);
+        let mut conn = self.conn.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .rpush(entries_key(&bucket), serde_json::to_string(&entry)?).ignore()
+            .set(options_key(&bucket), serde_json::to_string(options)?).ignore()
+            .sadd(DIGEST_BUCKETS_KEY, &bucket).ignore()
+            // the first digest covers events since now
+            .set_nx(sent_at_key(&bucket), Utc::now().timestamp()).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Buckets whose scheduled send time passed since their last digest.
+    pub async fn due(&self, now: DateTime<Utc>) -> Result<Vec<DueDigest>> {
+        let mut conn = self.conn.clone();
+        let buckets: Vec<String> = conn.smembers(DIGEST_BUCKETS_KEY).await?;
+        let mut due = vec![];
+        for bucket in buckets {
+            let options: Option<String> = conn.get(options_key(&bucket)).await?;
+            let options: NotificationOptions = match options.and_then(|o| serde_json::from_str(&o).ok()) {
+                Some(options) => options,
+                None => continue,
+            };
+            let every = match options.every {
+                Some(every) => every,
+                None => continue,
+            };
+            let scheduled = match last_send_time(every, options.send_at(), options.timezone(), now) {
+                Ok(scheduled) => scheduled,
+                Err(err) => {
+                    tracing::error!("invalid digest schedule of {}: {}", bucket, err);
+                    continue;
+                },
+            };
+            let sent_at: Option<i64> = conn.get(sent_at_key(&bucket)).await?;
+            if sent_at.unwrap_or(0) < scheduled.timestamp() {
+                due.push(DueDigest { bucket, options });
+            }
+        }
+        Ok(due)
+    }
+
+    /// Collected entries of the bucket. They stay in the bucket until the digest is marked as sent.
+    pub async fn pending(&self, bucket: &str) -> Result<PendingDigest> {
+        let mut conn = self.conn.clone();
+        let entries: Vec<String> = conn.lrange(entries_key(bucket), 0, -1).await?;
+        Ok(PendingDigest {
+            taken: entries.len(),
+            entries: entries.iter().filter_map(|e| serde_json::from_str(e).ok()).collect(),
+        })
+    }
+
+    /// Removes the entries of a sent digest and marks the bucket as sent.
+    /// Entries collected while the digest was being sent stay for the next one.
+    pub async fn sent(&self, bucket: &str, digest: &PendingDigest) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .ltrim(entries_key(bucket), digest.taken as isize, -1).ignore()
+            .set(sent_at_key(bucket), Utc::now().timestamp()).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+fn entries_key(bucket: &str) -> String {
+    format!("digest:{}:entries", bucket)
+}
+
+fn options_key(bucket: &str) -> String {
+    format!("digest:{}:options", bucket)
+}
+
+fn sent_at_key(bucket: &str) -> String {
+    format!("digest:{}:sent_at", bucket)
+}
+
+/// The latest scheduled send time of the period which is not after now.
+pub fn last_send_time(every: NotifyEvery, send_at: &str, timezone: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let tz: Tz = timezone.parse().map_err(|err| anyhow!("unknown timezone {}: {}", timezone, err))?;
+    let time = NaiveTime::parse_from_str(send_at, "%H:%M")?;
+    let today = now.with_timezone(&tz).date_naive();
+
+    let start = match every {
+        NotifyEvery::Day => today,
+        NotifyEvery::Week => today - Duration::days(today.weekday().num_days_from_monday().into()),
+        NotifyEvery::Month => today.with_day(1).expect("first day of month"),
+    };
+    let scheduled = local_instant(&tz, start, time)?;
+    if scheduled <= now {
+        return Ok(scheduled);
+    }
+    let previous = match every {
+        NotifyEvery::Day => start - Duration::days(1),
+        NotifyEvery::Week => start - Duration::days(7),
+        NotifyEvery::Month => (start - Duration::days(1)).with_day(1).expect("first day of month"),
+    };
+    local_instant(&tz, previous, time)
+}
+
+/// Local time of the date in UTC. Times skipped by a DST change move an hour forward.
+fn local_instant(tz: &Tz, date: NaiveDate, time: NaiveTime) -> Result<DateTime<Utc>> {
+    let local = date.and_time(time);
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|instant| instant.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("{} does not exist in {}", local, tz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn daily_digests_are_due_after_their_time() {
+        let now = at(2024, 5, 15, 10, 0);
+        assert_eq!(last_send_time(NotifyEvery::Day, "09:00", "UTC", now).unwrap(), at(2024, 5, 15, 9, 0));
+        assert_eq!(last_send_time(NotifyEvery::Day, "11:00", "UTC", now).unwrap(), at(2024, 5, 14, 11, 0));
+    }
+
+    #[test]
+    fn weekly_and_monthly_digests_start_their_periods() {
+        // 2024-05-15 is a Wednesday
+        let now = at(2024, 5, 15, 10, 0);
+        assert_eq!(last_send_time(NotifyEvery::Week, "09:00", "UTC", now).unwrap(), at(2024, 5, 13, 9, 0));
+        assert_eq!(last_send_time(NotifyEvery::Month, "09:00", "UTC", now).unwrap(), at(2024, 5, 1, 9, 0));
+        let first = at(2024, 3, 1, 8, 0);
+        assert_eq!(last_send_time(NotifyEvery::Month, "09:00", "UTC", first).unwrap(), at(2024, 2, 1, 9, 0));
+    }
+
+    #[test]
+    fn send_times_are_local() {
+        let now = at(2024, 1, 15, 12, 0);
+        assert_eq!(last_send_time(NotifyEvery::Day, "09:00", "Europe/Berlin", now).unwrap(), at(2024, 1, 15, 8, 0));
+        assert!(last_send_time(NotifyEvery::Day, "09:00", "Mars/Base", now).is_err());
+    }
+}
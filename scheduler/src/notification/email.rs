@@ -0,0 +1,42 @@
+use anyhow::Result;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+
+use crate::config::NotificationConfig;
+use crate::notification::Message;
+
+/// Sends notifications over SMTP. Without TLS it talks plain SMTP, e.g. to a local stand-in server.
+pub struct EmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl EmailSender {
+    pub fn new(cfg: &NotificationConfig) -> Result<Self> {
+        let mut builder = if cfg.smtp_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.smtp_host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.smtp_host)
+        };
+        builder = builder.port(cfg.smtp_port);
+        if !cfg.smtp_user.is_empty() {
+            builder = builder.credentials(Credentials::new(cfg.smtp_user.clone(), cfg.smtp_password.clone()));
+        }
+        Ok(EmailSender {
+            transport: builder.build(),
+            from: cfg.smtp_from.parse()?,
+        })
+    }
+
+    pub async fn send(&self, to: &str, message: &Message) -> Result<()> {
+        let email = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(&message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.text.clone())?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! In-process stand-ins for notification channels: a minimal SMTP server and an HTTP mock
+//! which record everything they receive. Point `SMTP_HOST`/`SMTP_PORT` and `TELEGRAM_API_URL`
+//! at them to check notifications end to end without real mail or Telegram accounts.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedMail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub data: String,
+}
+
+/// SMTP server speaking just enough of the protocol for plain (non TLS) clients.
+pub struct SmtpStandIn {
+    pub addr: SocketAddr,
+    mails: Arc<Mutex<Vec<ReceivedMail>>>,
+}
+
+impl SmtpStandIn {
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let mails = Arc::new(Mutex::new(vec![]));
+        let received = mails.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = serve_smtp(stream, received).await {
+                        tracing::warn!("smtp stand-in session failed: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(SmtpStandIn { addr, mails })
+    }
+
+    pub fn mails(&self) -> Vec<ReceivedMail> {
+        self.mails.lock().expect("smtp stand-in lock is poisoned").clone()
+    }
+}
+
+async fn serve_smtp(stream: TcpStream, received: Arc<Mutex<Vec<ReceivedMail>>>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut mail = ReceivedMail::default();
+    writer.write_all(b"220 localhost stand-in ESMTP\r\n").await?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let command = line.trim_end().to_string();
+        let verb = command.split_whitespace().next().unwrap_or_default().to_uppercase();
+        let reply: &[u8] = match verb.as_str() {
+            "EHLO" => b"250-localhost\r\n250 8BITMIME\r\n",
+            "HELO" | "RSET" | "NOOP" => b"250 OK\r\n",
+            "MAIL" => {
+                mail.from = address(&command);
+                b"250 OK\r\n"
+            },
+            "RCPT" => {
+                mail.to.push(address(&command));
+                b"250 OK\r\n"
+            },
+            "DATA" => {
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await?;
+                mail.data = read_data(&mut reader).await?;
+                received.lock().expect("smtp stand-in lock is poisoned").push(std::mem::take(&mut mail));
+                b"250 OK: queued\r\n"
+            },
+            "QUIT" => {
+                writer.write_all(b"221 Bye\r\n").await?;
+                return Ok(());
+            },
+            _ => b"502 Command not implemented\r\n",
+        };
+        writer.write_all(reply).await?;
+    }
+}
+
+async fn read_data<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<String> {
+    let mut data = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 || line == ".\r\n" {
+            return Ok(data);
+        }
+        // dot-stuffed lines lose their leading dot
+        data.push_str(line.strip_prefix('.').unwrap_or(&line));
+    }
+}
+
+fn address(command: &str) -> String {
+    command
+        .split_once(':')
+        .map(|(_, addr)| addr.trim().trim_start_matches('<').split('>').next().unwrap_or_default().to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+/// HTTP server answering every request with the same status and body.
+pub struct HttpMock {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+}
+
+impl HttpMock {
+    /// Mock of the Telegram Bot API answering every call successfully.
+    pub async fn telegram() -> Result<Self> {
+        Self::start(200, r#"{"ok":true,"result":{}}"#).await
+    }
+
+    pub async fn start(status: u16, body: &'static str) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = serve_http(stream, received, status, body).await {
+                        tracing::warn!("http mock request failed: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(HttpMock { addr, requests })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().expect("http mock lock is poisoned").clone()
+    }
+}
+
+async fn serve_http(
+    stream: TcpStream,
+    received: Arc<Mutex<Vec<ReceivedRequest>>>,
+    status: u16,
+    body: &str,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 || header == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut request_body = vec![0; content_length];
+    reader.read_exact(&mut request_body).await?;
+    received.lock().expect("http mock lock is poisoned").push(ReceivedRequest {
+        method,
+        path,
+        body: String::from_utf8_lossy(&request_body).into_owned(),
+    });
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    );
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
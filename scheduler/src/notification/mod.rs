@@ -0,0 +1,15 @@
+mod digest;
+mod email;
+mod notifier;
+mod telegram;
+mod templates;
+mod webhook;
+#[cfg(any(test, feature = "mocks"))]
+pub mod mock;
+
+pub use digest::*;
+pub use email::*;
+pub use notifier::*;
+pub use telegram::*;
+pub use templates::*;
+pub use webhook::*;
@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Result};
+
+use common::models::{NotificationOptions, NotifyVia};
+
+use crate::config::NotificationConfig;
+use crate::notification::{EmailSender, Message, NotificationKind, TelegramSender};
+
+/// Delivers rendered messages to every channel of the user's notification options.
+pub struct Notifier {
+    email: EmailSender,
+    telegram: Option<TelegramSender>,
+}
+
+impl Notifier {
+    pub fn new(cfg: &NotificationConfig) -> Result<Self> {
+        let telegram = match cfg.telegram_bot_token.is_empty() {
+            true => None,
+            false => Some(TelegramSender::new(cfg)),
+        };
+        Ok(Notifier {
+            email: EmailSender::new(cfg)?,
+            telegram,
+        })
+    }
+
+    /// Sends the message if the level allows its kind. Every channel is tried even if some of them fail.
+    pub async fn notify(&self, options: &NotificationOptions, kind: NotificationKind, message: &Message) -> Result<()> {
+        if !kind.is_allowed(&options.level) {
+            tracing::debug!("{:?} notification is muted by {} level", kind, options.level);
+            return Ok(());
+        }
+        let mut failed = vec![];
+        for via in &options.via {
+            if let Err(err) = self.send(via, message).await {
+                tracing::error!("cannot send {:?} notification via {}: {}", kind, via, err);
+                failed.push(via.to_string());
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow!("notification is not delivered via {}", failed.join(", "))),
+        }
+    }
+
+    pub async fn send(&self, via: &NotifyVia, message: &Message) -> Result<()> {
+        match via {
+            NotifyVia::Email(address) => self.email.send(address, message).await,
+            NotifyVia::Telegram(chat_id) => match &self.telegram {
+                Some(telegram) => telegram.send(chat_id, message).await,
+                None => Err(anyhow!("telegram bot token is not configured")),
+            },
+            NotifyVia::Webhook { url, .. } => {
+                // webhooks get data deliveries, not human readable messages
+                tracing::debug!("skipping notification for webhook {}", url);
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use envconfig::Envconfig;
+
+    use common::models::NotificationLevel;
+
+    use super::*;
+    use crate::notification::mock::{HttpMock, SmtpStandIn};
+
+    fn config(smtp: &SmtpStandIn, telegram: &HttpMock) -> NotificationConfig {
+        let vars = HashMap::from([
+            ("SMTP_HOST".to_string(), smtp.addr.ip().to_string()),
+            ("SMTP_PORT".to_string(), smtp.addr.port().to_string()),
+            ("TELEGRAM_API_URL".to_string(), telegram.url()),
+            ("TELEGRAM_BOT_TOKEN".to_string(), "token".to_string()),
+        ]);
+        NotificationConfig::init_from_hashmap(&vars).unwrap()
+    }
+
+    fn options(level: NotificationLevel) -> NotificationOptions {
+        NotificationOptions {
+            level,
+            via: vec![NotifyVia::Email("user@parsera.site".into()), NotifyVia::Telegram("42".into())],
+            every: None,
+            send_at: None,
+            timezone: None,
+        }
+    }
+
+    fn message() -> Message {
+        Message { subject: "Changes on shop.site".into(), text: "~ price: 10 -> 12\n".into() }
+    }
+
+    #[tokio::test]
+    async fn sends_to_every_channel() {
+        let (smtp, telegram) = (SmtpStandIn::start().await.unwrap(), HttpMock::telegram().await.unwrap());
+        let notifier = Notifier::new(&config(&smtp, &telegram)).unwrap();
+        notifier.notify(&options(NotificationLevel::JobsDone), NotificationKind::JobDone, &message()).await.unwrap();
+
+        let mails = smtp.mails();
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].to, vec!["user@parsera.site".to_string()]);
+        assert!(mails[0].data.contains("Subject: Changes on shop.site"));
+        let requests = telegram.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/bottoken/sendMessage");
+        assert_eq!(requests[0].method, "POST");
+        assert!(requests[0].body.contains(r#""chat_id":"42""#));
+    }
+
+    #[tokio::test]
+    async fn muted_kinds_are_not_sent() {
+        let (smtp, telegram) = (SmtpStandIn::start().await.unwrap(), HttpMock::telegram().await.unwrap());
+        let notifier = Notifier::new(&config(&smtp, &telegram)).unwrap();
+        notifier.notify(&options(NotificationLevel::Statistics), NotificationKind::JobDone, &message()).await.unwrap();
+        assert!(smtp.mails().is_empty());
+        assert!(telegram.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_channels_fail_the_notification() {
+        let smtp = SmtpStandIn::start().await.unwrap();
+        let telegram = HttpMock::start(500, r#"{"ok":false}"#).await.unwrap();
+        let notifier = Notifier::new(&config(&smtp, &telegram)).unwrap();
+        let sent = notifier.notify(&options(NotificationLevel::JobsDone), NotificationKind::Quota, &message()).await;
+        assert!(sent.is_err());
+        // other channels are still tried
+        assert_eq!(smtp.mails().len(), 1);
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::config::NotificationConfig;
+use crate::notification::Message;
+
+#[derive(Debug, Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: String,
+}
+
+/// Sends notifications with the Telegram Bot API. The api url can point to an HTTP mock.
+pub struct TelegramSender {
+    client: reqwest::Client,
+    api_url: String,
+    token: String,
+}
+
+impl TelegramSender {
+    pub fn new(cfg: &NotificationConfig) -> Self {
+        TelegramSender {
+            client: reqwest::Client::new(),
+            api_url: cfg.telegram_api_url.trim_end_matches('/').to_string(),
+            token: cfg.telegram_bot_token.clone(),
+        }
+    }
+
+    pub async fn send(&self, chat_id: &str, message: &Message) -> Result<()> {
+        let url = format!("{}/bot{}/sendMessage", self.api_url, self.token);
+        let body = SendMessage {
+            chat_id,
+            text: format!("{}\n\n{}", message.subject, message.text),
+        };
+        let response = self.client.post(url).json(&body).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("telegram responded with {}: {}", status, text));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use uuid::Uuid;
+
+use common::models::{FieldChange, NotificationLevel, NotifyEvery, Page, PageDiff, QuotaExceeded, RunStats, RunStatus};
+
+use crate::notification::DigestEntry;
+
+/// Kinds of notifications, each one is rendered by its own template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    JobDone,
+    JobFailed,
+    Stats,
+    Quota,
+}
+
+impl NotificationKind {
+    /// Whether the user wants notifications of this kind at the level.
+    pub fn is_allowed(&self, level: &NotificationLevel) -> bool {
+        match level {
+            NotificationLevel::JobsDone => true,
+            NotificationLevel::JobsFailed => {
+                matches!(self, NotificationKind::JobFailed | NotificationKind::Stats | NotificationKind::Quota)
+            },
+            // crawling stops until the quota resets, so users hear about it at any level but DoNotDisturb
+            NotificationLevel::Statistics => matches!(self, NotificationKind::Stats | NotificationKind::Quota),
+            NotificationLevel::DoNotDisturb => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub subject: String,
+    pub text: String,
+}
+
+/// Page data was extracted and differs from the last snapshot.
+pub fn render_job_done(diff: &PageDiff) -> Message {
+    let mut text = format!("Page {} has {} changed fields:\n", diff.url, diff.changes.len());
+    for change in &diff.changes {
+        let _ = match change {
+            FieldChange::Added { field, value } => writeln!(text, "+ {}: {}", field, value),
+            FieldChange::Removed { field, value } => writeln!(text, "- {}: {}", field, value),
+            FieldChange::Changed { field, old, new } => writeln!(text, "~ {}: {} -> {}", field, old, new),
+        };
+    }
+    Message {
+        subject: format!("Changes on {}", diff.url),
+        text,
+    }
+}
+
+/// Page could not be scraped or extracted after all retries.
+pub fn render_job_failed(page: &Page) -> Message {
+    let mut text = format!("Cannot process page {} after {} attempts.\n", page.url, page.fetch_attempts + 1);
+    if let Some(failure) = &page.failure {
+        let _ = writeln!(text, "Reason: {} ({})", failure.kind, failure.message);
+        if let Some(status) = failure.status {
+            let _ = writeln!(text, "Status: {}", status);
+        }
+    }
+    Message {
+        subject: format!("Failed to process {}", page.url),
+        text,
+    }
+}
+
+/// Finished run of a crawler with its page counters.
+pub fn render_stats(crawler_name: &str, run_id: Uuid, status: RunStatus, stats: &RunStats) -> Message {
+    let mut text = format!("Run {} of {} is {}.\n", run_id, crawler_name, status);
+    let _ = writeln!(text, "Pages dispatched: {}", stats.dispatched);
+    let _ = writeln!(text, "Pages scraped: {}", stats.scraped);
+    let _ = writeln!(text, "Pages extracted: {}", stats.extracted);
+    let _ = writeln!(text, "Pages unchanged: {}", stats.skipped);
+    let _ = writeln!(text, "Pages failed: {}", stats.failed);
+    Message {
+        subject: format!("{} run is {}", crawler_name, status),
+        text,
+    }
+}
+
+/// Pages of the user are paused until the quota window resets.
+pub fn render_quota_exceeded(exceeded: &QuotaExceeded) -> Message {
+    let mut text = format!("Your {} quota is used up: {} of {}.\n", exceeded.kind, exceeded.used, exceeded.limit);
+    let _ = match exceeded.resets_at {
+        Some(resets_at) => writeln!(text, "New pages are paused until {} and will be crawled then.", resets_at.format("%Y-%m-%d %H:%M UTC")),
+        None => writeln!(text, "Upgrade your plan to add more."),
+    };
+    Message {
+        subject: format!("{} quota exceeded", exceeded.kind),
+        text,
+    }
+}
+
+/// Single message of collected entries. Users at JobsFailed get failure sections only.
+pub fn render_digest(every: NotifyEvery, level: &NotificationLevel, entries: &[DigestEntry]) -> Message {
+    let only_failures = matches!(level, NotificationLevel::JobsFailed);
+    let mut changes = String::new();
+    let mut failures = String::new();
+    let mut runs = String::new();
+    let (mut scraped, mut failed, mut changed_fields) = (0, 0, 0);
+    for entry in entries.iter().filter(|e| e.kind().is_allowed(level)) {
+        let _ = match entry {
+            DigestEntry::Changed { url, fields, .. } => {
+                changed_fields += fields.len();
+                writeln!(changes, "~ {}: {}", url, fields.join(", "))
+            },
+            DigestEntry::Failed { url, reason, .. } => {
+                failed += 1;
+                writeln!(failures, "! {}: {}", url, reason)
+            },
+            DigestEntry::Run { crawler_name, status, stats, .. } => {
+                scraped += stats.scraped;
+                writeln!(runs, "* {} run is {}: {} scraped, {} failed", crawler_name, status, stats.scraped, stats.failed)
+            },
+        };
+    }
+
+    let mut text = String::new();
+    if !only_failures {
+        let _ = writeln!(text, "Pages scraped: {}\nFailures: {}\nChanged fields: {}\n", scraped, failed, changed_fields);
+    }
+    for (title, section) in [("Failures", &failures), ("Changes", &changes), ("Runs", &runs)] {
+        if section.is_empty() || (only_failures && title != "Failures") {
+            continue;
+        }
+        let _ = writeln!(text, "{}:\n{}", title, section);
+    }
+    if text.is_empty() {
+        text = "Nothing happened during this period.\n".into();
+    }
+    let period = match every {
+        NotifyEvery::Day => "Daily",
+        NotifyEvery::Week => "Weekly",
+        NotifyEvery::Month => "Monthly",
+    };
+    Message {
+        subject: format!("{} Parsera digest", period),
+        text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use common::models::QuotaKind;
+
+    use super::*;
+
+    #[test]
+    fn quota_notifications_are_muted_only_by_do_not_disturb() {
+        for level in [NotificationLevel::JobsDone, NotificationLevel::JobsFailed, NotificationLevel::Statistics] {
+            assert!(NotificationKind::Quota.is_allowed(&level));
+        }
+        assert!(!NotificationKind::Quota.is_allowed(&NotificationLevel::DoNotDisturb));
+        assert!(!NotificationKind::JobDone.is_allowed(&NotificationLevel::JobsFailed));
+    }
+
+    #[test]
+    fn renders_when_the_quota_resets() {
+        let exceeded = QuotaExceeded {
+            user_id: "user".into(),
+            kind: QuotaKind::PagesPerDay,
+            limit: 1000,
+            used: 1000,
+            resets_at: Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).single(),
+        };
+        let message = render_quota_exceeded(&exceeded);
+        assert_eq!(message.subject, "PagesPerDay quota exceeded");
+        assert!(message.text.contains("1000 of 1000"));
+        assert!(message.text.contains("until 2024-05-02 00:00 UTC"));
+    }
+}
@@ -0,0 +1,345 @@
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use serde::Serialize;
+use sha2::Sha256;
+use url::{Host, Url};
+use uuid::Uuid;
+
+use common::models::{NotifyVia, WebhookDeliveryStatus};
+
+use crate::config::NotificationConfig;
+
+pub const SIGNATURE_HEADER: &str = "X-Parsera-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Parsera-Timestamp";
+pub const DELIVERY_HEADER: &str = "X-Parsera-Delivery";
+pub const EVENT_HEADER: &str = "X-Parsera-Event";
+
+/// Kind of data a webhook delivers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookEvent {
+    PageExtracted,
+    RunFinished,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::PageExtracted => "page.extracted",
+            WebhookEvent::RunFinished => "run.finished",
+        }
+    }
+}
+
+/// Body POSTed to the webhook url.
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload<T: Serialize> {
+    pub id: Uuid,
+    pub event: &'static str,
+    pub created_at: DateTime<Utc>,
+    pub data: T,
+}
+
+/// A webhook delivery with its attempt history, stored until delivered or given up.
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub crawler_id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+}
+
+/// Result of a single delivery attempt.
+#[derive(Debug)]
+pub struct WebhookAttempt {
+    pub status: WebhookDeliveryStatus,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+/// Url and secret of every webhook in the notification options.
+pub fn webhook_targets(via: &[NotifyVia]) -> Vec<(&str, &str)> {
+    via.iter()
+        .filter_map(|via| match via {
+            NotifyVia::Webhook { url, secret } => Some((url.as_str(), secret.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hex encoded HMAC-SHA256 of "{timestamp}.{body}" keyed with the webhook secret.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().fold(String::with_capacity(64), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+/// Whether the address is reachable from the internet. Webhook urls come from users,
+/// so requests to loopback, private and link-local networks of the scheduler are refused.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (64..128).contains(&b);
+    let benchmarking = a == 198 && (18..20).contains(&b);
+    let reserved = a >= 240;
+    !(a == 0
+        || shared
+        || benchmarking
+        || reserved
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast())
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = first & 0xfe00 == 0xfc00;
+    let link_local = first & 0xffc0 == 0xfe80;
+    !(ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() || unique_local || link_local)
+}
+
+/// Resolves webhook hosts to public addresses only, so a host name can't point requests into our network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let public: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// POSTs signed payloads to user webhooks and decides when failed deliveries are retried.
+pub struct WebhookSender {
+    client: reqwest::Client,
+    max_attempts: i32,
+    retry_base_delay_ms: i64,
+    allow_private_targets: bool,
+}
+
+impl WebhookSender {
+    pub fn new(cfg: &NotificationConfig) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_millis(cfg.webhook_timeout_ms))
+            // redirects and proxies would take requests past the address checks
+            .redirect(Policy::none())
+            .no_proxy();
+        if !cfg.webhook_allow_private_targets {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        Ok(WebhookSender {
+            client: builder.build()?,
+            max_attempts: cfg.webhook_max_attempts,
+            retry_base_delay_ms: cfg.webhook_retry_base_delay_ms,
+            allow_private_targets: cfg.webhook_allow_private_targets,
+        })
+    }
+
+    /// Refuses urls which aren't http(s) or have a non-public ip as the host.
+    /// Host names are checked by the resolver when they are resolved.
+    fn check_target(&self, url: &str) -> Result<()> {
+        let url = Url::parse(url)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("webhook url scheme {} is not supported", url.scheme()));
+        }
+        let ip = match url.host() {
+            Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+            Some(Host::Domain(_)) => return Ok(()),
+            None => return Err(anyhow!("webhook url has no host")),
+        };
+        match self.allow_private_targets || is_public(ip) {
+            true => Ok(()),
+            false => Err(anyhow!("webhook address {} is not public", ip)),
+        }
+    }
+
+    /// Makes one delivery attempt. Failures are retried with exponential backoff until max attempts,
+    /// urls which are not allowed are given up right away.
+    pub async fn attempt(&self, delivery: &WebhookDelivery, secret: &str) -> WebhookAttempt {
+        let attempts = delivery.attempts + 1;
+        if let Err(err) = self.check_target(&delivery.url) {
+            return WebhookAttempt {
+                status: WebhookDeliveryStatus::Failed,
+                response_status: None,
+                error: Some(err.to_string()),
+                next_attempt_at: None,
+            };
+        }
+        let (response_status, error) = match self.post(delivery, secret).await {
+            Ok(code) => {
+                return WebhookAttempt {
+                    status: WebhookDeliveryStatus::Delivered,
+                    response_status: Some(code),
+                    error: None,
+                    next_attempt_at: None,
+                }
+            },
+            Err((status, err)) => (status, err.to_string()),
+        };
+        // client errors except timeouts and rate limits won't be fixed by retrying
+        let permanent = matches!(response_status, Some(code) if (400..500).contains(&code) && code != 408 && code != 429);
+        if permanent || attempts >= self.max_attempts {
+            return WebhookAttempt {
+                status: WebhookDeliveryStatus::Failed,
+                response_status,
+                error: Some(error),
+                next_attempt_at: None,
+            };
+        }
+        let delay = self.retry_base_delay_ms.saturating_mul(1 << (attempts - 1).min(16));
+        WebhookAttempt {
+            status: WebhookDeliveryStatus::Pending,
+            response_status,
+            error: Some(error),
+            next_attempt_at: Some(Utc::now() + chrono::Duration::milliseconds(delay)),
+        }
+    }
+
+    async fn post(&self, delivery: &WebhookDelivery, secret: &str) -> Result<u16, (Option<u16>, anyhow::Error)> {
+        let timestamp = Utc::now().timestamp();
+        let response = self.client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, timestamp, &delivery.payload)))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .header(EVENT_HEADER, &delivery.event)
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|err| (None, err.into()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err((Some(status.as_u16()), anyhow!("webhook responded with {}: {}", status, text)));
+        }
+        Ok(status.as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use envconfig::Envconfig;
+
+    use super::*;
+    use crate::notification::mock::HttpMock;
+
+    fn sender(allow_private_targets: bool) -> WebhookSender {
+        let vars = HashMap::from([(
+            "WEBHOOK_ALLOW_PRIVATE_TARGETS".to_string(),
+            allow_private_targets.to_string(),
+        )]);
+        WebhookSender::new(&NotificationConfig::init_from_hashmap(&vars).unwrap()).unwrap()
+    }
+
+    fn delivery(url: String) -> WebhookDelivery {
+        WebhookDelivery {
+            id: Uuid::now_v7(),
+            crawler_id: Uuid::now_v7(),
+            user_id: Uuid::now_v7(),
+            url,
+            event: WebhookEvent::PageExtracted.as_str().into(),
+            payload: r#"{"id":1}"#.into(),
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        assert_eq!(
+            sign("secret", 1700000000, r#"{"id":1}"#),
+            "3dd1b9aef568d75f6790a84bd2e5dfa1f44409eef3cbdbd3f10b837376100c11",
+        );
+        assert_ne!(sign("secret", 1700000001, r#"{"id":1}"#), sign("secret", 1700000000, r#"{"id":1}"#));
+    }
+
+    #[test]
+    fn only_public_addresses_are_allowed() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{} must not be public", ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse().unwrap()), "{} must be public", ip);
+        }
+    }
+
+    #[test]
+    fn benchmarking_and_reserved_ranges_are_not_public() {
+        for ip in ["198.18.0.1", "198.19.255.254", "240.0.0.1", "250.1.2.3", "::ffff:198.18.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{} must not be public", ip);
+        }
+        for ip in ["198.17.255.254", "198.20.0.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{} must be public", ip);
+        }
+    }
+
+    #[test]
+    fn refuses_private_and_non_http_urls() {
+        let sender = sender(false);
+        assert!(sender.check_target("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(sender.check_target("http://[::1]:8080/hook").is_err());
+        assert!(sender.check_target("file:///etc/passwd").is_err());
+        assert!(sender.check_target("https://hooks.example.com/parsera").is_ok());
+    }
+
+    #[tokio::test]
+    async fn private_targets_are_given_up_without_a_request() {
+        let mock = HttpMock::start(200, "{}").await.unwrap();
+        let attempt = sender(false).attempt(&delivery(format!("{}/hook", mock.url())), "secret").await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Failed);
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn delivers_and_retries_failures() {
+        let mock = HttpMock::start(200, "{}").await.unwrap();
+        let attempt = sender(true).attempt(&delivery(format!("{}/hook", mock.url())), "secret").await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(mock.requests()[0].body, r#"{"id":1}"#);
+
+        let failing = HttpMock::start(503, "{}").await.unwrap();
+        let attempt = sender(true).attempt(&delivery(format!("{}/hook", failing.url())), "secret").await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(attempt.response_status, Some(503));
+        assert!(attempt.next_attempt_at.is_some());
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{HashSet, VecDeque};
+
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+
+use common::models::{Priority, ScrapeBatch, ScrapeBatchItem};
+
+const PRIORITIES: [Priority; 4] = [Priority::Top, Priority::High, Priority::Common, Priority::Low];
+/// Queued pages which cannot be read back, kept for inspection instead of being dropped.
+const PENDING_DEAD_LETTERS_KEY: &str = "scrape_batches:dead_letters";
+/// Batches picked from one read of a queue, pages past them wait for the next read.
+const BATCHES_PER_READ: usize = 4;
+/// Reads of a queue before a take gives up to another instance taking from it at the same time.
+const TAKE_ATTEMPTS: usize = 3;
+
+/// Removes the pages read from the head of the queue if nobody took them meanwhile,
+/// parks the unreadable ones and puts the pages left over back in front, all at once.
+/// KEYS: queue, dead letters. ARGV: read count, read pages, dead count, dead pages, pages left over.
+const TAKE_SCRIPT: &str = r#"
+local read = tonumber(ARGV[1])
+local head = redis.call('lrange', KEYS[1], 0, read - 1)
+if #head ~= read then
+    return 0
+end
+for i = 1, read do
+    if head[i] ~= ARGV[1 + i] then
+        return 0
+    end
+end
+redis.call('ltrim', KEYS[1], read, -1)
+local dead = tonumber(ARGV[read + 2])
+for i = read + 3, read + 2 + dead do
+    redis.call('rpush', KEYS[2], ARGV[i])
+end
+for i = #ARGV, read + 3 + dead, -1 do
+    redis.call('lpush', KEYS[1], ARGV[i])
+end
+return 1
+"#;
+
+fn pending_key(priority: &Priority) -> String {
+    format!("scrape_batches:{}:pending", priority)
+}
+
+/// Groups pages dispatched to scrapers into batches to send fewer, larger messages.
+/// A batch holds pages of one priority and at most one page per domain, pages of a domain
+/// already in the batch wait for the next one, so batches interleave domains.
+/// Full batches are sent right away, the rest is flushed periodically.
+/// Queued pages live in redis, so they survive a restart and any instance can send them.
+pub struct ScrapeBatcher {
+    size: usize,
+    conn: ConnectionManager,
+}
+
+impl ScrapeBatcher {
+    /// Batching is off with a size of 1 or less.
+    pub fn new(conn: ConnectionManager, size: usize) -> Self {
+        ScrapeBatcher { size, conn }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.size > 1
+    }
+
+    /// Queues a page. Returns batches filled up by it.
+    pub async fn push(&self, item: ScrapeBatchItem) -> Result<Vec<ScrapeBatch>> {
+        let mut conn = self.conn.clone();
+        let priority = item.page.priority.clone();
+        let queued: usize = conn.rpush(pending_key(&priority), serde_json::to_string(&item)?).await?;
+        if queued < self.size {
+            return Ok(Vec::new());
+        }
+        self.take(&priority, true).await
+    }
+
+    /// Takes every queued page as batches, partial ones included.
+    pub async fn flush(&self) -> Result<Vec<ScrapeBatch>> {
+        let mut batches = Vec::new();
+        for priority in &PRIORITIES {
+            loop {
+                let taken = self.take(priority, false).await?;
+                if taken.is_empty() {
+                    break;
+                }
+                batches.extend(taken);
+            }
+        }
+        Ok(batches)
+    }
+
+    /// Puts pages of a batch which couldn't be sent back in front of the queue.
+    pub async fn requeue(&self, batch: ScrapeBatch) -> Result<()> {
+        let mut conn = self.conn.clone();
+        for item in batch.items.into_iter().rev() {
+            let _: () = conn.lpush(pending_key(&item.page.priority), serde_json::to_string(&item)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the head of the queue, splits it into batches and takes them off the queue with the pages
+    /// left over put back in front in one script, so a crash in between loses no page.
+    /// Another instance taking from the queue at the same time makes the read start over.
+    async fn take(&self, priority: &Priority, full_only: bool) -> Result<Vec<ScrapeBatch>> {
+        let mut conn = self.conn.clone();
+        let key = pending_key(priority);
+        let read = (self.size * BATCHES_PER_READ) as isize;
+        for _ in 0..TAKE_ATTEMPTS {
+            let payloads: Vec<String> = conn.lrange(&key, 0, read - 1).await?;
+            if payloads.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let mut queue = VecDeque::with_capacity(payloads.len());
+            let mut dead = Vec::new();
+            for payload in &payloads {
+                match serde_json::from_str::<ScrapeBatchItem>(payload) {
+                    Ok(item) => queue.push_back(item),
+                    Err(err) => {
+                        tracing::error!("cannot read a queued page, moving it to {}: {}", PENDING_DEAD_LETTERS_KEY, err);
+                        dead.push(payload.clone());
+                    },
+                }
+            }
+            let batches = split_batches(&mut queue, self.size, full_only);
+            let left = queue.iter().map(serde_json::to_string).collect::<serde_json::Result<Vec<_>>>()?;
+            let taken: i64 = Script::new(TAKE_SCRIPT)
+                .key(&key)
+                .key(PENDING_DEAD_LETTERS_KEY)
+                .arg(payloads.len())
+                .arg(&payloads)
+                .arg(dead.len())
+                .arg(&dead)
+                .arg(&left)
+                .invoke_async(&mut conn)
+                .await?;
+            if taken == 1 {
+                return Ok(batches);
+            }
+            tracing::debug!("queue {} changed while it was read, reading it again", key);
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Takes batches from the queue until it's empty, or only while it has enough distinct domains for a full batch.
+fn split_batches(queue: &mut VecDeque<ScrapeBatchItem>, size: usize, full_only: bool) -> Vec<ScrapeBatch> {
+    let mut batches = Vec::new();
+    while !queue.is_empty() && (!full_only || distinct_domains(queue) >= size) {
+        batches.push(ScrapeBatch::new(take_batch(queue, size)));
+    }
+    batches
+}
+
+fn distinct_domains(queue: &VecDeque<ScrapeBatchItem>) -> usize {
+    queue.iter().map(|item| item.page.domain.as_str()).collect::<HashSet<_>>().len()
+}
+
+/// Takes up to size pages of distinct domains in queue order, the others keep their places.
+fn take_batch(queue: &mut VecDeque<ScrapeBatchItem>, size: usize) -> Vec<ScrapeBatchItem> {
+    let mut domains = HashSet::new();
+    let mut batch = Vec::new();
+    let mut rest = VecDeque::with_capacity(queue.len());
+    for item in queue.drain(..) {
+        if batch.len() < size && domains.insert(item.page.domain.clone()) {
+            batch.push(item);
+        } else {
+            rest.push_back(item);
+        }
+    }
+    *queue = rest;
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use common::models::EventCommandStatus;
+
+    use super::*;
+    use crate::testing;
+
+    fn queue(urls: &[&str]) -> VecDeque<ScrapeBatchItem> {
+        urls.iter()
+            .map(|url| {
+                let mut page = testing::page(url);
+                page.domain = url::Url::parse(url).unwrap().host_str().unwrap().to_string();
+                ScrapeBatchItem {
+                    event_id: Uuid::now_v7(),
+                    correlation_id: page.id,
+                    status: EventCommandStatus::Pending,
+                    page,
+                }
+            })
+            .collect()
+    }
+
+    fn urls<'a>(items: impl IntoIterator<Item = &'a ScrapeBatchItem>) -> Vec<&'a str> {
+        items.into_iter().map(|item| item.page.url.as_str()).collect()
+    }
+
+    #[test]
+    fn batches_take_one_page_per_domain() {
+        let mut queue = queue(&["https://a.site/1", "https://a.site/2", "https://b.site/1", "https://c.site/1"]);
+
+        let batch = take_batch(&mut queue, 2);
+
+        assert_eq!(urls(&batch), ["https://a.site/1", "https://b.site/1"]);
+        assert_eq!(urls(&queue), ["https://a.site/2", "https://c.site/1"]);
+    }
+
+    #[test]
+    fn batches_are_partial_when_domains_run_out() {
+        let mut queue = queue(&["https://a.site/1", "https://a.site/2"]);
+
+        assert_eq!(urls(&take_batch(&mut queue, 3)), ["https://a.site/1"]);
+        assert_eq!(urls(&take_batch(&mut queue, 3)), ["https://a.site/2"]);
+        assert!(take_batch(&mut queue, 3).is_empty());
+    }
+
+    #[test]
+    fn full_batches_wait_for_enough_domains() {
+        let mut queue = queue(&["https://a.site/1", "https://a.site/2", "https://b.site/1", "https://a.site/3"]);
+
+        let batches = split_batches(&mut queue, 2, true);
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(urls(&batches[0].items), ["https://a.site/1", "https://b.site/1"]);
+        assert_eq!(urls(&queue), ["https://a.site/2", "https://a.site/3"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn pages_left_over_stay_queued_in_order() {
+        let batcher = ScrapeBatcher::new(testing::redis().await, 2);
+        batcher.flush().await.unwrap();
+        let mut items = queue(&["https://a.site/1", "https://a.site/2", "https://b.site/1"]).into_iter();
+
+        assert!(batcher.push(items.next().unwrap()).await.unwrap().is_empty());
+        assert!(batcher.push(items.next().unwrap()).await.unwrap().is_empty());
+        let batches = batcher.push(items.next().unwrap()).await.unwrap();
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(urls(&batches[0].items), ["https://a.site/1", "https://b.site/1"]);
+        let rest = batcher.flush().await.unwrap();
+        assert_eq!(rest.iter().flat_map(|batch| urls(&batch.items)).collect::<Vec<_>>(), ["https://a.site/2"]);
+    }
+
+    #[test]
+    fn flushing_takes_every_page() {
+        let mut queue = queue(&["https://a.site/1", "https://a.site/2", "https://b.site/1"]);
+
+        let batches = split_batches(&mut queue, 2, false);
+
+        assert_eq!(batches.iter().map(|batch| batch.items.len()).collect::<Vec<_>>(), [2, 1]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use uuid::Uuid;
+
+use common::models::{CrawlRun, Page, RunCounter, RunStatus, RunUsage, StopCondition, StopReason};
+
+use crate::orchestrator::{notify_run, push_run_finished, record_run, Orchestrator};
+
+/// Budget keys outlive any run, they are refreshed whenever a page of the run is admitted.
+const RUN_BUDGET_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+fn run_budget_key(run_id: Uuid) -> String {
+    format!("run:{}:budget", run_id)
+}
+
+fn run_pages_key(run_id: Uuid) -> String {
+    format!("run:{}:pages", run_id)
+}
+
+/// started_at, bytes and stopped fields of a budget hash.
+type BudgetFields = (Option<i64>, Option<u64>, Option<u8>);
+
+/// What a run used up so far as kept in redis. Runs started before budgets were kept
+/// have no start time yet.
+#[derive(Debug, Default, PartialEq)]
+pub struct BudgetState {
+    pub pages: u64,
+    pub bytes: u64,
+    pub started_at: Option<DateTime<Utc>>,
+    pub stopped: bool,
+}
+
+/// Usage of running runs shared by every scheduler instance, so a page is checked against
+/// the budget of its crawler without a database query. Pages are counted by id, so a page
+/// dispatched again after a retry is counted once.
+pub struct RunBudgets {
+    conn: ConnectionManager,
+}
+
+impl RunBudgets {
+    pub fn new(conn: ConnectionManager) -> Self {
+        RunBudgets { conn }
+    }
+
+    /// Starts keeping the usage of the run. A run started already keeps its usage.
+    pub async fn start(&self, run: &CrawlRun) -> Result<()> {
+        let key = run_budget_key(run.id);
+        let mut conn = self.conn.clone();
+        let _: () = redis::pipe()
+            .atomic()
+            .hset_nx(&key, "started_at", run.started_at.timestamp_millis()).ignore()
+            .hset_nx(&key, "bytes", run.stats.bytes).ignore()
+            .expire(&key, RUN_BUDGET_TTL_SECS).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Counts the page in its run when `counts_pages` is set and returns the usage of the run.
+    pub async fn admit(&self, run_id: Uuid, page_id: Uuid, counts_pages: bool) -> Result<BudgetState> {
+        let (key, pages_key) = (run_budget_key(run_id), run_pages_key(run_id));
+        let mut conn = self.conn.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if counts_pages {
+            pipe.sadd(&pages_key, page_id.to_string()).ignore()
+                .expire(&pages_key, RUN_BUDGET_TTL_SECS).ignore();
+        }
+        let (pages, fields): (u64, BudgetFields) = pipe
+            .scard(&pages_key)
+            .hget(&key, &["started_at", "bytes", "stopped"])
+            .expire(&key, RUN_BUDGET_TTL_SECS).ignore()
+            .query_async(&mut conn)
+            .await?;
+        let (started_at, bytes, stopped) = fields;
+        Ok(BudgetState {
+            pages,
+            bytes: bytes.unwrap_or_default(),
+            started_at: started_at.and_then(DateTime::from_timestamp_millis),
+            stopped: stopped.is_some(),
+        })
+    }
+
+    pub async fn add_bytes(&self, run_id: Uuid, bytes: u64) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = redis::cmd("HINCRBY").arg(run_budget_key(run_id)).arg("bytes").arg(bytes).query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Turns away every page of the run admitted from now on.
+    pub async fn stop(&self, run_id: Uuid) -> Result<()> {
+        let key = run_budget_key(run_id);
+        let mut conn = self.conn.clone();
+        let _: () = redis::pipe()
+            .hset(&key, "stopped", 1).ignore()
+            .expire(&key, RUN_BUDGET_TTL_SECS).ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Checks a page against the budget of its crawler before it's sent to scrapers.
+/// Pages deeper than allowed are skipped, pages over the other limits stop the whole run.
+/// Budgets are best effort, lookup errors are logged and the page is dispatched.
+pub async fn admit_page(orch: &Orchestrator, page: &Page) -> bool {
+    let run_id = match page.run_id {
+        Some(run_id) => run_id,
+        None => return true,
+    };
+    let crawler = match orch.crawlers.get(page.crawler_id) {
+        Some(crawler) if !crawler.budget.is_unlimited() || !crawler.stop_conditions.is_empty() => crawler,
+        _ => return true,
+    };
+    if !crawler.budget.allows_depth(page.depth) {
+        tracing::info!("skipping page {} of run {} at depth {}", page.url, run_id, page.depth);
+        record_run(orch, Some(run_id), RunCounter::Skipped).await;
+        return false;
+    }
+    let state = match orch.budgets.admit(run_id, page.id, crawler.budget.max_pages.is_some()).await {
+        Ok(state) => state,
+        Err(err) => {
+            tracing::error!("cannot check the budget of run {}: {}", run_id, err);
+            return true;
+        },
+    };
+    let usage = match state {
+        BudgetState { stopped: true, .. } => None,
+        BudgetState { pages, bytes, started_at: Some(started_at), .. } => Some(RunUsage { pages, bytes, started_at }),
+        BudgetState { pages, .. } => match restart_budget(orch, run_id).await {
+            Ok(Some(run)) if run.status == RunStatus::Stopped => None,
+            Ok(Some(run)) => Some(RunUsage { pages, bytes: run.stats.bytes, started_at: run.started_at }),
+            Ok(None) => return true,
+            Err(err) => {
+                tracing::error!("cannot check the budget of run {}: {}", run_id, err);
+                return true;
+            },
+        },
+    };
+    let usage = match usage {
+        Some(usage) => usage,
+        None => {
+            tracing::info!("dropping page {} of stopped run {}", page.url, run_id);
+            record_run(orch, Some(run_id), RunCounter::Skipped).await;
+            return false;
+        },
+    };
+    if let Some(reason) = crawler.budget.exceeded(&usage, Utc::now()) {
+        match orch.db.get_run(run_id).await {
+            Ok(Some(run)) => stop_run(orch, &run, reason).await,
+            Ok(None) => tracing::warn!("cannot stop unknown run {}", run_id),
+            Err(err) => tracing::error!("cannot stop run {}: {}", run_id, err),
+        }
+        record_run(orch, Some(run_id), RunCounter::Skipped).await;
+        return false;
+    }
+    true
+}
+
+/// Starts keeping the usage of a run started before budgets were kept in redis.
+/// Returns None for unknown runs.
+async fn restart_budget(orch: &Orchestrator, run_id: Uuid) -> Result<Option<CrawlRun>> {
+    let run = match orch.db.get_run(run_id).await? {
+        Some(run) => run,
+        None => return Ok(None),
+    };
+    orch.budgets.start(&run).await?;
+    if run.status == RunStatus::Stopped {
+        orch.budgets.stop(run_id).await?;
+    }
+    Ok(Some(run))
+}
+
+/// Adds the body size of a scraped page to its run.
+pub async fn record_bytes(orch: &Orchestrator, page: &Page) {
+    let (run_id, bytes) = match (page.run_id, &page.html) {
+        (Some(run_id), Some(html)) => (run_id, html.len() as u64),
+        _ => return,
+    };
+    if let Err(err) = orch.db.add_run_bytes(run_id, bytes).await {
+        tracing::error!("cannot count bytes of run {}: {}", run_id, err);
+    }
+    if let Err(err) = orch.budgets.add_bytes(run_id, bytes).await {
+        tracing::error!("cannot count bytes in the budget of run {}: {}", run_id, err);
+    }
+}
+
+/// Stops the run of an extracted page once its data meets a stop condition of the crawler.
+/// Pages in flight are still extracted, no new pages of the run are dispatched.
+pub async fn check_stop_conditions(orch: &Orchestrator, page: &Page) {
+    let (run_id, data) = match (page.run_id, &page.data) {
+        (Some(run_id), Some(data)) => (run_id, data),
+        _ => return,
+    };
+    let conditions = match orch.crawlers.get(page.crawler_id) {
+        Some(crawler) if !crawler.stop_conditions.is_empty() => crawler.stop_conditions,
+        _ => return,
+    };
+    let mut met = None;
+    for condition in &conditions {
+        let value = match data.get(condition.field()) {
+            Some(value) => value,
+            None => continue,
+        };
+        let is_met = match condition {
+            StopCondition::FieldEquals { value: expected, .. } => value == expected,
+            StopCondition::SeenLastRun { field } => seen_last_run(orch, page.crawler_id, run_id, field, value).await,
+        };
+        if is_met && met.is_none() {
+            met = Some(condition);
+        }
+    }
+    let condition = match met {
+        Some(condition) => condition,
+        None => return,
+    };
+    tracing::info!("page {} of run {} meets stop condition {:?}", page.url, run_id, condition);
+    match orch.db.get_run(run_id).await {
+        Ok(Some(run)) => stop_run(orch, &run, StopReason::StopCondition).await,
+        Ok(None) => tracing::warn!("cannot stop unknown run {}", run_id),
+        Err(err) => tracing::error!("cannot stop run {}: {}", run_id, err),
+    }
+}
+
+/// Remembers the value for the next run and checks it against the previous one.
+async fn seen_last_run(orch: &Orchestrator, crawler_id: Uuid, run_id: Uuid, field: &str, value: &str) -> bool {
+    if let Err(err) = orch.db.add_seen_value(run_id, crawler_id, field, value).await {
+        tracing::error!("cannot save {} value of run {}: {}", field, run_id, err);
+    }
+    orch.db.seen_last_run(crawler_id, run_id, field, value).await.unwrap_or_else(|err| {
+        tracing::error!("cannot check {} value of run {}: {}", field, run_id, err);
+        false
+    })
+}
+
+/// Ends a running run early with the reason and lets the user know.
+async fn stop_run(orch: &Orchestrator, run: &CrawlRun, reason: StopReason) {
+    if let Err(err) = orch.budgets.stop(run.id).await {
+        tracing::error!("cannot stop the budget of run {}: {}", run.id, err);
+    }
+    match orch.db.stop_run(run.id, reason).await {
+        Ok(true) => {
+            tracing::info!("run {} is stopped by {}: {:?}", run.id, reason, run.stats);
+            notify_run(orch, run.crawler_id, run.id, RunStatus::Stopped, &run.stats).await;
+            push_run_finished(orch, run.crawler_id, run.id, RunStatus::Stopped, &run.stats).await;
+        },
+        Ok(false) => {},
+        Err(err) => tracing::error!("cannot stop run {}: {}", run.id, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    async fn budgets() -> RunBudgets {
+        RunBudgets::new(testing::redis().await)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn counts_a_page_once() {
+        let budgets = budgets().await;
+        let run = CrawlRun::new(&testing::crawler());
+        budgets.start(&run).await.unwrap();
+        let page_id = Uuid::now_v7();
+        assert_eq!(budgets.admit(run.id, page_id, true).await.unwrap().pages, 1);
+        assert_eq!(budgets.admit(run.id, page_id, true).await.unwrap().pages, 1);
+        assert_eq!(budgets.admit(run.id, Uuid::now_v7(), true).await.unwrap().pages, 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn keeps_bytes_and_stops() {
+        let budgets = budgets().await;
+        let run = CrawlRun::new(&testing::crawler());
+        budgets.start(&run).await.unwrap();
+        budgets.add_bytes(run.id, 10).await.unwrap();
+        let state = budgets.admit(run.id, Uuid::now_v7(), false).await.unwrap();
+        assert_eq!(state.pages, 0);
+        assert_eq!(state.bytes, 10);
+        assert_eq!(state.started_at.map(|at| at.timestamp_millis()), Some(run.started_at.timestamp_millis()));
+        assert!(!state.stopped);
+
+        budgets.stop(run.id).await.unwrap();
+        assert!(budgets.admit(run.id, Uuid::now_v7(), false).await.unwrap().stopped);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn runs_without_budgets_have_no_start() {
+        let state = budgets().await.admit(Uuid::now_v7(), Uuid::now_v7(), true).await.unwrap();
+        assert_eq!(state.started_at, None);
+    }
+}
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use crate::jobs::SharedRoutines;
+use crate::orchestrator::SharedOrchestrator;
+
+/// Keeps the leader lease and syncs cron jobs of crawlers and routines registered through other instances,
+/// so a new leader fires every crawler and routine right away.
+pub async fn keep_leadership(orch: SharedOrchestrator, routines: SharedRoutines, every: Duration) {
+    let mut ticks = tokio::time::interval(every);
+    loop {
+        ticks.tick().await;
+        if let Err(err) = orch.leader.campaign().await {
+            tracing::error!("cannot renew the leader lease: {}", err);
+            orch.leader.step_down();
+        }
+        let synced = match orch.db.load_crawlers().await {
+            Ok(crawlers) => orch.crawlers.sync(&orch.sched, crawlers).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = synced {
+            tracing::error!("cannot sync crawler jobs: {}", err);
+        }
+        if let Err(err) = routines.sync().await {
+            tracing::error!("cannot sync routines: {}", err);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use common::models::{ControlTarget, Crawler, CrawlerState, EventProtocol, EventProtocolData, RunStatus};
+
+use crate::orchestrator::Orchestrator;
+
+fn control_target(event: &EventProtocol) -> Result<ControlTarget> {
+    match &event.data {
+        EventProtocolData::Control(target) => Ok(*target),
+        _ => Err(anyhow!("got a {} command but a message format is not control", event.command)),
+    }
+}
+
+pub async fn handle_pause_crawler(orch: &Orchestrator, event: EventProtocol) -> Result<()> {
+    let crawler_id = match control_target(&event)? {
+        ControlTarget::Crawler(crawler_id) => crawler_id,
+        ControlTarget::Run(_) => return Err(anyhow!("cannot pause a run, only crawlers can be paused")),
+    };
+    pause_crawler(orch, crawler_id).await
+}
+
+pub async fn handle_resume_crawler(orch: &Orchestrator, event: EventProtocol) -> Result<()> {
+    let crawler_id = match control_target(&event)? {
+        ControlTarget::Crawler(crawler_id) => crawler_id,
+        ControlTarget::Run(_) => return Err(anyhow!("cannot resume a run, only crawlers can be resumed")),
+    };
+    resume_crawler(orch, crawler_id).await
+}
+
+pub async fn pause_crawler(orch: &Orchestrator, crawler_id: Uuid) -> Result<()> {
+    set_crawler_state(orch, crawler_id, CrawlerState::Paused).await
+}
+
+pub async fn resume_crawler(orch: &Orchestrator, crawler_id: Uuid) -> Result<()> {
+    set_crawler_state(orch, crawler_id, CrawlerState::Active).await
+}
+
+/// Deletes the crawler everywhere and drops its cron job here, returns the deleted crawler.
+pub async fn delete_crawler(orch: &Orchestrator, crawler_id: Uuid) -> Result<Option<Crawler>> {
+    let Some((crawler, _)) = orch.db.load_crawler(crawler_id).await? else {
+        return Ok(None);
+    };
+    if !orch.db.delete_crawler(crawler_id).await? {
+        return Ok(None);
+    }
+    orch.crawlers.remove(&orch.sched, crawler_id).await?;
+    Ok(Some(crawler))
+}
+
+/// Stores the state first, so it survives restarts and other scheduler instances pick it up
+/// on their next sync even for crawlers registered elsewhere, then applies it to the jobs here.
+async fn set_crawler_state(orch: &Orchestrator, crawler_id: Uuid, state: CrawlerState) -> Result<()> {
+    let (crawler, _) = orch
+        .db
+        .load_crawler(crawler_id)
+        .await?
+        .ok_or_else(|| anyhow!("crawler {} is not registered", crawler_id))?;
+    orch.db.set_crawler_state(crawler_id, state).await?;
+    orch.crawlers.apply(&orch.sched, crawler, state).await
+}
+
+/// Marks a running run as cancelled. Its queued messages are dropped by the scheduler and services.
+pub async fn handle_cancel_run(orch: &Orchestrator, event: EventProtocol) -> Result<()> {
+    let run_id = match control_target(&event)? {
+        ControlTarget::Run(run_id) => run_id,
+        ControlTarget::Crawler(_) => return Err(anyhow!("cannot cancel a crawler, pause it instead")),
+    };
+    cancel_run(orch, run_id).await?;
+    Ok(())
+}
+
+/// Returns false when the run is finished already.
+pub async fn cancel_run(orch: &Orchestrator, run_id: Uuid) -> Result<bool> {
+    orch.cancellations.cancel(run_id).await?;
+    let cancelled = orch.db.finish_run(run_id, RunStatus::Cancelled).await?;
+    match cancelled {
+        true => tracing::info!("run {} is cancelled", run_id),
+        false => tracing::warn!("run {} is already finished, nothing to cancel", run_id),
+    }
+    Ok(cancelled)
+}
+
+/// Whether messages of the run must be dropped. Lookup errors are logged and the message is kept.
+pub async fn is_cancelled(orch: &Orchestrator, run_id: Option<Uuid>) -> bool {
+    let run_id = match run_id {
+        Some(run_id) => run_id,
+        None => return false,
+    };
+    orch.cancellations.is_cancelled(run_id).await.unwrap_or_else(|err| {
+        tracing::error!("cannot check if run {} is cancelled: {}", run_id, err);
+        false
+    })
+}
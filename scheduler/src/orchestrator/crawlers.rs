@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use common::models::{Crawler, CrawlerState};
+
+use crate::schedule::Schedule;
+use crate::SharedSheduler;
+
+struct CrawlerJob {
+    crawler: Crawler,
+    job_id: Option<Uuid>,
+}
+
+/// Cron jobs of registered crawlers. A firing job sends its crawler to the trigger channel
+/// which starts a new run. Paused crawlers keep their entry without a job.
+/// Every scheduler instance keeps the jobs, runs are only started by the leader.
+pub struct CrawlerJobs {
+    jobs: Mutex<HashMap<Uuid, CrawlerJob>>,
+    trigger: UnboundedSender<Crawler>,
+}
+
+impl CrawlerJobs {
+    pub fn new(trigger: UnboundedSender<Crawler>) -> Self {
+        CrawlerJobs {
+            jobs: Mutex::new(HashMap::new()),
+            trigger,
+        }
+    }
+
+    /// Registers the crawler's cron job, replacing the previous one.
+    pub async fn schedule(&self, sched: &SharedSheduler, crawler: Crawler) -> Result<()> {
+        let crawler_id = crawler.id;
+        let job_id = self.add_job(sched, &crawler).await?;
+        let previous = self.lock().insert(crawler_id, CrawlerJob { crawler, job_id: Some(job_id) });
+        if let Some(previous_id) = previous.and_then(|p| p.job_id) {
+            sched.lock().await.remove(&previous_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the cron job of the crawler, runs in flight are not affected.
+    pub async fn pause(&self, sched: &SharedSheduler, crawler_id: Uuid) -> Result<()> {
+        let job_id = self
+            .lock()
+            .get_mut(&crawler_id)
+            .ok_or_else(|| anyhow!("crawler {} is not registered", crawler_id))?
+            .job_id
+            .take();
+        if let Some(job_id) = job_id {
+            sched.lock().await.remove(&job_id).await?;
+        }
+        tracing::info!("crawler {} is paused", crawler_id);
+        Ok(())
+    }
+
+    /// Adds the cron job of a paused crawler back.
+    pub async fn resume(&self, sched: &SharedSheduler, crawler_id: Uuid) -> Result<()> {
+        let crawler = match self.lock().get(&crawler_id) {
+            Some(job) if job.job_id.is_some() => return Ok(()),
+            Some(job) => job.crawler.clone(),
+            None => return Err(anyhow!("crawler {} is not registered", crawler_id)),
+        };
+        let job_id = self.add_job(sched, &crawler).await?;
+        if let Some(job) = self.lock().get_mut(&crawler_id) {
+            job.job_id = Some(job_id);
+        }
+        tracing::info!("crawler {} is resumed", crawler_id);
+        Ok(())
+    }
+
+    /// Drops the crawler with its cron job, runs in flight are not affected.
+    pub async fn remove(&self, sched: &SharedSheduler, crawler_id: Uuid) -> Result<()> {
+        let removed = self.lock().remove(&crawler_id);
+        if let Some(job_id) = removed.and_then(|job| job.job_id) {
+            sched.lock().await.remove(&job_id).await?;
+        }
+        tracing::info!("crawler {} is removed", crawler_id);
+        Ok(())
+    }
+
+    /// Brings cron jobs in line with crawlers registered, paused and deleted through any scheduler instance.
+    /// A crawler whose job cannot be added, e.g. a one-shot in the past, doesn't hold back the others.
+    pub async fn sync(&self, sched: &SharedSheduler, crawlers: Vec<(Crawler, CrawlerState)>) -> Result<()> {
+        let stored: HashSet<Uuid> = crawlers.iter().map(|(crawler, _)| crawler.id).collect();
+        let deleted: Vec<Uuid> = self.lock().keys().filter(|id| !stored.contains(id)).copied().collect();
+        for crawler_id in deleted {
+            if let Err(err) = self.remove(sched, crawler_id).await {
+                tracing::warn!("cannot remove the job of deleted crawler {}: {}", crawler_id, err);
+            }
+        }
+        for (crawler, state) in crawlers {
+            let crawler_id = crawler.id;
+            if let Err(err) = self.apply(sched, crawler, state).await {
+                tracing::warn!("cannot sync the job of crawler {}: {}", crawler_id, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the cron job of one crawler in line with its stored state.
+    pub async fn apply(&self, sched: &SharedSheduler, crawler: Crawler, state: CrawlerState) -> Result<()> {
+        let crawler_id = crawler.id;
+        let known = self
+            .lock()
+            .get(&crawler_id)
+            .map(|job| (job.crawler.timer_rule.clone(), job.job_id.is_some()));
+        match (known, state) {
+            (None, CrawlerState::Active) => self.schedule(sched, crawler).await?,
+            (Some((rule, true)), CrawlerState::Active) if rule != crawler.timer_rule => {
+                self.schedule(sched, crawler).await?
+            }
+            (Some((_, true)), CrawlerState::Paused) => self.pause(sched, crawler_id).await?,
+            (Some((_, false)), CrawlerState::Active) => {
+                self.lock().insert(crawler_id, CrawlerJob { crawler, job_id: None });
+                self.resume(sched, crawler_id).await?
+            }
+            (None, CrawlerState::Paused) | (Some((_, false)), CrawlerState::Paused) => {
+                self.lock().insert(crawler_id, CrawlerJob { crawler, job_id: None });
+            }
+            _ => self.update(crawler)?,
+        }
+        Ok(())
+    }
+
+    /// Replaces the stored crawler keeping its job, e.g. after start pages were uploaded.
+    /// Runs started by the job afterwards use it.
+    pub fn update(&self, crawler: Crawler) -> Result<()> {
+        match self.lock().get_mut(&crawler.id) {
+            Some(job) => {
+                job.crawler = crawler;
+                Ok(())
+            },
+            None => Err(anyhow!("crawler {} is not registered", crawler.id)),
+        }
+    }
+
+    /// Registered crawlers with ids of their cron jobs, None for paused ones.
+    pub fn list(&self) -> Vec<(Crawler, Option<Uuid>)> {
+        self.lock().values().map(|job| (job.crawler.clone(), job.job_id)).collect()
+    }
+
+    pub fn get(&self, crawler_id: Uuid) -> Option<Crawler> {
+        self.lock().get(&crawler_id).map(|job| job.crawler.clone())
+    }
+
+    async fn add_job(&self, sched: &SharedSheduler, crawler: &Crawler) -> Result<Uuid> {
+        let trigger = self.trigger.clone();
+        let fired = crawler.clone();
+        let schedule: Schedule = crawler.timer_rule.parse()?;
+        let job = schedule.job(crawler.id, move || {
+            if let Err(err) = trigger.send(fired.clone()) {
+                tracing::error!("cannot trigger a run of crawler {}: {}", fired.id, err);
+            }
+        })?;
+        Ok(sched.lock().await.add(job).await?)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, CrawlerJob>> {
+        self.jobs.lock().expect("crawler jobs lock is poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc;
+    use tokio_cron_scheduler::JobScheduler;
+
+    use super::*;
+    use crate::testing;
+
+    async fn sched() -> SharedSheduler {
+        Arc::new(tokio::sync::Mutex::new(JobScheduler::new().await.unwrap()))
+    }
+
+    fn ids(jobs: &CrawlerJobs) -> HashSet<Uuid> {
+        jobs.list().into_iter().map(|(crawler, _)| crawler.id).collect()
+    }
+
+    #[tokio::test]
+    async fn sync_drops_crawlers_deleted_elsewhere() {
+        let (sched, (trigger, _triggered)) = (sched().await, mpsc::unbounded_channel());
+        let jobs = CrawlerJobs::new(trigger);
+        let (kept, deleted) = (testing::crawler(), testing::crawler());
+        jobs.sync(&sched, vec![(kept.clone(), CrawlerState::Active), (deleted.clone(), CrawlerState::Paused)])
+            .await
+            .unwrap();
+        assert_eq!(ids(&jobs), HashSet::from([kept.id, deleted.id]));
+
+        jobs.sync(&sched, vec![(kept.clone(), CrawlerState::Active)]).await.unwrap();
+
+        assert_eq!(ids(&jobs), HashSet::from([kept.id]));
+        assert!(jobs.get(deleted.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn sync_pauses_and_resumes_jobs() {
+        let (sched, (trigger, _triggered)) = (sched().await, mpsc::unbounded_channel());
+        let jobs = CrawlerJobs::new(trigger);
+        let crawler = testing::crawler();
+        let job_id = |jobs: &CrawlerJobs| jobs.list().first().and_then(|(_, job_id)| *job_id);
+
+        jobs.sync(&sched, vec![(crawler.clone(), CrawlerState::Active)]).await.unwrap();
+        assert!(job_id(&jobs).is_some());
+        jobs.sync(&sched, vec![(crawler.clone(), CrawlerState::Paused)]).await.unwrap();
+        assert!(job_id(&jobs).is_none());
+        jobs.sync(&sched, vec![(crawler, CrawlerState::Active)]).await.unwrap();
+        assert!(job_id(&jobs).is_some());
+    }
+}
@@ -1,14 +1,20 @@
-use anyhow::Result;
-use deadpool_lapin::lapin::publisher_confirm::PublisherConfirm;
-use serde::{Serialize, Deserialize};
-use tokio_cron_scheduler::Job;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
 
 use common::models::{
-    EventCommandStatus, Crawler, EventProtocol, EventProtocolData, Page, Priority, EventCommand, Site
+    EventCause, EventCommandStatus, Crawler, EventProtocol, EventProtocolData, Page, EventCommand, QuotaKind, RunCounter,
+    ScrapeBatch, ScrapeBatchItem
 };
 use uuid::Uuid;
 
-use crate::{broker::Rabbit, config::DatabaseConfig, database::Postgres, utils::ParseraService, SharedSheduler};
+use crate::{broker::{Broker, ParseraService}, cluster::Claim, database::Postgres};
+use crate::frontier::{csv_urls, Admission, CrawlerPatterns, StartUrls};
+use crate::schedule::Schedule;
+use crate::orchestrator::{
+    admit_page, check_stop_conditions, decide_retry, handle_cancel_run, handle_sitemap, seed_from_sitemap, notify, notify_quota, handle_pause_crawler, handle_resume_crawler, is_cancelled, push_page_extracted, record_bytes, record_run, start_run,
+    Orchestrator, RetryDecision,
+};
 
 // #[derive(Debug, Deserialize)]
 // struct Event {
@@ -18,7 +24,7 @@ use crate::{broker::Rabbit, config::DatabaseConfig, database::Postgres, utils::P
 // }
 //
 //
-// pub async fn handle_event(broker: &Rabbit, sched: SharedSheduler, event: &[u8]) {
+// pub async fn handle_event(broker: &dyn Broker, sched: SharedSheduler, event: &[u8]) {
 //     let event_job: Event = match serde_json::from_slice(event);
 //     tracing::info!("handling event {:?}", event_job);
 
@@ -35,7 +41,7 @@ use crate::{broker::Rabbit, config::DatabaseConfig, database::Postgres, utils::P
 //         .expect("cannot register a a new job");
 
 //     tracing::debug!("publishing command to queue");
-//     let confirm = match broker.publish(event, crate::utils::ParseraService::Notification).await {
+//     let confirm = match broker.publish(event, crate::broker::ParseraService::Notification).await {
 //         Ok(c) => c,
 //         Err(err) => {
 //             tracing::error!("cannot send an event to queue: {}", err);
@@ -44,135 +50,667 @@ use crate::{broker::Rabbit, config::DatabaseConfig, database::Postgres, utils::P
 //     };
 // }
 
-pub async fn handle_event(broker: &Rabbit, sched: SharedSheduler, msg: &[u8]) {
-    let event: EventProtocol = match serde_json::from_slice(msg) {
+/// Times failed pages of a scraped batch are sent back to the scheduler before they are given up on.
+const BATCH_RETRY_ATTEMPTS: u32 = 5;
+
+/// Outcome of a failed event handling used by the consumer to route a message.
+#[derive(Debug)]
+pub enum HandleError {
+    /// The message can never be handled (malformed payload), retrying is pointless.
+    Malformed(anyhow::Error),
+    /// Handling failed and the message can be retried.
+    Failed(anyhow::Error),
+    /// Another delivery of the event is being handled, the message is delivered again
+    /// without using up an attempt until that one is done or its claim expires.
+    Busy(anyhow::Error),
+}
+
+impl std::fmt::Display for HandleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandleError::Malformed(err) => write!(f, "malformed message: {}", err),
+            HandleError::Failed(err) => write!(f, "handling failed: {}", err),
+            HandleError::Busy(err) => write!(f, "handled elsewhere: {}", err),
+        }
+    }
+}
+
+pub async fn handle_event(broker: &dyn Broker, orch: &Orchestrator, msg: &[u8]) -> Result<(), HandleError> {
+    let event = match EventProtocol::decode(msg) {
         Ok(e) => e,
         Err(err) => {
-            let msg = std::str::from_utf8(msg).expect("invalid message slice");
+            let msg = String::from_utf8_lossy(msg);
             tracing::error!("Error trying handle a new message in consumer. Msg: {}, Err: {}", msg, err);
-            return;
+            return Err(HandleError::Malformed(err.into()));
         },
     };
 
+    let event_id = event.id;
+    let claim = match orch.processed.claim(event_id).await {
+        Ok(Claim::New(claim)) => claim,
+        Ok(Claim::Done) => {
+            tracing::info!("skipping duplicate {}", event.trace());
+            return Ok(());
+        },
+        Ok(Claim::InProgress) => {
+            return Err(HandleError::Busy(anyhow!("event {} is being handled by another delivery", event_id)));
+        },
+        Err(err) => return Err(HandleError::Failed(err)),
+    };
+
+    tracing::debug!("handling {}", event.trace());
+    let handling = dispatch(broker, orch, event);
+    tokio::pin!(handling);
+    let handled = tokio::select! {
+        handled = &mut handling => handled,
+        () = orch.processed.keep(event_id, &claim) => {
+            tracing::warn!("claim of event {} is taken over, it may be handled twice", event_id);
+            handling.await
+        },
+    };
+    let settled = match &handled {
+        Ok(()) => orch.processed.done(event_id).await,
+        Err(_) => orch.processed.release(event_id).await,
+    };
+    if let Err(err) = settled {
+        tracing::warn!("cannot update the claim of event {}: {}", event_id, err);
+    }
+    handled.map_err(HandleError::Failed)
+}
+
+async fn dispatch(broker: &dyn Broker, orch: &Orchestrator, event: EventProtocol) -> Result<()> {
     match event.command.clone() {
-        EventCommand::RegisterCrawler(_) => handle_register_crawler(broker, event).await,
-        EventCommand::ScrapePage(status) => handle_scrape(broker, status, event).await,
-        EventCommand::ExtractPage(status) => handle_extraction(broker, status, event).await,
-        EventCommand::StorePage(status) => handle_store(broker, status, event).await,
-        EventCommand::NotifyUser(status) => handle_notification(broker, status, event).await,
-        EventCommand::Sleep(status) => handle_sleep(broker, status, event).await,
-    };
-}
-
-pub async fn handle_register_crawler(broker: &Rabbit, event: EventProtocol) {
-    let db = Postgres::new(DatabaseConfig{
-        host: "localhost".to_string(),
-        port: 5432,
-        password: "pass".to_string(),
-        db: "crawlers".to_string(),
-        user: "postgres".to_string(),
-    });
+        EventCommand::RegisterCrawler(_) => handle_register_crawler(broker, orch, event).await,
+        EventCommand::ScrapePage(status) => handle_scrape(broker, orch, status, event).await,
+        EventCommand::ScrapeBatch(status) => handle_scrape_batch(broker, orch, status, event).await,
+        EventCommand::FetchSitemap(status) => handle_sitemap(broker, orch, status, event).await,
+        EventCommand::ExtractPage(status) => handle_extraction(broker, orch, status, event).await,
+        EventCommand::StorePage(_) => handle_store(event).await,
+        EventCommand::PageChanged(status) => handle_page_changed(broker, orch, status, event).await,
+        EventCommand::NotifyUser(_) => handle_notification(event).await,
+        EventCommand::PauseCrawler(_) => handle_pause_crawler(orch, event).await,
+        EventCommand::ResumeCrawler(_) => handle_resume_crawler(orch, event).await,
+        EventCommand::CancelRun(_) => handle_cancel_run(orch, event).await,
+        EventCommand::SuggestSelectors(status) => handle_suggestion(broker, orch, status, event).await,
+        EventCommand::Sleep(_) => handle_sleep(event).await,
+    }
+}
+
+pub async fn handle_register_crawler(broker: &dyn Broker, orch: &Orchestrator, event: EventProtocol) -> Result<()> {
     let crawler = match event.data {
         EventProtocolData::External(crawler) => crawler,
-        EventProtocolData::Internal(_) => {
-            tracing::error!("got a register crawler command but a message format is internal.");
-            return;
-        },
+        _ => return Err(anyhow!("got a register crawler command but a message format is not external")),
+    };
+    // a retried event goes on with the run the failed one started
+    register_crawler(broker, orch, crawler, event.id).await?;
+    Ok(())
+}
+
+/// Stores the crawler, schedules its cron job and starts its first run. Returns the run id.
+/// A CSV sent with the crawler replaces its uploaded start urls.
+pub async fn register_crawler(broker: &dyn Broker, orch: &Orchestrator, mut crawler: Crawler, run_id: Uuid) -> Result<Uuid> {
+    crawler.timer_rule.parse::<Schedule>()?.check()?;
+    CrawlerPatterns::validate(&crawler.url_patterns)?;
+    let csv = crawler.site.start_pages.csv.take().map(|csv| csv_urls(&csv));
+    let uploaded = match &csv {
+        Some(urls) => urls.clone(),
+        None => orch.db.load_start_urls(crawler.id).await?,
     };
-    let page = Page {
+    StartUrls::new(&crawler.site, uploaded)?;
+    if let Some(urls) = &csv {
+        orch.db.set_start_urls(crawler.id, urls).await?;
+    }
+    orch.db.add_crawler(&crawler).await?;
+    orch.crawlers.schedule(&orch.sched, crawler.clone()).await?;
+    start_crawl(broker, orch, crawler, run_id).await
+}
+
+/// Starts a run of the crawler from its start pages and sitemap. Returns the run id.
+/// A run started already goes on after the start pages and sitemap it sent.
+pub async fn start_crawl(broker: &dyn Broker, orch: &Orchestrator, crawler: Crawler, run_id: Uuid) -> Result<Uuid> {
+    // expanded before the run starts so that a broken template doesn't leave an empty run
+    let urls = StartUrls::new(&crawler.site, orch.db.load_start_urls(crawler.id).await?)?;
+    let run = start_run(orch, &crawler, run_id).await?;
+    let sent = orch.seeds.sent(run.id).await?;
+    for (i, url) in urls.iter_from(sent).enumerate() {
+        let page = seed_page(&crawler, run.id, url);
+        record_run(orch, page.run_id, RunCounter::Dispatched).await;
+        if let Err(err) = enqueue_page(broker, orch, page).await {
+            // the page isn't sent, the retried event sends it again
+            record_run(orch, Some(run.id), RunCounter::Skipped).await;
+            return Err(err);
+        }
+        orch.seeds.set_sent(run.id, sent + i + 1).await?;
+    }
+    if crawler.site.sitemap.is_some() && sent <= urls.len() {
+        seed_from_sitemap(broker, orch, &crawler, run.id, urls.iter().next().as_deref()).await?;
+        orch.seeds.set_sent(run.id, urls.len() + 1).await?;
+    }
+    Ok(run.id)
+}
+
+/// A first page of a run at the url, not a page followed from another one.
+pub fn seed_page(crawler: &Crawler, run_id: Uuid, url: String) -> Page {
+    Page {
         id: Uuid::now_v7(),
         crawler_id: crawler.id,
+        user_id: crawler.user_id,
+        run_id: Some(run_id),
         site_id: crawler.site.id,
-        url: crawler.site.start_page,
-        domain: crawler.site.domain,
+        url,
+        domain: crawler.site.domain.clone(),
         is_pagination: false,
+        depth: 0,
         times_reparsed: 0,
-        priority: crawler.priority,
-        notification: crawler.notification,
-        xpaths: crawler.site.page_xpaths,
+        fetch_attempts: 0,
+        priority: crawler.priority.clone(),
+        slot: None,
+        notification: crawler.notification.clone(),
+        xpaths: crawler.site.page_xpaths.clone(),
+        pagination_xpaths: crawler.site.pagination_xpaths.clone(),
+        links: Vec::new(),
         created_at: crawler.created_at,
         updated_at: crawler.updated_at,
         html: None,
         data: None,
-        meta: crawler.meta,
-    };
-    let scrape_event = EventProtocol {
-        command: EventCommand::ScrapePage(EventCommandStatus::Pending),
-        data: EventProtocolData::Internal(page),
-    };
-    handle_scrape(broker, EventCommandStatus::Pending, scrape_event).await;
+        invalid_xpaths: HashMap::new(),
+        fingerprint: None,
+        failure: None,
+        meta: crawler.meta.clone(),
+    }
+}
+
+/// A page of the run at a link found on the parent page, one level deeper.
+pub fn child_page(parent: &Page, url: String) -> Page {
+    Page {
+        id: Uuid::now_v7(),
+        url,
+        is_pagination: true,
+        depth: parent.depth + 1,
+        times_reparsed: 0,
+        fetch_attempts: 0,
+        slot: None,
+        html: None,
+        data: None,
+        links: Vec::new(),
+        invalid_xpaths: HashMap::new(),
+        fingerprint: None,
+        failure: None,
+        ..parent.clone()
+    }
 }
 
-pub async fn handle_scrape(broker: &Rabbit, status: EventCommandStatus, event: EventProtocol) {
+/// Sends the links the extractor found on a page of a run to scrapers as pages one level deeper.
+/// Every link is counted as dispatched before it's enqueued, so the run isn't settled meanwhile.
+async fn follow_links(broker: &dyn Broker, orch: &Orchestrator, page: &Page) -> Result<()> {
+    if page.run_id.is_none() {
+        return Ok(());
+    }
+    for link in &page.links {
+        let url = match orch.frontier.canonicalizer().join(&page.url, link) {
+            Ok(url) => url,
+            Err(err) => {
+                tracing::info!("skipping link {} on page {}: {}", link, page.url, err);
+                continue;
+            },
+        };
+        let child = child_page(page, url);
+        record_run(orch, child.run_id, RunCounter::Dispatched).await;
+        if let Err(err) = enqueue_page(broker, orch, child).await {
+            // the link isn't sent, the retried event sends it again
+            record_run(orch, page.run_id, RunCounter::Skipped).await;
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_scrape(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, mut event: EventProtocol) -> Result<()> {
     // TODO change status of event
-    let mut msg = match serde_json::to_string(&event) {
-        Ok(msg) => msg,
-        Err(err) => {
-            tracing::error!("cannot serialize event got: {}", err);
-            return;
+    let cause = event.cause();
+    if let EventProtocolData::Internal(page) = &mut event.data {
+        if is_cancelled(orch, page.run_id).await {
+            tracing::info!("dropping page {} of cancelled run", page.url);
+            return release_slot(broker, orch, page).await;
         }
-    };
+        // retried and escalated pages keep their slot until they are scraped or given up
+        if !matches!(status, EventCommandStatus::Pending | EventCommandStatus::Failed) {
+            release_slot(broker, orch, page).await?;
+        }
+        // pages are counted after the last step which can fail, so a retried page isn't counted twice
+        match status {
+            EventCommandStatus::Pending => return enqueue_page(broker, orch, page.clone()).await,
+            EventCommandStatus::Done => {
+                if is_unchanged(&orch.db, page).await {
+                    record_scraped(orch, page).await;
+                    handle_unchanged(orch, page).await;
+                    return Ok(());
+                }
+            },
+            EventCommandStatus::Failed => return handle_scrape_failure(broker, orch, cause, page).await,
+            EventCommandStatus::Unchanged => {
+                handle_unchanged(orch, page).await;
+                return Ok(());
+            },
+            EventCommandStatus::Skipped => {
+                tracing::info!("scraper skipped page {}", page.url);
+                record_run(orch, page.run_id, RunCounter::Skipped).await;
+                return Ok(());
+            },
+        }
+    }
+    let event = event.forward();
     match status {
         EventCommandStatus::Pending => {
-            broker.publish(msg.as_bytes(), ParseraService::Scraper).await;
+            broker.publish_event(&event, ParseraService::Scraper).await?;
         },
         EventCommandStatus::Done => {
-            broker.publish(msg.as_bytes(), ParseraService::Extractor).await;
+            broker.publish_event(&event, ParseraService::Extractor).await?;
+            if let EventProtocolData::Internal(page) = &event.data {
+                record_scraped(orch, page).await;
+            }
         },
         EventCommandStatus::Failed => {
-            broker.publish(msg.as_bytes(), ParseraService::HeavyArtillery).await;
+            broker.publish_event(&event, ParseraService::HeavyArtillery).await?;
+        },
+        EventCommandStatus::Unchanged | EventCommandStatus::Skipped => {
+            tracing::warn!("Got {} scrape message without a page", status);
         },
     }
+    Ok(())
 }
 
-pub async fn handle_extraction(broker: &Rabbit, status: EventCommandStatus, event: EventProtocol) {
-    // TODO change status of event
-    let msg = match serde_json::to_string(&event) {
-        Ok(msg) => msg,
+async fn record_scraped(orch: &Orchestrator, page: &Page) {
+    record_run(orch, page.run_id, RunCounter::Scraped).await;
+    record_bytes(orch, page).await;
+}
+
+/// Passes a new page of a run through the frontier and dispatches it at its canonical url.
+/// Pages the frontier turns away are counted as skipped. The frontier is best effort,
+/// when it's unavailable the page is dispatched as is. A page which could not be dispatched
+/// is forgotten by the frontier, so the retried event dispatches it.
+pub async fn enqueue_page(broker: &dyn Broker, orch: &Orchestrator, mut page: Page) -> Result<()> {
+    let patterns = orch.crawlers.get(page.crawler_id).map(|crawler| crawler.url_patterns).unwrap_or_default();
+    let mut admitted = None;
+    match orch.frontier.admit(page.crawler_id, &patterns, page.run_id, &page.url).await {
+        Ok(Admission::New(url)) => {
+            admitted = page.run_id.map(|run_id| (run_id, url.clone()));
+            page.url = url;
+        },
+        Ok(rejected) => {
+            tracing::info!("skipping page of run {:?}: {:?}", page.run_id, rejected);
+            record_run(orch, page.run_id, RunCounter::Skipped).await;
+            return Ok(());
+        },
+        Err(err) => tracing::error!("cannot pass page {} through the frontier: {}", page.url, err),
+    }
+    let result = dispatch_scrape(broker, orch, page).await;
+    if let (Err(_), Some((run_id, url))) = (&result, admitted) {
+        if let Err(err) = orch.frontier.forget(run_id, &url).await {
+            tracing::error!("cannot forget page {} of run {}: {}", url, run_id, err);
+        }
+    }
+    result
+}
+
+/// Sends a page to scrapers with its priority once the user has quota and a free fair share slot.
+pub async fn dispatch_scrape(broker: &dyn Broker, orch: &Orchestrator, page: Page) -> Result<()> {
+    if is_cancelled(orch, page.run_id).await {
+        tracing::info!("dropping page {} of cancelled run", page.url);
+        return Ok(());
+    }
+    if !admit_page(orch, &page).await {
+        return Ok(());
+    }
+    if !check_quota(broker, orch, &page).await? {
+        return Ok(());
+    }
+    let mut page = match orch.fair_share.acquire(page).await? {
+        Some(page) => page,
+        None => return Ok(()),
+    };
+    // attach cache validators so the scraper can send a conditional request
+    if page.fingerprint.is_none() {
+        page.fingerprint = orch.db.get_fingerprint(&page.url).await.unwrap_or_else(|err| {
+            tracing::error!("cannot get fingerprint of {}: {}", page.url, err);
+            None
+        });
+    }
+    let event = EventProtocol::new(
+        EventCommand::ScrapePage(EventCommandStatus::Pending),
+        EventProtocolData::Internal(page),
+    );
+    send_to_scraper(broker, orch, event).await
+}
+
+/// Publishes a scrape event, or queues its page for the next batch when batching is on.
+/// A page that cannot be queued is sent on its own.
+async fn send_to_scraper(broker: &dyn Broker, orch: &Orchestrator, event: EventProtocol) -> Result<()> {
+    if !orch.batcher.is_enabled() {
+        broker.publish_event(&event, ParseraService::Scraper).await?;
+        return Ok(());
+    }
+    let cause = event.cause();
+    let page = match &event.data {
+        EventProtocolData::Internal(page) => page.clone(),
+        _ => return Err(anyhow!("only pages can be batched")),
+    };
+    let item = ScrapeBatchItem {
+        event_id: cause.id,
+        correlation_id: cause.correlation_id,
+        status: EventCommandStatus::Pending,
+        page,
+    };
+    match orch.batcher.push(item).await {
+        Ok(batches) => {
+            for batch in batches {
+                publish_batch(broker, orch, batch).await;
+            }
+        },
+        Err(err) => {
+            tracing::error!("cannot queue a page for a scrape batch, sending it alone: {}", err);
+            broker.publish_event(&event, ParseraService::Scraper).await?;
+        },
+    }
+    Ok(())
+}
+
+/// Sends every queued page to scrapers, partial batches included.
+pub async fn flush_scrape_batches(broker: &dyn Broker, orch: &Orchestrator) {
+    let batches = match orch.batcher.flush().await {
+        Ok(batches) => batches,
         Err(err) => {
-            tracing::error!("cannot serialize event got: {}", err);
+            tracing::error!("cannot take queued scrape batches: {}", err);
             return;
+        },
+    };
+    for batch in batches {
+        publish_batch(broker, orch, batch).await;
+    }
+}
+
+/// Batches which cannot be sent are queued again and go out with the next flush.
+async fn publish_batch(broker: &dyn Broker, orch: &Orchestrator, batch: ScrapeBatch) {
+    tracing::debug!("sending batch {} of {} pages to scrapers", batch.id, batch.items.len());
+    let event = EventProtocol::new(
+        EventCommand::ScrapeBatch(EventCommandStatus::Pending),
+        EventProtocolData::Batch(batch),
+    );
+    if let Err(err) = broker.publish_event(&event, ParseraService::Scraper).await {
+        tracing::error!("cannot send a scrape batch, requeueing its pages: {}", err);
+        if let EventProtocolData::Batch(batch) = event.data {
+            if let Err(err) = orch.batcher.requeue(batch).await {
+                tracing::error!("cannot requeue pages of a scrape batch: {}", err);
+            }
         }
+    }
+}
+
+/// Handles per-page results of a scraped batch. Pages that fail to be handled are sent back
+/// to the scheduler as a smaller batch, so pages handled already aren't handled twice on a retry.
+/// Handling a page again after a failure frees its slot and counts it once, see `handle_scrape`.
+pub async fn handle_scrape_batch(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, event: EventProtocol) -> Result<()> {
+    if matches!(status, EventCommandStatus::Pending) {
+        return Err(anyhow!("got a pending scrape batch, batches are scraped by scrapers"));
+    }
+    let cause = event.cause();
+    let batch = match event.data {
+        EventProtocolData::Batch(batch) => batch,
+        _ => return Err(anyhow!("got a scrape batch command but a message format is not batch")),
     };
+    let (total, mut failed) = (batch.items.len(), Vec::new());
+    for item in batch.items {
+        let page_event = EventProtocol::caused_by(
+            item.cause(),
+            EventCommand::ScrapePage(item.status.clone()),
+            EventProtocolData::Internal(item.page.clone()),
+        );
+        if let Err(err) = handle_scrape(broker, orch, item.status.clone(), page_event).await {
+            tracing::warn!("cannot handle scraped page {} of batch {}: {}", item.page.url, batch.id, err);
+            failed.push(item);
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let attempts = batch.attempts + 1;
+    if attempts >= BATCH_RETRY_ATTEMPTS {
+        tracing::error!("giving up on {} of {} pages of batch {} after {} attempts", failed.len(), total, batch.id, attempts);
+        for item in failed {
+            record_run(orch, item.page.run_id, RunCounter::Failed).await;
+        }
+        return Ok(());
+    }
+    tracing::warn!("{} of {} pages of batch {} failed, retrying them", failed.len(), total, batch.id);
+    let retry = EventProtocol::caused_by(
+        cause,
+        EventCommand::ScrapeBatch(status),
+        EventProtocolData::Batch(ScrapeBatch { id: batch.id, items: failed, attempts }),
+    );
+    broker.publish_event_delayed(&retry, ParseraService::Scheduler, batch.attempts).await?;
+    Ok(())
+}
+
+/// Consumes page quotas of the user. Pages over quota are paused and the user is notified once per window.
+async fn check_quota(broker: &dyn Broker, orch: &Orchestrator, page: &Page) -> Result<bool> {
+    let mut kinds = vec![QuotaKind::PagesPerDay, QuotaKind::PagesPerMonth];
+    if page.times_reparsed > 0 {
+        kinds.push(QuotaKind::ReparsesPerDay);
+    }
+    let exceeded = match orch.quotas.try_consume(page.user_id, &kinds).await? {
+        Some(exceeded) => exceeded,
+        None => return Ok(true),
+    };
+    tracing::warn!("user {} is over {} quota ({}/{}), pausing page {}", page.user_id, exceeded.kind, exceeded.used, exceeded.limit, page.url);
+    if orch.quotas.pause(page, &exceeded).await? {
+        notify_quota(broker, orch, page, exceeded).await?;
+    }
+    Ok(false)
+}
+
+/// Frees the fair share slot held by the page, if any, and dispatches the next deferred page of the user.
+/// A page that cannot be sent goes back to the backlog with its slot freed.
+async fn release_slot(broker: &dyn Broker, orch: &Orchestrator, page: &mut Page) -> Result<()> {
+    let mut next = match orch.fair_share.release(page).await? {
+        Some(next) => next,
+        None => return Ok(()),
+    };
+    // a deferred page of a cancelled run gives its slot back right away
+    while is_cancelled(orch, next.run_id).await {
+        next = match orch.fair_share.release(&mut next).await? {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+    }
+    let event = EventProtocol::new(
+        EventCommand::ScrapePage(EventCommandStatus::Pending),
+        EventProtocolData::Internal(next.clone()),
+    );
+    if let Err(err) = send_to_scraper(broker, orch, event).await {
+        if let Err(requeue_err) = orch.fair_share.requeue(next).await {
+            tracing::error!("cannot put a deferred page back to the backlog: {}", requeue_err);
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Applies the retry policy of the failure class: delayed retry, Heavy Artillery or giving up.
+/// Retries are caused by the failed event, so every attempt of a page can be traced.
+async fn handle_scrape_failure(broker: &dyn Broker, orch: &Orchestrator, cause: EventCause, page: &mut Page) -> Result<()> {
+    let decision = decide_retry(page);
+    let kind = page.failure.as_ref().map(|f| f.kind.to_string()).unwrap_or_default();
+    tracing::warn!("scraping of {} failed ({}), attempt {}: {:?}", page.url, kind, page.fetch_attempts + 1, decision);
+
+    let (command, to) = match decision {
+        RetryDecision::Retry(level) => {
+            page.fetch_attempts += 1;
+            let event = EventProtocol::caused_by(
+                cause,
+                EventCommand::ScrapePage(EventCommandStatus::Pending),
+                EventProtocolData::Internal(page.clone()),
+            );
+            broker.publish_event_delayed(&event, ParseraService::Scraper, level).await?;
+            return Ok(());
+        },
+        RetryDecision::Escalate => {
+            page.fetch_attempts += 1;
+            (EventCommand::ScrapePage(EventCommandStatus::Pending), ParseraService::HeavyArtillery)
+        },
+        RetryDecision::GiveUp => {
+            release_slot(broker, orch, page).await?;
+            let event = EventProtocol::caused_by(
+                cause,
+                EventCommand::ScrapePage(EventCommandStatus::Failed),
+                EventProtocolData::Internal(page.clone()),
+            );
+            notify(broker, orch, &event).await?;
+            record_run(orch, page.run_id, RunCounter::Failed).await;
+            return Ok(());
+        },
+    };
+    let event = EventProtocol::caused_by(cause, command, EventProtocolData::Internal(page.clone()));
+    broker.publish_event(&event, to).await?;
+    Ok(())
+}
+
+/// Compares the content hash of a scraped page with the last stored one.
+/// The database manager saves the new fingerprint along with the extracted data.
+async fn is_unchanged(db: &Postgres, page: &Page) -> bool {
+    let fingerprint = match &page.fingerprint {
+        Some(fingerprint) if fingerprint.hash.is_some() => fingerprint,
+        _ => return false,
+    };
+    let previous = match db.get_fingerprint(&page.url).await {
+        Ok(previous) => previous,
+        Err(err) => {
+            tracing::error!("cannot get fingerprint of {}: {}", page.url, err);
+            return false;
+        }
+    };
+    previous.is_some_and(|p| p.hash == fingerprint.hash)
+}
+
+async fn handle_unchanged(orch: &Orchestrator, page: &Page) {
+    tracing::info!("page {} has not changed since the last scrape, skipping extraction", page.url);
+    record_run(orch, page.run_id, RunCounter::Skipped).await;
+}
+
+pub async fn handle_extraction(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, event: EventProtocol) -> Result<()> {
+    // TODO change status of event
+    let run_id = event.run_id.or_else(|| event.data.run_id());
+    if is_cancelled(orch, run_id).await {
+        tracing::info!("dropping extraction of cancelled run {:?}", run_id);
+        return Ok(());
+    }
+    let event = event.forward();
     match status {
         EventCommandStatus::Pending => {
             // broker.publish(payload, to)
             tracing::warn!("Got extraction message with pending status");
-            return;
         },
         EventCommandStatus::Done => {
-            broker.publish(msg.as_bytes(), ParseraService::DatabaseManager).await;
-            // TODO: check notification rule
-            broker.publish(msg.as_bytes(), ParseraService::Notification).await;
+            if let EventProtocolData::Internal(page) = &event.data {
+                follow_links(broker, orch, page).await?;
+            }
+            broker.publish_event(&event, ParseraService::DatabaseManager).await?;
+            notify(broker, orch, &event).await?;
+            if let EventProtocolData::Internal(page) = &event.data {
+                push_page_extracted(orch, page).await;
+                check_stop_conditions(orch, page).await;
+            }
+            record_run(orch, run_id, RunCounter::Extracted).await;
         },
         EventCommandStatus::Failed => {
             tracing::warn!("Got failed job from extractor. Store + Notification");
-            broker.publish(msg.as_bytes(), ParseraService::DatabaseManager).await;
-            broker.publish(msg.as_bytes(), ParseraService::Notification).await;
+            broker.publish_event(&event, ParseraService::DatabaseManager).await?;
+            notify(broker, orch, &event).await?;
+            record_run(orch, run_id, RunCounter::Failed).await;
+        },
+        EventCommandStatus::Unchanged | EventCommandStatus::Skipped => {
+            tracing::warn!("Got extraction message with {} status", status);
         },
     }
+    Ok(())
+}
+
+pub async fn handle_store(event: EventProtocol) -> Result<()> {
+    // TODO: implement, unsupported messages are dead-lettered instead of crashing the consumer
+    Err(anyhow!("{} is not supported yet", event.command))
 }
 
-pub async fn handle_store(broker: &Rabbit, status: EventCommandStatus, event: EventProtocol) {
+pub async fn handle_page_changed(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, event: EventProtocol) -> Result<()> {
     match status {
-        EventCommandStatus::Pending => todo!(),
-        EventCommandStatus::Done => todo!(),
-        EventCommandStatus::Failed => todo!(),
+        EventCommandStatus::Done => {
+            // only changed pages reach notifications
+            notify(broker, orch, &event).await?;
+        },
+        EventCommandStatus::Pending | EventCommandStatus::Failed | EventCommandStatus::Unchanged | EventCommandStatus::Skipped => {
+            tracing::warn!("Got page changed message with {} status", status);
+        },
     }
+    Ok(())
+}
+
+pub async fn handle_notification(event: EventProtocol) -> Result<()> {
+    // TODO: implement, unsupported messages are dead-lettered instead of crashing the consumer
+    Err(anyhow!("{} is not supported yet", event.command))
 }
 
-pub async fn handle_notification(broker: &Rabbit, status: EventCommandStatus, event: EventProtocol) {
+/// Passes selector suggestion requests on to extractors and stores the suggested xpaths they send back,
+/// so users can fetch them per crawler.
+pub async fn handle_suggestion(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, event: EventProtocol) -> Result<()> {
+    let request = match &event.data {
+        EventProtocolData::Suggestions(request) => request,
+        _ => return Err(anyhow!("got a suggest selectors command but a message format is not suggestions")),
+    };
+    let crawler_id = request
+        .crawler_id
+        .ok_or_else(|| anyhow!("suggestion request {} has no crawler", request.id))?;
     match status {
-        EventCommandStatus::Pending => todo!(),
-        EventCommandStatus::Done => todo!(),
-        EventCommandStatus::Failed => todo!(),
+        EventCommandStatus::Pending => {
+            orch.db.add_suggestion_request(crawler_id, request).await?;
+            tracing::debug!("forwarding suggestion request {} to extractors", request.id);
+            broker.publish_event(&event.forward(), ParseraService::Extractor).await?;
+        },
+        EventCommandStatus::Done | EventCommandStatus::Failed => {
+            tracing::info!("got suggestions for {} fields of request {}", request.suggestions.len(), request.id);
+            if !orch.db.set_suggestions(request, status).await? {
+                tracing::warn!("suggestion request {} of crawler {} is not stored", request.id, crawler_id);
+            }
+        },
+        EventCommandStatus::Unchanged | EventCommandStatus::Skipped => {
+            tracing::warn!("suggestion request {} finished with {} status", request.id, status);
+        },
     }
+    Ok(())
 }
 
-pub async fn handle_sleep(broker: &Rabbit, status: EventCommandStatus, event: EventProtocol) {
-    match status {
-        EventCommandStatus::Pending => todo!(),
-        EventCommandStatus::Done => todo!(),
-        EventCommandStatus::Failed => todo!(),
+pub async fn handle_sleep(event: EventProtocol) -> Result<()> {
+    // TODO: implement, unsupported messages are dead-lettered instead of crashing the consumer
+    Err(anyhow!("{} is not supported yet", event.command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn child_pages_go_one_level_deeper() {
+        let mut parent = testing::page("https://shop.site/list");
+        parent.links = vec!["/list?page=2".to_string()];
+        parent.html = Some("<html></html>".to_string());
+        parent.fetch_attempts = 2;
+
+        let child = child_page(&parent, "https://shop.site/list?page=2".to_string());
+        assert_eq!(child.depth, 1);
+        assert_eq!(child_page(&child, child.url.clone()).depth, 2);
+        assert!(child.is_pagination);
+        assert_ne!(child.id, parent.id);
+        assert_eq!(child.run_id, parent.run_id);
+        assert_eq!(child.pagination_xpaths, parent.pagination_xpaths);
+        assert!(child.links.is_empty() && child.html.is_none());
+        assert_eq!(child.fetch_attempts, 0);
     }
 }
@@ -0,0 +1,229 @@
+use anyhow::Result;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::Script;
+use uuid::Uuid;
+
+use common::models::Page;
+
+fn slots_key(user_id: Uuid) -> String {
+    format!("fair_share:{}:slots", user_id)
+}
+
+fn backlog_key(user_id: Uuid) -> String {
+    format!("fair_share:{}:backlog", user_id)
+}
+
+/// Defers the page, then hands out the oldest deferred page with a new slot if the user has a free one.
+/// KEYS: slots, backlog. ARGV: page, max in flight, now, slot expiry, new slot.
+const ACQUIRE_SCRIPT: &str = r#"
+redis.call('zremrangebyscore', KEYS[1], '-inf', ARGV[3])
+redis.call('rpush', KEYS[2], ARGV[1])
+if redis.call('zcard', KEYS[1]) >= tonumber(ARGV[2]) then
+    return false
+end
+redis.call('zadd', KEYS[1], ARGV[4], ARGV[5])
+redis.call('pexpireat', KEYS[1], ARGV[4])
+return redis.call('lpop', KEYS[2])
+"#;
+
+/// Frees the slot, then hands out the oldest deferred page with a new slot if the user has a free one.
+/// A slot freed already frees nothing, but a slot expired meanwhile still lets the backlog move on.
+/// KEYS: slots, backlog. ARGV: slot, max in flight, now, slot expiry, new slot.
+const RELEASE_SCRIPT: &str = r#"
+local freed = redis.call('zrem', KEYS[1], ARGV[1])
+local expired = redis.call('zremrangebyscore', KEYS[1], '-inf', ARGV[3])
+if freed + expired == 0 or redis.call('zcard', KEYS[1]) >= tonumber(ARGV[2]) then
+    return false
+end
+local page = redis.call('lpop', KEYS[2])
+if page then
+    redis.call('zadd', KEYS[1], ARGV[4], ARGV[5])
+    redis.call('pexpireat', KEYS[1], ARGV[4])
+end
+return page
+"#;
+
+/// Frees the slot of a page which could not be sent and puts the page first in the backlog.
+/// KEYS: slots, backlog. ARGV: slot, page.
+const REQUEUE_SCRIPT: &str = r#"
+redis.call('zrem', KEYS[1], ARGV[1])
+return redis.call('lpush', KEYS[2], ARGV[2])
+"#;
+
+/// Limits pages of a single user dispatched to scrapers at the same time,
+/// so a huge crawl of one user can't fill the queues ahead of everyone else's jobs.
+/// Pages over the limit wait in a backlog in redis until the user's pages are scraped,
+/// shared by every scheduler instance. Every dispatched page holds a slot with its own id,
+/// so a slot is freed once however many times the outcome of its page is delivered.
+/// Slots of pages whose outcome never comes expire after `slot_ttl_ms`.
+pub struct FairShare {
+    conn: ConnectionManager,
+    max_in_flight: usize,
+    slot_ttl_ms: u64,
+}
+
+impl FairShare {
+    pub fn new(conn: ConnectionManager, max_in_flight: usize, slot_ttl_ms: u64) -> Self {
+        FairShare { conn, max_in_flight, slot_ttl_ms }
+    }
+
+    /// Defers the page and takes a slot of its user if there is a free one.
+    /// Returns the page to send right now holding the slot, the oldest deferred page of the user first.
+    pub async fn acquire(&self, page: Page) -> Result<Option<Page>> {
+        let mut conn = self.conn.clone();
+        let (user_id, url) = (page.user_id, page.url.clone());
+        let now = Utc::now().timestamp_millis();
+        let slot = Uuid::now_v7();
+        let next: Option<String> = Script::new(ACQUIRE_SCRIPT)
+            .key(slots_key(user_id))
+            .key(backlog_key(user_id))
+            .arg(serde_json::to_string(&page)?)
+            .arg(self.max_in_flight)
+            .arg(now)
+            .arg(now + self.slot_ttl_ms as i64)
+            .arg(slot.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        if next.is_none() {
+            tracing::debug!("user {} has {} pages in flight, deferring page {}", user_id, self.max_in_flight, url);
+        }
+        with_slot(next, slot)
+    }
+
+    /// Frees the slot the page holds. Pages without a slot, or with a slot freed already, free nothing.
+    /// Returns the next deferred page of the user holding a new slot.
+    pub async fn release(&self, page: &mut Page) -> Result<Option<Page>> {
+        let freed = match page.slot.take() {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let mut conn = self.conn.clone();
+        let now = Utc::now().timestamp_millis();
+        let slot = Uuid::now_v7();
+        let next: Option<String> = Script::new(RELEASE_SCRIPT)
+            .key(slots_key(page.user_id))
+            .key(backlog_key(page.user_id))
+            .arg(freed.to_string())
+            .arg(self.max_in_flight)
+            .arg(now)
+            .arg(now + self.slot_ttl_ms as i64)
+            .arg(slot.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        with_slot(next, slot)
+    }
+
+    /// Puts back a page that got a slot but could not be sent. It goes first with the next free slot.
+    pub async fn requeue(&self, mut page: Page) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let slot = page.slot.take().map(|slot| slot.to_string()).unwrap_or_default();
+        let _: i64 = Script::new(REQUEUE_SCRIPT)
+            .key(slots_key(page.user_id))
+            .key(backlog_key(page.user_id))
+            .arg(slot)
+            .arg(serde_json::to_string(&page)?)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+fn with_slot(page: Option<String>, slot: Uuid) -> Result<Option<Page>> {
+    let mut page: Page = match page {
+        Some(page) => serde_json::from_str(&page)?,
+        None => return Ok(None),
+    };
+    page.slot = Some(slot);
+    Ok(Some(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const SLOT_TTL_MS: u64 = 60_000;
+
+    async fn share(max_in_flight: usize, slot_ttl_ms: u64) -> FairShare {
+        FairShare::new(testing::redis().await, max_in_flight, slot_ttl_ms)
+    }
+
+    /// Pages of one new user.
+    fn pages(n: usize) -> Vec<Page> {
+        let first = testing::page("https://shop.site/0");
+        (0..n)
+            .map(|i| Page {
+                id: Uuid::now_v7(),
+                url: format!("https://shop.site/{}", i),
+                ..first.clone()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn defers_pages_over_the_limit() {
+        let share = share(2, SLOT_TTL_MS).await;
+        let mut pages = pages(3).into_iter();
+        let mut first = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        assert!(first.slot.is_some());
+        assert!(share.acquire(pages.next().unwrap()).await.unwrap().is_some());
+        assert!(share.acquire(pages.next().unwrap()).await.unwrap().is_none());
+
+        let next = share.release(&mut first).await.unwrap().unwrap();
+        assert_eq!(next.url, "https://shop.site/2");
+        assert!(next.slot.is_some());
+        assert!(first.slot.is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn frees_a_slot_once() {
+        let share = share(1, SLOT_TTL_MS).await;
+        let mut pages = pages(3).into_iter();
+        let first = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        assert!(share.acquire(pages.next().unwrap()).await.unwrap().is_none());
+        assert!(share.acquire(pages.next().unwrap()).await.unwrap().is_none());
+
+        // a redelivered outcome of the same page carries the same slot
+        let (mut outcome, mut redelivered) = (first.clone(), first);
+        assert!(share.release(&mut outcome).await.unwrap().is_some());
+        assert!(share.release(&mut redelivered).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn pages_without_slots_free_nothing() {
+        let share = share(1, SLOT_TTL_MS).await;
+        let mut pages = pages(2).into_iter();
+        let mut unsent = pages.next().unwrap();
+        share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        assert!(share.release(&mut unsent).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn requeued_pages_go_first() {
+        let share = share(1, SLOT_TTL_MS).await;
+        let mut pages = pages(2).into_iter();
+        let first = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        share.requeue(first).await.unwrap();
+        let next = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        assert!(next.url.ends_with("/0"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn expired_slots_are_taken_again() {
+        let share = share(1, 1).await;
+        let mut pages = pages(2).into_iter();
+        let mut lost = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        let next = share.acquire(pages.next().unwrap()).await.unwrap().unwrap();
+        assert!(next.url.ends_with("/1"));
+        // the outcome of the lost page coming late frees nothing
+        assert!(share.release(&mut lost).await.unwrap().is_none());
+    }
+}
@@ -1,4 +1,30 @@
 
+mod batching;
+mod budget;
+mod cluster;
+mod control;
+mod crawlers;
 mod events;
+mod fairness;
+mod notify;
+mod progress;
+mod retry;
+mod runs;
+mod sitemaps;
+mod state;
+mod webhooks;
 
+pub use batching::*;
+pub use budget::*;
+pub use cluster::*;
+pub use control::*;
+pub use crawlers::*;
 pub use events::*;
+pub use fairness::*;
+pub use notify::*;
+pub use progress::*;
+pub use retry::*;
+pub use runs::*;
+pub use sitemaps::*;
+pub use state::*;
+pub use webhooks::*;
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use chrono::Utc;
+use uuid::Uuid;
+
+use common::models::{
+    EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, NotificationOptions, Page, QuotaExceeded,
+    RunStats, RunStatus,
+};
+
+use crate::broker::{Broker, ParseraService};
+use crate::notification::{
+    render_digest, render_job_done, render_job_failed, render_quota_exceeded, render_stats, DigestEntry, Message,
+    NotificationKind,
+};
+use crate::orchestrator::Orchestrator;
+
+/// Notifies the user about an event. Without a notifier events are left to the notification service.
+/// Delivery errors are logged and never fail the event, so it isn't handled twice.
+pub async fn notify(broker: &dyn Broker, orch: &Orchestrator, event: &EventProtocol) -> Result<()> {
+    if orch.notifier.is_none() {
+        broker.publish_event(event, ParseraService::Notification).await?;
+        return Ok(());
+    }
+    let (user_id, options, message, entry) = match (&event.command, &event.data) {
+        (EventCommand::PageChanged(_), EventProtocolData::Changes(diff)) => {
+            let crawler = match orch.crawlers.get(diff.crawler_id) {
+                Some(crawler) => crawler,
+                None => {
+                    tracing::warn!("cannot notify about changes of unknown crawler {}", diff.crawler_id);
+                    return Ok(());
+                },
+            };
+            let entry = DigestEntry::Changed {
+                crawler_id: diff.crawler_id,
+                url: diff.url.clone(),
+                fields: diff.changes.iter().map(|c| c.field().to_string()).collect(),
+            };
+            (crawler.user_id, crawler.notification, render_job_done(diff), entry)
+        },
+        (EventCommand::ScrapePage(EventCommandStatus::Failed), EventProtocolData::Internal(page))
+        | (EventCommand::ExtractPage(EventCommandStatus::Failed), EventProtocolData::Internal(page)) => {
+            let entry = DigestEntry::Failed {
+                crawler_id: page.crawler_id,
+                url: page.url.clone(),
+                reason: page.failure.as_ref().map(|f| f.message.clone()).unwrap_or_else(|| "unknown".into()),
+            };
+            (page.user_id, page.notification.clone(), render_job_failed(page), entry)
+        },
+        _ => {
+            tracing::debug!("{} event has no notification template", event.command);
+            return Ok(());
+        },
+    };
+    deliver(orch, user_id, &options, message, entry).await;
+    Ok(())
+}
+
+/// Tells the owner of the page its quota is used up, right away even if the user gets digests.
+/// Without a notifier the event is left to the notification service.
+pub async fn notify_quota(broker: &dyn Broker, orch: &Orchestrator, page: &Page, exceeded: QuotaExceeded) -> Result<()> {
+    let notifier = match &orch.notifier {
+        Some(notifier) => notifier,
+        None => {
+            let event = EventProtocol {
+                run_id: page.run_id,
+                ..EventProtocol::new(
+                    EventCommand::NotifyUser(EventCommandStatus::Pending),
+                    EventProtocolData::Quota(exceeded),
+                )
+            };
+            broker.publish_event(&event, ParseraService::Notification).await?;
+            return Ok(());
+        },
+    };
+    let message = render_quota_exceeded(&exceeded);
+    if let Err(err) = notifier.notify(&page.notification, NotificationKind::Quota, &message).await {
+        tracing::error!("cannot deliver quota notification to user {}: {}", page.user_id, err);
+    }
+    Ok(())
+}
+
+/// Sends run statistics to the crawler's owner once a run is finished.
+pub async fn notify_run(orch: &Orchestrator, crawler_id: Uuid, run_id: Uuid, status: RunStatus, stats: &RunStats) {
+    let crawler = match (&orch.notifier, orch.crawlers.get(crawler_id)) {
+        (Some(_), Some(crawler)) => crawler,
+        _ => return,
+    };
+    let message = render_stats(&crawler.name, run_id, status, stats);
+    let entry = DigestEntry::Run {
+        crawler_name: crawler.name.clone(),
+        run_id,
+        status,
+        stats: stats.clone(),
+    };
+    deliver(orch, crawler.user_id, &crawler.notification, message, entry).await;
+}
+
+/// Sends the message right away or collects the entry for a digest when the user has a period.
+async fn deliver(orch: &Orchestrator, user_id: Uuid, options: &NotificationOptions, message: Message, entry: DigestEntry) {
+    let notifier = match &orch.notifier {
+        Some(notifier) => notifier,
+        None => return,
+    };
+    let kind = entry.kind();
+    let delivered = match options.every {
+        Some(_) => orch.digests.add(user_id, options, entry).await,
+        None => notifier.notify(options, kind, &message).await,
+    };
+    if let Err(err) = delivered {
+        tracing::error!("cannot deliver {:?} notification to user {}: {}", kind, user_id, err);
+    }
+}
+
+/// Sends digests whose scheduled time has come.
+pub async fn send_digests(orch: &Orchestrator) -> Result<()> {
+    let notifier = match &orch.notifier {
+        Some(notifier) => notifier,
+        None => return Ok(()),
+    };
+    for digest in orch.digests.due(Utc::now()).await? {
+        let every = match digest.options.every {
+            Some(every) => every,
+            None => continue,
+        };
+        let pending = orch.digests.pending(&digest.bucket).await?;
+        if pending.entries.is_empty() {
+            orch.digests.sent(&digest.bucket, &pending).await?;
+            continue;
+        }
+        let message = render_digest(every, &digest.options.level, &pending.entries);
+        // digests bypass kind filtering, entries were filtered by level when collected
+        let mut delivered = digest.options.via.is_empty();
+        for via in &digest.options.via {
+            match notifier.send(via, &message).await {
+                Ok(()) => delivered = true,
+                Err(err) => tracing::error!("cannot send {} digest via {}: {}", digest.bucket, via, err),
+            }
+        }
+        // kept for the next attempt when no channel got it, resending to a channel that did would duplicate it
+        if !delivered {
+            continue;
+        }
+        orch.digests.sent(&digest.bucket, &pending).await?;
+        tracing::info!("sent {} digest with {} entries", digest.bucket, pending.entries.len());
+    }
+    Ok(())
+}
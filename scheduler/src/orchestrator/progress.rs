@@ -0,0 +1,53 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+/// Progress outlives any retry of a seeding event.
+const SEED_PROGRESS_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+fn seed_progress_key(seed_id: Uuid) -> String {
+    format!("seed:{}:progress", seed_id)
+}
+
+/// How many urls of a seed, e.g. a fetched sitemap, are sent already. Seeding goes through
+/// the urls in the same order every time, so a retried event goes on where the failed one
+/// stopped instead of sending and counting the first urls again.
+pub struct SeedProgress {
+    conn: ConnectionManager,
+}
+
+impl SeedProgress {
+    pub fn new(conn: ConnectionManager) -> Self {
+        SeedProgress { conn }
+    }
+
+    /// Urls of the seed sent so far.
+    pub async fn sent(&self, seed_id: Uuid) -> Result<usize> {
+        let mut conn = self.conn.clone();
+        let sent: Option<usize> = conn.get(seed_progress_key(seed_id)).await?;
+        Ok(sent.unwrap_or_default())
+    }
+
+    pub async fn set_sent(&self, seed_id: Uuid, sent: usize) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.set_ex(seed_progress_key(seed_id), sent, SEED_PROGRESS_TTL_SECS).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    #[ignore = "needs redis, start it with docker compose up redis"]
+    async fn new_seeds_have_sent_nothing() {
+        let progress = SeedProgress::new(testing::redis().await);
+        let seed_id = Uuid::now_v7();
+        assert_eq!(progress.sent(seed_id).await.unwrap(), 0);
+        progress.set_sent(seed_id, 3).await.unwrap();
+        assert_eq!(progress.sent(seed_id).await.unwrap(), 3);
+    }
+}
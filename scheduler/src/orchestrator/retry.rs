@@ -0,0 +1,88 @@
+use common::models::{FailureKind, Page};
+
+/// How the orchestrator reacts to a failed scrape of some class.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Delayed retries through the wait queues before escalation.
+    pub max_retries: u32,
+    /// Send the page to Heavy Artillery after retries are exhausted, otherwise give up.
+    pub escalate: bool,
+}
+
+impl RetryPolicy {
+    pub fn for_kind(kind: FailureKind) -> Self {
+        match kind {
+            FailureKind::Network => RetryPolicy { max_retries: 3, escalate: true },
+            FailureKind::Timeout => RetryPolicy { max_retries: 3, escalate: true },
+            FailureKind::ServerError => RetryPolicy { max_retries: 4, escalate: false },
+            FailureKind::Blocked => RetryPolicy { max_retries: 0, escalate: true },
+            FailureKind::ClientError => RetryPolicy { max_retries: 0, escalate: false },
+            FailureKind::Unknown => RetryPolicy { max_retries: 1, escalate: true },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RetryDecision {
+    /// Retry after the delay of the given wait queue level.
+    Retry(u32),
+    Escalate,
+    GiveUp,
+}
+
+/// Decides the next step for a failed page. Page::fetch_attempts counts failed fetches already retried,
+/// so a page that failed after escalation is given up.
+pub fn decide_retry(page: &Page) -> RetryDecision {
+    let kind = page.failure.as_ref().map(|f| f.kind).unwrap_or(FailureKind::Unknown);
+    let policy = RetryPolicy::for_kind(kind);
+    let attempts = page.fetch_attempts;
+    if attempts < policy.max_retries {
+        RetryDecision::Retry(attempts)
+    } else if policy.escalate && attempts == policy.max_retries {
+        RetryDecision::Escalate
+    } else {
+        RetryDecision::GiveUp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::models::ScrapeFailure;
+
+    use super::*;
+    use crate::testing;
+
+    fn failed(kind: FailureKind, fetch_attempts: u32) -> Page {
+        let mut page = testing::page("https://shop.site/item");
+        page.failure = Some(ScrapeFailure { kind, status: None, message: String::new() });
+        page.fetch_attempts = fetch_attempts;
+        page
+    }
+
+    #[test]
+    fn retries_then_escalates() {
+        assert_eq!(decide_retry(&failed(FailureKind::Network, 0)), RetryDecision::Retry(0));
+        assert_eq!(decide_retry(&failed(FailureKind::Network, 2)), RetryDecision::Retry(2));
+        assert_eq!(decide_retry(&failed(FailureKind::Network, 3)), RetryDecision::Escalate);
+        assert_eq!(decide_retry(&failed(FailureKind::Network, 4)), RetryDecision::GiveUp);
+    }
+
+    #[test]
+    fn blocked_pages_escalate_right_away() {
+        assert_eq!(decide_retry(&failed(FailureKind::Blocked, 0)), RetryDecision::Escalate);
+        assert_eq!(decide_retry(&failed(FailureKind::Blocked, 1)), RetryDecision::GiveUp);
+    }
+
+    #[test]
+    fn client_errors_are_given_up() {
+        assert_eq!(decide_retry(&failed(FailureKind::ClientError, 0)), RetryDecision::GiveUp);
+        assert_eq!(decide_retry(&failed(FailureKind::ServerError, 4)), RetryDecision::GiveUp);
+    }
+
+    #[test]
+    fn reparses_do_not_use_up_retries() {
+        let mut page = failed(FailureKind::ServerError, 0);
+        page.times_reparsed = 10;
+        assert_eq!(decide_retry(&page), RetryDecision::Retry(0));
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use common::models::{CrawlRun, Crawler, RunCounter, StopCondition};
+
+use crate::orchestrator::{notify_run, push_run_finished, Orchestrator};
+
+/// Registers a new run for a firing of the crawler. A run added already is returned as is,
+/// so a retried event goes on with the run it started.
+pub async fn start_run(orch: &Orchestrator, crawler: &Crawler, run_id: Uuid) -> Result<CrawlRun> {
+    let run = CrawlRun { id: run_id, ..CrawlRun::new(crawler) };
+    if !orch.db.add_run(&run).await? {
+        tracing::info!("crawler {} goes on with run {}", crawler.id, run.id);
+        // the stored run keeps its own start time and counters
+        return orch.db.get_run(run_id).await?.ok_or_else(|| anyhow!("run {} vanished while starting", run_id));
+    }
+    tracing::info!("crawler {} started run {}", crawler.id, run.id);
+    if let Err(err) = orch.budgets.start(&run).await {
+        tracing::error!("cannot keep the budget of run {}: {}", run.id, err);
+    }
+    let remembers = crawler.stop_conditions.iter().any(|c| matches!(c, StopCondition::SeenLastRun { .. }));
+    if remembers {
+        if let Err(err) = orch.db.prune_seen_values(crawler.id, run.id).await {
+            tracing::error!("cannot prune seen values of crawler {}: {}", crawler.id, err);
+        }
+    }
+    Ok(run)
+}
+
+/// Counts a page outcome in its run and finishes the run once every dispatched page is settled.
+/// Run statistics are best effort, errors are logged and never fail the event.
+pub async fn record_run(orch: &Orchestrator, run_id: Option<Uuid>, counter: RunCounter) {
+    let run_id = match run_id {
+        Some(run_id) => run_id,
+        None => return,
+    };
+    let progress = match orch.db.record_run(run_id, counter).await {
+        Ok(Some(run)) => run,
+        Ok(None) => {
+            tracing::warn!("cannot count {} page of unknown run {}", counter, run_id);
+            return;
+        },
+        Err(err) => {
+            tracing::error!("cannot count {} page of run {}: {}", counter, run_id, err);
+            return;
+        },
+    };
+    if !counter.is_terminal() || progress.status.is_finished() || !progress.stats.is_settled() {
+        return;
+    }
+    let status = CrawlRun::final_status(&progress.stats);
+    match orch.db.finish_run(run_id, status).await {
+        Ok(true) => {
+            tracing::info!("run {} finished as {}: {:?}", run_id, status, progress.stats);
+            notify_run(orch, progress.crawler_id, run_id, status, &progress.stats).await;
+            push_run_finished(orch, progress.crawler_id, run_id, status, &progress.stats).await;
+        },
+        Ok(false) => {},
+        Err(err) => tracing::error!("cannot finish run {}: {}", run_id, err),
+    }
+}
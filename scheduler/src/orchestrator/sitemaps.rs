@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+use url::Url;
+use uuid::Uuid;
+
+use common::models::{
+    Crawler, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, RunCounter, SitemapFetch, SitemapKind,
+};
+
+use crate::broker::{Broker, ParseraService};
+use crate::orchestrator::{enqueue_page, is_cancelled, record_run, seed_page, Orchestrator};
+
+/// Sitemap indexes deeper than this aren't followed, so index loops end.
+const MAX_SITEMAP_DEPTH: u32 = 3;
+
+/// Sends the crawler's sitemap, or robots.txt listing it, to scrapers. A sitemap counts as a page
+/// of the run until its pages are seeded, so the run isn't finished while it's fetched.
+/// Robots.txt is taken from the host of the first start url.
+pub async fn seed_from_sitemap(
+    broker: &dyn Broker,
+    orch: &Orchestrator,
+    crawler: &Crawler,
+    run_id: Uuid,
+    start_url: Option<&str>,
+) -> Result<()> {
+    let seed = match &crawler.site.sitemap {
+        Some(seed) => seed,
+        None => return Ok(()),
+    };
+    let (url, kind) = match &seed.url {
+        Some(url) => (url.clone(), SitemapKind::Sitemap),
+        None => {
+            let start_url = start_url.ok_or_else(|| anyhow!("no start url to find robots.txt of"))?;
+            (Url::parse(start_url)?.join("/robots.txt")?.to_string(), SitemapKind::Robots)
+        },
+    };
+    let since = match seed.changed_only {
+        true => orch.db.previous_run_started_at(crawler.id, run_id).await?,
+        false => None,
+    };
+    let fetch = SitemapFetch {
+        id: Uuid::now_v7(),
+        crawler_id: crawler.id,
+        user_id: crawler.user_id,
+        run_id: Some(run_id),
+        url,
+        kind,
+        depth: 0,
+        since,
+        sitemaps: Vec::new(),
+        pages: Vec::new(),
+        failure: None,
+    };
+    send_sitemap(broker, orch, fetch).await
+}
+
+async fn send_sitemap(broker: &dyn Broker, orch: &Orchestrator, fetch: SitemapFetch) -> Result<()> {
+    tracing::debug!("sending sitemap {} of run {:?} to scrapers", fetch.url, fetch.run_id);
+    record_run(orch, fetch.run_id, RunCounter::Dispatched).await;
+    let event = EventProtocol::new(
+        EventCommand::FetchSitemap(EventCommandStatus::Pending),
+        EventProtocolData::Sitemap(fetch),
+    );
+    if let Err(err) = broker.publish_event(&event, ParseraService::Scraper).await {
+        // the sitemap isn't sent, it's sent and counted again by the retried event
+        record_run(orch, event.data.run_id(), RunCounter::Skipped).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Seeds the run with pages of a fetched sitemap, changed since the previous run if the crawler asks so,
+/// and fetches sitemaps it lists. Seeded pages go through the frontier and the crawler's budget.
+/// Seeding is resumed by a retried event, nested sitemaps and pages sent already aren't sent again.
+pub async fn handle_sitemap(broker: &dyn Broker, orch: &Orchestrator, status: EventCommandStatus, event: EventProtocol) -> Result<()> {
+    let fetch = match event.data {
+        EventProtocolData::Sitemap(fetch) => fetch,
+        _ => return Err(anyhow!("got a fetch sitemap command but a message format is not sitemap")),
+    };
+    if is_cancelled(orch, fetch.run_id).await {
+        tracing::info!("dropping sitemap {} of cancelled run", fetch.url);
+        return Ok(());
+    }
+    match status {
+        EventCommandStatus::Pending => return Err(anyhow!("got a pending sitemap, sitemaps are fetched by scrapers")),
+        EventCommandStatus::Failed => {
+            let failure = fetch.failure.as_ref().map(|f| f.message.clone()).unwrap_or_default();
+            tracing::warn!("cannot fetch sitemap {} of run {:?}: {}", fetch.url, fetch.run_id, failure);
+            record_run(orch, fetch.run_id, RunCounter::Failed).await;
+            return Ok(());
+        },
+        EventCommandStatus::Done | EventCommandStatus::Unchanged | EventCommandStatus::Skipped => {},
+    }
+    let (crawler, run_id) = match (orch.crawlers.get(fetch.crawler_id), fetch.run_id) {
+        (Some(crawler), Some(run_id)) => (crawler, run_id),
+        _ => {
+            tracing::warn!("dropping sitemap {} of unknown crawler {} or run", fetch.url, fetch.crawler_id);
+            record_run(orch, fetch.run_id, RunCounter::Skipped).await;
+            return Ok(());
+        },
+    };
+    let sitemaps = match fetch.depth < MAX_SITEMAP_DEPTH {
+        true => fetch.sitemaps.as_slice(),
+        false => {
+            if !fetch.sitemaps.is_empty() {
+                tracing::warn!("not following {} sitemaps of {}, it's nested too deep", fetch.sitemaps.len(), fetch.url);
+            }
+            &[]
+        },
+    };
+    let changed = fetch
+        .pages
+        .iter()
+        .filter(|entry| match (fetch.since, entry.lastmod) {
+            (Some(since), Some(lastmod)) => lastmod > since,
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    // a retried event goes on after the nested sitemaps and pages sent already
+    let sent = orch.seeds.sent(fetch.id).await?;
+    if sent == 0 {
+        tracing::info!("seeding run {} with {} of {} pages of sitemap {}", run_id, changed.len(), fetch.pages.len(), fetch.url);
+    } else {
+        tracing::info!("seeding run {} from sitemap {} again after {} urls", run_id, fetch.url, sent);
+    }
+    for (i, url) in sitemaps.iter().enumerate().skip(sent) {
+        send_sitemap(broker, orch, fetch.nested(url.clone())).await?;
+        orch.seeds.set_sent(fetch.id, i + 1).await?;
+    }
+    for (i, entry) in changed.iter().enumerate().skip(sent.saturating_sub(sitemaps.len())) {
+        let page = seed_page(&crawler, run_id, entry.url.clone());
+        record_run(orch, page.run_id, RunCounter::Dispatched).await;
+        if let Err(err) = enqueue_page(broker, orch, page).await {
+            // the page isn't sent, the retried event sends it again
+            record_run(orch, Some(run_id), RunCounter::Skipped).await;
+            return Err(err);
+        }
+        orch.seeds.set_sent(fetch.id, sitemaps.len() + i + 1).await?;
+    }
+    // the sitemap itself is done once its pages are dispatched
+    record_run(orch, fetch.run_id, RunCounter::Scraped).await;
+    record_run(orch, fetch.run_id, RunCounter::Skipped).await;
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use crate::cluster::{Leadership, ProcessedEvents};
+use crate::control::Cancellations;
+use crate::frontier::Frontier;
+use crate::notification::{Digests, Notifier, WebhookSender};
+use crate::orchestrator::{CrawlerJobs, FairShare, RunBudgets, ScrapeBatcher, SeedProgress};
+use crate::quota::Quotas;
+use crate::{SharedDatabase, SharedSheduler};
+
+/// Shared state the event handlers work with.
+pub struct Orchestrator {
+    pub db: SharedDatabase,
+    pub sched: SharedSheduler,
+    pub fair_share: FairShare,
+    pub batcher: ScrapeBatcher,
+    pub quotas: Quotas,
+    pub crawlers: CrawlerJobs,
+    pub cancellations: Cancellations,
+    pub frontier: Frontier,
+    pub budgets: RunBudgets,
+    pub seeds: SeedProgress,
+    pub leader: Leadership,
+    pub processed: ProcessedEvents,
+    /// None when notifications are sent by the notification service.
+    pub notifier: Option<Notifier>,
+    pub digests: Digests,
+    pub webhooks: WebhookSender,
+}
+
+pub type SharedOrchestrator = Arc<Orchestrator>;
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use uuid::Uuid;
+
+use common::models::{NotificationOptions, Page, RunStats, RunStatus, WebhookDeliveryStatus};
+
+use crate::notification::{webhook_targets, WebhookAttempt, WebhookDelivery, WebhookEvent, WebhookPayload};
+use crate::orchestrator::Orchestrator;
+
+/// Deliveries leased by one retry pass.
+const RETRY_BATCH_SIZE: i64 = 100;
+/// Time a leased delivery is hidden from other retry passes while it's being sent.
+const RETRY_LEASE_SECS: i64 = 300;
+
+#[derive(Debug, Serialize)]
+struct PageExtracted<'a> {
+    crawler_id: Uuid,
+    run_id: Option<Uuid>,
+    page_id: Uuid,
+    url: &'a str,
+    data: &'a Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct RunFinished<'a> {
+    crawler_id: Uuid,
+    crawler_name: &'a str,
+    run_id: Uuid,
+    status: RunStatus,
+    stats: &'a RunStats,
+}
+
+/// Sends the extracted data of a page to the crawler's webhooks.
+pub async fn push_page_extracted(orch: &Orchestrator, page: &Page) {
+    let data = PageExtracted {
+        crawler_id: page.crawler_id,
+        run_id: page.run_id,
+        page_id: page.id,
+        url: &page.url,
+        data: &page.data,
+    };
+    push(orch, page.crawler_id, page.user_id, &page.notification, WebhookEvent::PageExtracted, &data).await;
+}
+
+/// Sends the summary of a finished run to the crawler's webhooks.
+pub async fn push_run_finished(orch: &Orchestrator, crawler_id: Uuid, run_id: Uuid, status: RunStatus, stats: &RunStats) {
+    let crawler = match orch.crawlers.get(crawler_id) {
+        Some(crawler) => crawler,
+        None => return,
+    };
+    let data = RunFinished {
+        crawler_id,
+        crawler_name: &crawler.name,
+        run_id,
+        status,
+        stats,
+    };
+    push(orch, crawler_id, crawler.user_id, &crawler.notification, WebhookEvent::RunFinished, &data).await;
+}
+
+/// Stores a delivery per webhook, the retry job sends it on its next pass. Sending only from there
+/// keeps a delivery from being sent twice. Webhooks receive data regardless of the notification level,
+/// errors are logged and never fail the event.
+async fn push<T: Serialize>(
+    orch: &Orchestrator,
+    crawler_id: Uuid,
+    user_id: Uuid,
+    options: &NotificationOptions,
+    event: WebhookEvent,
+    data: &T,
+) {
+    for (url, _) in webhook_targets(&options.via) {
+        let id = Uuid::now_v7();
+        let payload = WebhookPayload { id, event: event.as_str(), created_at: Utc::now(), data };
+        let payload = match serde_json::to_string(&payload) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::error!("cannot serialize {} webhook payload: {}", event.as_str(), err);
+                continue;
+            },
+        };
+        let delivery = WebhookDelivery {
+            id,
+            crawler_id,
+            user_id,
+            url: url.to_string(),
+            event: event.as_str().to_string(),
+            payload,
+            attempts: 0,
+        };
+        if let Err(err) = orch.db.add_webhook_delivery(&delivery).await {
+            tracing::error!("cannot store webhook delivery for crawler {}: {}", crawler_id, err);
+        }
+    }
+}
+
+/// Retries pending deliveries whose backoff has passed.
+pub async fn retry_webhooks(orch: &Orchestrator) -> Result<()> {
+    let deliveries = orch.db.lease_webhook_deliveries(RETRY_BATCH_SIZE, RETRY_LEASE_SECS).await?;
+    for delivery in deliveries {
+        // secrets aren't stored with deliveries, they are taken from the crawler's current options
+        let secret = orch.crawlers.get(delivery.crawler_id).and_then(|crawler| {
+            webhook_targets(&crawler.notification.via)
+                .into_iter()
+                .find(|(url, _)| *url == delivery.url)
+                .map(|(_, secret)| secret.to_string())
+        });
+        match secret {
+            Some(secret) => attempt(orch, &delivery, &secret).await,
+            None => {
+                let gave_up = WebhookAttempt {
+                    status: WebhookDeliveryStatus::Failed,
+                    response_status: None,
+                    error: Some("webhook is no longer configured for the crawler".into()),
+                    next_attempt_at: None,
+                };
+                record(orch, &delivery, &gave_up).await;
+            },
+        }
+    }
+    Ok(())
+}
+
+async fn attempt(orch: &Orchestrator, delivery: &WebhookDelivery, secret: &str) {
+    let attempt = orch.webhooks.attempt(delivery, secret).await;
+    match attempt.status {
+        WebhookDeliveryStatus::Delivered => tracing::info!("delivered webhook {} to {}", delivery.id, delivery.url),
+        _ => tracing::warn!(
+            "webhook {} to {} failed, attempt {} is {}: {:?}",
+            delivery.id, delivery.url, delivery.attempts + 1, attempt.status, attempt.error,
+        ),
+    }
+    record(orch, delivery, &attempt).await;
+}
+
+async fn record(orch: &Orchestrator, delivery: &WebhookDelivery, attempt: &WebhookAttempt) {
+    if let Err(err) = orch.db.record_webhook_attempt(delivery.id, attempt).await {
+        tracing::error!("cannot record attempt of webhook {}: {}", delivery.id, err);
+    }
+}
@@ -0,0 +1,4 @@
+
+mod store;
+
+pub use store::*;
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use common::models::{quota_limits_key, quota_plan_key, Page, QuotaExceeded, QuotaKind, QuotaLimits};
+
+const PAUSED_USERS_KEY: &str = "quota:paused_users";
+/// Paused pages which cannot be read back, kept for inspection instead of being dropped.
+const PAUSED_DEAD_LETTERS_KEY: &str = "quota:paused:dead_letters";
+
+fn paused_key(user_id: Uuid) -> String {
+    format!("quota:{}:paused", user_id)
+}
+
+/// Per-user limits with counters in redis. Pages over quota are paused in redis until the window resets.
+pub struct Quotas {
+    conn: ConnectionManager,
+}
+
+impl Quotas {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Quotas { conn }
+    }
+
+    /// Limits of a user: explicit override, then plan limits, then the free plan.
+    pub async fn limits(&self, user_id: Uuid) -> Result<QuotaLimits> {
+        let mut conn = self.conn.clone();
+        let custom: Option<String> = conn.get(quota_limits_key(user_id)).await?;
+        let plan: Option<String> = conn.get(quota_plan_key(user_id)).await?;
+        Ok(QuotaLimits::resolve(custom.as_deref(), plan.as_deref()))
+    }
+
+    /// Consumes one unit of every kind. Nothing is consumed if any of them is over the limit.
+    pub async fn try_consume(&self, user_id: Uuid, kinds: &[QuotaKind]) -> Result<Option<QuotaExceeded>> {
+        let limits = self.limits(user_id).await?;
+        let mut conn = self.conn.clone();
+        let now = Utc::now();
+        let mut consumed = vec![];
+        let mut exceeded = None;
+        for kind in kinds {
+            let key = kind.counter_key(user_id, now);
+            let used: u64 = conn.incr(&key, 1).await?;
+            if used == 1 {
+                if let Some(resets_at) = kind.resets_at(now) {
+                    let _: () = conn.expire_at(&key, resets_at.timestamp()).await?;
+                }
+            }
+            consumed.push(key);
+            let limit = kind.limit(&limits);
+            if used > limit {
+                exceeded = Some(QuotaExceeded {
+                    user_id: user_id.to_string(),
+                    kind: *kind,
+                    limit,
+                    used: used - 1,
+                    resets_at: kind.resets_at(now),
+                });
+                break;
+            }
+        }
+        if exceeded.is_some() {
+            for key in consumed {
+                let _: i64 = conn.decr(&key, 1).await?;
+            }
+        }
+        Ok(exceeded)
+    }
+
+    /// Units of the quota left in the current window.
+    pub async fn remaining(&self, user_id: Uuid, kind: QuotaKind) -> Result<u64> {
+        let limit = kind.limit(&self.limits(user_id).await?);
+        let mut conn = self.conn.clone();
+        let used: Option<u64> = conn.get(kind.counter_key(user_id, Utc::now())).await?;
+        Ok(limit.saturating_sub(used.unwrap_or(0)))
+    }
+
+    /// Stores the page until the quota allows it. Returns true when the user hasn't been
+    /// notified about this quota in the current window yet.
+    pub async fn pause(&self, page: &Page, exceeded: &QuotaExceeded) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        let _: u64 = conn.rpush(paused_key(page.user_id), serde_json::to_string(page)?).await?;
+        let _: u64 = conn.sadd(PAUSED_USERS_KEY, page.user_id.to_string()).await?;
+
+        let notified_key = format!("{}:notified", exceeded.kind.counter_key(page.user_id, Utc::now()));
+        let mut set = redis::cmd("SET");
+        set.arg(&notified_key).arg(1).arg("NX");
+        if let Some(resets_at) = exceeded.resets_at {
+            set.arg("EXAT").arg(resets_at.timestamp());
+        }
+        let first: Option<String> = set.query_async(&mut conn).await?;
+        Ok(first.is_some())
+    }
+
+    pub async fn paused_count(&self, user_id: Uuid) -> Result<u64> {
+        let mut conn = self.conn.clone();
+        Ok(conn.llen(paused_key(user_id)).await?)
+    }
+
+    pub async fn paused_users(&self) -> Result<Vec<Uuid>> {
+        let mut conn = self.conn.clone();
+        let users: Vec<String> = conn.smembers(PAUSED_USERS_KEY).await?;
+        Ok(users.iter().filter_map(|u| Uuid::parse_str(u).ok()).collect())
+    }
+
+    /// Pops up to count paused pages of the user. Pages which cannot be deserialized
+    /// are moved to the dead letter list and don't count.
+    pub async fn take_paused(&self, user_id: Uuid, count: usize) -> Result<Vec<Page>> {
+        let mut conn = self.conn.clone();
+        let mut pages = vec![];
+        while pages.len() < count {
+            let Some(payload): Option<String> = conn.lpop(paused_key(user_id), None).await? else {
+                break;
+            };
+            match serde_json::from_str(&payload) {
+                Ok(page) => pages.push(page),
+                Err(err) => {
+                    tracing::error!("cannot read paused page of user {}, moving it to dead letters: {}", user_id, err);
+                    let _: u64 = conn.rpush(PAUSED_DEAD_LETTERS_KEY, payload).await?;
+                },
+            }
+        }
+        if self.paused_count(user_id).await? == 0 {
+            let _: u64 = conn.srem(PAUSED_USERS_KEY, user_id.to_string()).await?;
+        }
+        Ok(pages)
+    }
+}
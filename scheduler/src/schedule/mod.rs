@@ -0,0 +1,4 @@
+mod parse;
+mod timing;
+
+pub use parse::*;
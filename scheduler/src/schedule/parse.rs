@@ -0,0 +1,278 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use cron::Schedule as CronSchedule;
+
+/// When a crawler or a routine fires.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Timing {
+    /// Six or seven field cron expression, "2/10 * * * * *".
+    Cron { expr: String, timezone: Tz },
+    /// "every 6h", counted from the moment the job is added.
+    Every(Duration),
+    /// "daily at 03:00 Europe/Berlin".
+    Daily { at: NaiveTime, timezone: Tz },
+    /// "weekly on monday at 03:00 UTC" or "every monday at 03:00".
+    Weekly { weekday: Weekday, at: NaiveTime, timezone: Tz },
+    /// "once at 2024-06-01T12:00:00Z", fires a single time.
+    Once(DateTime<Utc>),
+}
+
+/// A parsed timer rule. Rules may end with "jitter 30m" to spread fires of many crawlers
+/// over a window, each crawler is delayed by its own fixed offset within it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub timing: Timing,
+    pub jitter: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct ScheduleError(String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl ScheduleError {
+    pub(super) fn new(err: impl ToString) -> Self {
+        ScheduleError(err.to_string())
+    }
+}
+
+fn invalid(err: impl ToString) -> ScheduleError {
+    ScheduleError::new(err)
+}
+
+/// Parses durations like "90s", "30m", "6h", "1d" and their sums, "1h30m".
+pub fn parse_duration(value: &str) -> Result<Duration, ScheduleError> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let amount: u64 = number.parse().map_err(|_| invalid(format!("bad duration {}", value)))?;
+        number.clear();
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(invalid(format!("unknown unit {} in duration {}", c, value))),
+        };
+        total = amount
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| invalid(format!("duration {} is too long", value)))?;
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(invalid(format!("duration {} needs a unit, e.g. 30m", value)));
+    }
+    let duration = Duration::from_secs(total);
+    // fire times are counted in chrono, durations it can't add are refused here
+    TimeDelta::from_std(duration).map_err(|_| invalid(format!("duration {} is too long", value)))?;
+    Ok(duration)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    let mut out = String::new();
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            out.push_str(&format!("{}{}", secs / size, unit));
+            secs %= size;
+        }
+    }
+    out
+}
+
+fn parse_timezone(value: Option<&&str>) -> Result<Tz, ScheduleError> {
+    match value {
+        Some(tz) => Tz::from_str(tz).map_err(|_| invalid(format!("unknown timezone {}", tz))),
+        None => Ok(Tz::UTC),
+    }
+}
+
+/// Parses "at HH:MM [timezone]".
+fn parse_at(words: &[&str]) -> Result<(NaiveTime, Tz), ScheduleError> {
+    match words {
+        [at, time, rest @ ..] if at.eq_ignore_ascii_case("at") && rest.len() <= 1 => {
+            let at = NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| invalid(format!("bad time {}, expected HH:MM", time)))?;
+            Ok((at, parse_timezone(rest.first())?))
+        },
+        _ => Err(invalid("expected \"at HH:MM\" with an optional timezone")),
+    }
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday, ScheduleError> {
+    Weekday::from_str(value).map_err(|_| invalid(format!("unknown weekday {}", value)))
+}
+
+fn parse_cron(rule: &str) -> Result<Timing, ScheduleError> {
+    let words: Vec<&str> = rule.split_whitespace().collect();
+    // an optional timezone follows the fields
+    let (fields, timezone) = match words.split_last() {
+        Some((last, fields)) if Tz::from_str(last).is_ok() => (fields.join(" "), parse_timezone(Some(last))?),
+        _ => (words.join(" "), Tz::UTC),
+    };
+    CronSchedule::from_str(&fields).map_err(|err| invalid(format!("bad cron expression {}: {}", fields, err)))?;
+    Ok(Timing::Cron { expr: fields, timezone })
+}
+
+fn parse_timing(rule: &str) -> Result<Timing, ScheduleError> {
+    let words: Vec<&str> = rule.split_whitespace().collect();
+    let lower: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+    let lower: Vec<&str> = lower.iter().map(String::as_str).collect();
+    // keywords are matched in lower case, times and timezones are read as written
+    let tail = |rest: &[&str]| words[words.len() - rest.len()..].to_vec();
+    match lower.as_slice() {
+        [] => Err(invalid("empty rule")),
+        ["hourly"] => Ok(Timing::Every(Duration::from_secs(3600))),
+        ["daily"] => Ok(Timing::Daily { at: NaiveTime::MIN, timezone: Tz::UTC }),
+        ["once", "at", _] | [_] if lower[0] == "once" || words[0].contains(':') => {
+            let at = words[words.len() - 1];
+            let at = DateTime::parse_from_rfc3339(at).map_err(|err| invalid(format!("bad timestamp {}: {}", at, err)))?;
+            Ok(Timing::Once(at.with_timezone(&Utc)))
+        },
+        ["daily", rest @ ..] | ["every", "day", rest @ ..] => {
+            let (at, timezone) = parse_at(&tail(rest))?;
+            Ok(Timing::Daily { at, timezone })
+        },
+        ["weekly", "on", weekday, rest @ ..] => {
+            let (at, timezone) = parse_at(&tail(rest))?;
+            Ok(Timing::Weekly { weekday: parse_weekday(weekday)?, at, timezone })
+        },
+        ["every", interval] => Ok(Timing::Every(parse_duration(interval)?)),
+        ["every", weekday, rest @ ..] => {
+            let (at, timezone) = parse_at(&tail(rest))?;
+            Ok(Timing::Weekly { weekday: parse_weekday(weekday)?, at, timezone })
+        },
+        _ => parse_cron(rule),
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = rule.split_whitespace().collect();
+        let (timing, jitter) = match words.iter().rposition(|word| word.eq_ignore_ascii_case("jitter")) {
+            Some(at) if at + 2 == words.len() => {
+                let timing = match words[at.saturating_sub(1)] {
+                    with if with.eq_ignore_ascii_case("with") => &words[..at - 1],
+                    _ => &words[..at],
+                };
+                (timing.join(" "), Some(parse_duration(words[at + 1])?))
+            },
+            Some(_) => return Err(invalid("jitter must end the rule, e.g. \"daily at 03:00 jitter 30m\"")),
+            None => (words.join(" "), None),
+        };
+        Ok(Schedule { timing: parse_timing(&timing)?, jitter })
+    }
+}
+
+/// The normalized rule, parsing it gives the same schedule.
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.timing {
+            Timing::Cron { expr, timezone } => write!(f, "{} {}", expr, timezone)?,
+            Timing::Every(interval) => write!(f, "every {}", format_duration(*interval))?,
+            Timing::Daily { at, timezone } => write!(f, "daily at {} {}", at.format("%H:%M"), timezone)?,
+            Timing::Weekly { weekday, at, timezone } => {
+                write!(f, "weekly on {} at {} {}", weekday, at.format("%H:%M"), timezone)?
+            },
+            Timing::Once(at) => write!(f, "once at {}", at.to_rfc3339())?,
+        }
+        if let Some(jitter) = self.jitter {
+            write!(f, " jitter {}", format_duration(jitter))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(rule: &str) -> Schedule {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_durations_and_their_sums() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::from_secs(1_209_600));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("5y").is_err());
+    }
+
+    #[test]
+    fn refuses_durations_overflowing() {
+        assert!(parse_duration("18446744073709551615w").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+        // fits in u64 seconds but not in chrono
+        assert!(parse_duration("18446744073709551s").is_err());
+        assert!("every 99999999999999999w".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn parses_calendar_rules() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let at = NaiveTime::from_hms_opt(3, 0, 0).unwrap();
+        assert_eq!(parse("daily at 03:00 Europe/Berlin").timing, Timing::Daily { at, timezone: berlin });
+        assert_eq!(parse("Every Day at 03:00").timing, Timing::Daily { at, timezone: Tz::UTC });
+        assert_eq!(
+            parse("weekly on monday at 03:00").timing,
+            Timing::Weekly { weekday: Weekday::Mon, at, timezone: Tz::UTC }
+        );
+        assert_eq!(parse("every fri at 03:00 UTC").timing, Timing::Weekly { weekday: Weekday::Fri, at, timezone: Tz::UTC });
+        assert_eq!(parse("hourly").timing, Timing::Every(Duration::from_secs(3600)));
+        assert_eq!(parse("every 6h").timing, Timing::Every(Duration::from_secs(21600)));
+    }
+
+    #[test]
+    fn parses_cron_and_one_shots() {
+        assert_eq!(
+            parse("0 0 3 * * * Europe/Berlin").timing,
+            Timing::Cron { expr: "0 0 3 * * *".into(), timezone: "Europe/Berlin".parse().unwrap() }
+        );
+        let at = DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(parse("once at 2030-01-01T00:00:00Z").timing, Timing::Once(at));
+        assert!("0 0 3 * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn reads_jitter_at_the_end_only() {
+        let schedule = parse("daily at 03:00 with jitter 30m");
+        assert_eq!(schedule.jitter, Some(Duration::from_secs(1800)));
+        assert_eq!(parse("every 1h jitter 5m").jitter, Some(Duration::from_secs(300)));
+        assert!("jitter 5m every 1h".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn refuses_malformed_rules() {
+        for rule in ["", "daily at 25:00", "daily at 03:00 Mars/Base", "weekly on funday at 03:00", "every soon"] {
+            assert!(rule.parse::<Schedule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn normalized_rules_parse_back() {
+        for rule in ["every 1h30m jitter 10m", "daily at 03:00 Europe/Berlin", "weekly on Mon at 03:00 UTC", "0 0 3 * * * UTC"] {
+            let schedule = parse(rule);
+            assert_eq!(parse(&schedule.to_string()), schedule, "{}", rule);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use cron::Schedule as CronSchedule;
+use tokio_cron_scheduler::Job;
+use uuid::Uuid;
+
+use super::{Schedule, ScheduleError, Timing};
+
+fn add(at: DateTime<Utc>, delay: Duration) -> Option<DateTime<Utc>> {
+    at.checked_add_signed(TimeDelta::from_std(delay).ok()?)
+}
+
+fn out_of_range(from: DateTime<Utc>) -> ScheduleError {
+    ScheduleError::new(format!("fire times after {} are out of range", from.to_rfc3339()))
+}
+
+impl Schedule {
+    /// Cron expression and timezone of calendar based timings.
+    fn cron(&self) -> Option<(String, Tz)> {
+        match &self.timing {
+            Timing::Cron { expr, timezone } => Some((expr.clone(), *timezone)),
+            Timing::Daily { at, timezone } => Some((at.format("0 %M %H * * *").to_string(), *timezone)),
+            Timing::Weekly { weekday, at, timezone } => {
+                Some((format!("{} {}", at.format("0 %M %H * *"), weekday), *timezone))
+            },
+            Timing::Every(_) | Timing::Once(_) => None,
+        }
+    }
+
+    /// How long fires of `key` are delayed within the jitter window.
+    /// It's derived from the key, so every scheduler instance delays the same crawler equally.
+    pub fn offset(&self, key: Uuid) -> Duration {
+        match self.jitter.map(|jitter| jitter.as_millis()) {
+            Some(window) if window > 0 => Duration::from_millis((key.as_u128() % window) as u64),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Up to `count` fire times after `from`, delayed by the offset of `key`.
+    /// Interval schedules count from `from` as they start when the job is added.
+    /// Fails when a fire time is past the dates chrono can hold.
+    pub fn next_runs(&self, key: Uuid, from: DateTime<Utc>, count: usize) -> Result<Vec<DateTime<Utc>>, ScheduleError> {
+        let base: Vec<DateTime<Utc>> = match (&self.timing, self.cron()) {
+            (_, Some((expr, timezone))) => match CronSchedule::from_str(&expr) {
+                Ok(cron) => cron
+                    .after(&from.with_timezone(&timezone))
+                    .take(count)
+                    .map(|at| at.with_timezone(&Utc))
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+            (Timing::Every(interval), None) => (1..=count)
+                .map(|k| {
+                    u32::try_from(k)
+                        .ok()
+                        .and_then(|k| interval.checked_mul(k))
+                        .and_then(|delay| add(from, delay))
+                        .ok_or_else(|| out_of_range(from))
+                })
+                .collect::<Result<_, _>>()?,
+            (Timing::Once(at), None) if *at > from => vec![*at],
+            _ => Vec::new(),
+        };
+        let offset = self.offset(key);
+        base.into_iter().map(|at| add(at, offset).ok_or_else(|| out_of_range(at))).collect()
+    }
+
+    /// Whether a job can be made of the schedule right now, e.g. a one-shot is not in the past.
+    pub fn check(&self) -> Result<(), ScheduleError> {
+        match &self.timing {
+            Timing::Once(at) if *at <= Utc::now() => Err(ScheduleError::new(format!("{} is in the past", at.to_rfc3339()))),
+            Timing::Every(interval) if add(Utc::now(), *interval).is_none() => Err(out_of_range(Utc::now())),
+            _ => Ok(()),
+        }
+    }
+
+    /// A job calling `run` on this schedule, delayed by the offset of `key`.
+    pub fn job<F>(&self, key: Uuid, run: F) -> Result<Job, ScheduleError>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.check()?;
+        let run = Arc::new(run);
+        let offset = self.offset(key);
+        let fire = move |_uuid, _lock| {
+            if offset.is_zero() {
+                return run();
+            }
+            let run = run.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(offset).await;
+                run();
+            });
+        };
+        let job = match (&self.timing, self.cron()) {
+            (_, Some((expr, timezone))) => Job::new_tz(expr.as_str(), timezone, fire),
+            (Timing::Every(interval), None) => Job::new_repeated(*interval, fire),
+            (Timing::Once(at), None) => match (*at - Utc::now()).to_std() {
+                Ok(delay) => Job::new_one_shot(delay, fire),
+                // passed since the check
+                Err(_) => return Err(ScheduleError::new(format!("{} is in the past", at.to_rfc3339()))),
+            },
+            _ => unreachable!("calendar timings have a cron expression"),
+        };
+        job.map_err(|err| ScheduleError::new(format!("{:?}", err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn schedule(rule: &str) -> Schedule {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn previews_intervals_from_now() {
+        let runs = schedule("every 6h").next_runs(Uuid::nil(), at("2030-01-01T00:00:00Z"), 2).unwrap();
+        assert_eq!(runs, [at("2030-01-01T06:00:00Z"), at("2030-01-01T12:00:00Z")]);
+    }
+
+    #[test]
+    fn previews_calendar_rules_in_their_timezones() {
+        let runs = schedule("daily at 03:00 Europe/Berlin").next_runs(Uuid::nil(), at("2030-01-01T12:00:00Z"), 2).unwrap();
+        assert_eq!(runs, [at("2030-01-02T02:00:00Z"), at("2030-01-03T02:00:00Z")]);
+
+        let runs = schedule("weekly on monday at 03:00").next_runs(Uuid::nil(), at("2030-01-01T00:00:00Z"), 1).unwrap();
+        assert_eq!(runs, [at("2030-01-07T03:00:00Z")]);
+    }
+
+    #[test]
+    fn previews_one_shots_until_they_pass() {
+        let once = schedule("once at 2030-01-01T00:00:00Z");
+        assert_eq!(once.next_runs(Uuid::nil(), at("2029-12-31T00:00:00Z"), 5).unwrap(), [at("2030-01-01T00:00:00Z")]);
+        assert!(once.next_runs(Uuid::nil(), at("2030-01-02T00:00:00Z"), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delays_fires_by_the_offset_of_the_key() {
+        let jittered = schedule("daily at 03:00 jitter 30m");
+        let key = Uuid::from_u128(61_000);
+        assert_eq!(jittered.offset(key), Duration::from_millis(61_000));
+        let runs = jittered.next_runs(key, at("2030-01-01T00:00:00Z"), 1).unwrap();
+        assert_eq!(runs, [at("2030-01-01T03:01:01Z")]);
+        assert_eq!(schedule("daily at 03:00").offset(key), Duration::ZERO);
+    }
+
+    #[test]
+    fn refuses_fire_times_out_of_range() {
+        let every = schedule("every 5200w");
+        assert!(every.next_runs(Uuid::nil(), DateTime::<Utc>::MAX_UTC - TimeDelta::weeks(5300), 3).is_err());
+    }
+
+    #[test]
+    fn checks_one_shots_in_the_past() {
+        assert!(schedule("once at 2000-01-01T00:00:00Z").check().is_err());
+        assert!(schedule("once at 2999-01-01T00:00:00Z").check().is_ok());
+        assert!(schedule("every 1h").check().is_ok());
+    }
+}
@@ -1,42 +1,235 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
-use tokio::sync::RwLock;
+use redis::aio::ConnectionManager;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
 use tokio_cron_scheduler::JobScheduler;
+use uuid::Uuid;
 
+use common::infinite_retry;
+use common::models::Crawler;
 
+use crate::broker::{self, SharedBroker};
+use crate::config::{Config, DbAddr};
+use crate::jobs::{RoutineSpec, Routines, SharedRoutines};
+use crate::orchestrator::{self, Orchestrator, SharedOrchestrator};
+use crate::{api, cluster, control, database, frontier, jobs, notification, quota, SharedDatabase, SharedSheduler};
+
+/// The scheduler service. Owns the config, broker, database, cron jobs and the web server,
+/// starts them in order and shuts them down gracefully on SIGTERM or ctrl-c.
 pub struct Scheduler {
-    pub cfg: String,
-    pub broker: String,
-    pub database: String,
-    pub orchestrator: String,
-    pub api: String,
-    pub innner: Arc<RwLock<JobScheduler>>,
+    cfg: Config,
+    broker: SharedBroker,
+    orchestrator: SharedOrchestrator,
+    sched: SharedSheduler,
+    routines: SharedRoutines,
+    triggered: Option<mpsc::UnboundedReceiver<Crawler>>,
 }
 
 impl Scheduler {
-    pub async fn new() -> Result<Self> {
-        todo!()
+    pub async fn new(cfg: Config) -> Result<Self> {
+        let sched = Self::init_job_scheduler().await?;
+        let database = Self::init_database(&cfg)?;
+        let redis = Self::init_redis(&cfg).await?;
+        let (trigger, triggered) = mpsc::unbounded_channel();
+        let orchestrator = Arc::new(Orchestrator {
+            db: database.clone(),
+            sched: sched.clone(),
+            fair_share: orchestrator::FairShare::new(redis.clone(), cfg.fair_share_max_in_flight, cfg.fair_share_slot_ttl_ms),
+            batcher: orchestrator::ScrapeBatcher::new(redis.clone(), cfg.scrape_batch_size),
+            quotas: quota::Quotas::new(redis.clone()),
+            crawlers: orchestrator::CrawlerJobs::new(trigger),
+            cancellations: control::Cancellations::new(redis.clone()),
+            frontier: frontier::Frontier::new(redis.clone(), &cfg.frontier),
+            budgets: orchestrator::RunBudgets::new(redis.clone()),
+            seeds: orchestrator::SeedProgress::new(redis.clone()),
+            leader: cluster::Leadership::new(redis.clone(), &cfg.cluster),
+            processed: cluster::ProcessedEvents::new(redis.clone(), &cfg.cluster),
+            notifier: match cfg.notification.is_sent_by_scheduler() {
+                true => Some(notification::Notifier::new(&cfg.notification)?),
+                false => None,
+            },
+            digests: notification::Digests::new(redis),
+            webhooks: notification::WebhookSender::new(&cfg.notification)?,
+        });
+        let broker = Self::init_broker(&cfg).await?;
+        let routines = Routines::new(orchestrator.clone());
+        Ok(Scheduler { cfg, broker, orchestrator, sched, routines, triggered: Some(triggered) })
+    }
+
+    async fn init_job_scheduler() -> Result<SharedSheduler> {
+        // let metadata_storage = Box::new(PostgresMetadataStore::default());
+        // let notification_storage = Box::new(PostgresNotificationStore::default());
+        // let simple_job_code = Box::new(SimpleJobCode::default());
+        // let simple_notification_code = Box::new(SimpleNotificationCode::default());
+        // let job_sched = JobScheduler::new_with_storage_and_code(
+        //     metadata_storage,
+        //     notification_storage,
+        //     simple_job_code,
+        //     simple_notification_code
+        // ).await?;
+        let sched = Arc::new(Mutex::new(JobScheduler::new().await?));
+        Ok(sched)
+    }
+
+    async fn init_broker(cfg: &Config) -> Result<SharedBroker> {
+        tracing::info!("Connecting to {} broker", cfg.broker.backend);
+        broker::connect(&cfg.broker).await
+    }
+
+    fn init_database(cfg: &Config) -> Result<SharedDatabase> {
+        Ok(Arc::new(database::Postgres::new(cfg.database.clone())?))
+    }
+
+    /// One redis connection shared by every store, it reconnects by itself when the connection is lost.
+    async fn init_redis(cfg: &Config) -> Result<ConnectionManager> {
+        let client = redis::Client::open(cfg.redis.get_addr())?;
+        Ok(ConnectionManager::new(client).await?)
     }
 
-    async fn init_broker() -> Result<()> {
-        todo!()
+    /// Registers the scheduler's own jobs, listed with the routines, and routines stored so far.
+    async fn init_jobs(&self) -> Result<()> {
+        let system = |name: &str, rule: &str, task: &str| RoutineSpec { name: name.into(), rule: rule.into(), task: task.into() };
+        let job_id = jobs::register_initial_jobs(&mut self.sched.clone()).await?;
+        self.routines.add_system(system("initial", jobs::INITIAL_JOB_RULE, "log a heartbeat"), job_id);
+        let rule = &self.cfg.quota_resume_rule;
+        let job_id = jobs::register_quota_jobs(rule, self.broker.clone(), self.orchestrator.clone()).await?;
+        self.routines.add_system(system("quota resume", rule, "dispatch paused pages of users with quota left"), job_id);
+        let rule = &self.cfg.digest_check_rule;
+        let job_id = jobs::register_digest_jobs(rule, self.orchestrator.clone()).await?;
+        self.routines.add_system(system("digests", rule, "send notification digests which are due"), job_id);
+        let rule = &self.cfg.webhook_retry_rule;
+        let job_id = jobs::register_webhook_jobs(rule, self.orchestrator.clone()).await?;
+        self.routines.add_system(system("webhook retries", rule, "deliver failed webhooks again"), job_id);
+        if let Err(err) = self.routines.sync().await {
+            tracing::error!("cannot load routines: {}", err);
+        }
+        tracing::info!("Starting scheduler...");
+        self.sched.lock().await.start().await?;
+        Ok(())
     }
 
-    async fn init_database() -> Result<()> {
-        todo!()
+    /// Starts runs of crawlers whose cron jobs fired, on the leader only.
+    fn spawn_runner(&mut self) -> JoinHandle<()> {
+        let mut triggered = self.triggered.take().expect("crawl runner is spawned once");
+        let (broker, orch) = (self.broker.clone(), self.orchestrator.clone());
+        tokio::spawn(async move {
+            while let Some(crawler) = triggered.recv().await {
+                if !orch.leader.is_leader() {
+                    continue;
+                }
+                // the job holds the crawler as it was scheduled, start pages may have changed since
+                let crawler = orch.crawlers.get(crawler.id).unwrap_or(crawler);
+                if let Err(err) = orchestrator::start_crawl(broker.as_ref(), &orch, crawler, Uuid::now_v7()).await {
+                    tracing::error!("cannot start a crawl run: {}", err);
+                }
+            }
+        })
     }
 
-    async fn init_http_server() -> Result<()> {
-        todo!()
+    /// Sends partial batches periodically and everything left once shutdown is signalled.
+    fn spawn_batch_flusher(&self, mut shutdown: watch::Receiver<bool>) -> Option<JoinHandle<()>> {
+        if !self.orchestrator.batcher.is_enabled() {
+            return None;
+        }
+        let (broker, orch) = (self.broker.clone(), self.orchestrator.clone());
+        let linger = Duration::from_millis(self.cfg.scrape_batch_linger_ms);
+        Some(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(linger);
+            loop {
+                let stopped = tokio::select! {
+                    _ = ticks.tick() => false,
+                    _ = shutdown.wait_for(|stop| *stop) => true,
+                };
+                orchestrator::flush_scrape_batches(broker.as_ref(), &orch).await;
+                if stopped {
+                    break;
+                }
+            }
+        }))
     }
 
-    async fn init_grpc_server() -> Result<()> {
-        // TODO: migrate from http to grpc
-        todo!()
+    fn spawn_consumer(&self, shutdown: watch::Receiver<bool>) -> JoinHandle<()> {
+        let (broker, orch) = (self.broker.clone(), self.orchestrator.clone());
+        tokio::spawn(async move {
+            // retried until it returns Ok
+            let _ = infinite_retry!("broker consumer", broker::consume(broker.as_ref(), orch.clone(), shutdown.clone()).await);
+        })
     }
 
-    pub async fn run() -> Result<()> {
-        todo!()
+    fn init_http_server(&self) -> Result<actix_web::dev::Server> {
+        api::server(&self.cfg, self.routines.clone(), self.broker.clone(), self.orchestrator.clone())
     }
-}
\ No newline at end of file
+
+    fn init_grpc_server(&self, shutdown: watch::Receiver<bool>) -> JoinHandle<Result<()>> {
+        let addr = self.cfg.get_grpc_socket_addr();
+        let token = self.cfg.grpc_token.clone();
+        tokio::spawn(api::serve_grpc(addr, token, self.orchestrator.clone(), self.broker.clone(), shutdown))
+    }
+
+    /// Runs until SIGTERM or ctrl-c, then stops taking new work, drains the event in hand,
+    /// sends pending batches, stops cron jobs and closes the broker once outstanding publishes are confirmed.
+    /// An event not drained in time is left unsettled and redelivered.
+    pub async fn run(mut self) -> Result<()> {
+        self.init_jobs().await?;
+        let (stop_consumer, consumer_shutdown) = watch::channel(false);
+        let (stop_flusher, flusher_shutdown) = watch::channel(false);
+        let (stop_grpc, grpc_shutdown) = watch::channel(false);
+        let runner = self.spawn_runner();
+        let leadership = tokio::spawn(orchestrator::keep_leadership(self.orchestrator.clone(), self.routines.clone(), self.cfg.cluster.renew_interval()));
+        let flusher = self.spawn_batch_flusher(flusher_shutdown);
+        let mut consumer = self.spawn_consumer(consumer_shutdown);
+        let server = self.init_http_server()?;
+        let http = server.handle();
+        let serving = tokio::spawn(server);
+        let grpc = self.init_grpc_server(grpc_shutdown);
+
+        wait_for_shutdown().await?;
+        tracing::info!("Shutting down scheduler...");
+        http.stop(true).await;
+        let _ = stop_grpc.send(true);
+        if let Err(err) = grpc.await? {
+            tracing::error!("gRPC server failed: {}", err);
+        }
+        // the event in hand is drained while cron jobs and the leader lease are still around
+        let _ = stop_consumer.send(true);
+        let drain_timeout = Duration::from_millis(self.cfg.shutdown_drain_timeout_ms);
+        match tokio::time::timeout(drain_timeout, &mut consumer).await {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => tracing::error!("broker consumer failed: {}", err),
+            Err(_) => {
+                tracing::warn!("the event in hand is not drained in {:?}, leaving it to be redelivered", drain_timeout);
+                consumer.abort();
+            },
+        }
+        // pages queued by the drained events go out before the broker is closed
+        let _ = stop_flusher.send(true);
+        if let Some(flusher) = flusher {
+            if let Err(err) = flusher.await {
+                tracing::error!("batch flusher failed: {}", err);
+            }
+        }
+        self.sched.lock().await.shutdown().await?;
+        runner.abort();
+        leadership.abort();
+        if let Err(err) = self.orchestrator.leader.resign().await {
+            tracing::warn!("cannot give up the leader lease: {}", err);
+        }
+        self.broker.close().await?;
+        serving.await??;
+        tracing::info!("Scheduler is stopped");
+        Ok(())
+    }
+}
+
+async fn wait_for_shutdown() -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => tracing::info!("Got SIGTERM"),
+        _ = tokio::signal::ctrl_c() => tracing::info!("Got ctrl-c"),
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Fixtures shared by unit tests.
+use std::collections::HashMap;
+
+use chrono::Utc;
+use envconfig::Envconfig;
+use redis::aio::ConnectionManager;
+use uuid::Uuid;
+
+use common::models::{Crawler, NotificationLevel, NotificationOptions, Page, Priority, Site};
+
+use crate::config::{DbAddr, RedisConfig};
+use crate::orchestrator::seed_page;
+
+/// Connection to the redis of the env config, for tests which need one.
+pub async fn redis() -> ConnectionManager {
+    let cfg = RedisConfig::init_from_env().unwrap();
+    ConnectionManager::new(redis::Client::open(cfg.get_addr()).unwrap()).await.unwrap()
+}
+
+pub fn crawler() -> Crawler {
+    Crawler {
+        id: Uuid::now_v7(),
+        name: "shop".to_string(),
+        user_id: Uuid::now_v7(),
+        timer_rule: "every 1h".to_string(),
+        priority: Priority::Common,
+        notification: NotificationOptions {
+            level: NotificationLevel::JobsDone,
+            via: vec![],
+            every: None,
+            send_at: None,
+            timezone: None,
+        },
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        site: Site {
+            id: Uuid::now_v7(),
+            domain: "shop.site".to_string(),
+            start_page: "https://shop.site/".to_string(),
+            page_xpaths: HashMap::new(),
+            pagination_xpaths: HashMap::new(),
+            start_pages: Default::default(),
+            sitemap: None,
+            meta: None,
+        },
+        budget: Default::default(),
+        stop_conditions: vec![],
+        url_patterns: Default::default(),
+        meta: None,
+    }
+}
+
+/// A start page of a new run of `crawler()`.
+pub fn page(url: &str) -> Page {
+    seed_page(&crawler(), Uuid::now_v7(), url.to_string())
+}
@@ -1,9 +0,0 @@
-
-pub enum ParseraService {
-    Scraper,
-    HeavyArtillery,
-    Extractor,
-    Notification,
-    DatabaseManager,
-    StatusManager,
-}
\ No newline at end of file
@@ -0,0 +1,56 @@
+extern crate log;
+
+use std::error::Error;
+use std::sync::Arc;
+
+pub use common::broker::{Broker, ParseraService};
+
+use crate::cancellation::CancelledRuns;
+use crate::config;
+use crate::fingerprint::ContentHasher;
+use crate::handlers;
+use crate::requests::Requests;
+
+pub type SharedBroker = Arc<dyn Broker>;
+
+/// Connects to the configured broker and declares the topology shared by all services.
+pub async fn connect(conf: &config::ConfigRabbitMQ) -> Result<SharedBroker, Box<dyn Error>> {
+    Ok(common::broker::connect(conf.settings()).await?)
+}
+
+/// Scrapes pages sent by the scheduler and reports them back.
+/// Pages that cannot be handled are parked, pages that cannot be reported are retried.
+pub async fn consume(
+    broker: &dyn Broker,
+    hasher: ContentHasher,
+    cancelled: CancelledRuns,
+) -> Result<(), Box<dyn Error>> {
+    let requests = Requests::new();
+    let mut subscription = broker.subscribe(ParseraService::Scraper).await?;
+    log::info!(" [*] Waiting for messages. To exit press CTRL+C");
+    while let Some(delivery) = subscription.next().await {
+        log::info!(" [x] Received {}", String::from_utf8_lossy(&delivery.payload));
+        let handled = match delivery.event() {
+            Ok(event) => handlers::handle_event(&requests, &hasher, &cancelled, event).await,
+            Err(e) => Err(e.into()),
+        };
+        let settled = match handled {
+            Ok(Some(event)) => match broker.publish_event(&event, ParseraService::Scheduler).await {
+                Ok(()) => delivery.ack().await,
+                Err(e) => {
+                    log::error!("Error publishing scraped page: {}", e);
+                    delivery.retry().await
+                }
+            },
+            Ok(None) => delivery.ack().await,
+            Err(e) => {
+                log::error!("Error in handle_event: {}", e);
+                delivery.park().await
+            }
+        };
+        if let Err(e) = settled {
+            log::error!("Cannot settle a delivery: {}", e);
+        }
+    }
+    Ok(())
+}
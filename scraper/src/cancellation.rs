@@ -0,0 +1,31 @@
+extern crate log;
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisError};
+
+use common::models::cancelled_run_key;
+
+/// Lookup of runs cancelled by the scheduler, their queued pages are dropped without fetching.
+pub struct CancelledRuns {
+    conn: ConnectionManager,
+}
+
+impl CancelledRuns {
+    pub async fn new(url: &str) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(CancelledRuns { conn })
+    }
+
+    /// Lookup errors are logged and the page is scraped anyway.
+    pub async fn contains(&self, run_id: &str) -> bool {
+        let mut conn = self.conn.clone();
+        match conn.exists(cancelled_run_key(run_id)).await {
+            Ok(cancelled) => cancelled,
+            Err(e) => {
+                log::error!("cannot check if run {} is cancelled: {}", run_id, e);
+                false
+            }
+        }
+    }
+}
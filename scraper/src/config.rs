@@ -1,6 +1,7 @@
 extern crate dotenv;
 extern crate log;
 
+use common::broker::{BrokerBackend, BrokerSettings, KafkaSettings, RabbitSettings, Topology};
 use envconfig::Envconfig;
 use std::env;
 
@@ -20,6 +21,16 @@ pub struct ConfigRabbitMQ {
     // pub exchange_names: [String],
     #[envconfig(from = "RABBITMQ_PORT")]
     pub port: u16,
+    #[envconfig(from = "RABBITMQ_PREFETCH_COUNT", default = "16")]
+    pub prefetch_count: u16,
+    #[envconfig(from = "BROKER_BACKEND", default = "rabbitmq")]
+    pub backend: BrokerBackend,
+    #[envconfig(from = "KAFKA_BROKERS", default = "localhost:9092")]
+    pub kafka_brokers: String,
+    #[envconfig(from = "KAFKA_PARTITIONS", default = "12")]
+    pub kafka_partitions: i32,
+    #[envconfig(from = "KAFKA_REPLICATION_FACTOR", default = "1")]
+    pub kafka_replication_factor: i32,
 }
 
 impl ConfigRabbitMQ {
@@ -29,14 +40,65 @@ impl ConfigRabbitMQ {
             self.user, self.password, self.host, self.port
         )
     }
+
+    /// Queue names and retry settings are shared with the scheduler and read from the same variables.
+    pub fn settings(&self) -> BrokerSettings {
+        let topology = Topology::from_env();
+        BrokerSettings {
+            backend: self.backend,
+            rabbit: RabbitSettings {
+                url: self.get_url(),
+                topology: topology.clone(),
+                prefetch_count: self.prefetch_count,
+            },
+            kafka: KafkaSettings {
+                brokers: self.kafka_brokers.clone(),
+                topology,
+                partitions: self.kafka_partitions,
+                replication_factor: self.kafka_replication_factor,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Envconfig)]
+pub struct ConfigRedis {
+    #[envconfig(from = "REDIS_HOST")]
+    pub host: String,
+    #[envconfig(from = "REDIS_PORT", default = "6379")]
+    pub port: u16,
+    #[envconfig(from = "REDIS_PASSWORD")]
+    pub password: String,
+}
+
+impl ConfigRedis {
+    pub fn get_url(&self) -> String {
+        format!("redis://default:{}@{}:{}/", self.password, self.host, self.port)
+    }
 }
 
 #[derive(Debug, Envconfig)]
 pub struct Config {
     #[envconfig(nested = true)]
     pub rabbit: ConfigRabbitMQ,
+    #[envconfig(nested = true)]
+    pub redis: ConfigRedis,
     #[envconfig(from = "LOG_LEVEL")]
     pub log_level: String,
+    /// Regexes separated by ';' for page regions ignored by content hashing,
+    /// on top of the built-in csrf token, nonce and generation stamp patterns.
+    #[envconfig(from = "SCRAPER_VOLATILE_PATTERNS", default = "")]
+    pub volatile_patterns: String,
+}
+
+impl Config {
+    pub fn volatile_patterns(&self) -> Vec<String> {
+        self.volatile_patterns
+            .split(';')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
 }
 
 pub fn get() -> Config {
@@ -0,0 +1,125 @@
+use common::models::{FailureKind, ScrapeFailure};
+
+/// Lowercase markers only bot protection vendors put on their challenge pages.
+const CHALLENGE_MARKERS: [&str; 5] = [
+    "id=\"challenge-form\"",
+    "cf-challenge",
+    "cf_chl_opt",
+    "_incapsula_resource",
+    "id=\"px-captcha\"",
+];
+
+/// Lowercase titles of challenge and block pages.
+const CHALLENGE_TITLES: [&str; 7] = [
+    "just a moment",
+    "attention required",
+    "access denied",
+    "are you a robot",
+    "verify you are human",
+    "security check",
+    "captcha",
+];
+
+/// Lowercase markers that only mean a block on a page too short to have any content,
+/// content pages mention them or embed a captcha in a form.
+const SHORT_PAGE_MARKERS: [&str; 5] = [
+    "g-recaptcha",
+    "h-captcha",
+    "unusual traffic",
+    "verify you are human",
+    "access denied",
+];
+
+const SHORT_PAGE_BYTES: usize = 8 * 1024;
+
+pub fn classify_error(err: &reqwest::Error) -> ScrapeFailure {
+    let status = err.status().map(|s| s.as_u16());
+    let kind = if err.is_timeout() {
+        FailureKind::Timeout
+    } else if let Some(status) = status {
+        classify_status(status)
+    } else if err.is_connect() || err.is_request() {
+        FailureKind::Network
+    } else {
+        FailureKind::Unknown
+    };
+    ScrapeFailure {
+        kind,
+        status,
+        message: err.to_string(),
+    }
+}
+
+pub fn classify_status(status: u16) -> FailureKind {
+    match status {
+        403 | 429 => FailureKind::Blocked,
+        408 => FailureKind::Timeout,
+        400..=499 => FailureKind::ClientError,
+        500..=599 => FailureKind::ServerError,
+        _ => FailureKind::Unknown,
+    }
+}
+
+/// Detects captcha and challenge pages that are served with 200 status by challenge forms,
+/// page titles and, on short pages only, captcha widgets and block messages.
+pub fn detect_block(html: &str) -> Option<ScrapeFailure> {
+    let lowered = html.to_lowercase();
+    let title = title(&lowered).unwrap_or_default();
+    let short = lowered.len() <= SHORT_PAGE_BYTES;
+    let marker = CHALLENGE_MARKERS
+        .iter()
+        .find(|m| lowered.contains(*m))
+        .or_else(|| CHALLENGE_TITLES.iter().find(|m| title.contains(*m)))
+        .or_else(|| SHORT_PAGE_MARKERS.iter().find(|m| short && lowered.contains(*m)))?;
+    Some(ScrapeFailure {
+        kind: FailureKind::Blocked,
+        status: None,
+        message: format!("bot protection page detected: {}", marker),
+    })
+}
+
+fn title(html: &str) -> Option<&str> {
+    let start = html.find("<title")?;
+    let start = start + html[start..].find('>')? + 1;
+    let end = start + html[start..].find("</title")?;
+    Some(html[start..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_page(body: &str) -> String {
+        format!(
+            "<html><head><title>Shop</title></head><body>{}<p>{}</p></body></html>",
+            body,
+            "Product description. ".repeat(SHORT_PAGE_BYTES / 10)
+        )
+    }
+
+    #[test]
+    fn detects_challenge_pages() {
+        let cloudflare = r#"<html><head><title>Just a moment...</title></head><body><form id="challenge-form"></form></body></html>"#;
+        assert!(detect_block(cloudflare).is_some());
+        assert!(detect_block("<title>Access Denied</title><h1>You don't have permission</h1>").is_some());
+        assert!(detect_block(r#"<div class="g-recaptcha" data-sitekey="k"></div>"#).is_some());
+        assert!(detect_block(&long_page("<script>window._cf_chl_opt = {};</script>")).is_some());
+    }
+
+    #[test]
+    fn keeps_content_pages_mentioning_blocks() {
+        assert!(detect_block(&long_page("<h2>How to solve a captcha</h2><p>Access denied errors explained</p>")).is_none());
+        assert!(detect_block(&long_page(r#"<form><div class="g-recaptcha"></div></form>"#)).is_none());
+        assert!(detect_block("<title>Shop</title><p>Widget</p>").is_none());
+    }
+
+    #[test]
+    fn classifies_statuses() {
+        assert_eq!(classify_status(403), FailureKind::Blocked);
+        assert_eq!(classify_status(429), FailureKind::Blocked);
+        assert_eq!(classify_status(408), FailureKind::Timeout);
+        assert_eq!(classify_status(404), FailureKind::ClientError);
+        assert_eq!(classify_status(503), FailureKind::ServerError);
+        assert_eq!(classify_status(302), FailureKind::Unknown);
+    }
+}
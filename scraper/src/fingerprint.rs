@@ -0,0 +1,91 @@
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// Page regions that change on every render without the content changing. Dates in the page text
+/// are content, only generation stamps, tokens and cache busters are ignored.
+pub const VOLATILE_PATTERNS: [&str; 5] = [
+    r#"<input[^>]*name="[^"]*(csrf|token)[^"]*"[^>]*>"#,
+    r#"<meta[^>]*name="[^"]*(csrf|token)[^"]*"[^>]*>"#,
+    r#"nonce="[^"]*""#,
+    r"(?i)<!--[^>]*(generated|rendered|served|cached)[^>]*-->",
+    r"[?&](_|v|ver|t|ts|cb)=\d{6,}",
+];
+
+/// Computes a content hash of html ignoring volatile regions (csrf tokens, nonces, generation stamps).
+pub struct ContentHasher {
+    volatile: Vec<Regex>,
+    whitespace: Regex,
+}
+
+impl ContentHasher {
+    /// Site specific patterns are ignored on top of `VOLATILE_PATTERNS`.
+    pub fn new(patterns: &[String]) -> Result<Self, regex::Error> {
+        let volatile = VOLATILE_PATTERNS
+            .iter()
+            .copied()
+            .chain(patterns.iter().map(String::as_str))
+            .filter(|p| !p.is_empty())
+            .map(Regex::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let whitespace = Regex::new(r"\s+")?;
+        Ok(ContentHasher {
+            volatile,
+            whitespace,
+        })
+    }
+
+    pub fn normalize(&self, html: &str) -> String {
+        let mut normalized = html.to_string();
+        for pattern in &self.volatile {
+            normalized = pattern.replace_all(&normalized, "").into_owned();
+        }
+        self.whitespace
+            .replace_all(normalized.trim(), " ")
+            .into_owned()
+    }
+
+    pub fn hash(&self, html: &str) -> String {
+        let digest = Sha256::digest(self.normalize(html).as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hasher() -> ContentHasher {
+        ContentHasher::new(&[]).unwrap()
+    }
+
+    #[test]
+    fn ignores_tokens_nonces_and_generation_stamps() {
+        let hasher = hasher();
+        let first = r#"<meta name="csrf-token" content="a1"><input type="hidden" name="authenticity_token" value="x1">
+            <script nonce="n1" src="/app.js?v=1700000001"></script><!-- Generated at 2024-05-01 10:00 --><p>Widget</p>"#;
+        let second = r#"<meta name="csrf-token" content="b2"><input type="hidden" name="authenticity_token" value="y2">
+            <script nonce="n2" src="/app.js?v=1700000002"></script><!-- Generated at 2024-05-02 11:30 --><p>Widget</p>"#;
+        assert_eq!(hasher.hash(first), hasher.hash(second));
+    }
+
+    #[test]
+    fn keeps_dates_of_the_content() {
+        let hasher = hasher();
+        let before = "<p>Sale ends 2024-05-01 10:00</p>";
+        let after = "<p>Sale ends 2024-06-01 10:00</p>";
+        assert_ne!(hasher.hash(before), hasher.hash(after));
+        assert_ne!(hasher.hash("<a href=\"/list?page=2\">"), hasher.hash("<a href=\"/list?page=3\">"));
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(hasher().normalize("  <p>\n  Widget </p> "), "<p> Widget </p>");
+    }
+
+    #[test]
+    fn applies_extra_patterns() {
+        let hasher = ContentHasher::new(&[r"visitors: \d+".to_string()]).unwrap();
+        assert_eq!(hasher.hash("<p>visitors: 10</p>"), hasher.hash("<p>visitors: 12</p>"));
+        assert!(ContentHasher::new(&["(".to_string()]).is_err());
+    }
+}
@@ -1,11 +1,22 @@
 use std::{
     error::Error,
     collections::HashMap,
+    fmt::Display,
 };
 
-use crate::requests::Requests;
+use crate::cancellation::CancelledRuns;
+use crate::failure;
+use crate::fingerprint::ContentHasher;
+use crate::requests::{Fetched, Requests};
+use crate::sitemap;
 use crate::models::EventStatus;
 
+use common::models::{
+    ContentFingerprint, EventCommand, EventCommandStatus, EventProtocol, EventProtocolData, Page,
+    ScrapeBatch, ScrapeBatchItem, ScrapeFailure,
+};
+use futures::future::join_all;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -49,4 +60,157 @@ pub async fn handle_srcap_event(data: &str) -> Result<Vec<u8>, Box<dyn Error>> {
     };
     let out = serde_json::to_vec(&msg_out).expect("serialize error in handle_parse_event");
     Ok(out)
-}
\ No newline at end of file
+}
+
+/// Dispatches events sent to scrapers by their command.
+pub async fn handle_event(
+    requests: &Requests,
+    hasher: &ContentHasher,
+    cancelled: &CancelledRuns,
+    event: EventProtocol,
+) -> Result<Option<EventProtocol>, Box<dyn Error>> {
+    match event.command {
+        EventCommand::ScrapeBatch(_) => handle_scrape_batch(requests, hasher, cancelled, event).await,
+        EventCommand::FetchSitemap(_) => handle_sitemap_event(requests, cancelled, event).await,
+        _ => handle_scrape_event(requests, hasher, cancelled, event).await,
+    }
+}
+
+/// Fetches the page of a ScrapePage event and attaches html with its content fingerprint.
+/// Not modified responses are reported as Unchanged without a body,
+/// pages of cancelled runs as Skipped without fetching them.
+pub async fn handle_scrape_event(
+    requests: &Requests,
+    hasher: &ContentHasher,
+    cancelled: &CancelledRuns,
+    event: EventProtocol,
+) -> Result<Option<EventProtocol>, Box<dyn Error>> {
+    log::debug!("handling {}", event.trace());
+    let cause = event.cause();
+    let page = match event.data {
+        EventProtocolData::Internal(page) => page,
+        _ => return Err("scrape event data is not a page".into()),
+    };
+    let run_id = event.run_id.or(page.run_id);
+    let (status, page) = scrape_page(requests, hasher, cancelled, run_id, page).await;
+    Ok(Some(EventProtocol::caused_by(cause, EventCommand::ScrapePage(status), EventProtocolData::Internal(page))))
+}
+
+/// Fetches a sitemap of a run and reports the pages and nested sitemaps it lists.
+/// Sitemaps of cancelled runs are dropped and nothing is returned.
+pub async fn handle_sitemap_event(
+    requests: &Requests,
+    cancelled: &CancelledRuns,
+    event: EventProtocol,
+) -> Result<Option<EventProtocol>, Box<dyn Error>> {
+    log::debug!("handling {}", event.trace());
+    let cause = event.cause();
+    let mut fetch = match event.data {
+        EventProtocolData::Sitemap(fetch) => fetch,
+        _ => return Err("sitemap event data is not a sitemap".into()),
+    };
+    if let Some(run_id) = fetch.run_id {
+        if cancelled.contains(&run_id.to_string()).await {
+            log::info!("dropping sitemap {} of cancelled run {}", fetch.url, run_id);
+            return Ok(None);
+        }
+    }
+    let status = match sitemap::fetch(requests, &mut fetch).await {
+        true => EventCommandStatus::Done,
+        false => EventCommandStatus::Failed,
+    };
+    Ok(Some(EventProtocol::caused_by(
+        cause,
+        EventCommand::FetchSitemap(status),
+        EventProtocolData::Sitemap(fetch),
+    )))
+}
+
+/// Fetches pages of a batch concurrently and reports them back in one batch with a status per page.
+/// Batches hold pages of distinct domains, so fetching them at once doesn't hammer a single site.
+/// Every page is reported, pages of cancelled runs as Skipped so the scheduler frees their slots.
+pub async fn handle_scrape_batch(
+    requests: &Requests,
+    hasher: &ContentHasher,
+    cancelled: &CancelledRuns,
+    event: EventProtocol,
+) -> Result<Option<EventProtocol>, Box<dyn Error>> {
+    log::debug!("handling {}", event.trace());
+    let cause = event.cause();
+    let batch = match event.data {
+        EventProtocolData::Batch(batch) => batch,
+        _ => return Err("scrape batch event data is not a batch".into()),
+    };
+    let scraped = batch.items.into_iter().map(|item| async move {
+        let run_id = item.page.run_id;
+        let (status, page) = scrape_page(requests, hasher, cancelled, run_id, item.page).await;
+        ScrapeBatchItem { status, page, ..item }
+    });
+    let items: Vec<ScrapeBatchItem> = join_all(scraped).await;
+    Ok(Some(EventProtocol::caused_by(
+        cause,
+        EventCommand::ScrapeBatch(EventCommandStatus::Done),
+        EventProtocolData::Batch(ScrapeBatch { items, ..batch }),
+    )))
+}
+
+/// Fetches the page and returns it with the scrape status. Pages of cancelled runs are skipped,
+/// the scheduler still hears about them to free what they hold.
+async fn scrape_page(
+    requests: &Requests,
+    hasher: &ContentHasher,
+    cancelled: &CancelledRuns,
+    run_id: Option<impl Display>,
+    mut page: Page,
+) -> (EventCommandStatus, Page) {
+    if let Some(run_id) = run_id {
+        if cancelled.contains(&run_id.to_string()).await {
+            log::info!("skipping page {} of cancelled run {}", page.url, run_id);
+            return (EventCommandStatus::Skipped, page);
+        }
+    }
+
+    let status = match requests.get_conditional(&page.url, page.fingerprint.as_ref()).await {
+        Ok(Fetched::NotModified) => {
+            log::info!("page {} is not modified", page.url);
+            EventCommandStatus::Unchanged
+        }
+        Ok(Fetched::Body { html, etag, last_modified }) => match failure::detect_block(&html) {
+            Some(blocked) => {
+                log::warn!("page {} is blocked: {}", page.url, blocked.message);
+                page.failure = Some(blocked);
+                EventCommandStatus::Failed
+            }
+            None => {
+                page.fingerprint = Some(ContentFingerprint {
+                    hash: Some(hasher.hash(&html)),
+                    etag,
+                    last_modified,
+                });
+                page.html = Some(html);
+                page.failure = None;
+                EventCommandStatus::Done
+            }
+        },
+        Ok(Fetched::Status { status, html }) => {
+            let kind = failure::detect_block(&html)
+                .map(|blocked| blocked.kind)
+                .unwrap_or_else(|| failure::classify_status(status));
+            log::error!("cannot fetch page {}: status {} ({})", page.url, status, kind);
+            page.failure = Some(ScrapeFailure {
+                kind,
+                status: Some(status),
+                message: format!("unsuccessful status {}", status),
+            });
+            EventCommandStatus::Failed
+        }
+        Err(e) => {
+            let failure = failure::classify_error(&e);
+            log::error!("cannot fetch page {}: {} ({})", page.url, e, failure.kind);
+            page.failure = Some(failure);
+            EventCommandStatus::Failed
+        }
+    };
+
+    (status, page)
+}
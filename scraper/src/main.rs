@@ -3,11 +3,15 @@ extern crate log;
 use std::error::Error;
 use tokio;
 
+mod broker;
+mod cancellation;
 mod config;
+mod failure;
+mod fingerprint;
 mod models;
-mod rabbit;
 mod requests;
 mod handlers;
+mod sitemap;
 mod identity;
 mod redis;
 
@@ -15,13 +19,16 @@ mod redis;
 async fn main() -> Result<(), Box<dyn Error>> {
     json_env_logger::init();
     log::info!("Starting scrapper");
+    common::models::set_event_producer("scraper");
 
     log::info!("Loading config");
     let config = config::get();
     log::debug!("Config loaded: {:?}", config);
 
     log::info!("Initializing rabbit listener");
-    let broker = rabbit::Broker::new(config.rabbit).await?;
-    broker.start().await?;
+    let hasher = fingerprint::ContentHasher::new(&config.volatile_patterns())?;
+    let cancelled = cancellation::CancelledRuns::new(&config.redis.get_url()).await?;
+    let broker = broker::connect(&config.rabbit).await?;
+    broker::consume(broker.as_ref(), hasher, cancelled).await?;
     Ok(())
 }
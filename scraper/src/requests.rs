@@ -1,6 +1,21 @@
 // use futures_lite::stream::{self, StreamExt};
 use futures::stream;
-use reqwest::Client;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+
+use common::models::ContentFingerprint;
+
+/// Result of a conditional GET request.
+pub enum Fetched {
+    NotModified,
+    Body {
+        html: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// Unsuccessful status, body is kept for block detection.
+    Status { status: u16, html: String },
+}
 
 pub struct Requests {
     client: Client,
@@ -23,6 +38,64 @@ impl Requests {
         Ok(resp)
     }
 
+    /// GET returning the raw body with the status, for documents which aren't html,
+    /// e.g. gzip compressed sitemaps. Reading stops once the body is over `limit` bytes,
+    /// so a longer body comes back cut off, still over the limit.
+    pub async fn get_bytes(&self, url: &str, limit: usize) -> Result<(u16, Vec<u8>), reqwest::Error> {
+        let mut resp = self.client.get(url).send().await?;
+        let status = resp.status().as_u16();
+        let mut body = Vec::new();
+        while body.len() <= limit {
+            match resp.chunk().await? {
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        Ok((status, body))
+    }
+
+    /// GET with If-None-Match / If-Modified-Since taken from the previous fingerprint.
+    pub async fn get_conditional(
+        &self,
+        url: &str,
+        previous: Option<&ContentFingerprint>,
+    ) -> Result<Fetched, reqwest::Error> {
+        let mut req = self.client.get(url);
+        if let Some(previous) = previous {
+            if let Some(etag) = &previous.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &previous.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = req.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let status = resp.status();
+        let html = resp.text().await?;
+        if !status.is_success() {
+            return Ok(Fetched::Status {
+                status: status.as_u16(),
+                html,
+            });
+        }
+        Ok(Fetched::Body {
+            html,
+            etag,
+            last_modified,
+        })
+    }
+
     // pub async fn get_from_urls(&self, urls: Vec<&str>) -> Vec<Result<String, reqwest::Error>> {
     //     let bodies = stream::iter(urls)
     //         .map(|url| {
@@ -0,0 +1,253 @@
+use std::error::Error;
+use std::io::Read;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use common::models::{FailureKind, ScrapeFailure, SitemapEntry, SitemapFetch, SitemapKind};
+use flate2::read::GzDecoder;
+use regex::Regex;
+
+use crate::failure;
+use crate::requests::Requests;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Sitemaps are limited to 50MB uncompressed by the protocol, larger ones are refused
+/// before they are read or decompressed further.
+const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+
+struct Patterns {
+    sitemap: Regex,
+    url: Regex,
+    loc: Regex,
+    lastmod: Regex,
+}
+
+/// `<sitemap>` entries of sitemap indexes and `<url>` entries of url sets with their fields.
+/// `\b` keeps `<sitemap` and `<url` from matching `<sitemapindex` and `<urlset`.
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        sitemap: Regex::new(r"(?s)<sitemap\b[^>]*>(.*?)</sitemap>").unwrap(),
+        url: Regex::new(r"(?s)<url\b[^>]*>(.*?)</url>").unwrap(),
+        loc: Regex::new(r"(?s)<loc>(.*?)</loc>").unwrap(),
+        lastmod: Regex::new(r"(?s)<lastmod>(.*?)</lastmod>").unwrap(),
+    })
+}
+
+/// Fetches the robots.txt or sitemap and fills in the sitemaps and pages it lists.
+/// Returns false with a failure attached when the sitemap cannot be fetched.
+pub async fn fetch(requests: &Requests, sitemap: &mut SitemapFetch) -> bool {
+    let (status, body) = match requests.get_bytes(&sitemap.url, MAX_SITEMAP_BYTES).await {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            let failure = failure::classify_error(&e);
+            log::error!("cannot fetch sitemap {}: {} ({})", sitemap.url, e, failure.kind);
+            sitemap.failure = Some(failure);
+            return false;
+        }
+    };
+    if body.len() > MAX_SITEMAP_BYTES {
+        log::error!("cannot fetch sitemap {}: it's over {} bytes", sitemap.url, MAX_SITEMAP_BYTES);
+        sitemap.failure = Some(ScrapeFailure {
+            kind: FailureKind::ClientError,
+            status: Some(status),
+            message: format!("sitemap is over {} bytes", MAX_SITEMAP_BYTES),
+        });
+        return false;
+    }
+    let result = match (sitemap.kind, status) {
+        // sites without robots.txt usually keep their sitemap at the default place
+        (SitemapKind::Robots, 200..=299) => Ok(parse_robots(&sitemap.url, &String::from_utf8_lossy(&body))),
+        (SitemapKind::Robots, 404 | 410) => Ok(vec![default_sitemap(&sitemap.url)]),
+        (SitemapKind::Sitemap, 200..=299) => parse_sitemap(&body).map(|(sitemaps, pages)| {
+            sitemap.pages = pages;
+            sitemaps
+        }),
+        (_, status) => {
+            let kind = failure::classify_status(status);
+            log::error!("cannot fetch sitemap {}: status {} ({})", sitemap.url, status, kind);
+            sitemap.failure = Some(ScrapeFailure {
+                kind,
+                status: Some(status),
+                message: format!("unsuccessful status {}", status),
+            });
+            return false;
+        }
+    };
+    match result {
+        Ok(sitemaps) => {
+            sitemap.sitemaps = sitemaps;
+            log::info!("sitemap {} lists {} sitemaps and {} pages", sitemap.url, sitemap.sitemaps.len(), sitemap.pages.len());
+            true
+        }
+        Err(e) => {
+            log::error!("cannot parse sitemap {}: {}", sitemap.url, e);
+            sitemap.failure = Some(ScrapeFailure {
+                kind: FailureKind::Unknown,
+                status: Some(status),
+                message: format!("cannot parse sitemap: {}", e),
+            });
+            false
+        }
+    }
+}
+
+/// Sitemaps from `Sitemap:` lines, the default one when there are none.
+fn parse_robots(robots_url: &str, robots: &str) -> Vec<String> {
+    let sitemaps: Vec<String> = robots
+        .lines()
+        .filter_map(|line| {
+            let (field, value) = line.split_once(':')?;
+            field.trim().eq_ignore_ascii_case("sitemap").then(|| value.trim().to_string())
+        })
+        .filter(|url| !url.is_empty())
+        .collect();
+    match sitemaps.is_empty() {
+        true => vec![default_sitemap(robots_url)],
+        false => sitemaps,
+    }
+}
+
+fn default_sitemap(robots_url: &str) -> String {
+    match robots_url.strip_suffix("robots.txt") {
+        Some(root) => format!("{}sitemap.xml", root),
+        None => format!("{}/sitemap.xml", robots_url.trim_end_matches('/')),
+    }
+}
+
+/// Nested sitemaps of a sitemap index and pages of a url set, gzip compressed or not.
+fn parse_sitemap(body: &[u8]) -> Result<(Vec<String>, Vec<SitemapEntry>), Box<dyn Error>> {
+    let xml = match body.starts_with(&GZIP_MAGIC) {
+        true => {
+            let mut xml = Vec::new();
+            GzDecoder::new(body).take(MAX_SITEMAP_BYTES as u64 + 1).read_to_end(&mut xml)?;
+            if xml.len() > MAX_SITEMAP_BYTES {
+                return Err(format!("decompressed sitemap is over {} bytes", MAX_SITEMAP_BYTES).into());
+            }
+            String::from_utf8(xml)?
+        }
+        false => String::from_utf8_lossy(body).into_owned(),
+    };
+    let patterns = patterns();
+    let field = |entry: &str, pattern: &Regex| pattern.captures(entry).map(|c| xml_text(&c[1]));
+    let sitemaps = patterns
+        .sitemap
+        .captures_iter(&xml)
+        .filter_map(|entry| field(&entry[1], &patterns.loc))
+        .collect();
+    let pages = patterns
+        .url
+        .captures_iter(&xml)
+        .filter_map(|entry| {
+            let url = field(&entry[1], &patterns.loc)?;
+            let lastmod = field(&entry[1], &patterns.lastmod).and_then(|lastmod| parse_lastmod(&lastmod));
+            Some(SitemapEntry { url, lastmod })
+        })
+        .collect();
+    Ok((sitemaps, pages))
+}
+
+/// Text of an element with CDATA unwrapped and predefined entities unescaped.
+fn xml_text(raw: &str) -> String {
+    let raw = raw.trim();
+    let raw = raw
+        .strip_prefix("<![CDATA[")
+        .and_then(|raw| raw.strip_suffix("]]>"))
+        .unwrap_or(raw);
+    raw.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// W3C datetime of a lastmod, a plain date means its midnight in UTC.
+fn parse_lastmod(lastmod: &str) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(lastmod) {
+        return Some(at.with_timezone(&Utc));
+    }
+    if let Ok(at) = DateTime::parse_from_str(lastmod, "%Y-%m-%dT%H:%M%:z") {
+        return Some(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(lastmod, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|at| at.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use chrono::TimeZone;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn reads_sitemaps_from_robots() {
+        let robots = "User-agent: *\nDisallow: /cart\nSitemap: https://shop.site/a.xml\nsitemap:https://shop.site/b.xml\nSitemap:\n";
+        assert_eq!(
+            parse_robots("https://shop.site/robots.txt", robots),
+            vec!["https://shop.site/a.xml", "https://shop.site/b.xml"]
+        );
+    }
+
+    #[test]
+    fn robots_without_sitemaps_fall_back_to_the_default_one() {
+        assert_eq!(parse_robots("https://shop.site/robots.txt", "User-agent: *\n"), vec!["https://shop.site/sitemap.xml"]);
+        assert_eq!(default_sitemap("https://shop.site/"), "https://shop.site/sitemap.xml");
+    }
+
+    #[test]
+    fn parses_url_sets() {
+        let xml = br#"<?xml version="1.0"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url><loc>https://shop.site/a?x=1&amp;y=2</loc><lastmod>2024-05-01</lastmod></url>
+                <url><loc><![CDATA[https://shop.site/b]]></loc></url>
+                <url><lastmod>2024-05-01</lastmod></url>
+            </urlset>"#;
+        let (sitemaps, pages) = parse_sitemap(xml).unwrap();
+        assert!(sitemaps.is_empty());
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].url, "https://shop.site/a?x=1&y=2");
+        assert_eq!(pages[0].lastmod, Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(pages[1].url, "https://shop.site/b");
+        assert_eq!(pages[1].lastmod, None);
+    }
+
+    #[test]
+    fn parses_gzipped_sitemap_indexes() {
+        let xml = br#"<sitemapindex><sitemap><loc>https://shop.site/1.xml.gz</loc></sitemap>
+            <sitemap><loc> https://shop.site/2.xml </loc></sitemap></sitemapindex>"#;
+        let (sitemaps, pages) = parse_sitemap(&gzip(xml)).unwrap();
+        assert_eq!(sitemaps, vec!["https://shop.site/1.xml.gz", "https://shop.site/2.xml"]);
+        assert!(pages.is_empty());
+    }
+
+    #[test]
+    fn refuses_gzip_bombs() {
+        let bomb = gzip(&vec![b' '; MAX_SITEMAP_BYTES + 1]);
+        assert!(bomb.len() < MAX_SITEMAP_BYTES / 100);
+        assert!(parse_sitemap(&bomb).is_err());
+    }
+
+    #[test]
+    fn parses_lastmods() {
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+        assert_eq!(parse_lastmod("2024-05-01T10:30:00Z"), Some(at));
+        assert_eq!(parse_lastmod("2024-05-01T12:30:00+02:00"), Some(at));
+        assert_eq!(parse_lastmod("2024-05-01T12:30+02:00"), Some(at));
+        assert_eq!(parse_lastmod("2024-05-01"), Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(parse_lastmod("yesterday"), None);
+        assert_eq!(parse_lastmod("2024-13-01"), None);
+    }
+}